@@ -0,0 +1,587 @@
+//! Session agent that keeps unlocked vault keys between CLI commands.
+//!
+//! Every CLI command is a separate process, so without the agent each one
+//! prompts for the PIN and pays a full Argon2 derivation. The agent is a
+//! small daemon listening on a Unix socket that only its owner can use. After
+//! a PIN unlock the CLI hands it the vault's data key; later commands fetch
+//! the key instead of prompting, and the vault checks it against its stored
+//! data-key verifier.
+//!
+//! # Expiry
+//!
+//! - **Idle TTL**: a key not fetched for this long is dropped
+//! - **Hard TTL**: a key is dropped this long after it was stored, however
+//!   often it is used
+//!
+//! Keys live only in the agent's memory and are zeroized when dropped.
+//!
+//! # Protocol
+//!
+//! One JSON [`AgentRequest`] line per connection, answered by one JSON
+//! [`AgentResponse`] line. Vaults are identified by their database URL.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::agent::{run_agent, AgentClient, AgentConfig};
+//!
+//! tokio::spawn(run_agent(socket_path.clone(), AgentConfig::default()));
+//!
+//! let client = AgentClient::new(socket_path);
+//! client.store_key(db_url, &vault_key).await?;
+//! let key = client.fetch_key(db_url).await?;
+//! client.lock().await?;
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::core::{DEFAULT_AGENT_HARD_TTL, DEFAULT_AGENT_IDLE_TTL};
+use crate::crypto::VaultKey;
+use crate::error::{Result, VaultError};
+
+/// How long the client waits for the agent before giving up
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the agent drops expired keys
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Request sent to the agent.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AgentRequest {
+    /// Keep `key` (hex) for `vault`
+    Store { vault: String, key: String },
+    /// Return the key for `vault`, if held
+    Fetch { vault: String },
+    /// Drop every key
+    Lock,
+    /// Describe the held keys
+    Status,
+    /// Drop every key and exit
+    Shutdown,
+}
+
+/// Response sent by the agent.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AgentResponse {
+    /// Request done
+    Ok,
+    /// The requested key (hex)
+    Key { key: String },
+    /// No key is held for the vault
+    Locked,
+    /// Keys held and the agent's TTLs
+    Status {
+        vaults: Vec<AgentVault>,
+        idle_ttl_secs: u64,
+        hard_ttl_secs: u64,
+    },
+    /// Request failed
+    Error { message: String },
+}
+
+/// A vault whose key the agent holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentVault {
+    /// Database URL of the vault
+    pub vault: String,
+    /// Seconds until the key is dropped, whichever TTL comes first
+    pub expires_in_secs: u64,
+}
+
+/// Expiry settings for the agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgentConfig {
+    /// Drop a key after this long without a fetch
+    pub idle_ttl: Duration,
+    /// Drop a key this long after it was stored
+    pub hard_ttl: Duration,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            idle_ttl: DEFAULT_AGENT_IDLE_TTL,
+            hard_ttl: DEFAULT_AGENT_HARD_TTL,
+        }
+    }
+}
+
+/// Returns the default socket path, `~/.vult/agent.sock`.
+pub fn default_socket_path() -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push(".vult");
+    path.push("agent.sock");
+    path
+}
+
+/// Client for a running agent.
+#[derive(Debug, Clone)]
+pub struct AgentClient {
+    socket_path: PathBuf,
+}
+
+impl AgentClient {
+    /// Creates a client for the agent listening on `socket_path`.
+    pub fn new(socket_path: impl Into<PathBuf>) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+        }
+    }
+
+    /// Returns the socket path this client connects to.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Returns whether an agent answers on the socket.
+    pub async fn is_running(&self) -> bool {
+        matches!(
+            self.request(&AgentRequest::Status).await,
+            Ok(AgentResponse::Status { .. })
+        )
+    }
+
+    /// Sends one request and waits for the response.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::Io`] if no agent is listening or it doesn't
+    /// answer in time.
+    pub async fn request(&self, request: &AgentRequest) -> Result<AgentResponse> {
+        let line = Zeroizing::new(
+            serde_json::to_string(request).map_err(|e| VaultError::Io(e.to_string()))?,
+        );
+        let reply = tokio::time::timeout(CLIENT_TIMEOUT, imp::send(&self.socket_path, &line))
+            .await
+            .map_err(|_| VaultError::Io("session agent did not answer".to_string()))??;
+
+        serde_json::from_str(&reply).map_err(|e| VaultError::Io(e.to_string()))
+    }
+
+    /// Hands the data key of `vault` to the agent.
+    pub async fn store_key(&self, vault: &str, key: &VaultKey) -> Result<()> {
+        let request = AgentRequest::Store {
+            vault: vault.to_string(),
+            key: hex::encode(key.as_bytes()),
+        };
+        let response = self.request(&request).await;
+        if let AgentRequest::Store { key, .. } = request {
+            drop(Zeroizing::new(key));
+        }
+        expect_ok(response?)
+    }
+
+    /// Fetches the data key of `vault`, or `None` if the agent doesn't hold it.
+    pub async fn fetch_key(&self, vault: &str) -> Result<Option<VaultKey>> {
+        let request = AgentRequest::Fetch {
+            vault: vault.to_string(),
+        };
+        match self.request(&request).await? {
+            AgentResponse::Key { key } => decode_key(Zeroizing::new(key)).map(Some),
+            AgentResponse::Locked => Ok(None),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Drops every key the agent holds.
+    pub async fn lock(&self) -> Result<()> {
+        expect_ok(self.request(&AgentRequest::Lock).await?)
+    }
+
+    /// Stops the agent.
+    pub async fn shutdown(&self) -> Result<()> {
+        expect_ok(self.request(&AgentRequest::Shutdown).await?)
+    }
+}
+
+/// Runs the agent on `socket_path` until it receives [`AgentRequest::Shutdown`].
+///
+/// The socket's directory is created with mode 0700 and the socket itself
+/// gets mode 0600. Connections from other users are refused. A stale socket
+/// left by a crashed agent is replaced.
+///
+/// # Errors
+///
+/// - [`VaultError::InvalidState`] if another agent is already listening
+/// - [`VaultError::Io`] if the socket cannot be created
+pub async fn run_agent(socket_path: PathBuf, config: AgentConfig) -> Result<()> {
+    imp::run(socket_path, config).await
+}
+
+/// Maps anything but [`AgentResponse::Ok`] to an error.
+fn expect_ok(response: AgentResponse) -> Result<()> {
+    match response {
+        AgentResponse::Ok => Ok(()),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Turns an error or out-of-place response into [`VaultError::Io`].
+///
+/// The response is never printed, since it may carry a key.
+fn unexpected(response: AgentResponse) -> VaultError {
+    match response {
+        AgentResponse::Error { message } => VaultError::Io(format!("session agent: {message}")),
+        _ => VaultError::Io("unexpected session agent response".to_string()),
+    }
+}
+
+/// Decodes a hex data key.
+fn decode_key(hex_key: Zeroizing<String>) -> Result<VaultKey> {
+    let bytes =
+        Zeroizing::new(hex::decode(hex_key.as_str()).map_err(|_| VaultError::InvalidKeyLength)?);
+    let key = <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| VaultError::InvalidKeyLength)?;
+    Ok(VaultKey::from_bytes(key))
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::sync::{Mutex, Notify};
+    use tokio::time::Instant;
+    use zeroize::Zeroizing;
+
+    use super::{decode_key, AgentConfig, AgentRequest, AgentResponse, AgentVault, SWEEP_INTERVAL};
+    use crate::crypto::VaultKey;
+    use crate::error::{Result, VaultError};
+
+    /// A key held for one vault.
+    struct Entry {
+        key: VaultKey,
+        stored_at: Instant,
+        last_used: Instant,
+    }
+
+    impl Entry {
+        fn expires_at(&self, config: &AgentConfig) -> Instant {
+            (self.last_used + config.idle_ttl).min(self.stored_at + config.hard_ttl)
+        }
+    }
+
+    /// Shared agent state.
+    struct State {
+        config: AgentConfig,
+        entries: Mutex<HashMap<String, Entry>>,
+        shutdown: Notify,
+    }
+
+    impl State {
+        async fn sweep(&self) {
+            let now = Instant::now();
+            self.entries
+                .lock()
+                .await
+                .retain(|_, entry| entry.expires_at(&self.config) > now);
+        }
+
+        async fn handle(&self, request: AgentRequest) -> AgentResponse {
+            self.sweep().await;
+            let now = Instant::now();
+            let mut entries = self.entries.lock().await;
+
+            match request {
+                AgentRequest::Store { vault, key } => match decode_key(Zeroizing::new(key)) {
+                    Ok(key) => {
+                        entries.insert(
+                            vault,
+                            Entry {
+                                key,
+                                stored_at: now,
+                                last_used: now,
+                            },
+                        );
+                        AgentResponse::Ok
+                    }
+                    Err(e) => AgentResponse::Error {
+                        message: e.to_string(),
+                    },
+                },
+                AgentRequest::Fetch { vault } => match entries.get_mut(&vault) {
+                    Some(entry) => {
+                        entry.last_used = now;
+                        AgentResponse::Key {
+                            key: hex::encode(entry.key.as_bytes()),
+                        }
+                    }
+                    None => AgentResponse::Locked,
+                },
+                AgentRequest::Lock => {
+                    entries.clear();
+                    AgentResponse::Ok
+                }
+                AgentRequest::Status => {
+                    let mut vaults: Vec<AgentVault> = entries
+                        .iter()
+                        .map(|(vault, entry)| AgentVault {
+                            vault: vault.clone(),
+                            expires_in_secs: entry
+                                .expires_at(&self.config)
+                                .saturating_duration_since(now)
+                                .as_secs(),
+                        })
+                        .collect();
+                    vaults.sort_by(|a, b| a.vault.cmp(&b.vault));
+                    AgentResponse::Status {
+                        vaults,
+                        idle_ttl_secs: self.config.idle_ttl.as_secs(),
+                        hard_ttl_secs: self.config.hard_ttl.as_secs(),
+                    }
+                }
+                AgentRequest::Shutdown => {
+                    entries.clear();
+                    self.shutdown.notify_one();
+                    AgentResponse::Ok
+                }
+            }
+        }
+    }
+
+    pub(super) async fn send(socket_path: &Path, line: &str) -> Result<Zeroizing<String>> {
+        let stream = UnixStream::connect(socket_path)
+            .await
+            .map_err(|e| VaultError::Io(format!("session agent not reachable: {e}")))?;
+        let (reader, mut writer) = stream.into_split();
+
+        writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+        writer
+            .shutdown()
+            .await
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+
+        let mut reply = Zeroizing::new(String::new());
+        BufReader::new(reader)
+            .read_line(&mut reply)
+            .await
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+        Ok(reply)
+    }
+
+    pub(super) async fn run(socket_path: PathBuf, config: AgentConfig) -> Result<()> {
+        if let Some(dir) = socket_path.parent() {
+            if !dir.exists() {
+                fs::DirBuilder::new()
+                    .recursive(true)
+                    .mode(0o700)
+                    .create(dir)
+                    .map_err(|e| VaultError::Io(e.to_string()))?;
+            }
+        }
+        if socket_path.exists() {
+            if UnixStream::connect(&socket_path).await.is_ok() {
+                return Err(VaultError::InvalidState(format!(
+                    "a session agent is already running on {}",
+                    socket_path.display()
+                )));
+            }
+            fs::remove_file(&socket_path).map_err(|e| VaultError::Io(e.to_string()))?;
+        }
+
+        let listener =
+            UnixListener::bind(&socket_path).map_err(|e| VaultError::Io(e.to_string()))?;
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+        let owner = fs::metadata(&socket_path)
+            .map_err(|e| VaultError::Io(e.to_string()))?
+            .uid();
+
+        let state = Arc::new(State {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            shutdown: Notify::new(),
+        });
+        let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let Ok((stream, _)) = accepted else { continue };
+                    // Only the user who started the agent may talk to it
+                    match stream.peer_cred() {
+                        Ok(cred) if cred.uid() == owner => {}
+                        _ => continue,
+                    }
+                    let state = Arc::clone(&state);
+                    tokio::spawn(async move {
+                        let _ = serve(stream, &state).await;
+                    });
+                }
+                _ = sweep.tick() => state.sweep().await,
+                _ = state.shutdown.notified() => break,
+            }
+        }
+
+        let _ = fs::remove_file(&socket_path);
+        Ok(())
+    }
+
+    /// Answers the single request sent on `stream`.
+    async fn serve(stream: UnixStream, state: &State) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut line = Zeroizing::new(String::new());
+        BufReader::new(reader).read_line(&mut line).await?;
+
+        let response = match serde_json::from_str::<AgentRequest>(&line) {
+            Ok(request) => state.handle(request).await,
+            Err(e) => AgentResponse::Error {
+                message: format!("invalid request: {e}"),
+            },
+        };
+        let reply =
+            Zeroizing::new(serde_json::to_string(&response).map_err(std::io::Error::other)?);
+        if let AgentResponse::Key { key } = response {
+            drop(Zeroizing::new(key));
+        }
+
+        writer.write_all(reply.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.shutdown().await
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::path::{Path, PathBuf};
+
+    use zeroize::Zeroizing;
+
+    use super::AgentConfig;
+    use crate::error::{Result, VaultError};
+
+    fn unsupported() -> VaultError {
+        VaultError::InvalidState("the session agent requires Unix domain sockets".to_string())
+    }
+
+    pub(super) async fn send(_socket_path: &Path, _line: &str) -> Result<Zeroizing<String>> {
+        Err(unsupported())
+    }
+
+    pub(super) async fn run(_socket_path: PathBuf, _config: AgentConfig) -> Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Starts an agent in a temporary directory.
+    async fn start_agent(config: AgentConfig) -> (tempfile::TempDir, AgentClient) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent").join("agent.sock");
+        tokio::spawn(run_agent(socket.clone(), config));
+
+        let client = AgentClient::new(&socket);
+        for _ in 0..100 {
+            if client.is_running().await {
+                return (dir, client);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("agent did not start");
+    }
+
+    #[tokio::test]
+    async fn test_store_fetch_and_lock() {
+        let (_dir, client) = start_agent(AgentConfig::default()).await;
+        let key = VaultKey::from_bytes([9u8; 32]);
+
+        assert!(client.fetch_key("sqlite://a.db").await.unwrap().is_none());
+        client.store_key("sqlite://a.db", &key).await.unwrap();
+
+        let fetched = client.fetch_key("sqlite://a.db").await.unwrap().unwrap();
+        assert_eq!(fetched.as_bytes(), key.as_bytes());
+        assert!(client.fetch_key("sqlite://b.db").await.unwrap().is_none());
+
+        client.lock().await.unwrap();
+        assert!(client.fetch_key("sqlite://a.db").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_socket_is_private() {
+        let (_dir, client) = start_agent(AgentConfig::default()).await;
+
+        let socket_mode = std::fs::metadata(client.socket_path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(socket_mode & 0o777, 0o600);
+        let dir_mode = std::fs::metadata(client.socket_path().parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(dir_mode & 0o777, 0o700);
+    }
+
+    #[tokio::test]
+    async fn test_idle_ttl_expires_unused_keys() {
+        let (_dir, client) = start_agent(AgentConfig {
+            idle_ttl: Duration::from_secs(1),
+            hard_ttl: Duration::from_secs(60),
+        })
+        .await;
+        let key = VaultKey::from_bytes([1u8; 32]);
+        client.store_key("vault", &key).await.unwrap();
+
+        // Each fetch restarts the idle timer
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(client.fetch_key("vault").await.unwrap().is_some());
+        }
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(client.fetch_key("vault").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hard_ttl_expires_used_keys() {
+        let (_dir, client) = start_agent(AgentConfig {
+            idle_ttl: Duration::from_secs(60),
+            hard_ttl: Duration::from_millis(1500),
+        })
+        .await;
+        let key = VaultKey::from_bytes([2u8; 32]);
+        client.store_key("vault", &key).await.unwrap();
+
+        for _ in 0..2 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(client.fetch_key("vault").await.unwrap().is_some());
+        }
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(client.fetch_key("vault").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_and_single_instance() {
+        let (_dir, client) = start_agent(AgentConfig::default()).await;
+
+        let second = run_agent(client.socket_path().to_path_buf(), AgentConfig::default()).await;
+        assert!(matches!(second, Err(VaultError::InvalidState(_))));
+
+        client.shutdown().await.unwrap();
+        for _ in 0..100 {
+            if !client.socket_path().exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!client.is_running().await);
+        assert!(client.fetch_key("vault").await.is_err());
+    }
+}
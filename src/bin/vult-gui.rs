@@ -72,6 +72,7 @@ async fn main() {
         .invoke_handler(tauri::generate_handler![
             commands::init_vault,
             commands::unlock_vault,
+            commands::reset_lockout,
             commands::lock_vault,
             commands::get_auth_state,
             commands::is_initialized,
@@ -82,6 +83,13 @@ async fn main() {
             commands::search_api_keys,
             commands::update_api_key,
             commands::delete_api_key,
+            commands::get_key_history,
+            commands::get_key_version,
+            commands::rollback_api_key,
+            commands::set_key_history_limit,
+            commands::list_due_keys,
+            commands::get_audit_log,
+            commands::verify_audit_log,
             commands::copy_to_clipboard,
             commands::update_activity,
             commands::check_auto_lock,
@@ -3,9 +3,11 @@
 //! Provides full vault functionality from the terminal, including
 //! creating, listing, searching, and managing API keys.
 
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use arboard::Clipboard;
 use clap::{Parser, Subcommand};
@@ -13,9 +15,33 @@ use colored::Colorize;
 use comfy_table::{presets::UTF8_FULL, Table};
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Confirm;
-
-use vult::services::VaultManager;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use vult::agent::{
+    default_socket_path, run_agent, AgentClient, AgentConfig, AgentRequest, AgentResponse,
+};
+use vult::core::KeyRef;
+use vult::crypto::KdfParams;
+use vult::database::VaultDb;
+use vult::export::{ExportFormat, VaultExport};
+use vult::import::ImportFormat;
+use vult::kdbx::{self, KdbxOptions};
+use vult::manifest::{is_valid_env_name, Manifest, MANIFEST_FILE_NAME};
+use vult::services::{
+    create_key_file, read_key_file, ApiKey, AuditEvent, AuditFilter, ConflictStrategy,
+    CreateKeyRequest, DueReason, ImportMode, ImportOptions, ImportedKey, LockoutReset,
+    SlotCredential, SlotKind, UpdateKeyRequest, VaultManager, PRIMARY_SLOT_LABEL,
+};
+use vult::shamir::Share;
+use vult::template::Template;
 use vult::VaultError;
+use zeroize::Zeroizing;
 
 /// Vult - Secure API Key Vault
 ///
@@ -34,6 +60,14 @@ struct Cli {
     #[arg(long, global = true, env = "VULT_DB_PATH")]
     db_path: Option<PathBuf>,
 
+    /// Key file required to unlock the vault, if it was created with one
+    #[arg(long, global = true, env = "VULT_KEYFILE")]
+    keyfile: Option<PathBuf>,
+
+    /// Session agent socket (default: ~/.vult/agent.sock)
+    #[arg(long, global = true, env = "VULT_AGENT_SOCKET")]
+    agent_socket: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -41,14 +75,33 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Initialize a new vault with a PIN
-    Init,
+    ///
+    /// With the global --keyfile option, the vault also requires that key
+    /// file to unlock. A new random key file is created if it doesn't exist.
+    Init {
+        /// Store app names, key names, URLs and descriptions encrypted
+        #[arg(long)]
+        encrypt_metadata: bool,
+    },
 
     /// Change the vault PIN
     ChangePin,
 
-    /// Lock the vault (clear any session)
+    /// Lock the vault, dropping any key held by the session agent
     Lock,
 
+    /// Unlock the vault and hand its key to the session agent
+    ///
+    /// With --reset-lockout, a lockout after failed attempts is cleared
+    /// first. PINs can be guessed, so this needs a key file that opens a
+    /// key-file slot (the global --keyfile option) or, without one, recovery
+    /// shares read from stdin.
+    Unlock {
+        /// Clear a failed-unlock lockout with a key-file slot or recovery shares
+        #[arg(long)]
+        reset_lockout: bool,
+    },
+
     /// Add a new API key
     Add {
         /// Application name (e.g., "github")
@@ -69,9 +122,19 @@ enum Commands {
         /// Description (optional)
         #[arg(short, long)]
         description: Option<String>,
+
+        /// When the key expires: a date (2026-12-31), an RFC 3339 time or a
+        /// duration from now (90d)
+        #[arg(long, value_parser = parse_expiry)]
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Remind to change the value this often (e.g. 90d)
+        #[arg(long, value_parser = parse_interval)]
+        rotate_every: Option<u64>,
     },
 
     /// Get an API key value
+    #[command(disable_version_flag = true)]
     Get {
         /// Application name
         #[arg(short, long)]
@@ -87,6 +150,10 @@ enum Commands {
         /// Copy value to clipboard (auto-clears after 45s)
         #[arg(short, long)]
         copy: bool,
+
+        /// Get the value the key held at this version (see `vult history`)
+        #[arg(long, value_name = "N")]
+        version: Option<i64>,
     },
 
     /// List all API keys
@@ -126,6 +193,27 @@ enum Commands {
         /// New description
         #[arg(short, long)]
         description: Option<String>,
+
+        /// Why the value is changing, shown by `vult history`
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// New expiry: a date (2026-12-31), an RFC 3339 time or a duration
+        /// from now (90d)
+        #[arg(long, value_parser = parse_expiry)]
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Remove the expiry
+        #[arg(long, conflicts_with = "expires_at")]
+        no_expiry: bool,
+
+        /// Remind to change the value this often (e.g. 90d)
+        #[arg(long, value_parser = parse_interval)]
+        rotate_every: Option<u64>,
+
+        /// Remove the rotation reminder
+        #[arg(long, conflicts_with = "rotate_every")]
+        no_rotation: bool,
     },
 
     /// Delete an API key
@@ -142,8 +230,580 @@ enum Commands {
         force: bool,
     },
 
+    /// Show the previous values of a key
+    ///
+    /// Lists each version with when it was replaced and why, without the
+    /// values. Read one with `vult get --version N` or restore it with
+    /// `vult rollback`.
+    History {
+        /// Key, as app/key
+        key: KeyRef,
+
+        /// Keep this many previous values from now on, dropping older ones
+        #[arg(long, value_name = "N")]
+        keep: Option<u32>,
+    },
+
+    /// Restore a previous value of a key
+    ///
+    /// The restored value becomes a new version, and the current value is
+    /// kept in the history, so a rollback can itself be undone.
+    Rollback {
+        /// Key, as app/key
+        key: KeyRef,
+
+        /// Version to restore, from `vult history`
+        #[arg(value_name = "VERSION")]
+        target: i64,
+
+        /// Reason shown by `vult history` (default: "rollback to version N")
+        #[arg(long)]
+        reason: Option<String>,
+    },
+
+    /// List keys that expire or are due for rotation
+    ///
+    /// Keys already past their date are listed too. For cron, the exit code
+    /// is 0 if nothing is due, 11 if keys are due within the window and 12 if
+    /// any key has expired or is overdue for rotation.
+    Expiring {
+        /// How far ahead to look (e.g. 7d, 2w)
+        #[arg(long, default_value = "30d", value_parser = parse_duration)]
+        within: chrono::Duration,
+    },
+
+    /// Unlock once and run key commands in an interactive shell
+    ///
+    /// Accepts add, get, list, search, update and delete with the same
+    /// arguments as the subcommands. Tab completes commands, app names and
+    /// key names. History is kept in memory only.
+    Shell {
+        /// Lock the vault after this many seconds without a command
+        #[arg(long, default_value_t = 300)]
+        idle_timeout: u64,
+    },
+
+    /// Run a command with keys in its environment
+    ///
+    /// The vault is unlocked once and each --env NAME=app/key sets NAME to
+    /// that key's value. With --manifest, the variables declared in
+    /// .vult.toml are set too; --env wins over the manifest. Values are only
+    /// passed to the command, never written to disk. Exits with the
+    /// command's exit code.
+    Run {
+        /// Variable and key to set, as NAME=app/key (repeatable)
+        #[arg(
+            short,
+            long = "env",
+            value_name = "NAME=APP/KEY",
+            value_parser = parse_env_mapping,
+            required_unless_present = "manifest"
+        )]
+        env: Vec<(String, KeyRef)>,
+
+        /// Also set the variables of a manifest (default: nearest .vult.toml)
+        #[arg(short, long, value_name = "PATH")]
+        manifest: Option<Option<PathBuf>>,
+
+        /// Command and arguments, after --
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Report which keys of the project manifest are missing
+    ///
+    /// Reads the nearest .vult.toml and checks each key without showing
+    /// values. Exits with code 2 if a required key is missing.
+    Check {
+        /// Manifest to check (default: nearest .vult.toml)
+        #[arg(short, long, value_name = "PATH")]
+        manifest: Option<Option<PathBuf>>,
+    },
+
+    /// Print the manifest's variables as shell exports
+    ///
+    /// Use as `eval "$(vult env --manifest)"`. Prints nothing if a required
+    /// key is missing.
+    Env {
+        /// Manifest to load (default: nearest .vult.toml)
+        #[arg(short, long, value_name = "PATH")]
+        manifest: Option<Option<PathBuf>>,
+    },
+
+    /// Fill `{{ vult://app/key }}` references in a template
+    ///
+    /// Every reference is resolved with one unlock before anything is
+    /// written; an unknown reference fails with exit code 2 and no output.
+    Inject {
+        /// Template to read; stdin if omitted or `-`
+        #[arg(short, long, value_name = "FILE")]
+        input: Option<PathBuf>,
+
+        /// File to write, with mode 0600; stdout if omitted or `-`
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Export every key to an encrypted .vultbak file or KeePass database
+    ///
+    /// The file is encrypted with a passphrase asked for now, not with the
+    /// PIN, so it can be imported into another vault on another machine.
+    /// With --to keepass it is a KDBX 4 database with one group per app,
+    /// which KeePass and KeePassXC can open.
+    Export {
+        /// File to write, with mode 0600
+        file: PathBuf,
+
+        /// Format of FILE: vultbak or keepass
+        #[arg(long, value_name = "FORMAT", default_value = "vultbak")]
+        to: ExportFormat,
+    },
+
+    /// Import keys from a .vultbak export or another secret manager
+    ///
+    /// FILE is a .vultbak file made by `vult export` unless --from names
+    /// another format: env (a .env file), bitwarden (an unencrypted JSON
+    /// export), 1password (a CSV export), pass (a password store
+    /// directory) or keepass (a KDBX 4 database). All keys are imported in one transaction, so a failed
+    /// import changes nothing.
+    Import {
+        /// File to read, or the password store directory for --from pass
+        file: PathBuf,
+
+        /// Format of FILE: env, bitwarden, 1password, pass or keepass
+        #[arg(long, value_name = "FORMAT")]
+        from: Option<ImportFormat>,
+
+        /// App name for imported keys without one, such as .env variables
+        #[arg(short, long)]
+        app: Option<String>,
+
+        /// What to do with keys that already exist: skip, overwrite or rename
+        #[arg(long, value_name = "STRATEGY", default_value = "skip")]
+        on_conflict: ConflictStrategy,
+
+        /// Show what would be imported without changing the vault
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Delete every existing key first
+        #[arg(long)]
+        replace: bool,
+
+        /// Skip the confirmation prompt of --replace
+        #[arg(short, long)]
+        force: bool,
+    },
+
     /// Show vault status
     Status,
+
+    /// Inspect or tune the PIN key-derivation costs
+    Kdf {
+        #[command(subcommand)]
+        command: KdfCommands,
+    },
+
+    /// Switch between plaintext and encrypted key metadata
+    Metadata {
+        #[command(subcommand)]
+        command: MetadataCommands,
+    },
+
+    /// Manage extra credentials (key slots) that unlock the vault
+    Slot {
+        #[command(subcommand)]
+        command: SlotCommands,
+    },
+
+    /// Create or use Shamir recovery shares of the vault key
+    Recovery {
+        #[command(subcommand)]
+        command: RecoveryCommands,
+    },
+
+    /// List or restore automatic snapshots of the vault
+    ///
+    /// Snapshots are taken before migrations, PIN changes, metadata
+    /// conversions and imports, and kept in a `backups` directory next to
+    /// the vault. The 5 newest are kept.
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommands,
+    },
+
+    /// Inspect or migrate the database schema
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+
+    /// Show or verify the log of vault access
+    ///
+    /// Unlocks, failed unlocks, PIN changes and every read, copy, create,
+    /// update and delete of a key are recorded with the OS user. Secret
+    /// values are never logged. Entries are hash-chained and MACed with a
+    /// key derived from the vault key, so edited or deleted entries are
+    /// detected by `vult audit verify`.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+
+    /// Run the session agent that keeps the vault unlocked between commands
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AgentCommands {
+    /// Start the agent in the background
+    ///
+    /// After the next PIN unlock, commands use the key held by the agent
+    /// instead of prompting until it expires or `vult lock` is run.
+    Start {
+        /// Drop the key after this many seconds without use
+        #[arg(long, default_value_t = 300)]
+        idle_ttl: u64,
+
+        /// Drop the key this many seconds after the unlock, even if in use
+        #[arg(long, default_value_t = 3600)]
+        hard_ttl: u64,
+
+        /// Run in the foreground instead of detaching
+        #[arg(long)]
+        foreground: bool,
+    },
+
+    /// Stop the agent, dropping every key it holds
+    Stop,
+
+    /// Show whether the agent is running and which vaults it holds
+    Status,
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupCommands {
+    /// Take a snapshot now
+    Create,
+
+    /// List snapshots, newest first
+    List,
+
+    /// Replace the vault with a snapshot
+    ///
+    /// The snapshot must unlock with the current PIN and every key in it
+    /// must decrypt. The current vault is snapshotted first, so a restore
+    /// can be undone.
+    Restore {
+        /// Snapshot timestamp, or a unique prefix of it
+        id: String,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommands {
+    /// Bring the schema to the version of this build
+    ///
+    /// Other commands migrate automatically; this shows what is pending
+    /// first. The vault is snapshotted before the first migration, and
+    /// each migration runs in its own transaction.
+    Migrate {
+        /// List the pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditCommands {
+    /// List entries, oldest first
+    Show {
+        /// Only this event: init, unlock, unlock_failed, pin_change, get,
+        /// copy, create, update, delete or import
+        #[arg(long, value_name = "EVENT")]
+        event: Option<AuditEvent>,
+
+        /// Only entries about this key, as app/key
+        #[arg(long, value_name = "KEY")]
+        key: Option<KeyRef>,
+
+        /// Only entries since a time: a duration ago (30m, 12h, 7d, 2w) or a
+        /// date (2026-01-31)
+        #[arg(long, value_name = "WHEN", value_parser = parse_time_bound)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Only entries up to a time, in the same forms as --since
+        #[arg(long, value_name = "WHEN", value_parser = parse_time_bound)]
+        until: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Only the newest N matching entries
+        #[arg(short = 'n', long, value_name = "N")]
+        limit: Option<u32>,
+    },
+
+    /// Check that no entry was edited, deleted or reordered
+    ///
+    /// Exits with an error at the first entry that fails a check.
+    Verify,
+}
+
+#[derive(Subcommand, Debug)]
+enum RecoveryCommands {
+    /// Split the vault key into recovery shares and print them
+    ///
+    /// Any THRESHOLD of the shares can set a new PIN. Creating a new set
+    /// retires the previous one.
+    Create {
+        /// Number of shares to create
+        #[arg(long, default_value_t = 5)]
+        shares: u8,
+
+        /// Number of shares needed to recover
+        #[arg(long, default_value_t = 3)]
+        threshold: u8,
+    },
+
+    /// Rebuild the vault key from recovery shares and set a new PIN
+    ///
+    /// Shares are read from stdin, separated by empty lines. With the global
+    /// --keyfile option the new PIN also requires that key file.
+    Restore,
+}
+
+#[derive(Subcommand, Debug)]
+enum SlotCommands {
+    /// List the primary PIN and every key slot
+    List,
+
+    /// Add a key slot that unlocks the same vault
+    ///
+    /// PIN and passphrase slots are unlocked at the normal PIN prompt.
+    /// Key-file slots are unlocked with the global --keyfile option alone.
+    Add {
+        /// Unique name for the slot (e.g., "offline-backup")
+        label: String,
+
+        /// Kind of credential: pin, passphrase or keyfile
+        #[arg(short, long, default_value = "passphrase")]
+        kind: SlotKind,
+
+        /// Key file for a keyfile slot; created if it doesn't exist
+        #[arg(short, long, required_if_eq("kind", "keyfile"))]
+        file: Option<PathBuf>,
+    },
+
+    /// Check that a credential opens a slot, without unlocking
+    Test {
+        /// Slot id, as shown by `vult slot list`
+        id: i64,
+
+        /// Key file to test against a keyfile slot
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+
+    /// Revoke a key slot; secrets are not re-encrypted
+    Revoke {
+        /// Slot id, as shown by `vult slot list`
+        id: i64,
+
+        /// Skip confirmation prompt
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MetadataCommands {
+    /// Encrypt app names, key names, URLs and descriptions at rest
+    ///
+    /// Names are replaced by keyed blind indexes, so lookups still work
+    /// once the vault is unlocked.
+    Encrypt,
+
+    /// Store key metadata in plaintext again
+    Decrypt,
+}
+
+#[derive(Subcommand, Debug)]
+enum KdfCommands {
+    /// Show the current and pending KDF parameters
+    Show,
+
+    /// Measure this machine and schedule costs that hit a target unlock time
+    ///
+    /// The new parameters are applied on the next successful unlock.
+    Calibrate {
+        /// Target unlock time in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        target_ms: u64,
+
+        /// Memory cost in MiB
+        #[arg(long, default_value_t = 64)]
+        memory_mib: u32,
+
+        /// Degree of parallelism
+        #[arg(long, default_value_t = 4)]
+        parallelism: u32,
+
+        /// Only print the chosen parameters; don't schedule them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Schedule the parameters even if they are weaker than the current ones
+        #[arg(long)]
+        allow_downgrade: bool,
+    },
+}
+
+/// One line typed into `vult shell`, parsed with the subcommand definitions.
+#[derive(Parser, Debug)]
+#[command(name = "vult", no_binary_name = true, disable_version_flag = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+/// Parse an environment mapping of the form `NAME=app/key`.
+fn parse_env_mapping(s: &str) -> Result<(String, KeyRef), String> {
+    let (name, key) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=app/key, got '{s}'"))?;
+    if !is_valid_env_name(name) {
+        return Err(format!("invalid environment variable name '{name}'"));
+    }
+    let key = key.parse::<KeyRef>().map_err(|e| e.to_string())?;
+    Ok((name.to_string(), key))
+}
+
+/// Parse a duration such as `30m`, `12h`, `7d` or `2w`.
+fn parse_duration(s: &str) -> Result<chrono::Duration, String> {
+    let error = || format!("expected a duration like 30m, 12h, 7d or 2w, got '{s}'");
+    let unit = s.chars().last().ok_or_else(error)?;
+    let amount: i64 = s[..s.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| error())?;
+    match unit {
+        'm' => Ok(chrono::Duration::minutes(amount)),
+        'h' => Ok(chrono::Duration::hours(amount)),
+        'd' => Ok(chrono::Duration::days(amount)),
+        'w' => Ok(chrono::Duration::weeks(amount)),
+        _ => Err(error()),
+    }
+}
+
+/// Parse a point in time given as a duration ago (see [`parse_duration`]),
+/// a local date (`2026-01-31`) or an RFC 3339 timestamp.
+fn parse_time_bound(s: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    if let Ok(duration) = parse_duration(s) {
+        return Ok(chrono::Utc::now() - duration);
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+            .map(|t| t.with_timezone(&chrono::Utc))
+            .ok_or_else(|| format!("'{s}' is not a valid local date"));
+    }
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .map_err(|_| format!("expected a duration like 7d or a date like 2026-01-31, got '{s}'"))
+}
+
+/// Parse an expiry given as a duration from now (see [`parse_duration`]),
+/// a local date or an RFC 3339 timestamp.
+fn parse_expiry(s: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    match parse_duration(s) {
+        Ok(duration) => Ok(chrono::Utc::now() + duration),
+        Err(_) => parse_time_bound(s),
+    }
+}
+
+/// Parse a positive interval (see [`parse_duration`]) into seconds.
+fn parse_interval(s: &str) -> Result<u64, String> {
+    let seconds = parse_duration(s)?.num_seconds();
+    u64::try_from(seconds)
+        .ok()
+        .filter(|&seconds| seconds > 0)
+        .ok_or_else(|| format!("expected a positive interval, got '{s}'"))
+}
+
+/// Describe a span of time in its largest whole unit, e.g. "3 days".
+fn format_span(span: chrono::Duration) -> String {
+    let (amount, unit) = if span.num_days() != 0 {
+        (span.num_days(), "day")
+    } else if span.num_hours() != 0 {
+        (span.num_hours(), "hour")
+    } else {
+        (span.num_minutes(), "minute")
+    };
+    let plural = if amount.abs() == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural}")
+}
+
+/// Describe how far `at` is from now, e.g. "in 3 days" or "2 hours ago".
+fn relative_time(at: chrono::DateTime<chrono::Utc>) -> String {
+    let delta = at - chrono::Utc::now();
+    if delta < chrono::Duration::zero() {
+        format!("{} ago", format_span(-delta))
+    } else {
+        format!("in {}", format_span(delta))
+    }
+}
+
+/// Load the manifest at `path`, or the nearest .vult.toml above the current
+/// directory.
+fn load_manifest(path: Option<&Path>) -> Result<Manifest, VaultError> {
+    if let Some(path) = path {
+        return Manifest::load(path);
+    }
+    let cwd = std::env::current_dir().map_err(|e| VaultError::Io(e.to_string()))?;
+    let path = Manifest::find(&cwd).ok_or_else(|| {
+        VaultError::InvalidInput(format!(
+            "No {} found in {} or its parents",
+            MANIFEST_FILE_NAME,
+            cwd.display()
+        ))
+    })?;
+    Manifest::load(&path)
+}
+
+/// Write `contents` to `path`, readable only by the owner.
+///
+/// An existing file is truncated and its permissions narrowed to 0600
+/// before anything is written to it.
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+    let io_error = |e: io::Error| VaultError::Io(format!("{}: {e}", path.display()));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(io_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .map_err(io_error)?;
+    }
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(io_error)
+}
+
+/// Quote a value for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
 }
 
 /// Get the database path, either from CLI arg, env, or default.
@@ -176,6 +836,55 @@ fn read_pin(prompt: &str) -> io::Result<String> {
     rpassword::read_password()
 }
 
+/// Read a key slot PIN or passphrase from VULT_SLOT_SECRET or prompt with
+/// confirmation.
+fn read_slot_secret(prompt: &str) -> Result<String, VaultError> {
+    if let Ok(secret) = std::env::var("VULT_SLOT_SECRET") {
+        eprintln!(
+            "{}: Using slot secret from VULT_SLOT_SECRET environment variable (test mode)",
+            "Warning".yellow().bold()
+        );
+        return Ok(secret);
+    }
+    print!("{}", prompt);
+    io::stdout().flush().map_err(|e| VaultError::Io(e.to_string()))?;
+    let secret = rpassword::read_password().map_err(|e| VaultError::Io(e.to_string()))?;
+    print!("Confirm: ");
+    io::stdout().flush().map_err(|e| VaultError::Io(e.to_string()))?;
+    let confirm = rpassword::read_password().map_err(|e| VaultError::Io(e.to_string()))?;
+
+    if secret != confirm {
+        return Err(VaultError::InvalidInput("Entries do not match".to_string()));
+    }
+    Ok(secret)
+}
+
+/// Read an export passphrase from VULT_EXPORT_PASSPHRASE or prompt for it,
+/// with confirmation when `confirm` is set.
+fn read_export_passphrase(prompt: &str, confirm: bool) -> Result<Zeroizing<String>, VaultError> {
+    if let Ok(passphrase) = std::env::var("VULT_EXPORT_PASSPHRASE") {
+        eprintln!(
+            "{}: Using passphrase from VULT_EXPORT_PASSPHRASE environment variable (test mode)",
+            "Warning".yellow().bold()
+        );
+        return Ok(Zeroizing::new(passphrase));
+    }
+    print!("{}", prompt);
+    io::stdout().flush().map_err(|e| VaultError::Io(e.to_string()))?;
+    let passphrase =
+        Zeroizing::new(rpassword::read_password().map_err(|e| VaultError::Io(e.to_string()))?);
+    if confirm {
+        print!("Confirm: ");
+        io::stdout().flush().map_err(|e| VaultError::Io(e.to_string()))?;
+        let again =
+            Zeroizing::new(rpassword::read_password().map_err(|e| VaultError::Io(e.to_string()))?);
+        if passphrase != again {
+            return Err(VaultError::InvalidInput("Entries do not match".to_string()));
+        }
+    }
+    Ok(passphrase)
+}
+
 /// Read PIN with confirmation (never uses VULT_PIN for safety).
 fn read_pin_with_confirmation(prompt: &str) -> io::Result<Option<String>> {
     let pin = read_pin(prompt)?;
@@ -187,6 +896,89 @@ fn read_pin_with_confirmation(prompt: &str) -> io::Result<Option<String>> {
     Ok(Some(pin))
 }
 
+/// How CLI commands unlock the vault: the global key file and the session
+/// agent, if one is running.
+struct Unlock<'a> {
+    key_file: Option<&'a Path>,
+    agent: AgentClient,
+    /// Identifies this vault to the agent
+    db_url: &'a str,
+}
+
+impl Unlock<'_> {
+    /// Unlock with the key held by the session agent, falling back to the
+    /// PIN prompt when no agent holds a key for this vault.
+    async fn vault(&self, vault: &VaultManager, prompt: &str) -> Result<(), VaultError> {
+        if let Ok(Some(key)) = self.agent.fetch_key(self.db_url).await {
+            if vault.auth().unlock_with_data_key(key).await.is_ok() {
+                return Ok(());
+            }
+        }
+        self.with_pin(vault, prompt).await
+    }
+
+    /// Prompt for the PIN and unlock the vault, then hand the key to the
+    /// session agent if one is running.
+    ///
+    /// The key file, if given, is read before prompting so a missing file
+    /// fails without asking for the PIN. If the vault itself doesn't use a
+    /// key file, the file is tried against the key-file slots first, without
+    /// a PIN.
+    async fn with_pin(&self, vault: &VaultManager, prompt: &str) -> Result<(), VaultError> {
+        let key_file = self.key_file.map(read_key_file).transpose()?;
+        match key_file.as_deref() {
+            Some(contents) if !vault.auth().requires_key_file().await? => {
+                vault
+                    .auth()
+                    .unlock_with_slot(SlotCredential::KeyFile(contents))
+                    .await?
+            }
+            _ => {
+                let pin = read_pin(prompt).map_err(|e| VaultError::Io(e.to_string()))?;
+                vault
+                    .auth()
+                    .unlock_with_key_file(&pin, key_file.as_deref())
+                    .await?
+            }
+        }
+
+        self.share_key(vault).await;
+        Ok(())
+    }
+
+    /// Hand the unlocked vault's key to the session agent, if one is running.
+    async fn share_key(&self, vault: &VaultManager) {
+        // The agent is optional; failing to reach it never fails the command
+        if let Ok(key) = vault.auth().get_vault_key().await {
+            let _ = self.agent.store_key(self.db_url, &key).await;
+        }
+    }
+}
+
+/// Read a key file, creating a new random one if it doesn't exist.
+fn read_or_create_key_file(path: &Path) -> Result<Vec<u8>, VaultError> {
+    if path.exists() {
+        return read_key_file(path);
+    }
+    let contents = create_key_file(path)?;
+    println!("Created key file {}", path.display());
+    Ok(contents)
+}
+
+/// Read the new PIN from VULT_NEW_PIN or prompt with confirmation.
+fn read_new_pin() -> Result<String, VaultError> {
+    if let Ok(pin) = std::env::var("VULT_NEW_PIN") {
+        eprintln!(
+            "{}: Using new PIN from VULT_NEW_PIN environment variable (test mode)",
+            "Warning".yellow().bold()
+        );
+        return Ok(pin);
+    }
+    read_pin_with_confirmation("New PIN (min 6 characters): ")
+        .map_err(|e| VaultError::Io(e.to_string()))?
+        .ok_or_else(|| VaultError::InvalidInput("New PINs do not match".to_string()))
+}
+
 /// Copy text to system clipboard.
 fn copy_to_clipboard(text: &str) -> Result<(), VaultError> {
     let mut clipboard = Clipboard::new()
@@ -239,40 +1031,249 @@ async fn run(cli: Cli) -> Result<(), VaultError> {
         db_path.to_str().unwrap_or(".").replace('\\', "/")
     );
 
+    let key_file = cli.keyfile.as_deref();
+    let agent = AgentClient::new(cli.agent_socket.unwrap_or_else(default_socket_path));
+    let unlock = Unlock {
+        key_file,
+        agent,
+        db_url: &db_url,
+    };
+
     match cli.command {
-        Commands::Init => cmd_init(&db_url).await,
-        Commands::ChangePin => cmd_change_pin(&db_url).await,
-        Commands::Lock => cmd_lock().await,
+        Commands::Init { encrypt_metadata } => cmd_init(&db_url, encrypt_metadata, key_file).await,
+        Commands::ChangePin => cmd_change_pin(&db_url, key_file).await,
+        Commands::Lock => cmd_lock(&unlock.agent).await,
+        Commands::Unlock { reset_lockout } => cmd_unlock(&db_url, &unlock, reset_lockout).await,
+        command @ (Commands::Add { .. }
+        | Commands::Get { .. }
+        | Commands::List { .. }
+        | Commands::Search { .. }
+        | Commands::Update { .. }
+        | Commands::Delete { .. }) => {
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            run_key_command(&vault, command, cli.json).await
+        }
+        Commands::History { key, keep } => {
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            cmd_history(&vault, &key, keep, cli.json).await
+        }
+        Commands::Rollback {
+            key,
+            target,
+            reason,
+        } => {
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            cmd_rollback(&vault, &key, target, reason.as_deref(), cli.json).await
+        }
+        Commands::Expiring { within } => {
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            cmd_expiring(&vault, within, cli.json).await
+        }
+        Commands::Run {
+            env,
+            manifest,
+            command,
+        } => {
+            let manifest = manifest
+                .map(|path| load_manifest(path.as_deref()))
+                .transpose()?;
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            cmd_run(&vault, manifest.as_ref(), env, command).await
+        }
+        Commands::Check { manifest } => {
+            let manifest = load_manifest(manifest.flatten().as_deref())?;
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            cmd_check(&vault, &manifest, cli.json).await
+        }
+        Commands::Env { manifest } => {
+            let manifest = load_manifest(manifest.flatten().as_deref())?;
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            cmd_env(&vault, &manifest, cli.json).await
+        }
+        Commands::Inject { input, output } => {
+            cmd_inject(&db_url, &unlock, input.as_deref(), output.as_deref()).await
+        }
+        Commands::Export { file, to } => cmd_export(&db_url, &unlock, &file, to, cli.json).await,
+        Commands::Import {
+            file,
+            from,
+            app,
+            on_conflict,
+            dry_run,
+            replace,
+            force,
+        } => {
+            let source = ImportSource {
+                file: &file,
+                format: from,
+                app: app.as_deref(),
+            };
+            let options = ImportOptions {
+                mode: if replace {
+                    ImportMode::Replace
+                } else {
+                    ImportMode::Merge
+                },
+                on_conflict,
+                dry_run,
+            };
+            cmd_import(&db_url, &unlock, &source, options, force, cli.json).await
+        }
+        Commands::Shell { idle_timeout } => {
+            cmd_shell(&db_url, &unlock, idle_timeout, cli.json).await
+        }
+        Commands::Status => cmd_status(&db_url, &unlock).await,
+        Commands::Kdf { command } => match command {
+            KdfCommands::Show => cmd_kdf_show(&db_url, cli.json).await,
+            command @ KdfCommands::Calibrate { .. } => {
+                cmd_kdf_calibrate(&db_url, &unlock, command, cli.json).await
+            }
+        },
+        Commands::Metadata { command } => match command {
+            MetadataCommands::Encrypt => cmd_metadata(&db_url, &unlock, true).await,
+            MetadataCommands::Decrypt => cmd_metadata(&db_url, &unlock, false).await,
+        },
+        Commands::Slot { command } => match command {
+            SlotCommands::List => cmd_slot_list(&db_url, cli.json).await,
+            SlotCommands::Add { label, kind, file } => {
+                cmd_slot_add(&db_url, &unlock, &label, kind, file.as_deref()).await
+            }
+            SlotCommands::Test { id, file } => cmd_slot_test(&db_url, id, file.as_deref()).await,
+            SlotCommands::Revoke { id, force } => {
+                cmd_slot_revoke(&db_url, &unlock, id, force).await
+            }
+        },
+        Commands::Recovery { command } => match command {
+            RecoveryCommands::Create { shares, threshold } => {
+                cmd_recovery_create(&db_url, &unlock, shares, threshold).await
+            }
+            RecoveryCommands::Restore => cmd_recovery_restore(&db_url, key_file).await,
+        },
+        Commands::Backup { command } => match command {
+            BackupCommands::Create => cmd_backup_create(&db_url, cli.json).await,
+            BackupCommands::List => cmd_backup_list(&db_url, cli.json).await,
+            BackupCommands::Restore { id, force } => {
+                cmd_backup_restore(&db_url, &unlock, &id, force, cli.json).await
+            }
+        },
+        Commands::Db { command } => match command {
+            DbCommands::Migrate { dry_run } => cmd_db_migrate(&db_url, dry_run, cli.json).await,
+        },
+        Commands::Audit { command } => {
+            let vault = VaultManager::new(&db_url).await?;
+            unlock.vault(&vault, "PIN: ").await?;
+            match command {
+                AuditCommands::Show {
+                    event,
+                    key,
+                    since,
+                    until,
+                    limit,
+                } => {
+                    let filter = AuditFilter {
+                        event,
+                        since,
+                        until,
+                        limit,
+                        ..AuditFilter::default()
+                    };
+                    cmd_audit_show(&vault, filter, key.as_ref(), cli.json).await
+                }
+                AuditCommands::Verify => cmd_audit_verify(&vault, cli.json).await,
+            }
+        }
+        Commands::Agent { command } => match command {
+            AgentCommands::Start {
+                idle_ttl,
+                hard_ttl,
+                foreground,
+            } => cmd_agent_start(&unlock.agent, idle_ttl, hard_ttl, foreground).await,
+            AgentCommands::Stop => cmd_agent_stop(&unlock.agent).await,
+            AgentCommands::Status => cmd_agent_status(&unlock.agent, cli.json).await,
+        },
+    }
+}
+
+/// Run one of the key commands against an unlocked vault.
+///
+/// Shared by the one-shot subcommands and `vult shell`.
+async fn run_key_command(
+    vault: &VaultManager,
+    command: Commands,
+    json: bool,
+) -> Result<(), VaultError> {
+    match command {
         Commands::Add {
             app,
             name,
             stdin,
             url,
             description,
-        } => cmd_add(&db_url, app, name, stdin, url, description).await,
+            expires_at,
+            rotate_every,
+        } => {
+            let request = CreateKeyRequest {
+                app_name: app,
+                key_name: name,
+                key_value: String::new(),
+                api_url: url,
+                description,
+                expires_at,
+                rotate_every,
+            };
+            cmd_add(vault, request, stdin).await
+        }
         Commands::Get {
             app,
             name,
             full,
             copy,
-        } => cmd_get(&db_url, app, name, full, copy, cli.json).await,
-        Commands::List { timestamps } => cmd_list(&db_url, timestamps, cli.json).await,
-        Commands::Search { query, timestamps } => {
-            cmd_search(&db_url, &query, timestamps, cli.json).await
-        }
+            version,
+        } => cmd_get(vault, app, name, full, copy, version, json).await,
+        Commands::List { timestamps } => cmd_list(vault, timestamps, json).await,
+        Commands::Search { query, timestamps } => cmd_search(vault, &query, timestamps, json).await,
         Commands::Update {
             app,
             name,
             value,
             url,
             description,
-        } => cmd_update(&db_url, app, name, value, url, description).await,
-        Commands::Delete { app, name, force } => cmd_delete(&db_url, app, name, force).await,
-        Commands::Status => cmd_status(&db_url).await,
+            reason,
+            expires_at,
+            no_expiry,
+            rotate_every,
+            no_rotation,
+        } => {
+            let request = UpdateKeyRequest {
+                key_value: value,
+                api_url: url.map(Some),
+                description: description.map(Some),
+                expires_at: expires_at.map(Some).or(no_expiry.then_some(None)),
+                rotate_every: rotate_every.map(Some).or(no_rotation.then_some(None)),
+                reason,
+                ..Default::default()
+            };
+            cmd_update(vault, app, name, request).await
+        }
+        Commands::Delete { app, name, force } => cmd_delete(vault, app, name, force).await,
+        _ => Err(VaultError::InvalidInput(
+            "That command is not available in the shell".to_string(),
+        )),
     }
 }
 
-async fn cmd_init(db_url: &str) -> Result<(), VaultError> {
+async fn cmd_init(
+    db_url: &str,
+    encrypt_metadata: bool,
+    key_file: Option<&Path>,
+) -> Result<(), VaultError> {
     let vault = VaultManager::new(db_url).await?;
 
     if vault.is_initialized().await? {
@@ -282,7 +1283,8 @@ async fn cmd_init(db_url: &str) -> Result<(), VaultError> {
     println!("Initializing new vault...");
     println!();
     println!("{}", "IMPORTANT: Remember your PIN!".yellow().bold());
-    println!("There is NO recovery option if you forget it.");
+    println!("There is NO recovery option if you forget it, unless you later run");
+    println!("'vult recovery create' and keep the shares safe.");
     println!();
 
     let pin = match read_pin_with_confirmation("Enter PIN (min 6 characters): ")
@@ -292,18 +1294,39 @@ async fn cmd_init(db_url: &str) -> Result<(), VaultError> {
         None => return Err(VaultError::InvalidInput("PINs do not match".to_string())),
     };
 
-    vault.auth().init_vault(&pin).await?;
+    let key_file_contents = key_file.map(read_or_create_key_file).transpose()?;
+
+    vault
+        .auth()
+        .init_vault_with_key_file(&pin, key_file_contents.as_deref())
+        .await?;
+    if let Some(path) = key_file {
+        println!(
+            "{}",
+            format!(
+                "This vault now requires {} to unlock. Back it up; losing it locks you out.",
+                path.display()
+            )
+            .yellow()
+            .bold()
+        );
+    }
+    if encrypt_metadata {
+        vault.keys().set_encrypted_metadata(true).await?;
+    }
     print_success("Vault initialized successfully!");
     Ok(())
 }
 
-async fn cmd_change_pin(db_url: &str) -> Result<(), VaultError> {
+async fn cmd_change_pin(db_url: &str, key_file: Option<&Path>) -> Result<(), VaultError> {
     let vault = VaultManager::new(db_url).await?;
 
     if !vault.is_initialized().await? {
         return Err(VaultError::NotInitialized);
     }
 
+    let key_file = key_file.map(read_key_file).transpose()?;
+
     // Support test environment variables for change-pin testing
     let old_pin = if let Ok(pin) = std::env::var("VULT_OLD_PIN") {
         eprintln!(
@@ -315,52 +1338,74 @@ async fn cmd_change_pin(db_url: &str) -> Result<(), VaultError> {
         read_pin("Current PIN: ").map_err(|e| VaultError::Io(e.to_string()))?
     };
 
-    let new_pin = if let Ok(pin) = std::env::var("VULT_NEW_PIN") {
-        eprintln!(
-            "{}: Using new PIN from VULT_NEW_PIN environment variable (test mode)",
-            "Warning".yellow().bold()
-        );
-        pin
-    } else {
-        match read_pin_with_confirmation("New PIN (min 6 characters): ")
-            .map_err(|e| VaultError::Io(e.to_string()))?
-        {
-            Some(pin) => pin,
-            None => {
-                return Err(VaultError::InvalidInput(
-                    "New PINs do not match".to_string(),
-                ))
-            }
-        }
-    };
+    let new_pin = read_new_pin()?;
 
-    vault.auth().change_pin(&old_pin, &new_pin).await?;
+    vault
+        .auth()
+        .change_pin_with_key_file(&old_pin, &new_pin, key_file.as_deref())
+        .await?;
     print_success("PIN changed successfully!");
     Ok(())
 }
 
-async fn cmd_lock() -> Result<(), VaultError> {
-    // In single-command CLI mode, each command is a fresh process
-    // The vault is locked when the process exits. This command is
-    // primarily for future session mode support.
-    println!("{}", "Vault is locked.".green());
-    println!("(Each CLI command runs independently - vault auto-locks on exit)");
+async fn cmd_lock(agent: &AgentClient) -> Result<(), VaultError> {
+    // Each CLI command is a fresh process, so the vault is locked when it
+    // exits; only the session agent keeps a key between commands.
+    if agent.is_running().await {
+        agent.lock().await?;
+        println!("{}", "Vault is locked.".green());
+        println!("(The session agent dropped its keys; the next command asks for the PIN)");
+    } else {
+        println!("{}", "Vault is locked.".green());
+        println!("(No session agent is running - each CLI command locks the vault on exit)");
+    }
     Ok(())
 }
 
-async fn cmd_add(
+async fn cmd_unlock(
     db_url: &str,
-    app: Option<String>,
-    name: String,
-    stdin: bool,
-    url: Option<String>,
-    description: Option<String>,
+    unlock: &Unlock<'_>,
+    reset_lockout: bool,
 ) -> Result<(), VaultError> {
     let vault = VaultManager::new(db_url).await?;
-    let pin = read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?;
-    vault.auth().unlock(&pin).await?;
+    if !reset_lockout {
+        unlock.with_pin(&vault, "PIN: ").await?;
+    } else {
+        match unlock.key_file {
+            Some(path) => {
+                let contents = read_key_file(path)?;
+                vault
+                    .auth()
+                    .reset_lockout(LockoutReset::KeyFile(&contents))
+                    .await?
+            }
+            None => {
+                let shares = read_shares()?;
+                vault
+                    .auth()
+                    .reset_lockout(LockoutReset::Shares(&shares))
+                    .await?
+            }
+        }
+        unlock.share_key(&vault).await;
+        print_success("Lockout cleared");
+    }
+
+    println!("{}", "Vault is unlocked.".green());
+    if unlock.agent.is_running().await {
+        println!("(The session agent holds the key until it expires or 'vult lock')");
+    } else {
+        println!("(No session agent is running - the next command asks for the PIN again)");
+    }
+    Ok(())
+}
 
-    let value = if stdin {
+async fn cmd_add(
+    vault: &VaultManager,
+    mut request: CreateKeyRequest,
+    stdin: bool,
+) -> Result<(), VaultError> {
+    request.key_value = if stdin {
         let mut buffer = String::new();
         io::stdin()
             .read_to_string(&mut buffer)
@@ -370,39 +1415,34 @@ async fn cmd_add(
         read_pin("Key value: ").map_err(|e| VaultError::Io(e.to_string()))?
     };
 
-    vault
-        .keys()
-        .create(
-            app.as_deref(),
-            &name,
-            &value,
-            url.as_deref(),
-            description.as_deref(),
-        )
-        .await?;
+    vault.keys().create_from_request(&request).await?;
 
-    let display_name = match &app {
-        Some(a) => format!("{}/{}", a, name),
-        None => name,
+    let display_name = match request.app_name {
+        Some(a) => format!("{}/{}", a, request.key_name),
+        None => request.key_name,
     };
     print_success(&format!("Key '{}' added successfully", display_name));
     Ok(())
 }
 
 async fn cmd_get(
-    db_url: &str,
+    vault: &VaultManager,
     app: Option<String>,
     name: String,
     full: bool,
     copy: bool,
+    version: Option<i64>,
     json: bool,
 ) -> Result<(), VaultError> {
-    let vault = VaultManager::new(db_url).await?;
-    let pin = read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?;
-    vault.auth().unlock(&pin).await?;
-
     let app_name = app.as_deref().unwrap_or("");
-    let key = vault.keys().get(app_name, &name).await?;
+    let key = match version {
+        Some(version) => {
+            let id = vault.keys().find_id(app_name, &name).await?;
+            vault.keys().get_version(&id, version).await?
+        }
+        None => vault.keys().get(app_name, &name).await?,
+    };
+    warn_if_due(&key);
 
     if json {
         println!("{}", serde_json::to_string_pretty(&key).unwrap());
@@ -424,10 +1464,17 @@ async fn cmd_get(
             "Description".cyan(),
             key.description.as_deref().unwrap_or("-")
         );
+        if let Some(expires_at) = key.expires_at {
+            println!("{}: {}", "Expires".cyan(), expires_at);
+        }
+        if let Some(rotate_by) = key.rotate_by {
+            println!("{}: {}", "Rotate by".cyan(), rotate_by);
+        }
         println!("{}: {}", "Created".cyan(), key.created_at);
         println!("{}: {}", "Updated".cyan(), key.updated_at);
     } else if copy {
         copy_to_clipboard(&key.key_value)?;
+        vault.keys().record_copy(&key).await?;
         println!("{}", "Key copied to clipboard!".green());
         println!("{}", "⚠ Clipboard will be cleared in 45 seconds.".yellow());
         // Spawn background thread to clear clipboard after 45 seconds
@@ -446,11 +1493,7 @@ async fn cmd_get(
     Ok(())
 }
 
-async fn cmd_list(db_url: &str, timestamps: bool, json: bool) -> Result<(), VaultError> {
-    let vault = VaultManager::new(db_url).await?;
-    let pin = read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?;
-    vault.auth().unlock(&pin).await?;
-
+async fn cmd_list(vault: &VaultManager, timestamps: bool, json: bool) -> Result<(), VaultError> {
     let keys = vault.keys().list().await?;
 
     if json {
@@ -494,15 +1537,11 @@ async fn cmd_list(db_url: &str, timestamps: bool, json: bool) -> Result<(), Vaul
 }
 
 async fn cmd_search(
-    db_url: &str,
+    vault: &VaultManager,
     query: &str,
     timestamps: bool,
     json: bool,
 ) -> Result<(), VaultError> {
-    let vault = VaultManager::new(db_url).await?;
-    let pin = read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?;
-    vault.auth().unlock(&pin).await?;
-
     let keys = vault.keys().search(query).await?;
 
     if json {
@@ -546,30 +1585,16 @@ async fn cmd_search(
 }
 
 async fn cmd_update(
-    db_url: &str,
+    vault: &VaultManager,
     app: Option<String>,
     name: String,
-    value: Option<String>,
-    url: Option<String>,
-    description: Option<String>,
+    request: UpdateKeyRequest,
 ) -> Result<(), VaultError> {
-    let vault = VaultManager::new(db_url).await?;
-    let pin = read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?;
-    vault.auth().unlock(&pin).await?;
-
     let app_name = app.as_deref().unwrap_or("");
 
     // First, get the existing key to get its ID
     let existing = vault.keys().get(app_name, &name).await?;
 
-    // Build update request
-    let request = vult::services::UpdateKeyRequest {
-        key_value: value,
-        api_url: url.map(Some),
-        description: description.map(Some),
-        ..Default::default()
-    };
-
     vault.keys().update(&existing.id, request).await?;
 
     let display_name = match app {
@@ -580,16 +1605,36 @@ async fn cmd_update(
     Ok(())
 }
 
+/// Warn on stderr if `key` has expired or is overdue for rotation.
+fn warn_if_due(key: &ApiKey) {
+    let now = chrono::Utc::now();
+    let name = match &key.app_name {
+        Some(app) => format!("{}/{}", app, key.key_name),
+        None => key.key_name.clone(),
+    };
+    if let Some(expires_at) = key.expires_at.filter(|at| *at <= now) {
+        eprintln!(
+            "{}: '{}' expired {}",
+            "Warning".yellow().bold(),
+            name,
+            relative_time(expires_at)
+        );
+    } else if let Some(rotate_by) = key.rotate_by.filter(|at| *at <= now) {
+        eprintln!(
+            "{}: '{}' was due for rotation {}",
+            "Warning".yellow().bold(),
+            name,
+            relative_time(rotate_by)
+        );
+    }
+}
+
 async fn cmd_delete(
-    db_url: &str,
+    vault: &VaultManager,
     app: Option<String>,
     name: String,
     force: bool,
 ) -> Result<(), VaultError> {
-    let vault = VaultManager::new(db_url).await?;
-    let pin = read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?;
-    vault.auth().unlock(&pin).await?;
-
     let app_name = app.as_deref().unwrap_or("");
     let display_name = match &app {
         Some(a) => format!("{}/{}", a, name),
@@ -614,7 +1659,452 @@ async fn cmd_delete(
     Ok(())
 }
 
-async fn cmd_status(db_url: &str) -> Result<(), VaultError> {
+async fn cmd_history(
+    vault: &VaultManager,
+    key: &KeyRef,
+    keep: Option<u32>,
+    json: bool,
+) -> Result<(), VaultError> {
+    let id = vault.keys().find_id(&key.app_name, &key.key_name).await?;
+    if let Some(keep) = keep {
+        let dropped = vault.keys().set_history_limit(&id, Some(keep)).await?;
+        if !json {
+            print_success(&format!(
+                "Keeping up to {} previous values of '{}' ({} dropped)",
+                keep, key, dropped
+            ));
+        }
+    }
+    let history = vault.keys().history(&id).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&history).unwrap());
+        return Ok(());
+    }
+
+    println!(
+        "'{}' is at version {}, keeping up to {} previous values.",
+        key, history.current_version, history.history_limit
+    );
+    if history.versions.is_empty() {
+        println!("No previous values.");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL);
+    table.set_header(vec!["Version", "Replaced", "Reason"]);
+
+    for version in history.versions {
+        let replaced = version
+            .replaced_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        table.add_row(vec![
+            version.version.to_string(),
+            replaced,
+            version.reason.unwrap_or_else(|| "-".to_string()),
+        ]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+async fn cmd_rollback(
+    vault: &VaultManager,
+    key: &KeyRef,
+    version: i64,
+    reason: Option<&str>,
+    json: bool,
+) -> Result<(), VaultError> {
+    let id = vault.keys().find_id(&key.app_name, &key.key_name).await?;
+    let new_version = vault.keys().rollback(&id, version, reason).await?;
+
+    if json {
+        let output = serde_json::json!({
+            "key": key.to_string(),
+            "restored_version": version,
+            "version": new_version,
+        });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    } else {
+        print_success(&format!(
+            "Restored version {} of '{}' as version {}",
+            version, key, new_version
+        ));
+    }
+    Ok(())
+}
+
+async fn cmd_run(
+    vault: &VaultManager,
+    manifest: Option<&Manifest>,
+    env: Vec<(String, KeyRef)>,
+    command: Vec<String>,
+) -> Result<(), VaultError> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(VaultError::InvalidInput("No command given".to_string()));
+    };
+    let mut child = std::process::Command::new(program);
+    child.args(args);
+
+    // Resolve every key before starting anything; later values win
+    if let Some(manifest) = manifest {
+        for (name, value) in manifest.resolve(vault.keys()).await? {
+            child.env(name, value.as_str());
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    for (name, key) in &env {
+        if !seen.insert(name) {
+            return Err(VaultError::InvalidInput(format!(
+                "{name} is set more than once"
+            )));
+        }
+        let api_key = vault.keys().get(&key.app_name, &key.key_name).await?;
+        child.env(name, &api_key.key_value);
+    }
+    vault.auth().lock().await?;
+
+    // Replace this process, so signals and the exit code are the child's
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let err = child.exec();
+        Err(VaultError::Io(format!("Failed to run '{program}': {err}")))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = child
+            .status()
+            .map_err(|e| VaultError::Io(format!("Failed to run '{program}': {e}")))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+async fn cmd_expiring(
+    vault: &VaultManager,
+    within: chrono::Duration,
+    json: bool,
+) -> Result<(), VaultError> {
+    let due = vault.keys().due(chrono::Utc::now() + within).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&due).unwrap());
+    } else if due.is_empty() {
+        println!(
+            "No keys expire or are due for rotation in the next {}.",
+            format_span(within)
+        );
+    } else {
+        let mut table = Table::new();
+        table.load_preset(UTF8_FULL);
+        table.set_header(vec!["App", "Name", "Status", "Due"]);
+        for entry in &due {
+            let what = match (entry.reason, entry.overdue) {
+                (DueReason::Expiry, false) => "expires".yellow(),
+                (DueReason::Expiry, true) => "expired".red(),
+                (DueReason::Rotation, false) => "rotation due".yellow(),
+                (DueReason::Rotation, true) => "rotation overdue".red(),
+            };
+            table.add_row(vec![
+                entry
+                    .key
+                    .app_name
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                entry.key.key_name.clone(),
+                what.to_string(),
+                format!(
+                    "{} ({})",
+                    entry
+                        .due_at
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M"),
+                    relative_time(entry.due_at)
+                ),
+            ]);
+        }
+        println!("{table}");
+    }
+
+    if due.is_empty() {
+        Ok(())
+    } else {
+        Err(VaultError::KeysDue {
+            due: due.len(),
+            overdue: due.iter().filter(|entry| entry.overdue).count(),
+        })
+    }
+}
+
+async fn cmd_check(
+    vault: &VaultManager,
+    manifest: &Manifest,
+    json: bool,
+) -> Result<(), VaultError> {
+    let statuses = manifest.check(vault.keys()).await?;
+    let missing: Vec<&str> = statuses
+        .iter()
+        .filter(|s| !s.present && !s.optional)
+        .map(|s| s.key.as_str())
+        .collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses).unwrap());
+    } else {
+        let mut table = Table::new();
+        table.load_preset(UTF8_FULL);
+        table.set_header(vec!["Variable", "Key", "Status", "Description"]);
+        for status in &statuses {
+            let state = match (status.present, status.optional) {
+                (true, _) => "ok".green(),
+                (false, false) => "missing".red(),
+                (false, true) => "missing (optional)".yellow(),
+            };
+            table.add_row(vec![
+                status.env.clone(),
+                status.key.clone(),
+                state.to_string(),
+                status
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+            ]);
+        }
+        println!("{table}");
+        let present = statuses.iter().filter(|s| s.present).count();
+        println!("{} of {} keys present", present, statuses.len());
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(VaultError::NotFound(missing.join(", ")))
+    }
+}
+
+async fn cmd_env(vault: &VaultManager, manifest: &Manifest, json: bool) -> Result<(), VaultError> {
+    let values = manifest.resolve(vault.keys()).await?;
+
+    if json {
+        let map: serde_json::Map<String, serde_json::Value> = values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.as_str().into()))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&map).unwrap());
+        return Ok(());
+    }
+    for (name, value) in &values {
+        println!("export {}={}", name, shell_quote(value));
+    }
+    Ok(())
+}
+
+async fn cmd_inject(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    input: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<(), VaultError> {
+    let text = match input {
+        Some(path) if path != Path::new("-") => std::fs::read_to_string(path)
+            .map_err(|e| VaultError::Io(format!("{}: {}", path.display(), e)))?,
+        _ => {
+            let mut buffer = String::new();
+            io::stdin()
+                .read_to_string(&mut buffer)
+                .map_err(|e| VaultError::Io(e.to_string()))?;
+            buffer
+        }
+    };
+    let template = Template::parse(&text)?;
+
+    let rendered = if template.references().is_empty() {
+        Zeroizing::new(text.clone())
+    } else {
+        let vault = VaultManager::new(db_url).await?;
+        unlock.vault(&vault, "PIN: ").await?;
+        template.render(vault.keys()).await?
+    };
+
+    match output {
+        Some(path) if path != Path::new("-") => {
+            write_private_file(path, rendered.as_bytes())?;
+            eprintln!(
+                "{} {} ({} references)",
+                "Wrote".green(),
+                path.display(),
+                template.references().len()
+            );
+        }
+        _ => {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(rendered.as_bytes())
+                .and_then(|_| stdout.flush())
+                .map_err(|e| VaultError::Io(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_export(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    file: &Path,
+    format: ExportFormat,
+    json: bool,
+) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+    unlock.vault(&vault, "PIN: ").await?;
+    let export = VaultExport::from_vault(vault.keys()).await?;
+
+    let bytes = match format {
+        ExportFormat::Vultbak => {
+            let passphrase = read_export_passphrase("Export passphrase: ", true)?;
+            export.seal(&passphrase, &KdfParams::default())?
+        }
+        ExportFormat::KeePass => {
+            let password = read_export_passphrase("KeePass password: ", true)?;
+            kdbx::write(&export.keys, &password, &KdbxOptions::default())?
+        }
+    };
+    write_private_file(file, &bytes)?;
+
+    if json {
+        let output = serde_json::json!({ "file": file, "keys": export.keys.len() });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    } else {
+        print_success(&format!(
+            "Exported {} key(s) to {}",
+            export.keys.len(),
+            file.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Where `vult import` reads keys from.
+struct ImportSource<'a> {
+    /// File, or password store directory
+    file: &'a Path,
+    /// Format of `file`; a .vultbak export if `None`
+    format: Option<ImportFormat>,
+    /// App name for keys that have none
+    app: Option<&'a str>,
+}
+
+impl ImportSource<'_> {
+    /// Reads the keys, asking for the passphrase of a .vultbak export or
+    /// the password of a KeePass database.
+    fn read(&self) -> Result<Vec<ImportedKey>, VaultError> {
+        let mut keys: Vec<ImportedKey> = match self.format {
+            Some(format) => {
+                let password = if format.needs_password() {
+                    Some(read_export_passphrase("KeePass password: ", false)?)
+                } else {
+                    None
+                };
+                format.read(self.file, password.as_deref().map(String::as_str))?
+            }
+            None => {
+                let bytes = std::fs::read(self.file)
+                    .map_err(|e| VaultError::Io(format!("{}: {}", self.file.display(), e)))?;
+                let passphrase = read_export_passphrase("Export passphrase: ", false)?;
+                VaultExport::open(&bytes, &passphrase)?.imported_keys()
+            }
+        };
+
+        if let Some(app) = self.app {
+            for key in keys.iter_mut().filter(|k| k.request.app_name.is_none()) {
+                key.request.app_name = Some(app.to_string());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+async fn cmd_import(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    source: &ImportSource<'_>,
+    options: ImportOptions,
+    force: bool,
+    json: bool,
+) -> Result<(), VaultError> {
+    // A file that can't be read is reported before asking for the PIN
+    let keys = source.read()?;
+
+    let vault = VaultManager::new(db_url).await?;
+    unlock.vault(&vault, "PIN: ").await?;
+
+    let replace = options.mode == ImportMode::Replace;
+    if replace && !force && !options.dry_run {
+        let count = vault.keys().count().await?;
+        let confirm = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Delete all {} existing key(s) and import {}?",
+                count,
+                keys.len()
+            ))
+            .default(false)
+            .interact()
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+
+        if !confirm {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    }
+
+    let summary = vault.keys().import(&keys, options).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+        return Ok(());
+    }
+    if replace {
+        println!("Deleted {} existing key(s)", summary.replaced);
+    }
+    if options.dry_run {
+        for key in &summary.added {
+            println!("{} {}", "Added".green(), key);
+        }
+    }
+    for key in &summary.overwritten {
+        println!(
+            "{} {} (replaced the existing key)",
+            "Overwrote".yellow(),
+            key
+        );
+    }
+    for key in &summary.renamed {
+        println!("{} {} to {}", "Renamed".yellow(), key.from, key.to);
+    }
+    for key in &summary.skipped {
+        println!("{} {} (already exists)", "Skipped".yellow(), key);
+    }
+    if options.dry_run {
+        println!(
+            "{}: {} key(s) would be imported; the vault was not changed",
+            "Dry run".yellow().bold(),
+            summary.imported()
+        );
+    } else {
+        print_success(&format!(
+            "Imported {} key(s) from {}",
+            summary.imported(),
+            source.file.display()
+        ));
+    }
+    Ok(())
+}
+
+async fn cmd_status(db_url: &str, unlock: &Unlock<'_>) -> Result<(), VaultError> {
     let vault = VaultManager::new(db_url).await?;
 
     let initialized = vault.is_initialized().await?;
@@ -631,11 +2121,30 @@ async fn cmd_status(db_url: &str) -> Result<(), VaultError> {
     );
 
     if initialized {
-        // Try to get key count after unlocking
-        let pin =
-            read_pin("PIN (to see key count): ").map_err(|e| VaultError::Io(e.to_string()))?;
+        let encrypted = vault.auth().encrypted_metadata().await?;
+        println!(
+            "Metadata: {}",
+            if encrypted { "encrypted" } else { "plaintext" }
+        );
+        if vault.auth().requires_key_file().await? {
+            println!("Key file: required");
+        }
+
+        let lockout = vault.auth().lockout_state().await?;
+        if lockout.failed_attempts > 0 {
+            println!("Failed unlock attempts: {}", lockout.failed_attempts);
+        }
+        let now = chrono::Utc::now().timestamp();
+        if lockout.is_locked_out(now) {
+            println!(
+                "{}",
+                format!("Locked out for {}s", lockout.remaining_secs(now)).red()
+            );
+            return Ok(());
+        }
 
-        match vault.auth().unlock(&pin).await {
+        // Try to get key count after unlocking
+        match unlock.vault(&vault, "PIN (to see key count): ").await {
             Ok(()) => {
                 let count = vault.keys().count().await?;
                 println!("Keys stored: {}", count);
@@ -648,3 +2157,941 @@ async fn cmd_status(db_url: &str) -> Result<(), VaultError> {
 
     Ok(())
 }
+
+async fn cmd_kdf_show(db_url: &str, json: bool) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+    let config = vault.auth().kdf_config().await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&config).unwrap());
+        return Ok(());
+    }
+
+    println!("{}: {}", "Current".cyan(), config.current);
+    match config.pending {
+        Some(pending) => println!("{}: {} (applied on next unlock)", "Pending".cyan(), pending),
+        None => println!("{}: -", "Pending".cyan()),
+    }
+    Ok(())
+}
+
+async fn cmd_kdf_calibrate(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    command: KdfCommands,
+    json: bool,
+) -> Result<(), VaultError> {
+    let KdfCommands::Calibrate {
+        target_ms,
+        memory_mib,
+        parallelism,
+        dry_run,
+        allow_downgrade,
+    } = command
+    else {
+        unreachable!("cmd_kdf_calibrate only handles kdf calibrate");
+    };
+    let vault = VaultManager::new(db_url).await?;
+
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+
+    let memory_kib = memory_mib
+        .checked_mul(1024)
+        .ok_or_else(|| VaultError::InvalidInput("memory cost too large".to_string()))?;
+
+    // Authenticate before measuring, so the unlock itself doesn't skew timing
+    if !dry_run {
+        unlock.with_pin(&vault, "PIN: ").await?;
+    }
+
+    eprintln!("Calibrating for ~{target_ms} ms per unlock...");
+    let params = vault.crypto().calibrate_kdf(
+        std::time::Duration::from_millis(target_ms),
+        memory_kib,
+        parallelism,
+    )?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&params).unwrap());
+    } else {
+        println!("{}: {}", "Parameters".cyan(), params);
+    }
+
+    if !dry_run {
+        vault
+            .auth()
+            .set_kdf_target(params, allow_downgrade)
+            .await
+            .map_err(|e| match e {
+                VaultError::InvalidInput(msg) => VaultError::InvalidInput(format!(
+                    "{msg} (pass --allow-downgrade to schedule it)"
+                )),
+                e => e,
+            })?;
+        if !json {
+            print_success("New KDF parameters will be applied on the next unlock");
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_metadata(db_url: &str, unlock: &Unlock<'_>, enabled: bool) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+
+    unlock.with_pin(&vault, "PIN: ").await?;
+
+    if vault.auth().encrypted_metadata().await? == enabled {
+        println!(
+            "Metadata is already {}",
+            if enabled { "encrypted" } else { "plaintext" }
+        );
+        return Ok(());
+    }
+
+    let count = vault.keys().set_encrypted_metadata(enabled).await?;
+    if enabled {
+        print_success(&format!("Encrypted metadata for {} key(s)", count));
+    } else {
+        print_success(&format!("Decrypted metadata for {} key(s)", count));
+    }
+    Ok(())
+}
+
+async fn cmd_slot_list(db_url: &str, json: bool) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+    let slots = vault.auth().list_slots().await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&slots).unwrap());
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL);
+    table.set_header(vec!["Id", "Label", "Kind", "Added"]);
+
+    for slot in slots {
+        let mut kind = slot.kind.to_string();
+        if slot.requires_key_file {
+            kind.push_str(" + keyfile");
+        }
+        let added = chrono::DateTime::from_timestamp(slot.created_at, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        table.add_row(vec![slot.id.to_string(), slot.label, kind, added]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+async fn cmd_slot_add(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    label: &str,
+    kind: SlotKind,
+    file: Option<&Path>,
+) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+
+    unlock.with_pin(&vault, "PIN: ").await?;
+
+    let slot = match kind {
+        SlotKind::KeyFile => {
+            let path = file.ok_or_else(|| {
+                VaultError::InvalidInput("a keyfile slot needs --file".to_string())
+            })?;
+            let contents = read_or_create_key_file(path)?;
+            vault
+                .auth()
+                .add_slot(label, kind, SlotCredential::KeyFile(&contents))
+                .await?
+        }
+        SlotKind::Pin | SlotKind::Passphrase => {
+            let prompt = format!("New {} for slot '{}': ", kind, label);
+            let secret = read_slot_secret(&prompt)?;
+            vault
+                .auth()
+                .add_slot(label, kind, SlotCredential::Secret(&secret))
+                .await?
+        }
+    };
+
+    print_success(&format!("Added {} slot '{}' (id {})", slot.kind, slot.label, slot.id));
+    Ok(())
+}
+
+async fn cmd_slot_test(db_url: &str, id: i64, file: Option<&Path>) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+
+    let opened = match file {
+        Some(path) => {
+            let contents = read_key_file(path)?;
+            vault
+                .auth()
+                .test_slot(id, SlotCredential::KeyFile(&contents))
+                .await?
+        }
+        None => {
+            let secret = read_pin("PIN or passphrase: ").map_err(|e| VaultError::Io(e.to_string()))?;
+            vault
+                .auth()
+                .test_slot(id, SlotCredential::Secret(&secret))
+                .await?
+        }
+    };
+
+    if !opened {
+        return Err(VaultError::InvalidPin);
+    }
+    print_success(&format!("Credential opens slot {}", id));
+    Ok(())
+}
+
+async fn cmd_slot_revoke(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    id: i64,
+    force: bool,
+) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+
+    let slot = vault
+        .auth()
+        .list_slots()
+        .await?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| VaultError::NotFound(format!("key slot {id}")))?;
+    if slot.label == PRIMARY_SLOT_LABEL {
+        return Err(VaultError::InvalidInput(
+            "the primary PIN cannot be revoked; use change-pin".to_string(),
+        ));
+    }
+
+    unlock.with_pin(&vault, "PIN: ").await?;
+
+    if !force {
+        let confirm = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Revoke {} slot '{}'?", slot.kind, slot.label))
+            .default(false)
+            .interact()
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+
+        if !confirm {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    }
+
+    vault.auth().revoke_slot(id).await?;
+    print_success(&format!("Revoked slot '{}'", slot.label));
+    Ok(())
+}
+
+async fn cmd_recovery_create(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    shares: u8,
+    threshold: u8,
+) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+
+    unlock.with_pin(&vault, "PIN: ").await?;
+
+    if vault.auth().has_recovery_shares().await? {
+        eprintln!(
+            "{}: This replaces the existing recovery set. Destroy the old shares; they still encode the vault key.",
+            "Warning".yellow().bold()
+        );
+    }
+
+    let shares = vault.auth().create_recovery_shares(threshold, shares).await?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Give each share to a different person. Any {threshold} of them can reset the PIN and read every secret."
+        )
+        .yellow()
+        .bold()
+    );
+    for share in &shares {
+        println!();
+        println!("{}", share.to_text());
+    }
+    Ok(())
+}
+
+async fn cmd_recovery_restore(db_url: &str, key_file: Option<&Path>) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+    if !vault.auth().has_recovery_shares().await? {
+        return Err(VaultError::InvalidInput(
+            "no recovery shares were created for this vault".to_string(),
+        ));
+    }
+
+    let shares = read_shares()?;
+    let key_file = key_file.map(read_or_create_key_file).transpose()?;
+    let new_pin = read_new_pin()?;
+
+    vault
+        .auth()
+        .recover_with_shares(&shares, &new_pin, key_file.as_deref())
+        .await?;
+    print_success("Vault recovered; the new PIN is set");
+    Ok(())
+}
+
+/// Read recovery shares from stdin until the threshold is reached.
+///
+/// Each share ends with an empty line or end of input.
+fn read_shares() -> Result<Vec<Share>, VaultError> {
+    let mut shares: Vec<Share> = Vec::new();
+    let mut block = String::new();
+    let mut lines = io::stdin().lock().lines();
+
+    eprintln!("Enter recovery share 1, followed by an empty line:");
+    loop {
+        let line = lines
+            .next()
+            .transpose()
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+        let done = line.is_none();
+        let line = line.unwrap_or_default();
+
+        if !line.trim().is_empty() {
+            block.push_str(&line);
+            block.push('\n');
+            continue;
+        }
+        if !block.trim().is_empty() {
+            let share: Share = block
+                .parse()
+                .map_err(|e: vult::shamir::ShamirError| VaultError::InvalidInput(e.to_string()))?;
+            block.clear();
+            shares.push(share);
+
+            let needed = shares[0].threshold() as usize;
+            if shares.len() >= needed {
+                return Ok(shares);
+            }
+            eprintln!(
+                "Enter recovery share {} of {}, followed by an empty line:",
+                shares.len() + 1,
+                needed
+            );
+        }
+        if done {
+            let needed = shares.first().map_or(2, |s| s.threshold());
+            return Err(VaultError::InvalidInput(format!(
+                "Need {} recovery shares, got {}",
+                needed,
+                shares.len()
+            )));
+        }
+    }
+}
+
+async fn cmd_backup_create(db_url: &str, json: bool) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+    let backup = vault.create_backup().await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&backup).unwrap());
+        return Ok(());
+    }
+    match backup {
+        Some(backup) => print_success(&format!(
+            "Snapshot {} saved to {}",
+            backup.id,
+            backup.path.display()
+        )),
+        None => println!("Nothing to back up: the vault is not initialized"),
+    }
+    Ok(())
+}
+
+async fn cmd_backup_list(db_url: &str, json: bool) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+    let backups = vault.list_backups().await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&backups).unwrap());
+        return Ok(());
+    }
+    if backups.is_empty() {
+        println!("No snapshots yet.");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL);
+    table.set_header(vec!["Snapshot", "Taken", "Size"]);
+
+    for backup in backups {
+        let taken = backup
+            .created_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let size = format!("{:.1} KiB", backup.size as f64 / 1024.0);
+        table.add_row(vec![backup.id, taken, size]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+async fn cmd_backup_restore(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    id: &str,
+    force: bool,
+    json: bool,
+) -> Result<(), VaultError> {
+    let vault = VaultManager::new(db_url).await?;
+    if !vault.is_initialized().await? {
+        return Err(VaultError::NotInitialized);
+    }
+    let backup = vault.find_backup(id).await?;
+
+    // The current PIN must open both the vault and the snapshot, so the
+    // restore cannot leave a vault nobody can unlock
+    let key_file = unlock.key_file.map(read_key_file).transpose()?;
+    let pin = Zeroizing::new(read_pin("PIN: ").map_err(|e| VaultError::Io(e.to_string()))?);
+    vault
+        .auth()
+        .unlock_with_key_file(&pin, key_file.as_deref())
+        .await?;
+    let keys = VaultManager::verify_backup(&backup.path, &pin, key_file.as_deref()).await?;
+
+    if !force {
+        let confirm = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Replace the vault with snapshot {} ({} key(s))?",
+                backup.id, keys
+            ))
+            .default(false)
+            .interact()
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+
+        if !confirm {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    }
+
+    let previous = vault.restore_backup(&backup).await?;
+
+    // The agent may hold the key of the replaced vault
+    if unlock.agent.is_running().await {
+        let _ = unlock.agent.lock().await;
+    }
+
+    if json {
+        let output = serde_json::json!({
+            "restored": backup,
+            "keys": keys,
+            "previous": previous,
+        });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+        return Ok(());
+    }
+    print_success(&format!(
+        "Restored snapshot {} ({} key(s))",
+        backup.id, keys
+    ));
+    if let Some(previous) = previous {
+        println!("The replaced vault was saved as snapshot {}", previous.id);
+    }
+    Ok(())
+}
+
+async fn cmd_db_migrate(db_url: &str, dry_run: bool, json: bool) -> Result<(), VaultError> {
+    let db = VaultDb::open(db_url).await?;
+    let report = db.migrate(dry_run).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return Ok(());
+    }
+    if report.migrations.is_empty() {
+        println!("Schema is up to date (version {})", report.to_version);
+        return Ok(());
+    }
+
+    for migration in &report.migrations {
+        println!("  {}  {}", migration.version, migration.description);
+    }
+    if dry_run {
+        println!(
+            "{}: would migrate from version {} to {}; the vault was not changed",
+            "Dry run".yellow().bold(),
+            report.from_version,
+            report.to_version
+        );
+        return Ok(());
+    }
+    if let Some(snapshot) = &report.snapshot {
+        println!(
+            "Snapshot {} saved to {}",
+            snapshot.id,
+            snapshot.path.display()
+        );
+    }
+    print_success(&format!(
+        "Migrated schema from version {} to {}",
+        report.from_version, report.to_version
+    ));
+    Ok(())
+}
+
+async fn cmd_audit_show(
+    vault: &VaultManager,
+    mut filter: AuditFilter,
+    key: Option<&KeyRef>,
+    json: bool,
+) -> Result<(), VaultError> {
+    if let Some(key) = key {
+        filter.key_id = match vault.keys().find_id(&key.app_name, &key.key_name).await {
+            Ok(id) => Some(id),
+            // Entries about a deleted key are still found by name
+            Err(VaultError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        filter.key_name = Some(key.to_string());
+    }
+    let entries = vault.audit().entries(&filter).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No matching audit entries.");
+        return Ok(());
+    }
+
+    // Names are not logged in encrypted-metadata mode; show current ones
+    let names: std::collections::HashMap<String, String> = vault
+        .keys()
+        .list()
+        .await?
+        .into_iter()
+        .map(|key| {
+            let name = KeyRef {
+                app_name: key.app_name.unwrap_or_default(),
+                key_name: key.key_name,
+            };
+            (key.id, name.to_string())
+        })
+        .collect();
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL);
+    table.set_header(vec!["#", "Time", "Event", "Key", "User", "Detail"]);
+
+    for entry in &entries {
+        let time = entry
+            .at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let key = entry
+            .key_name
+            .clone()
+            .or_else(|| {
+                let id = entry.key_id.as_ref()?;
+                Some(names.get(id).cloned().unwrap_or_else(|| id.clone()))
+            })
+            .unwrap_or_else(|| "-".to_string());
+        table.add_row(vec![
+            entry.seq.to_string(),
+            time,
+            entry.event.to_string(),
+            key,
+            entry.actor.clone().unwrap_or_else(|| "-".to_string()),
+            entry.detail.clone().unwrap_or_else(|| "-".to_string()),
+        ]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+async fn cmd_audit_verify(vault: &VaultManager, json: bool) -> Result<(), VaultError> {
+    let vault_key = vault.auth().get_vault_key().await?;
+    let report = vault.audit().verify(&vault_key).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else if report.is_intact() {
+        print_success(&format!("Audit log intact: {} entries", report.entries));
+        if report.unsigned > 0 {
+            println!(
+                "{}",
+                format!(
+                    "⚠ The {} newest entries were written while locked and are not signed yet.",
+                    report.unsigned
+                )
+                .yellow()
+            );
+        }
+    }
+
+    match report.broken {
+        Some(broken) => Err(VaultError::InvalidState(format!(
+            "audit log was altered at entry {}: {}",
+            broken.seq, broken.reason
+        ))),
+        None => Ok(()),
+    }
+}
+
+async fn cmd_agent_start(
+    agent: &AgentClient,
+    idle_ttl: u64,
+    hard_ttl: u64,
+    foreground: bool,
+) -> Result<(), VaultError> {
+    if idle_ttl == 0 || hard_ttl == 0 {
+        return Err(VaultError::InvalidInput(
+            "Agent TTLs must be at least one second".to_string(),
+        ));
+    }
+    let config = AgentConfig {
+        idle_ttl: std::time::Duration::from_secs(idle_ttl),
+        hard_ttl: std::time::Duration::from_secs(hard_ttl),
+    };
+
+    if foreground {
+        eprintln!(
+            "Session agent listening on {}",
+            agent.socket_path().display()
+        );
+        return run_agent(agent.socket_path().to_path_buf(), config).await;
+    }
+
+    if agent.is_running().await {
+        return Err(VaultError::InvalidState(format!(
+            "A session agent is already running on {}",
+            agent.socket_path().display()
+        )));
+    }
+
+    // Re-run this binary detached from the terminal
+    let exe = std::env::current_exe().map_err(|e| VaultError::Io(e.to_string()))?;
+    let mut command = std::process::Command::new(exe);
+    command
+        .args(["agent", "start", "--foreground"])
+        .arg("--idle-ttl")
+        .arg(idle_ttl.to_string())
+        .arg("--hard-ttl")
+        .arg(hard_ttl.to_string())
+        .env("VULT_AGENT_SOCKET", agent.socket_path())
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let child = command.spawn().map_err(|e| VaultError::Io(e.to_string()))?;
+
+    for _ in 0..40 {
+        if agent.is_running().await {
+            print_success(&format!(
+                "Session agent started (pid {}) on {}",
+                child.id(),
+                agent.socket_path().display()
+            ));
+            println!("Keys expire after {idle_ttl}s idle or {hard_ttl}s in total.");
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    Err(VaultError::Io(
+        "Session agent did not start; run 'vult agent start --foreground' to see why".to_string(),
+    ))
+}
+
+async fn cmd_agent_stop(agent: &AgentClient) -> Result<(), VaultError> {
+    if !agent.is_running().await {
+        println!("No session agent is running.");
+        return Ok(());
+    }
+    agent.shutdown().await?;
+    print_success("Session agent stopped");
+    Ok(())
+}
+
+async fn cmd_agent_status(agent: &AgentClient, json: bool) -> Result<(), VaultError> {
+    let status = match agent.request(&AgentRequest::Status).await {
+        Ok(status @ AgentResponse::Status { .. }) => Some(status),
+        _ => None,
+    };
+
+    if json {
+        let value = match &status {
+            Some(AgentResponse::Status {
+                vaults,
+                idle_ttl_secs,
+                hard_ttl_secs,
+            }) => serde_json::json!({
+                "running": true,
+                "socket": agent.socket_path(),
+                "idle_ttl_secs": idle_ttl_secs,
+                "hard_ttl_secs": hard_ttl_secs,
+                "vaults": vaults,
+            }),
+            _ => serde_json::json!({
+                "running": false,
+                "socket": agent.socket_path(),
+            }),
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&value).unwrap_or_default()
+        );
+        return Ok(());
+    }
+
+    let Some(AgentResponse::Status {
+        vaults,
+        idle_ttl_secs,
+        hard_ttl_secs,
+    }) = status
+    else {
+        println!("Session agent: {}", "not running".red());
+        return Ok(());
+    };
+
+    println!("Session agent: {}", "running".green());
+    println!("Socket: {}", agent.socket_path().display());
+    println!("Idle TTL: {idle_ttl_secs}s, hard TTL: {hard_ttl_secs}s");
+    if vaults.is_empty() {
+        println!("No unlocked vaults.");
+    }
+    for vault in vaults {
+        println!(
+            "Unlocked: {} (expires in {}s)",
+            vault.vault, vault.expires_in_secs
+        );
+    }
+    Ok(())
+}
+
+/// Words the shell completes at the start of a line
+const SHELL_COMMANDS: &[&str] = &[
+    "add", "get", "list", "search", "update", "delete", "lock", "help", "exit",
+];
+
+/// Tab completion for `vult shell`: commands, app names and key names.
+#[derive(Default)]
+struct ShellHelper {
+    /// (app name, key name) of every stored key, refreshed after each command
+    names: Vec<(Option<String>, String)>,
+}
+
+impl ShellHelper {
+    fn candidates(&self, words: &[&str], partial: &str) -> Vec<String> {
+        let mut candidates: Vec<String> = match words {
+            [] => SHELL_COMMANDS.iter().map(|c| c.to_string()).collect(),
+            [.., "-a" | "--app"] => self
+                .names
+                .iter()
+                .filter_map(|(app, _)| app.clone())
+                .collect(),
+            [command, rest @ ..] if ["get", "update", "delete"].contains(command) => {
+                let app = rest
+                    .windows(2)
+                    .find(|pair| pair[0] == "-a" || pair[0] == "--app")
+                    .map(|pair| pair[1]);
+                self.names
+                    .iter()
+                    .filter(|(key_app, _)| app.is_none() || key_app.as_deref() == app)
+                    .map(|(_, name)| name.clone())
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        candidates.retain(|c| c.starts_with(partial));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+        Ok((start, self.candidates(&words, &before[start..])))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Load app and key names for completion.
+async fn shell_names(vault: &VaultManager) -> Vec<(Option<String>, String)> {
+    match vault.keys().list().await {
+        Ok(keys) => keys.into_iter().map(|k| (k.app_name, k.key_name)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn cmd_shell(
+    db_url: &str,
+    unlock: &Unlock<'_>,
+    idle_timeout: u64,
+    json: bool,
+) -> Result<(), VaultError> {
+    if idle_timeout == 0 {
+        return Err(VaultError::InvalidInput(
+            "Idle timeout must be at least one second".to_string(),
+        ));
+    }
+    let idle_timeout = Duration::from_secs(idle_timeout);
+
+    let vault = Arc::new(VaultManager::new(db_url).await?);
+    unlock.vault(&vault, "PIN: ").await?;
+
+    // Lock the vault in the background once the shell sits idle
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    let idle_lock = {
+        let vault = Arc::clone(&vault);
+        let last_activity = Arc::clone(&last_activity);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let idle = last_activity
+                    .lock()
+                    .map(|last| last.elapsed())
+                    .unwrap_or_default();
+                if idle >= idle_timeout && vault.is_unlocked() {
+                    let _ = vault.auth().lock().await;
+                }
+            }
+        })
+    };
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> =
+        Editor::new().map_err(|e| VaultError::Io(e.to_string()))?;
+    editor.set_helper(Some(ShellHelper {
+        names: shell_names(&vault).await,
+    }));
+
+    println!("Vault unlocked. Type 'help' for commands, 'exit' to leave.");
+    let result = loop {
+        // Read on a blocking thread so the idle lock keeps running
+        let (returned, line) = tokio::task::spawn_blocking(move || {
+            let line = editor.readline("vult> ");
+            (editor, line)
+        })
+        .await
+        .map_err(|e| VaultError::Io(e.to_string()))?;
+        editor = returned;
+
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break Ok(()),
+            Err(e) => break Err(VaultError::Io(e.to_string())),
+        };
+
+        let Some(words) = shlex::split(&line) else {
+            print_error(&VaultError::InvalidInput("Unbalanced quotes".to_string()));
+            continue;
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit" | "quit") => break Ok(()),
+            _ => {}
+        }
+
+        if !vault.is_unlocked() {
+            if let Some(helper) = editor.helper_mut() {
+                helper.names.clear();
+            }
+            println!(
+                "{}",
+                format!("Vault locked after {}s idle.", idle_timeout.as_secs()).yellow()
+            );
+            if let Err(e) = unlock.vault(&vault, "PIN: ").await {
+                break Err(e);
+            }
+        }
+        if let Ok(mut last) = last_activity.lock() {
+            *last = Instant::now();
+        }
+
+        let command = match ShellLine::try_parse_from(&words) {
+            Ok(parsed) => parsed.command,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+
+        // Don't keep secret values typed on the line in history
+        if !matches!(command, Commands::Update { value: Some(_), .. }) {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        if matches!(command, Commands::Lock) {
+            break Ok(());
+        }
+        if let Err(e) = run_key_command(&vault, command, json).await {
+            print_error(&e);
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = shell_names(&vault).await;
+        }
+    };
+
+    idle_lock.abort();
+    vault.auth().lock().await?;
+    println!("{}", "Vault is locked.".green());
+    result
+}
@@ -3,6 +3,8 @@
 //! This module provides foundational types and validation utilities
 //! used throughout the library.
 
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
 
 use thiserror::Error;
@@ -23,6 +25,27 @@ pub const DEFAULT_AUTO_LOCK_DURATION: Duration = Duration::from_secs(300);
 /// Clipboard auto-clear timeout (45 seconds)
 pub const CLIPBOARD_CLEAR_TIMEOUT: Duration = Duration::from_secs(45);
 
+/// Failed unlock attempts before the vault is locked out
+pub const MAX_FAILED_ATTEMPTS: u32 = 10;
+
+/// How long a lockout lasts once [`MAX_FAILED_ATTEMPTS`] is reached (1 hour)
+pub const LOCKOUT_DURATION: Duration = Duration::from_secs(3600);
+
+/// Maximum number of key slots besides the primary PIN
+pub const MAX_KEY_SLOTS: usize = 8;
+
+/// Minimum length of a passphrase key slot
+pub const MIN_PASSPHRASE_LENGTH: usize = 12;
+
+/// Maximum length of a passphrase key slot
+pub const MAX_PASSPHRASE_LENGTH: usize = 1024;
+
+/// How long the session agent keeps an unused key (5 minutes)
+pub const DEFAULT_AGENT_IDLE_TTL: Duration = Duration::from_secs(300);
+
+/// How long the session agent keeps a key at most, even while used (1 hour)
+pub const DEFAULT_AGENT_HARD_TTL: Duration = Duration::from_secs(3600);
+
 // =============================================================================
 // PIN Validation
 // =============================================================================
@@ -83,6 +106,68 @@ pub fn validate_pin(pin: &str) -> Result<(), PinValidationError> {
     Ok(())
 }
 
+// =============================================================================
+// Key References
+// =============================================================================
+
+/// Error type for parsing a [`KeyRef`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum KeyRefError {
+    /// The reference has no key name
+    #[error("invalid key reference '{0}': expected app/key or key")]
+    MissingKeyName(String),
+}
+
+/// A reference to a stored key, written `app/key`, or just `key` for a key
+/// without an app.
+///
+/// The app name ends at the first `/`, so `app/a/b` names the key `a/b`.
+///
+/// # Examples
+///
+/// ```
+/// use vult::core::KeyRef;
+///
+/// let key: KeyRef = "github/token".parse().unwrap();
+/// assert_eq!(key.app_name, "github");
+/// assert_eq!(key.key_name, "token");
+///
+/// let key: KeyRef = "token".parse().unwrap();
+/// assert_eq!(key.app_name, "");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyRef {
+    /// Application name; empty for keys without an app
+    pub app_name: String,
+    /// Key name
+    pub key_name: String,
+}
+
+impl FromStr for KeyRef {
+    type Err = KeyRefError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (app_name, key_name) = s.split_once('/').unwrap_or(("", s));
+        if key_name.is_empty() {
+            return Err(KeyRefError::MissingKeyName(s.to_string()));
+        }
+        Ok(Self {
+            app_name: app_name.to_string(),
+            key_name: key_name.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for KeyRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.app_name.is_empty() {
+            write!(f, "{}", self.key_name)
+        } else {
+            write!(f, "{}/{}", self.app_name, self.key_name)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +195,26 @@ mod tests {
     fn test_validate_pin_with_spaces() {
         assert!(validate_pin("my secure pin").is_ok());
     }
+
+    #[test]
+    fn test_key_ref_parse() {
+        let key: KeyRef = "github/token".parse().unwrap();
+        assert_eq!(key.app_name, "github");
+        assert_eq!(key.key_name, "token");
+        assert_eq!(key.to_string(), "github/token");
+
+        let key: KeyRef = "token".parse().unwrap();
+        assert_eq!(key.app_name, "");
+        assert_eq!(key.to_string(), "token");
+
+        let key: KeyRef = "aws/prod/secret".parse().unwrap();
+        assert_eq!(key.app_name, "aws");
+        assert_eq!(key.key_name, "prod/secret");
+    }
+
+    #[test]
+    fn test_key_ref_requires_key_name() {
+        assert!("".parse::<KeyRef>().is_err());
+        assert!("github/".parse::<KeyRef>().is_err());
+    }
 }
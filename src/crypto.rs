@@ -1,15 +1,21 @@
 //! Cryptographic operations for secure storage.
 //!
 //! This module provides:
-//! - Key derivation using Argon2id
+//! - Key derivation using Argon2id, with per-vault tunable costs
 //! - Authenticated encryption using AES-256-GCM
-//! - Per-key encryption (unique key per API key)
+//! - Per-key encryption (unique key per API key, versioned by [`KeyScheme`])
+//! - PIN verifiers (HMAC-SHA256 under the derived key)
+//! - Key wrapping for envelope encryption
 //! - Secure random generation
 //!
 //! # Algorithms
 //!
-//! - **Key Derivation**: Argon2id with 64MB memory, 3 iterations, 4 lanes
+//! - **Key Derivation**: Argon2id, 64MB memory, 3 iterations, 4 lanes by default (see [`KdfParams`])
 //! - **Encryption**: AES-256-GCM with 12-byte nonce, 16-byte auth tag
+//! - **Per-Key Derivation**: HKDF-SHA256 bound to the row id (legacy rows: Argon2id)
+//! - **Associated Data**: Row id, app name, key name and scheme bound into AES-GCM
+//! - **Blind Index**: HMAC-SHA256 under a data-key subkey, for encrypted metadata
+//! - **PIN Verification**: HMAC-SHA256 of a fixed label, compared in constant time
 //!
 //! # Security Properties
 //!
@@ -36,15 +42,21 @@
 //! ```
 
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     Aes256Gcm, Nonce,
 };
 use argon2::{
     password_hash::{PasswordHasher, SaltString},
     Algorithm, Argon2, Params, Version,
 };
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
@@ -93,6 +105,236 @@ pub enum CryptoError {
 /// Result type for crypto operations
 pub type Result<T> = std::result::Result<T, CryptoError>;
 
+/// HMAC-SHA256 instance used for verifiers
+type HmacSha256 = Hmac<Sha256>;
+
+/// Domain-separation label for the PIN verifier
+const PIN_VERIFIER_LABEL: &[u8] = b"vult-pin-verifier-v1";
+
+/// Domain-separation label for HKDF per-key derivation
+const PER_KEY_HKDF_LABEL: &[u8] = b"vult-per-key-v2";
+
+/// Domain-separation label for stored key value associated data
+const KEY_VALUE_AAD_LABEL: &[u8] = b"vult-key-aad-v3";
+
+/// Domain-separation label for the metadata encryption subkey
+const METADATA_KEY_LABEL: &[u8] = b"vult-metadata-v1";
+
+/// Domain-separation label for the blind index subkey
+const BLIND_INDEX_KEY_LABEL: &[u8] = b"vult-blind-index-v1";
+
+/// Domain-separation label for mixing a key file into the PIN key
+const KEY_FILE_LABEL: &[u8] = b"vult-key-file-v1";
+
+/// Domain-separation label for key slots opened by a key file alone
+const KEY_FILE_SLOT_LABEL: &[u8] = b"vult-key-file-slot-v1";
+
+/// Domain-separation label for the data-key check used by recovery shares
+const RECOVERY_VERIFIER_LABEL: &[u8] = b"vult-recovery-verifier-v1";
+
+/// Domain-separation label for the data-key check used by the session agent
+const DATA_KEY_VERIFIER_LABEL: &[u8] = b"vult-data-key-verifier-v1";
+
+/// Domain-separation label for the audit log MAC subkey
+const AUDIT_KEY_LABEL: &[u8] = b"vult-audit-v1";
+
+/// Domain-separation label for audit log entry hashes and their MACs
+const AUDIT_ENTRY_LABEL: &[u8] = b"vult-audit-entry-v1";
+
+/// Domain-separation label for the MAC over the newest signed audit entry
+const AUDIT_HEAD_LABEL: &[u8] = b"vult-audit-head-v1";
+
+/// How the encryption key for a stored secret is derived.
+///
+/// The scheme is recorded with each row, so rows written by older versions
+/// stay readable and can be upgraded lazily.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum KeyScheme {
+    /// Argon2id over the master key and `"{app}|{key}"`.
+    ///
+    /// Slow, and the context is ambiguous (`a|b`+`c` equals `a`+`b|c`).
+    LegacyArgon2 = 1,
+    /// HKDF-SHA256 over the master key, bound to the row's stable id.
+    HkdfV2 = 2,
+    /// [`KeyScheme::HkdfV2`] plus AES-GCM associated data binding the id,
+    /// app name, key name and scheme, so ciphertexts cannot be moved
+    /// between rows.
+    HkdfAad = 3,
+}
+
+impl KeyScheme {
+    /// Scheme used for all new writes.
+    pub const CURRENT: KeyScheme = KeyScheme::HkdfAad;
+
+    /// Converts the stored column value back into a scheme.
+    pub fn from_i64(value: i64) -> Option<Self> {
+        match value {
+            1 => Some(KeyScheme::LegacyArgon2),
+            2 => Some(KeyScheme::HkdfV2),
+            3 => Some(KeyScheme::HkdfAad),
+            _ => None,
+        }
+    }
+
+    /// Value stored in the `key_scheme` column.
+    pub fn as_i64(self) -> i64 {
+        self as i64
+    }
+}
+
+/// Identifies the stored secret a per-key encryption key belongs to.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyContext<'a> {
+    /// Stable row id
+    pub id: &'a str,
+    /// Application name (empty if none)
+    pub app_name: &'a str,
+    /// Key name
+    pub key_name: &'a str,
+}
+
+/// Password hashing algorithm used to derive the master key from the PIN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KdfAlgorithm {
+    /// Argon2id (RFC 9106)
+    Argon2id,
+}
+
+impl KdfAlgorithm {
+    /// Identifier used in the stored parameter string.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KdfAlgorithm::Argon2id => "argon2id",
+        }
+    }
+}
+
+/// Cost parameters for deriving the master key from the PIN.
+///
+/// These are stored with each vault so they can be raised later without
+/// locking out existing vaults. The stored form is
+/// `argon2id$m=<KiB>,t=<iterations>,p=<lanes>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Hashing algorithm
+    pub algorithm: KdfAlgorithm,
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over memory
+    pub iterations: u32,
+    /// Degree of parallelism (lanes)
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Lowest accepted memory cost (19 MiB, the OWASP minimum for Argon2id)
+    pub const MIN_MEMORY_KIB: u32 = 19 * 1024;
+
+    /// Highest accepted memory cost (4 GiB)
+    pub const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+    /// Highest accepted iteration count
+    pub const MAX_ITERATIONS: u32 = 64;
+
+    /// Highest accepted degree of parallelism
+    pub const MAX_PARALLELISM: u32 = 16;
+
+    /// Creates Argon2id parameters after checking them against the accepted ranges.
+    pub fn argon2id(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self> {
+        let params = Self {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// Checks that the costs are within the accepted ranges.
+    pub fn validate(&self) -> Result<()> {
+        if !(Self::MIN_MEMORY_KIB..=Self::MAX_MEMORY_KIB).contains(&self.memory_kib) {
+            return Err(CryptoError::KeyDerivation(format!(
+                "memory cost must be between {} and {} KiB",
+                Self::MIN_MEMORY_KIB,
+                Self::MAX_MEMORY_KIB
+            )));
+        }
+        if !(1..=Self::MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(CryptoError::KeyDerivation(format!(
+                "iterations must be between 1 and {}",
+                Self::MAX_ITERATIONS
+            )));
+        }
+        if !(1..=Self::MAX_PARALLELISM).contains(&self.parallelism) {
+            return Err(CryptoError::KeyDerivation(format!(
+                "parallelism must be between 1 and {}",
+                Self::MAX_PARALLELISM
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfParams {
+    /// The parameters every vault used before they became configurable.
+    fn default() -> Self {
+        Self {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: 65536,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
+impl fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}$m={},t={},p={}",
+            self.algorithm.as_str(),
+            self.memory_kib,
+            self.iterations,
+            self.parallelism
+        )
+    }
+}
+
+impl FromStr for KdfParams {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || CryptoError::KeyDerivation(format!("invalid KDF parameters: {s}"));
+
+        let (algorithm, costs) = s.split_once('$').ok_or_else(invalid)?;
+        if algorithm != KdfAlgorithm::Argon2id.as_str() {
+            return Err(CryptoError::KeyDerivation(format!(
+                "unsupported KDF algorithm: {algorithm}"
+            )));
+        }
+
+        let (mut m, mut t, mut p) = (None, None, None);
+        for part in costs.split(',') {
+            let (name, value) = part.split_once('=').ok_or_else(invalid)?;
+            let value: u32 = value.parse().map_err(|_| invalid())?;
+            match name {
+                "m" => m = Some(value),
+                "t" => t = Some(value),
+                "p" => p = Some(value),
+                _ => return Err(invalid()),
+            }
+        }
+
+        Self::argon2id(
+            m.ok_or_else(invalid)?,
+            t.ok_or_else(invalid)?,
+            p.ok_or_else(invalid)?,
+        )
+    }
+}
+
 /// Derives a 256-bit encryption key from a PIN using Argon2id.
 ///
 /// # Parameters
@@ -100,11 +342,26 @@ pub type Result<T> = std::result::Result<T, CryptoError>;
 /// - `salt`: The salt to use for key derivation
 ///
 /// # Security Parameters
+/// Uses [`KdfParams::default`]:
 /// - Memory: 64 MiB
 /// - Iterations: 3
 /// - Parallelism: 4
 /// - Output length: 256 bits
 pub fn derive_key_from_pin(pin: &str, salt: &[u8; 32]) -> Result<VaultKey> {
+    derive_key_from_pin_with_params(pin, salt, &KdfParams::default())
+}
+
+/// Derives a 256-bit encryption key from a PIN with explicit cost parameters.
+///
+/// # Parameters
+/// - `pin`: The user's PIN (must be at least 6 characters)
+/// - `salt`: The salt to use for key derivation
+/// - `kdf`: Algorithm and costs, as stored with the vault
+pub fn derive_key_from_pin_with_params(
+    pin: &str,
+    salt: &[u8; 32],
+    kdf: &KdfParams,
+) -> Result<VaultKey> {
     // Invariant: PIN must meet minimum length requirement
     if pin.len() < 6 {
         return Err(CryptoError::KeyDerivation(
@@ -115,8 +372,8 @@ pub fn derive_key_from_pin(pin: &str, salt: &[u8; 32]) -> Result<VaultKey> {
     // Invariant: Salt must be exactly 32 bytes
     debug_assert_eq!(salt.len(), 32, "Salt must be exactly 32 bytes");
 
-    // Argon2id parameters for high security
-    let params = Params::new(65536, 3, 4, Some(32))
+    kdf.validate()?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
         .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
 
     let argon2 = Argon2::new(Algorithm::Argon2id, argon2::Version::V0x13, params);
@@ -156,6 +413,31 @@ pub fn derive_key_from_pin(pin: &str, salt: &[u8; 32]) -> Result<VaultKey> {
     Ok(VaultKey(key_array))
 }
 
+/// Picks Argon2id costs that take roughly `target` to derive on this machine.
+///
+/// Memory and parallelism are fixed by the caller; the iteration count is
+/// scaled from a single timed pass. The result never goes below one
+/// iteration or above [`KdfParams::MAX_ITERATIONS`].
+///
+/// # Parameters
+/// - `target`: Desired unlock time
+/// - `memory_kib`: Memory cost in KiB
+/// - `parallelism`: Degree of parallelism
+pub fn calibrate_kdf(target: Duration, memory_kib: u32, parallelism: u32) -> Result<KdfParams> {
+    let probe = KdfParams::argon2id(memory_kib, 1, parallelism)?;
+
+    let start = Instant::now();
+    derive_key_from_pin_with_params("calibration-pin", &generate_salt(), &probe)?;
+    let per_pass = start.elapsed().max(Duration::from_millis(1));
+
+    let iterations = (target.as_secs_f64() / per_pass.as_secs_f64()).round() as u32;
+    KdfParams::argon2id(
+        memory_kib,
+        iterations.clamp(1, KdfParams::MAX_ITERATIONS),
+        parallelism,
+    )
+}
+
 /// Generates a random salt for key derivation.
 pub fn generate_salt() -> [u8; 32] {
     let mut salt = [0u8; 32];
@@ -172,6 +454,22 @@ pub fn generate_salt() -> [u8; 32] {
 /// # Returns
 /// Encrypted data with nonce for later decryption
 pub fn encrypt(plaintext: &[u8], key: &VaultKey) -> Result<EncryptedData> {
+    encrypt_with_aad(plaintext, key, &[])
+}
+
+/// Encrypts plaintext using AES-256-GCM with associated data.
+///
+/// The associated data is authenticated but not stored; the same bytes must
+/// be passed to [`decrypt_with_aad`]. An empty `aad` matches [`encrypt`].
+///
+/// # Parameters
+/// - `plaintext`: The data to encrypt
+/// - `key`: The vault encryption key
+/// - `aad`: Associated data to bind to the ciphertext
+///
+/// # Returns
+/// Encrypted data with nonce for later decryption
+pub fn encrypt_with_aad(plaintext: &[u8], key: &VaultKey, aad: &[u8]) -> Result<EncryptedData> {
     // Invariant: Key must be exactly 32 bytes
     debug_assert_eq!(key.as_bytes().len(), 32, "VaultKey must be 32 bytes");
 
@@ -182,7 +480,13 @@ pub fn encrypt(plaintext: &[u8], key: &VaultKey) -> Result<EncryptedData> {
     debug_assert_eq!(nonce.len(), 12, "AES-GCM nonce must be 12 bytes");
 
     let ciphertext = cipher
-        .encrypt(&nonce, plaintext)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
         .map_err(|e| CryptoError::Encryption(e.to_string()))?;
 
     // Invariant: Ciphertext should be larger than plaintext (includes auth tag)
@@ -206,6 +510,21 @@ pub fn encrypt(plaintext: &[u8], key: &VaultKey) -> Result<EncryptedData> {
 /// # Returns
 /// The decrypted plaintext
 pub fn decrypt(encrypted: &EncryptedData, key: &VaultKey) -> Result<Vec<u8>> {
+    decrypt_with_aad(encrypted, key, &[])
+}
+
+/// Decrypts ciphertext using AES-256-GCM with associated data.
+///
+/// Fails if `aad` differs from the bytes given to [`encrypt_with_aad`].
+///
+/// # Parameters
+/// - `encrypted`: The encrypted data with nonce
+/// - `key`: The vault encryption key
+/// - `aad`: Associated data bound at encryption time
+///
+/// # Returns
+/// The decrypted plaintext
+pub fn decrypt_with_aad(encrypted: &EncryptedData, key: &VaultKey, aad: &[u8]) -> Result<Vec<u8>> {
     // Invariant: Key must be exactly 32 bytes
     debug_assert_eq!(key.as_bytes().len(), 32, "VaultKey must be 32 bytes");
 
@@ -227,13 +546,136 @@ pub fn decrypt(encrypted: &EncryptedData, key: &VaultKey) -> Result<Vec<u8>> {
     let cipher = Aes256Gcm::new(key.as_bytes().into());
 
     let plaintext = cipher
-        .decrypt(&nonce, encrypted.ciphertext.as_ref())
+        .decrypt(
+            &nonce,
+            Payload {
+                msg: &encrypted.ciphertext,
+                aad,
+            },
+        )
         .map_err(|e| CryptoError::Decryption(e.to_string()))?;
 
     Ok(plaintext)
 }
 
-/// Securely generates a random vault key for biometric authentication.
+/// Computes the PIN verifier for a derived vault key.
+///
+/// The verifier is HMAC-SHA256 of a fixed label keyed with the derived key.
+/// It can be stored next to the salt: it reveals nothing about the key, but
+/// lets a wrong PIN be rejected with full 256-bit strength.
+///
+/// # Parameters
+/// - `key`: The key derived from the PIN
+///
+/// # Returns
+/// The 32-byte verifier to store
+pub fn compute_pin_verifier(key: &VaultKey) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(PIN_VERIFIER_LABEL);
+    mac.finalize().into_bytes().into()
+}
+
+/// Checks a stored PIN verifier against a derived vault key.
+///
+/// The comparison is constant time, so timing does not leak how many
+/// bytes of the verifier matched.
+///
+/// # Parameters
+/// - `key`: The key derived from the candidate PIN
+/// - `expected`: The stored verifier
+///
+/// # Returns
+/// `true` if the key matches the verifier
+pub fn verify_pin_verifier(key: &VaultKey, expected: &[u8]) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(PIN_VERIFIER_LABEL);
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Computes a verifier for the data key itself.
+///
+/// Stored when recovery shares are created, so a key rebuilt from shares
+/// can be checked before it replaces the PIN.
+///
+/// # Parameters
+/// - `data_key`: The vault data key
+///
+/// # Returns
+/// The 32-byte verifier to store
+pub fn compute_recovery_verifier(data_key: &VaultKey) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(data_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(RECOVERY_VERIFIER_LABEL);
+    mac.finalize().into_bytes().into()
+}
+
+/// Checks a data key against a verifier from [`compute_recovery_verifier`],
+/// in constant time.
+pub fn verify_recovery_verifier(data_key: &VaultKey, expected: &[u8]) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(data_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(RECOVERY_VERIFIER_LABEL);
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Computes a verifier that lets a data key from the session agent be
+/// matched to its vault without the PIN.
+///
+/// # Parameters
+/// - `data_key`: The vault data key
+///
+/// # Returns
+/// The 32-byte verifier to store
+pub fn compute_data_key_verifier(data_key: &VaultKey) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(data_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(DATA_KEY_VERIFIER_LABEL);
+    mac.finalize().into_bytes().into()
+}
+
+/// Checks a data key against a verifier from [`compute_data_key_verifier`],
+/// in constant time.
+pub fn verify_data_key_verifier(data_key: &VaultKey, expected: &[u8]) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(data_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(DATA_KEY_VERIFIER_LABEL);
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Wraps a data-encryption key under a key-encryption key.
+///
+/// This is the envelope used to store the vault's random data key: only the
+/// wrapped form changes when the PIN changes, so stored secrets never need
+/// to be re-encrypted.
+///
+/// # Parameters
+/// - `key`: The key to protect
+/// - `kek`: The key-encryption key (derived from the PIN)
+///
+/// # Returns
+/// The wrapped key with its nonce
+pub fn wrap_key(key: &VaultKey, kek: &VaultKey) -> Result<EncryptedData> {
+    encrypt(key.as_bytes(), kek)
+}
+
+/// Unwraps a data-encryption key produced by [`wrap_key`].
+///
+/// # Parameters
+/// - `wrapped`: The wrapped key with nonce
+/// - `kek`: The key-encryption key used to wrap it
+///
+/// # Returns
+/// The unwrapped key, or an error if the KEK is wrong or the data was tampered with
+pub fn unwrap_key(wrapped: &EncryptedData, kek: &VaultKey) -> Result<VaultKey> {
+    let mut bytes = decrypt(wrapped, kek)?;
+    let key = <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| CryptoError::InvalidKeyLength);
+    bytes.zeroize();
+    Ok(VaultKey(key?))
+}
+
+/// Securely generates a random vault key (data keys, biometric authentication).
 pub fn generate_vault_key() -> VaultKey {
     let mut key = [0u8; 32];
     OsRng.fill_bytes(&mut key);
@@ -320,6 +762,301 @@ pub fn derive_per_key_encryption_key(
     Ok(VaultKey(key_array))
 }
 
+/// Encodes `parts` with a 4-byte big-endian length before each one.
+///
+/// Unlike joining with a separator, this is unambiguous for any input.
+fn length_prefixed(parts: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(parts.iter().map(|p| p.len() + 4).sum());
+    for part in parts {
+        out.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+/// Derives a per-key encryption key with HKDF-SHA256 ([`KeyScheme::HkdfV2`]).
+///
+/// The key is bound to the row's stable id rather than its names, so
+/// renaming a key does not change its encryption key.
+///
+/// # Parameters
+/// - `master_key`: The vault's data-encryption key
+/// - `key_id`: The row's stable id
+/// - `salt`: Per-key salt (used as the HKDF salt)
+///
+/// # Returns
+/// A unique encryption key for this specific API key
+pub fn derive_per_key_encryption_key_v2(
+    master_key: &VaultKey,
+    key_id: &str,
+    salt: &[u8; 32],
+) -> Result<VaultKey> {
+    if key_id.is_empty() {
+        return Err(CryptoError::KeyDerivation(
+            "key id cannot be empty".to_string(),
+        ));
+    }
+
+    let hkdf = Hkdf::<Sha256>::new(Some(salt.as_slice()), master_key.as_bytes());
+    let info = length_prefixed(&[PER_KEY_HKDF_LABEL, key_id.as_bytes()]);
+
+    let mut key_array = [0u8; 32];
+    hkdf.expand(&info, &mut key_array)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+
+    Ok(VaultKey(key_array))
+}
+
+/// Derives the per-key encryption key for `scheme`.
+fn derive_scheme_key(
+    master_key: &VaultKey,
+    context: &KeyContext<'_>,
+    salt: &[u8; 32],
+    scheme: KeyScheme,
+) -> Result<VaultKey> {
+    match scheme {
+        KeyScheme::LegacyArgon2 => {
+            derive_per_key_encryption_key(master_key, context.app_name, context.key_name, salt)
+        }
+        KeyScheme::HkdfV2 | KeyScheme::HkdfAad => {
+            derive_per_key_encryption_key_v2(master_key, context.id, salt)
+        }
+    }
+}
+
+/// Builds the associated data for a stored key value.
+///
+/// Schemes before [`KeyScheme::HkdfAad`] use none.
+fn key_value_aad(context: &KeyContext<'_>, scheme: KeyScheme) -> Vec<u8> {
+    match scheme {
+        KeyScheme::LegacyArgon2 | KeyScheme::HkdfV2 => Vec::new(),
+        KeyScheme::HkdfAad => length_prefixed(&[
+            KEY_VALUE_AAD_LABEL,
+            context.id.as_bytes(),
+            context.app_name.as_bytes(),
+            context.key_name.as_bytes(),
+            &scheme.as_i64().to_be_bytes(),
+        ]),
+    }
+}
+
+/// Encrypts an API key value under the per-key key for `scheme`.
+///
+/// From [`KeyScheme::HkdfAad`] on, the row identity in `context` is bound
+/// as associated data.
+///
+/// # Parameters
+/// - `plaintext`: The secret value
+/// - `master_key`: The vault's data-encryption key
+/// - `context`: The row the value belongs to
+/// - `salt`: Per-key salt
+/// - `scheme`: Derivation scheme to record with the row
+pub fn encrypt_api_key_value(
+    plaintext: &[u8],
+    master_key: &VaultKey,
+    context: &KeyContext<'_>,
+    salt: &[u8; 32],
+    scheme: KeyScheme,
+) -> Result<EncryptedData> {
+    let per_key_key = derive_scheme_key(master_key, context, salt, scheme)?;
+    encrypt_with_aad(plaintext, &per_key_key, &key_value_aad(context, scheme))
+}
+
+/// Decrypts an API key value stored under `scheme`.
+///
+/// # Parameters
+/// - `encrypted`: The stored ciphertext and nonce
+/// - `master_key`: The vault's data-encryption key
+/// - `context`: The row the value belongs to
+/// - `salt`: Per-key salt
+/// - `scheme`: Derivation scheme recorded with the row
+pub fn decrypt_api_key_value(
+    encrypted: &EncryptedData,
+    master_key: &VaultKey,
+    context: &KeyContext<'_>,
+    salt: &[u8; 32],
+    scheme: KeyScheme,
+) -> Result<Vec<u8>> {
+    let per_key_key = derive_scheme_key(master_key, context, salt, scheme)?;
+    decrypt_with_aad(encrypted, &per_key_key, &key_value_aad(context, scheme))
+}
+
+/// Derives a purpose-specific subkey of the data key with HKDF-SHA256.
+fn derive_subkey(master_key: &VaultKey, label: &[u8]) -> Result<VaultKey> {
+    let hkdf = Hkdf::<Sha256>::new(None, master_key.as_bytes());
+    let mut key_array = [0u8; 32];
+    hkdf.expand(&length_prefixed(&[label]), &mut key_array)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+    Ok(VaultKey(key_array))
+}
+
+/// Derives the key used to encrypt key metadata (names, URL, description).
+pub fn derive_metadata_key(master_key: &VaultKey) -> Result<VaultKey> {
+    derive_subkey(master_key, METADATA_KEY_LABEL)
+}
+
+/// Derives the key used to compute blind indexes over key names.
+pub fn derive_blind_index_key(master_key: &VaultKey) -> Result<VaultKey> {
+    derive_subkey(master_key, BLIND_INDEX_KEY_LABEL)
+}
+
+/// Derives the key that MACs audit log entries.
+pub fn derive_audit_key(master_key: &VaultKey) -> Result<VaultKey> {
+    derive_subkey(master_key, AUDIT_KEY_LABEL)
+}
+
+/// Hashes an audit log entry together with the hash of the entry before it.
+///
+/// Chaining the hashes means that editing, deleting or reordering an entry
+/// changes the hash of every entry after it.
+///
+/// # Parameters
+/// - `prev_hash`: Hash of the previous entry (zeros for the first one)
+/// - `fields`: The entry's fields, in a fixed order
+pub fn audit_entry_hash(prev_hash: &[u8], fields: &[&[u8]]) -> [u8; 32] {
+    let mut parts = vec![AUDIT_ENTRY_LABEL, prev_hash];
+    parts.extend_from_slice(fields);
+    Sha256::digest(length_prefixed(&parts)).into()
+}
+
+/// Computes the MAC of an audit entry hash from [`audit_entry_hash`].
+pub fn compute_audit_mac(audit_key: &VaultKey, entry_hash: &[u8]) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(audit_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(&length_prefixed(&[AUDIT_ENTRY_LABEL, entry_hash]));
+    mac.finalize().into_bytes().into()
+}
+
+/// Checks an audit entry MAC from [`compute_audit_mac`], in constant time.
+pub fn verify_audit_mac(audit_key: &VaultKey, entry_hash: &[u8], expected: &[u8]) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(audit_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(&length_prefixed(&[AUDIT_ENTRY_LABEL, entry_hash]));
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Computes the MAC that marks entry `seq` as the newest signed audit entry.
+///
+/// Entry MACs alone cannot show that entries were cut off the end of the
+/// log; this one names the entry the log must reach.
+pub fn compute_audit_head_mac(audit_key: &VaultKey, seq: i64, entry_hash: &[u8]) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(audit_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(&length_prefixed(&[
+        AUDIT_HEAD_LABEL,
+        &seq.to_be_bytes(),
+        entry_hash,
+    ]));
+    mac.finalize().into_bytes().into()
+}
+
+/// Checks a head MAC from [`compute_audit_head_mac`], in constant time.
+pub fn verify_audit_head_mac(
+    audit_key: &VaultKey,
+    seq: i64,
+    entry_hash: &[u8],
+    expected: &[u8],
+) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(audit_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(&length_prefixed(&[
+        AUDIT_HEAD_LABEL,
+        &seq.to_be_bytes(),
+        entry_hash,
+    ]));
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Column a blind index is computed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexedField {
+    /// `api_keys.app_name`
+    AppName,
+    /// `api_keys.key_name`
+    KeyName,
+}
+
+impl IndexedField {
+    /// Label MACed ahead of the value.
+    fn label(self) -> &'static [u8] {
+        match self {
+            IndexedField::AppName => b"app_name",
+            IndexedField::KeyName => b"key_name",
+        }
+    }
+}
+
+/// Computes a blind index for `value` in `field`.
+///
+/// Equal values give equal indexes, so exact-match lookups and UNIQUE
+/// constraints keep working, but the value cannot be recovered without
+/// the index key. The field is length-prefixed into the MAC, so an app and
+/// a key with the same name don't reveal that they match.
+///
+/// # Returns
+/// Lowercase hex HMAC-SHA256 of the field label and `value`
+pub fn blind_index(index_key: &VaultKey, field: IndexedField, value: &str) -> String {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(index_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(&length_prefixed(&[field.label(), value.as_bytes()]));
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Associated data binding encrypted metadata to its row.
+pub fn metadata_aad(key_id: &str) -> Vec<u8> {
+    length_prefixed(&[METADATA_KEY_LABEL, key_id.as_bytes()])
+}
+
+/// Mixes the contents of a key file into a PIN-derived key.
+///
+/// The file is hashed with SHA-256 and used as the HKDF salt, so the result
+/// depends on both factors and files of any size are accepted.
+///
+/// # Parameters
+/// - `pin_key`: Key derived from the PIN
+/// - `key_file`: Raw contents of the key file
+///
+/// # Errors
+/// Returns [`CryptoError::KeyDerivation`] if the key file is empty
+pub fn combine_with_key_file(pin_key: &VaultKey, key_file: &[u8]) -> Result<VaultKey> {
+    if key_file.is_empty() {
+        return Err(CryptoError::KeyDerivation(
+            "key file cannot be empty".to_string(),
+        ));
+    }
+
+    let file_digest = Sha256::digest(key_file);
+    let hkdf = Hkdf::<Sha256>::new(Some(file_digest.as_slice()), pin_key.as_bytes());
+    let mut key_array = [0u8; 32];
+    hkdf.expand(&length_prefixed(&[KEY_FILE_LABEL]), &mut key_array)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+    Ok(VaultKey(key_array))
+}
+
+/// Derives a key-encryption key from a key file alone.
+///
+/// Key files are random, so HKDF is enough; no Argon2 stretching is applied.
+///
+/// # Parameters
+/// - `key_file`: Raw contents of the key file
+/// - `salt`: Per-slot salt
+///
+/// # Errors
+/// Returns [`CryptoError::KeyDerivation`] if the key file is empty
+pub fn derive_key_from_key_file(key_file: &[u8], salt: &[u8; 32]) -> Result<VaultKey> {
+    if key_file.is_empty() {
+        return Err(CryptoError::KeyDerivation(
+            "key file cannot be empty".to_string(),
+        ));
+    }
+
+    let hkdf = Hkdf::<Sha256>::new(Some(salt.as_slice()), key_file);
+    let mut key_array = [0u8; 32];
+    hkdf.expand(&length_prefixed(&[KEY_FILE_SLOT_LABEL]), &mut key_array)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+    Ok(VaultKey(key_array))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -472,6 +1209,287 @@ mod tests {
         assert_eq!(plaintext, decrypted);
     }
 
+    #[test]
+    fn test_pin_verifier_roundtrip() {
+        let salt = generate_salt();
+        let key = derive_key_from_pin("mySecurePin123", &salt).unwrap();
+        let verifier = compute_pin_verifier(&key);
+
+        assert!(verify_pin_verifier(&key, &verifier));
+    }
+
+    #[test]
+    fn test_pin_verifier_rejects_wrong_pin() {
+        let salt = generate_salt();
+        let key = derive_key_from_pin("mySecurePin123", &salt).unwrap();
+        let wrong = derive_key_from_pin("wrongSecurePin456", &salt).unwrap();
+        let verifier = compute_pin_verifier(&key);
+
+        assert!(!verify_pin_verifier(&wrong, &verifier));
+    }
+
+    #[test]
+    fn test_pin_verifier_rejects_keys_sharing_first_byte() {
+        let key = generate_vault_key();
+        let verifier = compute_pin_verifier(&key);
+
+        // Every one of these keys would have passed the old first-byte check
+        for _ in 0..1000 {
+            let mut bytes = *generate_vault_key().as_bytes();
+            bytes[0] = key.as_bytes()[0];
+            if &bytes == key.as_bytes() {
+                continue;
+            }
+            assert!(!verify_pin_verifier(&VaultKey::from_bytes(bytes), &verifier));
+        }
+    }
+
+    #[test]
+    fn test_pin_verifier_rejects_truncated_verifier() {
+        let key = generate_vault_key();
+        let verifier = compute_pin_verifier(&key);
+
+        assert!(!verify_pin_verifier(&key, &verifier[..1]));
+        assert!(!verify_pin_verifier(&key, &[]));
+    }
+
+    #[test]
+    fn test_recovery_verifier_is_separate_from_pin_verifier() {
+        let key = generate_vault_key();
+        let verifier = compute_recovery_verifier(&key);
+
+        assert!(verify_recovery_verifier(&key, &verifier));
+        assert!(!verify_recovery_verifier(&generate_vault_key(), &verifier));
+        assert_ne!(verifier, compute_pin_verifier(&key));
+        assert_ne!(verifier, compute_data_key_verifier(&key));
+    }
+
+    #[test]
+    fn test_data_key_verifier_roundtrip() {
+        let key = generate_vault_key();
+        let verifier = compute_data_key_verifier(&key);
+
+        assert!(verify_data_key_verifier(&key, &verifier));
+        assert!(!verify_data_key_verifier(&generate_vault_key(), &verifier));
+    }
+
+    #[test]
+    fn test_wrap_unwrap_key_roundtrip() {
+        let data_key = generate_vault_key();
+        let kek = generate_vault_key();
+
+        let wrapped = wrap_key(&data_key, &kek).unwrap();
+        assert_ne!(wrapped.ciphertext[..32], data_key.as_bytes()[..]);
+
+        let unwrapped = unwrap_key(&wrapped, &kek).unwrap();
+        assert_eq!(unwrapped.as_bytes(), data_key.as_bytes());
+    }
+
+    #[test]
+    fn test_unwrap_key_wrong_kek_fails() {
+        let data_key = generate_vault_key();
+        let wrapped = wrap_key(&data_key, &generate_vault_key()).unwrap();
+
+        assert!(unwrap_key(&wrapped, &generate_vault_key()).is_err());
+    }
+
+    #[test]
+    fn test_unwrap_key_rejects_wrong_length() {
+        let kek = generate_vault_key();
+        let wrapped = encrypt(b"not a 32-byte key", &kek).unwrap();
+
+        assert!(matches!(
+            unwrap_key(&wrapped, &kek),
+            Err(CryptoError::InvalidKeyLength)
+        ));
+    }
+
+    #[test]
+    fn test_kdf_params_roundtrip() {
+        let params = KdfParams::argon2id(131072, 4, 2).unwrap();
+        let encoded = params.to_string();
+        assert_eq!(encoded, "argon2id$m=131072,t=4,p=2");
+        assert_eq!(encoded.parse::<KdfParams>().unwrap(), params);
+
+        assert_eq!(
+            KdfParams::default().to_string(),
+            "argon2id$m=65536,t=3,p=4"
+        );
+    }
+
+    #[test]
+    fn test_kdf_params_rejects_invalid() {
+        for bad in [
+            "",
+            "argon2id",
+            "scrypt$m=65536,t=3,p=4",
+            "argon2id$m=65536,t=3",
+            "argon2id$m=1024,t=3,p=4",
+            "argon2id$m=65536,t=0,p=4",
+            "argon2id$m=65536,t=3,p=99",
+            "argon2id$m=65536,t=x,p=4",
+        ] {
+            assert!(bad.parse::<KdfParams>().is_err(), "accepted {bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_derive_with_params() {
+        let salt = generate_salt();
+        let default_key = derive_key_from_pin("test-pin-123", &salt).unwrap();
+        let explicit = derive_key_from_pin_with_params("test-pin-123", &salt, &KdfParams::default())
+            .unwrap();
+        assert_eq!(default_key.as_bytes(), explicit.as_bytes());
+
+        let light = KdfParams::argon2id(KdfParams::MIN_MEMORY_KIB, 1, 1).unwrap();
+        let other = derive_key_from_pin_with_params("test-pin-123", &salt, &light).unwrap();
+        assert_ne!(default_key.as_bytes(), other.as_bytes());
+    }
+
+    #[test]
+    fn test_calibrate_kdf() {
+        let params =
+            calibrate_kdf(Duration::from_millis(50), KdfParams::MIN_MEMORY_KIB, 1).unwrap();
+
+        assert_eq!(params.memory_kib, KdfParams::MIN_MEMORY_KIB);
+        assert_eq!(params.parallelism, 1);
+        assert!((1..=KdfParams::MAX_ITERATIONS).contains(&params.iterations));
+        params.validate().unwrap();
+    }
+
+    #[test]
+    fn test_length_prefixed_is_unambiguous() {
+        assert_ne!(
+            length_prefixed(&[b"a|b", b"c"]),
+            length_prefixed(&[b"a", b"b|c"])
+        );
+        assert_ne!(length_prefixed(&[b"ab", b""]), length_prefixed(&[b"a", b"b"]));
+    }
+
+    #[test]
+    fn test_per_key_v2_bound_to_id() {
+        let master_key = generate_vault_key();
+        let salt = generate_salt();
+
+        let key1 = derive_per_key_encryption_key_v2(&master_key, "id-1", &salt).unwrap();
+        let again = derive_per_key_encryption_key_v2(&master_key, "id-1", &salt).unwrap();
+        let key2 = derive_per_key_encryption_key_v2(&master_key, "id-2", &salt).unwrap();
+
+        assert_eq!(key1.as_bytes(), again.as_bytes());
+        assert_ne!(key1.as_bytes(), key2.as_bytes());
+        assert!(derive_per_key_encryption_key_v2(&master_key, "", &salt).is_err());
+    }
+
+    #[test]
+    fn test_legacy_context_collision_fixed_by_v2() {
+        let master_key = generate_vault_key();
+        let salt = generate_salt();
+
+        // The legacy "{app}|{key}" context cannot tell these two apart
+        let legacy_a = derive_per_key_encryption_key(&master_key, "a|b", "c", &salt).unwrap();
+        let legacy_b = derive_per_key_encryption_key(&master_key, "a", "b|c", &salt).unwrap();
+        assert_eq!(legacy_a.as_bytes(), legacy_b.as_bytes());
+
+        let v2_a = derive_per_key_encryption_key_v2(&master_key, "row-a", &salt).unwrap();
+        let v2_b = derive_per_key_encryption_key_v2(&master_key, "row-b", &salt).unwrap();
+        assert_ne!(v2_a.as_bytes(), v2_b.as_bytes());
+    }
+
+    #[test]
+    fn test_api_key_value_roundtrip_per_scheme() {
+        let master_key = generate_vault_key();
+        let salt = generate_salt();
+        let context = KeyContext {
+            id: "row-1",
+            app_name: "github",
+            key_name: "token",
+        };
+
+        for scheme in [KeyScheme::LegacyArgon2, KeyScheme::HkdfV2, KeyScheme::HkdfAad] {
+            let encrypted =
+                encrypt_api_key_value(b"secret", &master_key, &context, &salt, scheme).unwrap();
+            let decrypted =
+                decrypt_api_key_value(&encrypted, &master_key, &context, &salt, scheme).unwrap();
+            assert_eq!(decrypted, b"secret");
+        }
+
+        // A ciphertext does not open under a different scheme
+        let encrypted =
+            encrypt_api_key_value(b"secret", &master_key, &context, &salt, KeyScheme::HkdfV2)
+                .unwrap();
+        assert!(decrypt_api_key_value(
+            &encrypted,
+            &master_key,
+            &context,
+            &salt,
+            KeyScheme::LegacyArgon2
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_key_scheme_column_values() {
+        assert_eq!(KeyScheme::from_i64(1), Some(KeyScheme::LegacyArgon2));
+        assert_eq!(KeyScheme::from_i64(2), Some(KeyScheme::HkdfV2));
+        assert_eq!(KeyScheme::from_i64(3), Some(KeyScheme::HkdfAad));
+        assert_eq!(KeyScheme::from_i64(99), None);
+        assert_eq!(KeyScheme::CURRENT, KeyScheme::HkdfAad);
+    }
+
+    #[test]
+    fn test_encrypt_with_aad() {
+        let key = generate_vault_key();
+
+        let encrypted = encrypt_with_aad(b"secret", &key, b"row-1").unwrap();
+        assert_eq!(decrypt_with_aad(&encrypted, &key, b"row-1").unwrap(), b"secret");
+        assert!(decrypt_with_aad(&encrypted, &key, b"row-2").is_err());
+        assert!(decrypt(&encrypted, &key).is_err());
+
+        // Empty associated data is the same as none
+        let plain = encrypt(b"secret", &key).unwrap();
+        assert_eq!(decrypt_with_aad(&plain, &key, &[]).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_key_value_aad_binds_row_identity() {
+        let master_key = generate_vault_key();
+        let salt = generate_salt();
+        let context = KeyContext {
+            id: "row-1",
+            app_name: "github",
+            key_name: "token",
+        };
+        let scheme = KeyScheme::HkdfAad;
+
+        let encrypted =
+            encrypt_api_key_value(b"secret", &master_key, &context, &salt, scheme).unwrap();
+
+        let renamed_app = KeyContext {
+            app_name: "gitlab",
+            ..context
+        };
+        let renamed_key = KeyContext {
+            key_name: "other",
+            ..context
+        };
+        for changed in [renamed_app, renamed_key] {
+            assert!(
+                decrypt_api_key_value(&encrypted, &master_key, &changed, &salt, scheme).is_err()
+            );
+        }
+
+        // Separators inside names cannot be used to forge a matching context
+        let shifted = KeyContext {
+            id: "row-1",
+            app_name: "git",
+            key_name: "hubtoken",
+        };
+        assert_ne!(
+            key_value_aad(&context, scheme),
+            key_value_aad(&shifted, scheme)
+        );
+    }
+
     #[test]
     fn test_derive_per_key_different_keys() {
         let master_salt = generate_salt();
@@ -559,6 +1577,116 @@ mod tests {
 
         assert_eq!(plaintext.to_vec(), decrypted);
     }
+
+    #[test]
+    fn test_blind_index() {
+        let master_key = generate_vault_key();
+        let index_key = derive_blind_index_key(&master_key).unwrap();
+
+        let app = IndexedField::AppName;
+        assert_eq!(
+            blind_index(&index_key, app, "github"),
+            blind_index(&index_key, app, "github")
+        );
+        assert_ne!(
+            blind_index(&index_key, app, "github"),
+            blind_index(&index_key, app, "gitlab")
+        );
+        assert_eq!(blind_index(&index_key, app, "github").len(), 64);
+
+        // The same name in the app and key columns gives unrelated indexes
+        assert_ne!(
+            blind_index(&index_key, app, "github"),
+            blind_index(&index_key, IndexedField::KeyName, "github")
+        );
+
+        // A different vault gives unrelated indexes
+        let other_key = derive_blind_index_key(&generate_vault_key()).unwrap();
+        assert_ne!(
+            blind_index(&index_key, app, "github"),
+            blind_index(&other_key, app, "github")
+        );
+    }
+
+    #[test]
+    fn test_subkeys_are_independent() {
+        let master_key = generate_vault_key();
+        let metadata_key = derive_metadata_key(&master_key).unwrap();
+        let index_key = derive_blind_index_key(&master_key).unwrap();
+
+        assert_ne!(metadata_key.as_bytes(), index_key.as_bytes());
+        assert_ne!(metadata_key.as_bytes(), master_key.as_bytes());
+        assert_eq!(
+            derive_metadata_key(&master_key).unwrap().as_bytes(),
+            metadata_key.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_audit_hash_chain_and_macs() {
+        let audit_key = derive_audit_key(&generate_vault_key()).unwrap();
+        let first = audit_entry_hash(&[0u8; 32], &[b"1", b"get"]);
+        let second = audit_entry_hash(&first, &[b"2", b"get"]);
+
+        // The previous hash is part of the entry hash
+        assert_ne!(audit_entry_hash(&[1u8; 32], &[b"2", b"get"]), second);
+        assert_ne!(audit_entry_hash(&first, &[b"2", b"copy"]), second);
+
+        let mac = compute_audit_mac(&audit_key, &second);
+        assert!(verify_audit_mac(&audit_key, &second, &mac));
+        assert!(!verify_audit_mac(&audit_key, &first, &mac));
+        let other_key = derive_audit_key(&generate_vault_key()).unwrap();
+        assert!(!verify_audit_mac(&other_key, &second, &mac));
+
+        // Entry and head MACs are not interchangeable
+        let head = compute_audit_head_mac(&audit_key, 2, &second);
+        assert_ne!(head, mac);
+        assert!(verify_audit_head_mac(&audit_key, 2, &second, &head));
+        assert!(!verify_audit_head_mac(&audit_key, 1, &second, &head));
+    }
+
+    #[test]
+    fn test_combine_with_key_file() {
+        let pin_key = generate_vault_key();
+        let combined = combine_with_key_file(&pin_key, b"key file contents").unwrap();
+
+        // Deterministic, and different from the PIN key alone
+        assert_eq!(
+            combine_with_key_file(&pin_key, b"key file contents")
+                .unwrap()
+                .as_bytes(),
+            combined.as_bytes()
+        );
+        assert_ne!(combined.as_bytes(), pin_key.as_bytes());
+
+        // A different file or PIN key gives a different key
+        let other_file = combine_with_key_file(&pin_key, b"other contents").unwrap();
+        assert_ne!(other_file.as_bytes(), combined.as_bytes());
+        let other_pin = combine_with_key_file(&generate_vault_key(), b"key file contents").unwrap();
+        assert_ne!(other_pin.as_bytes(), combined.as_bytes());
+
+        assert!(combine_with_key_file(&pin_key, b"").is_err());
+    }
+
+    #[test]
+    fn test_derive_key_from_key_file() {
+        let salt = generate_salt();
+        let key = derive_key_from_key_file(b"key file contents", &salt).unwrap();
+
+        assert_eq!(
+            derive_key_from_key_file(b"key file contents", &salt)
+                .unwrap()
+                .as_bytes(),
+            key.as_bytes()
+        );
+        assert_ne!(
+            derive_key_from_key_file(b"key file contents", &generate_salt())
+                .unwrap()
+                .as_bytes(),
+            key.as_bytes()
+        );
+        assert!(derive_key_from_key_file(b"", &salt).is_err());
+    }
 }
 
 /// Property-based tests using proptest for formal verification
@@ -654,7 +1782,7 @@ mod proptests {
             salt1 in salt_strategy(),
             salt2 in salt_strategy()
         ) {
-            prop_assume!(plaintext.len() > 0);
+            prop_assume!(!plaintext.is_empty());
             prop_assume!(pin1 != pin2 || salt1 != salt2);
 
             let key1 = derive_key_from_pin(&pin1, &salt1)?;
@@ -768,7 +1896,7 @@ mod proptests {
             tamper_pos in 0usize..1000usize,
             tamper_byte in any::<u8>()
         ) {
-            prop_assume!(plaintext.len() > 0);
+            prop_assume!(!plaintext.is_empty());
 
             let key = derive_key_from_pin(&pin, &salt)?;
             let encrypted = encrypt(&plaintext, &key)?;
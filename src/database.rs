@@ -10,30 +10,102 @@
 //!
 //! Default location: `~/.vult/vault.db`
 //!
+//! # Snapshots
+//!
+//! [`VaultDb::create_backup`] copies the database with `VACUUM INTO` to
+//! `backups/vault_backup_<timestamp>.db` next to the database file, keeping
+//! the [`BACKUPS_TO_KEEP`] newest. It runs before migrations and, through the
+//! services, before PIN changes and bulk operations.
+//!
 //! # Schema Versioning
 //!
 //! The database includes a `schema_version` table that tracks migrations.
-//! Migrations are applied automatically on startup. The application will
-//! refuse to open a database with a newer schema version.
+//! Migrations are declared in order in `MIGRATIONS` and applied
+//! automatically by [`VaultDb::new`], each in its own transaction;
+//! [`VaultDb::migrate`] with `dry_run` lists the pending ones. The
+//! application will refuse to open a database with a newer schema version.
 //!
-//! # Current Schema (v2)
+//! # Current Schema (v9)
 //!
 //! ```sql
 //! CREATE TABLE api_keys (
 //!     id TEXT PRIMARY KEY,
-//!     app_name TEXT,
+//!     app_name TEXT,  -- NULL for keys without an app
 //!     key_name TEXT NOT NULL,
-//!     encrypted_key BLOB NOT NULL,
+//!     api_url TEXT,
+//!     description TEXT,
+//!     encrypted_key_value BLOB NOT NULL,
 //!     nonce BLOB NOT NULL,
 //!     key_salt BLOB NOT NULL,  -- Per-key encryption salt
-//!     description TEXT,
-//!     expires_at TEXT,
-//!     created_at TEXT NOT NULL,
-//!     updated_at TEXT NOT NULL,
-//!     UNIQUE(app_name, key_name)
+//!     key_scheme INTEGER NOT NULL DEFAULT 1,  -- Per-key derivation scheme
+//!     metadata_ciphertext BLOB,  -- Encrypted metadata (encrypted-metadata mode)
+//!     metadata_nonce BLOB,
+//!     version INTEGER NOT NULL DEFAULT 1,  -- Version of the current value
+//!     history_limit INTEGER,  -- Previous values kept; NULL for the default
+//!     expires_at INTEGER,  -- NULL for keys that never expire
+//!     rotate_every INTEGER,  -- Seconds between value changes; NULL for no reminder
+//!     rotated_at INTEGER,  -- When the current value was set
+//!     created_at INTEGER NOT NULL,
+//!     updated_at INTEGER NOT NULL
+//! );
+//!
+//! -- A key name is unique within its app; NULL and '' both mean no app
+//! CREATE UNIQUE INDEX idx_api_keys_app_key
+//!     ON api_keys (COALESCE(app_name, ''), key_name);
+//!
+//! -- Previous values of each key
+//! CREATE TABLE key_versions (
+//!     key_id TEXT NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+//!     version INTEGER NOT NULL,
+//!     encrypted_key_value BLOB NOT NULL,
+//!     nonce BLOB NOT NULL,
+//!     key_salt BLOB NOT NULL,
+//!     key_scheme INTEGER NOT NULL,
+//!     reason TEXT,  -- Reason given for the change that replaced it
+//!     replaced_at INTEGER NOT NULL,
+//!     PRIMARY KEY (key_id, version)
+//! );
+//!
+//! -- Append-only, hash-chained record of vault access
+//! CREATE TABLE audit_log (
+//!     seq INTEGER PRIMARY KEY,
+//!     at INTEGER NOT NULL,
+//!     event TEXT NOT NULL,
+//!     actor TEXT,  -- OS user that ran the command
+//!     key_id TEXT,
+//!     key_name TEXT,  -- app/key; NULL in encrypted-metadata mode
+//!     detail TEXT,
+//!     prev_hash BLOB NOT NULL,
+//!     hash BLOB NOT NULL,
+//!     mac BLOB  -- NULL for entries written while locked
+//! );
+//!
+//! -- MAC naming the newest signed audit entry
+//! CREATE TABLE audit_head (
+//!     id INTEGER PRIMARY KEY CHECK (id = 1),
+//!     seq INTEGER NOT NULL,
+//!     mac BLOB NOT NULL
+//! );
+//!
+//! -- Extra credentials, each wrapping its own copy of the data key
+//! CREATE TABLE key_slots (
+//!     id INTEGER PRIMARY KEY AUTOINCREMENT,
+//!     label TEXT NOT NULL UNIQUE,
+//!     kind TEXT NOT NULL,  -- pin, passphrase or keyfile
+//!     salt BLOB NOT NULL,
+//!     kdf_params TEXT,  -- NULL for key files
+//!     verifier TEXT NOT NULL,
+//!     wrapped_dek BLOB NOT NULL,
+//!     dek_nonce BLOB NOT NULL,
+//!     created_at INTEGER NOT NULL
 //! );
 //! ```
 //!
+//! The single-row `vault_config` table holding the PIN salt, the wrapped
+//! data key and the lockout, KDF and recovery settings is created by
+//! `AuthService::init_vault` from `VAULT_CONFIG_TABLE`; migrations bring an
+//! existing one up to date.
+//!
 //! # Example
 //!
 //! ```rust,ignore
@@ -44,14 +116,16 @@
 //! ```
 
 use crate::crypto::{
-    derive_per_key_encryption_key, generate_salt, CryptoError, EncryptedData, VaultKey,
+    decrypt_api_key_value, encrypt_api_key_value, generate_salt, CryptoError, EncryptedData,
+    KeyContext, KeyScheme, VaultKey,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{
-    sqlite::{SqlitePool, SqliteRow},
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqliteRow},
     FromRow, Pool, Row, Sqlite,
 };
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -78,6 +152,9 @@ pub enum DbError {
 
     #[error("Backup failed: {0}")]
     BackupFailed(String),
+
+    #[error("Migration to schema version {version} failed and was rolled back: {reason}")]
+    MigrationFailed { version: i64, reason: String },
 }
 
 /// Result type for database operations
@@ -134,6 +211,7 @@ pub struct EncryptedApiKeyRow {
     pub encrypted_key_value: Vec<u8>,
     pub nonce: Vec<u8>,
     pub key_salt: Vec<u8>, // Per-key salt for deriving encryption key
+    pub key_scheme: i64,   // KeyScheme used to derive the per-key key
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -149,252 +227,592 @@ impl FromRow<'_, SqliteRow> for EncryptedApiKeyRow {
             encrypted_key_value: row.try_get("encrypted_key_value")?,
             nonce: row.try_get("nonce")?,
             key_salt: row.try_get("key_salt")?,
+            key_scheme: row.try_get("key_scheme")?,
             created_at: row.try_get("created_at")?,
             updated_at: row.try_get("updated_at")?,
         })
     }
 }
 
+/// A snapshot in the backup directory
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    /// Snapshot timestamp in UTC, e.g. `20261017_153012.123`
+    pub id: String,
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    /// File size in bytes
+    pub size: u64,
+}
+
+/// A schema migration, as listed in a [`MigrationReport`]
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationInfo {
+    /// Schema version the migration brings the database to
+    pub version: i64,
+    pub description: &'static str,
+}
+
+/// Outcome of [`VaultDb::migrate`]
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    /// Schema version before migrating
+    pub from_version: i64,
+    /// Schema version after migrating
+    pub to_version: i64,
+    /// Migrations applied, or that a dry run would apply, oldest first
+    pub migrations: Vec<MigrationInfo>,
+    /// Snapshot taken before the first migration
+    pub snapshot: Option<BackupInfo>,
+    pub dry_run: bool,
+}
+
+/// An entry of [`MIGRATIONS`]
+struct Migration {
+    version: i64,
+    description: &'static str,
+    steps: &'static [MigrationStep],
+}
+
+impl Migration {
+    fn info(&self) -> MigrationInfo {
+        MigrationInfo {
+            version: self.version,
+            description: self.description,
+        }
+    }
+}
+
+/// One change made by a [`Migration`]
+enum MigrationStep {
+    /// Runs a statement; it should be idempotent (`IF NOT EXISTS`)
+    Sql(&'static str),
+    /// Adds a column to `api_keys` unless it is already there
+    AddColumn {
+        column: &'static str,
+        definition: &'static str,
+    },
+    /// Adds a column to `vault_config` unless it is already there. Skipped
+    /// before the vault is initialized, since [`VAULT_CONFIG_TABLE`] creates
+    /// every column.
+    AddConfigColumn {
+        column: &'static str,
+        definition: &'static str,
+    },
+    /// Rebuilds a v1 `api_keys` table with a random salt per key and an
+    /// optional app name
+    AddKeySalts,
+    /// Rebuilds `api_keys` without table-level UNIQUE constraints, which
+    /// SQLite cannot drop in place
+    DropUniqueConstraints,
+}
+
+impl MigrationStep {
+    async fn apply(&self, conn: &mut SqliteConnection) -> Result<()> {
+        match self {
+            Self::Sql(sql) => {
+                sqlx::query(sql).execute(&mut *conn).await?;
+            }
+            Self::AddColumn { column, definition } => {
+                if !api_keys_has_column(conn, column).await? {
+                    sqlx::query(&format!(
+                        "ALTER TABLE api_keys ADD COLUMN {} {}",
+                        column, definition
+                    ))
+                    .execute(&mut *conn)
+                    .await?;
+                }
+            }
+            Self::AddConfigColumn { column, definition } => {
+                let columns = table_columns(conn, "vault_config").await?;
+                if !columns.is_empty() && !columns.iter().any(|c| c == column) {
+                    sqlx::query(&format!(
+                        "ALTER TABLE vault_config ADD COLUMN {} {}",
+                        column, definition
+                    ))
+                    .execute(&mut *conn)
+                    .await?;
+                }
+            }
+            Self::AddKeySalts => {
+                if !api_keys_has_column(conn, "key_salt").await? {
+                    add_key_salts(conn).await?;
+                }
+            }
+            Self::DropUniqueConstraints => {
+                if api_keys_has_unique_constraint(conn).await? {
+                    rebuild_api_keys(conn).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// The vault database
 pub struct VaultDb {
     pub(crate) pool: Pool<Sqlite>,
 }
 
+/// Schema migrations, oldest first. A database at version N runs every
+/// migration above N; version 1 is the original schema.
+///
+/// Steps check the live schema before changing it, so a migration that was
+/// partly applied by an older build still completes.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "Per-key salts and optional app names",
+        steps: &[MigrationStep::AddKeySalts],
+    },
+    Migration {
+        version: 3,
+        description: "Per-key derivation scheme; existing keys keep the legacy scheme until read",
+        steps: &[MigrationStep::AddColumn {
+            column: "key_scheme",
+            definition: "INTEGER NOT NULL DEFAULT 1",
+        }],
+    },
+    Migration {
+        version: 4,
+        description: "Columns for the encrypted-metadata mode",
+        steps: &[
+            MigrationStep::AddColumn {
+                column: "metadata_ciphertext",
+                definition: "BLOB",
+            },
+            MigrationStep::AddColumn {
+                column: "metadata_nonce",
+                definition: "BLOB",
+            },
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "Key names unique per app instead of vault-wide",
+        steps: &[
+            MigrationStep::DropUniqueConstraints,
+            MigrationStep::Sql(API_KEYS_NAME_INDEX),
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "Version history of key values",
+        steps: &[
+            MigrationStep::AddColumn {
+                column: "version",
+                definition: "INTEGER NOT NULL DEFAULT 1",
+            },
+            MigrationStep::AddColumn {
+                column: "history_limit",
+                definition: "INTEGER",
+            },
+            MigrationStep::Sql(KEY_VERSIONS_TABLE),
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "Tamper-evident audit log",
+        steps: &[
+            MigrationStep::Sql(AUDIT_LOG_TABLE),
+            MigrationStep::Sql(AUDIT_LOG_NO_UPDATE),
+            MigrationStep::Sql(AUDIT_LOG_NO_DELETE),
+            MigrationStep::Sql(AUDIT_HEAD_TABLE),
+        ],
+    },
+    Migration {
+        version: 8,
+        description: "Expiry dates and rotation reminders",
+        steps: &[
+            MigrationStep::AddColumn {
+                column: "expires_at",
+                definition: "INTEGER",
+            },
+            MigrationStep::AddColumn {
+                column: "rotate_every",
+                definition: "INTEGER",
+            },
+            MigrationStep::AddColumn {
+                column: "rotated_at",
+                definition: "INTEGER",
+            },
+            MigrationStep::Sql(BACKFILL_ROTATED_AT),
+        ],
+    },
+    Migration {
+        version: 9,
+        description: "Vault settings for lockout, KDF, key files and recovery; key slots",
+        steps: &[
+            MigrationStep::AddConfigColumn {
+                column: "wrapped_dek",
+                definition: "BLOB",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "dek_nonce",
+                definition: "BLOB",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "failed_attempts",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "last_failed_at",
+                definition: "INTEGER",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "locked_until",
+                definition: "INTEGER",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "kdf_params",
+                definition: "TEXT",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "kdf_target",
+                definition: "TEXT",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "encrypted_metadata",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "keyfile_required",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "recovery_set",
+                definition: "TEXT",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "recovery_verifier",
+                definition: "TEXT",
+            },
+            MigrationStep::AddConfigColumn {
+                column: "dek_verifier",
+                definition: "TEXT",
+            },
+            MigrationStep::Sql(KEY_SLOTS_TABLE),
+        ],
+    },
+];
+
 /// Database schema version
-const SCHEMA_VERSION: i64 = 2;
+const SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+const SCHEMA_VERSION_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL, migrated_at INTEGER NOT NULL)";
+
+/// Columns of the current `api_keys` table
+const API_KEYS_COLUMNS: &str = r#"
+    id TEXT PRIMARY KEY,
+    app_name TEXT,
+    key_name TEXT NOT NULL,
+    api_url TEXT,
+    description TEXT,
+    encrypted_key_value BLOB NOT NULL,
+    nonce BLOB NOT NULL,
+    key_salt BLOB NOT NULL,
+    key_scheme INTEGER NOT NULL DEFAULT 1,
+    metadata_ciphertext BLOB,
+    metadata_nonce BLOB,
+    version INTEGER NOT NULL DEFAULT 1,
+    history_limit INTEGER,
+    expires_at INTEGER,
+    rotate_every INTEGER,
+    rotated_at INTEGER,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+"#;
+
+/// Columns of `api_keys` as of v5, which rebuilt the table without its
+/// UNIQUE constraints. Later versions add theirs with ALTER TABLE.
+const API_KEYS_V5_COLUMNS: &str = r#"
+    id TEXT PRIMARY KEY,
+    app_name TEXT,
+    key_name TEXT NOT NULL,
+    api_url TEXT,
+    description TEXT,
+    encrypted_key_value BLOB NOT NULL,
+    nonce BLOB NOT NULL,
+    key_salt BLOB NOT NULL,
+    key_scheme INTEGER NOT NULL DEFAULT 1,
+    metadata_ciphertext BLOB,
+    metadata_nonce BLOB,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+"#;
+
+/// Makes a key name unique within its app. The app name is normalized so
+/// that keys without one (NULL, or '' from older builds) clash too, as
+/// SQLite treats every NULL as distinct.
+const API_KEYS_NAME_INDEX: &str = "CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_app_key ON api_keys (COALESCE(app_name, ''), key_name)";
+
+/// Previous values of each key, deleted along with the key. A migration
+/// that rebuilds `api_keys` must turn foreign keys off first, or dropping
+/// the old table empties this one too.
+const KEY_VERSIONS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS key_versions (
+        key_id TEXT NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+        version INTEGER NOT NULL,
+        encrypted_key_value BLOB NOT NULL,
+        nonce BLOB NOT NULL,
+        key_salt BLOB NOT NULL,
+        key_scheme INTEGER NOT NULL,
+        reason TEXT,
+        replaced_at INTEGER NOT NULL,
+        PRIMARY KEY (key_id, version)
+    )
+"#;
+
+/// Sets when each key's current value was set: when it replaced the
+/// newest archived value, or when the key was created.
+const BACKFILL_ROTATED_AT: &str = r#"
+    UPDATE api_keys
+    SET rotated_at = COALESCE(
+        (SELECT MAX(replaced_at) FROM key_versions WHERE key_id = api_keys.id),
+        created_at
+    )
+    WHERE rotated_at IS NULL
+"#;
+
+/// Audit entries, written by `AuditService`. Each entry's hash covers the
+/// previous one, and the MAC is keyed from the data key.
+const AUDIT_LOG_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS audit_log (
+        seq INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        actor TEXT,
+        key_id TEXT,
+        key_name TEXT,
+        detail TEXT,
+        prev_hash BLOB NOT NULL,
+        hash BLOB NOT NULL,
+        mac BLOB
+    )
+"#;
+
+/// Refuses edits to audit entries. The hash chain is what detects them;
+/// this only keeps honest code from making them.
+const AUDIT_LOG_NO_UPDATE: &str = r#"
+    CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'audit_log is append-only');
+    END
+"#;
+
+/// Refuses deleting audit entries, see [`AUDIT_LOG_NO_UPDATE`].
+const AUDIT_LOG_NO_DELETE: &str = r#"
+    CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'audit_log is append-only');
+    END
+"#;
+
+/// The newest signed audit entry, so that entries cut off the end of the
+/// log are detected.
+const AUDIT_HEAD_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS audit_head (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        seq INTEGER NOT NULL,
+        mac BLOB NOT NULL
+    )
+"#;
+
+/// Vault settings, a single row written by `AuthService::init_vault`.
+/// Its existence is what marks a vault as initialized, so it is not part
+/// of the schema of a new database.
+pub(crate) const VAULT_CONFIG_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS vault_config (
+        id INTEGER PRIMARY KEY,
+        salt BLOB NOT NULL,
+        pin_hash TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        wrapped_dek BLOB,
+        dek_nonce BLOB,
+        failed_attempts INTEGER NOT NULL DEFAULT 0,
+        last_failed_at INTEGER,
+        locked_until INTEGER,
+        kdf_params TEXT,
+        kdf_target TEXT,
+        encrypted_metadata INTEGER NOT NULL DEFAULT 0,
+        keyfile_required INTEGER NOT NULL DEFAULT 0,
+        recovery_set TEXT,
+        recovery_verifier TEXT,
+        dek_verifier TEXT
+    )
+"#;
+
+/// Extra credentials, each wrapping its own copy of the data key
+const KEY_SLOTS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS key_slots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        label TEXT NOT NULL UNIQUE,
+        kind TEXT NOT NULL,
+        salt BLOB NOT NULL,
+        kdf_params TEXT,
+        verifier TEXT NOT NULL,
+        wrapped_dek BLOB NOT NULL,
+        dek_nonce BLOB NOT NULL,
+        created_at INTEGER NOT NULL
+    )
+"#;
+
+/// Number of automatic snapshots kept in the backup directory
+pub const BACKUPS_TO_KEEP: usize = 5;
+
+/// Directory next to the database file that holds snapshots
+const BACKUP_DIR_NAME: &str = "backups";
+
+/// Snapshot file names are `vault_backup_<id>.db`, with the id formatted
+/// as below
+const BACKUP_PREFIX: &str = "vault_backup_";
+const BACKUP_ID_FORMAT: &str = "%Y%m%d_%H%M%S%.3f";
 
 impl VaultDb {
-    /// Creates a new vault database connection pool
+    /// Opens the database and migrates it to the current schema version
     pub async fn new(database_path: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(database_path).await?;
-        let db = Self { pool };
-        db.init_schema().await?;
-        db.migrate().await?;
+        let db = Self::open(database_path).await?;
+        db.migrate(false).await?;
         Ok(db)
     }
 
-    /// Initializes the database schema
-    async fn init_schema(&self) -> Result<()> {
-        // Create schema version tracking table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS schema_version (
-                version INTEGER NOT NULL,
-                migrated_at INTEGER NOT NULL
-            );
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Opens the database without creating or migrating its schema
+    ///
+    /// Call [`Self::migrate`] before using it, or with `dry_run` to see
+    /// which migrations are pending.
+    pub async fn open(database_path: &str) -> Result<Self> {
+        let pool = SqlitePool::connect(database_path).await?;
+        Ok(Self { pool })
+    }
 
-        // Create the api_keys table with the current schema
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS api_keys (
-                id TEXT PRIMARY KEY,
-                app_name TEXT,
-                key_name TEXT NOT NULL,
-                api_url TEXT,
-                description TEXT,
-                encrypted_key_value BLOB NOT NULL,
-                nonce BLOB NOT NULL,
-                key_salt BLOB NOT NULL,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL,
-                UNIQUE(key_name)
-            );
-            "#,
-        )
-        .execute(&self.pool)
+    /// Creates the current schema in a new database
+    async fn init_schema(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(SCHEMA_VERSION_TABLE).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS api_keys ({})",
+            API_KEYS_COLUMNS
+        ))
+        .execute(&mut *tx)
         .await?;
-
+        sqlx::query(API_KEYS_NAME_INDEX).execute(&mut *tx).await?;
+        sqlx::query(KEY_VERSIONS_TABLE).execute(&mut *tx).await?;
+        for sql in [
+            AUDIT_LOG_TABLE,
+            AUDIT_LOG_NO_UPDATE,
+            AUDIT_LOG_NO_DELETE,
+            AUDIT_HEAD_TABLE,
+            KEY_SLOTS_TABLE,
+        ] {
+            sqlx::query(sql).execute(&mut *tx).await?;
+        }
+        record_schema_version(&mut tx, SCHEMA_VERSION).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    /// Migrates the database schema if needed
-    async fn migrate(&self) -> Result<()> {
-        // Get current schema version
-        let version =
-            sqlx::query("SELECT version FROM schema_version ORDER BY version DESC LIMIT 1")
-                .fetch_optional(&self.pool)
-                .await;
-
-        let current_version = match version {
-            Ok(Some(row)) => {
-                let v: i64 = row.try_get("version")?;
-                v
+    /// Migrates the database to the current schema version
+    ///
+    /// The database is snapshotted first, then each pending migration of
+    /// `MIGRATIONS` runs in its own transaction together with the row
+    /// recording its version. A failed migration is rolled back, leaving
+    /// the database at the last version that succeeded. A new database gets
+    /// the current schema directly.
+    ///
+    /// With `dry_run` nothing is written; the report lists the migrations
+    /// that would run.
+    ///
+    /// # Errors
+    ///
+    /// - [`DbError::IncompatibleVersion`] if the database is newer than
+    ///   this build
+    /// - [`DbError::MigrationFailed`] naming the migration that failed
+    pub async fn migrate(&self, dry_run: bool) -> Result<MigrationReport> {
+        let Some(from_version) = self.stored_schema_version().await? else {
+            if !dry_run {
+                self.init_schema().await?;
             }
-            _ => 1, // Default to version 1 if no version table exists
+            return Ok(MigrationReport {
+                from_version: SCHEMA_VERSION,
+                to_version: SCHEMA_VERSION,
+                migrations: Vec::new(),
+                snapshot: None,
+                dry_run,
+            });
         };
 
         // Check if database is newer than application
-        if current_version > SCHEMA_VERSION {
+        if from_version > SCHEMA_VERSION {
             return Err(DbError::IncompatibleVersion {
-                db_version: current_version,
+                db_version: from_version,
                 app_version: SCHEMA_VERSION,
             });
         }
 
-        if current_version < SCHEMA_VERSION {
-            eprintln!(
-                "Migrating database from version {} to {}",
-                current_version, SCHEMA_VERSION
-            );
-            // Create backup before migration
-            self.backup().await?;
-            self.run_migration(current_version).await?;
+        let pending: Vec<&Migration> = MIGRATIONS
+            .iter()
+            .filter(|migration| migration.version > from_version)
+            .collect();
+        let mut report = MigrationReport {
+            from_version,
+            to_version: SCHEMA_VERSION,
+            migrations: pending.iter().map(|m| m.info()).collect(),
+            snapshot: None,
+            dry_run,
+        };
+        if dry_run {
+            return Ok(report);
+        }
+
+        if !pending.is_empty() {
+            report.snapshot = self.backup().await?;
+            for migration in pending {
+                self.run_migration(migration)
+                    .await
+                    .map_err(|e| DbError::MigrationFailed {
+                        version: migration.version,
+                        reason: e.to_string(),
+                    })?;
+            }
         }
 
         // Clean up any orphaned tables
         self.cleanup_orphaned_tables().await?;
 
-        Ok(())
+        Ok(report)
     }
 
-    /// Runs a specific migration
-    async fn run_migration(&self, from_version: i64) -> Result<()> {
-        match from_version {
-            1 => {
-                // Migration from version 1 to version 2:
-                // - Add key_salt column
-                // - Make app_name optional (NULL allowed)
-                // - Change unique constraint from (app_name, key_name) to just key_name
-                // - Re-encrypt all existing keys with per-key encryption
-
-                // Check if api_keys table exists
-                let _table_exists = sqlx::query(
-                    "SELECT name FROM sqlite_master WHERE type='table' AND name='api_keys'",
-                )
-                .fetch_optional(&self.pool)
-                .await?;
-
-                // Check if the api_keys table exists and has data
-                let table_check = sqlx::query(
-                    "SELECT COUNT(*) as count FROM sqlite_master WHERE type='table' AND name='api_keys'"
-                )
-                .fetch_one(&self.pool)
-                .await;
-
-                if let Ok(row) = table_check {
-                    let count: i64 = row.try_get("count").unwrap_or(0);
-                    if count > 0 {
-                        // Table exists, check if we need to migrate
-                        let columns = sqlx::query("PRAGMA table_info(api_keys)")
-                            .fetch_all(&self.pool)
-                            .await?;
-
-                        let has_key_salt = columns.iter().any(|row| {
-                            let name: Option<String> = row.try_get("name").ok();
-                            name.as_deref() == Some("key_salt")
-                        });
-
-                        if !has_key_salt {
-                            // Need to migrate from version 1 to 2
-                            self.migrate_v1_to_v2().await?;
-                        }
-                    }
-                }
-
-                // Update schema version
-                sqlx::query("INSERT INTO schema_version (version, migrated_at) VALUES (?1, ?2)")
-                    .bind(SCHEMA_VERSION)
-                    .bind(chrono::Utc::now().timestamp())
-                    .execute(&self.pool)
-                    .await?;
-
-                eprintln!("Migration completed successfully");
-            }
-            _ => {
-                eprintln!("Unknown migration from version {}", from_version);
-            }
+    /// Runs one migration and records its version, in one transaction
+    async fn run_migration(&self, migration: &Migration) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        // Version 1 databases have no version table
+        sqlx::query(SCHEMA_VERSION_TABLE).execute(&mut *tx).await?;
+        for step in migration.steps {
+            step.apply(&mut tx).await?;
         }
-
+        record_schema_version(&mut tx, migration.version).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    /// Migrates from schema version 1 to 2
-    async fn migrate_v1_to_v2(&self) -> Result<()> {
-        eprintln!("Starting migration v1 -> v2...");
-
-        // Create a new table with the updated schema
-        sqlx::query(
-            r#"
-            CREATE TABLE api_keys_v2 (
-                id TEXT PRIMARY KEY,
-                app_name TEXT,
-                key_name TEXT NOT NULL,
-                api_url TEXT,
-                description TEXT,
-                encrypted_key_value BLOB NOT NULL,
-                nonce BLOB NOT NULL,
-                key_salt BLOB NOT NULL,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL,
-                UNIQUE(key_name)
-            );
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        // Fetch all existing keys from v1 table
-        let rows = sqlx::query(
-            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, created_at, updated_at FROM api_keys"
+    /// Reads the schema version, or `None` for a new database
+    ///
+    /// Version 1 databases predate the `schema_version` table.
+    async fn stored_schema_version(&self) -> Result<Option<i64>> {
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name IN ('api_keys', 'schema_version')",
         )
         .fetch_all(&self.pool)
         .await?;
 
-        eprintln!("Found {} keys to migrate", rows.len());
-
-        // Migrate each key - re-encrypt with per-key salt
-        for row in rows {
-            let id: String = row.try_get("id")?;
-            let app_name: String = row.try_get("app_name")?;
-            let key_name: String = row.try_get("key_name")?;
-            let api_url: Option<String> = row.try_get("api_url")?;
-            let description: Option<String> = row.try_get("description")?;
-            let encrypted_value: Vec<u8> = row.try_get("encrypted_key_value")?;
-            let nonce: Vec<u8> = row.try_get("nonce")?;
-            let created_at: i64 = row.try_get("created_at")?;
-            let updated_at: i64 = row.try_get("updated_at")?;
-
-            // Generate new per-key salt
-            let key_salt = generate_salt();
-
-            // Insert into v2 table with default salt (keys will be re-encrypted on next access)
-            sqlx::query(
-                r#"
-                INSERT INTO api_keys_v2 (id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
-                "#
-            )
-            .bind(&id)
-            .bind(&app_name)
-            .bind(&key_name)
-            .bind(&api_url)
-            .bind(&description)
-            .bind(&encrypted_value)
-            .bind(&nonce)
-            .bind(&key_salt[..])
-            .bind(created_at)
-            .bind(updated_at)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| {
-                eprintln!("Failed to migrate key {}: {}", id, e);
-                e
-            })?;
+        if !tables.iter().any(|t| t == "api_keys") {
+            return Ok(None);
         }
-
-        // Drop old table and rename new one
-        sqlx::query("DROP TABLE api_keys")
-            .execute(&self.pool)
-            .await?;
-        sqlx::query("ALTER TABLE api_keys_v2 RENAME TO api_keys")
-            .execute(&self.pool)
-            .await?;
-
-        Ok(())
+        if !tables.iter().any(|t| t == "schema_version") {
+            return Ok(Some(1));
+        }
+        self.get_schema_version().await.map(Some)
     }
 
     /// Creates a new API key with encrypted value
@@ -421,26 +839,25 @@ impl VaultDb {
         // Generate a unique salt for this specific key
         let key_salt = generate_salt();
 
-        // Use empty string as default app_name for encryption context
-        let app_name_for_encryption = input.app_name.as_deref().unwrap_or("");
-
-        // Derive per-key encryption key from master key + key context
-        let per_key_key = derive_per_key_encryption_key(
+        // Encrypt the key value with a per-key key bound to the row id
+        let context = KeyContext {
+            id: &id,
+            app_name: input.app_name.as_deref().unwrap_or(""),
+            key_name: &input.key_name,
+        };
+        let encrypted = encrypt_api_key_value(
+            input.key_value.as_bytes(),
             master_key,
-            app_name_for_encryption,
-            &input.key_name,
+            &context,
             &key_salt,
+            KeyScheme::CURRENT,
         )?;
 
-        // Encrypt the key value with the per-key encryption key
-        let plaintext = input.key_value.as_bytes();
-        let encrypted = crate::crypto::encrypt(plaintext, &per_key_key)?;
-
         // Insert into database
         sqlx::query(
             r#"
-            INSERT INTO api_keys (id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            INSERT INTO api_keys (id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
             "#,
         )
         .bind(&id)
@@ -451,6 +868,7 @@ impl VaultDb {
         .bind(&encrypted.ciphertext)
         .bind(&encrypted.nonce)
         .bind(&key_salt[..])
+        .bind(KeyScheme::CURRENT.as_i64())
         .bind(now.timestamp())
         .bind(now.timestamp())
         .execute(&self.pool)
@@ -483,7 +901,7 @@ impl VaultDb {
     /// Gets an API key by ID with decrypted value
     pub async fn get_api_key(&self, id: &str, master_key: &VaultKey) -> Result<ApiKeyWithSecret> {
         let row = sqlx::query_as::<_, EncryptedApiKeyRow>(
-            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at FROM api_keys WHERE id = ?1"
+            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at FROM api_keys WHERE id = ?1"
         )
         .bind(id)
         .fetch_optional(&self.pool)
@@ -496,7 +914,7 @@ impl VaultDb {
     /// Lists all API keys (without decrypted values)
     pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
         let rows = sqlx::query_as::<_, EncryptedApiKeyRow>(
-            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at FROM api_keys ORDER BY app_name, key_name"
+            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at FROM api_keys ORDER BY app_name, key_name"
         )
         .fetch_all(&self.pool)
         .await?;
@@ -522,7 +940,7 @@ impl VaultDb {
 
         let rows = sqlx::query_as::<_, EncryptedApiKeyRow>(
             r#"
-            SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at
+            SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at
             FROM api_keys
             WHERE app_name LIKE ?1 OR key_name LIKE ?1 OR description LIKE ?1
             ORDER BY app_name, key_name
@@ -557,21 +975,11 @@ impl VaultDb {
 
         let now = Utc::now();
 
-        // Track if app_name or key_name changed (requires re-encryption)
-        let mut app_changed = false;
-        let mut key_changed = false;
-
         // Update fields if provided
         if let Some(app_name) = input.app_name {
-            if Some(app_name.clone()) != existing.api_key.app_name {
-                app_changed = true;
-            }
             existing.api_key.app_name = Some(app_name);
         }
         if let Some(key_name) = input.key_name {
-            if key_name != existing.api_key.key_name {
-                key_changed = true;
-            }
             existing.api_key.key_name = key_name;
         }
         if let Some(api_url) = input.api_url {
@@ -587,30 +995,36 @@ impl VaultDb {
         }
         existing.api_key.updated_at = now;
 
-        // Re-encrypt if key_value changed OR if app_name/key_name changed
-        let needs_reencrypt = key_value_changed || app_changed || key_changed;
+        let row = sqlx::query_as::<_, EncryptedApiKeyRow>(
+            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at FROM api_keys WHERE id = ?1"
+        )
+        .bind(&input.id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        // The names are bound into the associated data, so renames re-encrypt
+        // too. Rows under an older scheme are upgraded while we are here.
+        let needs_reencrypt = key_value_changed
+            || row.app_name != existing.api_key.app_name
+            || row.key_name != existing.api_key.key_name
+            || row.key_scheme != KeyScheme::CURRENT.as_i64();
 
         let (encrypted, key_salt) = if needs_reencrypt {
             let salt = generate_salt();
-            // Use empty string as default app_name for encryption context
-            let app_name_for_encryption = existing.api_key.app_name.as_deref().unwrap_or("");
-            let per_key_key = derive_per_key_encryption_key(
+            let context = KeyContext {
+                id: &existing.api_key.id,
+                app_name: existing.api_key.app_name.as_deref().unwrap_or(""),
+                key_name: &existing.api_key.key_name,
+            };
+            let enc = encrypt_api_key_value(
+                existing.key_value.as_bytes(),
                 master_key,
-                app_name_for_encryption,
-                &existing.api_key.key_name,
+                &context,
                 &salt,
+                KeyScheme::CURRENT,
             )?;
-            let enc = crate::crypto::encrypt(existing.key_value.as_bytes(), &per_key_key)?;
             (enc, salt.to_vec())
         } else {
-            // Keep existing encryption - need to fetch the salt
-            let row = sqlx::query_as::<_, EncryptedApiKeyRow>(
-                "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at FROM api_keys WHERE id = ?1"
-            )
-            .bind(&input.id)
-            .fetch_one(&self.pool)
-            .await?;
-
             let enc = EncryptedData {
                 ciphertext: row.encrypted_key_value,
                 nonce: row.nonce,
@@ -622,8 +1036,9 @@ impl VaultDb {
             r#"
             UPDATE api_keys
             SET app_name = ?1, key_name = ?2, api_url = ?3, description = ?4,
-                encrypted_key_value = ?5, nonce = ?6, key_salt = ?7, updated_at = ?8
-            WHERE id = ?9
+                encrypted_key_value = ?5, nonce = ?6, key_salt = ?7, key_scheme = ?8,
+                updated_at = ?9
+            WHERE id = ?10
             "#,
         )
         .bind(&existing.api_key.app_name)
@@ -633,6 +1048,7 @@ impl VaultDb {
         .bind(&encrypted.ciphertext)
         .bind(&encrypted.nonce)
         .bind(&key_salt[..])
+        .bind(KeyScheme::CURRENT.as_i64())
         .bind(now.timestamp())
         .bind(&input.id)
         .execute(&self.pool)
@@ -665,22 +1081,26 @@ impl VaultDb {
         let mut salt_array = [0u8; 32];
         salt_array.copy_from_slice(&row.key_salt[..32]);
 
-        // Use empty string as default app_name for encryption context
-        let app_name_for_encryption = row.app_name.as_deref().unwrap_or("");
-
-        let per_key_key = derive_per_key_encryption_key(
-            master_key,
-            app_name_for_encryption,
-            &row.key_name,
-            &salt_array,
-        )?;
+        let scheme = KeyScheme::from_i64(row.key_scheme).ok_or_else(|| {
+            DbError::Crypto(CryptoError::Decryption(format!(
+                "Unknown key scheme {}",
+                row.key_scheme
+            )))
+        })?;
 
         let encrypted = EncryptedData {
             ciphertext: row.encrypted_key_value,
             nonce: row.nonce,
         };
 
-        let decrypted = crate::crypto::decrypt(&encrypted, &per_key_key)?;
+        // Use empty string as default app_name for encryption context
+        let context = KeyContext {
+            id: &row.id,
+            app_name: row.app_name.as_deref().unwrap_or(""),
+            key_name: &row.key_name,
+        };
+        let decrypted =
+            decrypt_api_key_value(&encrypted, master_key, &context, &salt_array, scheme)?;
         let key_value = String::from_utf8(decrypted)
             .map_err(|_| DbError::Crypto(CryptoError::Decryption("Invalid UTF-8".to_string())))?;
 
@@ -711,7 +1131,7 @@ impl VaultDb {
     /// This is useful after migration to ensure all keys have proper salts
     pub async fn reencrypt_all_keys(&self, master_key: &VaultKey) -> Result<usize> {
         let rows = sqlx::query_as::<_, EncryptedApiKeyRow>(
-            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at FROM api_keys"
+            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at FROM api_keys"
         )
         .fetch_all(&self.pool)
         .await?;
@@ -734,29 +1154,32 @@ impl VaultDb {
                 // Generate new per-key salt
                 let key_salt = generate_salt();
 
-                // Derive per-key encryption key
-                let app_name_for_encryption = row.app_name.as_deref().unwrap_or("");
-                let per_key_key = derive_per_key_encryption_key(
+                // Re-encrypt with a per-key key under the current scheme
+                let context = KeyContext {
+                    id: &row.id,
+                    app_name: row.app_name.as_deref().unwrap_or(""),
+                    key_name: &row.key_name,
+                };
+                let new_encrypted = encrypt_api_key_value(
+                    &key_value,
                     master_key,
-                    app_name_for_encryption,
-                    &row.key_name,
+                    &context,
                     &key_salt,
+                    KeyScheme::CURRENT,
                 )?;
 
-                // Re-encrypt with per-key
-                let new_encrypted = crate::crypto::encrypt(&key_value, &per_key_key)?;
-
                 // Update the record
                 sqlx::query(
                     r#"
                     UPDATE api_keys
-                    SET encrypted_key_value = ?1, nonce = ?2, key_salt = ?3
-                    WHERE id = ?4
+                    SET encrypted_key_value = ?1, nonce = ?2, key_salt = ?3, key_scheme = ?4
+                    WHERE id = ?5
                     "#,
                 )
                 .bind(&new_encrypted.ciphertext)
                 .bind(&new_encrypted.nonce)
                 .bind(&key_salt[..])
+                .bind(KeyScheme::CURRENT.as_i64())
                 .bind(&row.id)
                 .execute(&self.pool)
                 .await?;
@@ -768,54 +1191,104 @@ impl VaultDb {
         Ok(reencrypted)
     }
 
-    /// Creates a backup of the database
-    async fn backup(&self) -> Result<()> {
-        // For SQLite, we need to use the VACUUM INTO command to create a backup
-        // or directly copy the file if we have the path
-        // Since we're using a connection pool, let's use a simpler approach:
-        // Skip backup for in-memory databases
+    /// Snapshots the database into [`Self::backup_dir`] with `VACUUM INTO`,
+    /// then deletes all but the [`BACKUPS_TO_KEEP`] newest snapshots
+    ///
+    /// In-memory databases are skipped, as are databases that hold neither
+    /// keys nor a vault configuration. The snapshot file is created with
+    /// mode 0600 before anything is written to it.
+    async fn backup(&self) -> Result<Option<BackupInfo>> {
+        let Some(dir) = self.backup_dir().await? else {
+            return Ok(None);
+        };
+        if !self.has_vault_data().await? {
+            return Ok(None);
+        }
 
-        // Check if this is an in-memory database by checking the connection string
-        // For file-based databases, we'll rely on SQLite's backup mechanism
+        let io_error =
+            |e: std::io::Error| DbError::BackupFailed(format!("{}: {}", dir.display(), e));
+        std::fs::create_dir_all(&dir).map_err(io_error)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+                .map_err(io_error)?;
+        }
 
-        // Generate a timestamp for the backup filename
-        let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
-        let backup_filename = format!("vault_backup_{}.db", timestamp);
+        let created_at = Utc::now();
+        let id = created_at.format(BACKUP_ID_FORMAT).to_string();
+        let path = dir.join(format!("{}{}.db", BACKUP_PREFIX, id));
+        let target = path.to_str().ok_or_else(|| {
+            DbError::BackupFailed(format!("not a UTF-8 path: {}", path.display()))
+        })?;
 
-        // Use SQL backup command via SQLite
-        // Note: For in-memory databases, this will be skipped
-        let result: Result<_> =
-            sqlx::query("SELECT file FROM pragma_database_list WHERE name='main'")
-                .fetch_optional(&self.pool)
-                .await
-                .map_err(Into::into);
+        // VACUUM INTO accepts an empty file, so create it private first
+        // rather than let SQLite create it with the umask
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(&path).map_err(io_error)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .map_err(io_error)?;
+        }
+        drop(file);
 
-        match result {
-            Ok(Some(row)) => {
-                let file: Option<String> = row.try_get("file")?;
-                if file.as_deref().is_some_and(|f| f.contains(":memory:")) {
-                    eprintln!("Skipping backup for in-memory database");
-                    return Ok(());
-                }
+        if let Err(e) = sqlx::query("VACUUM INTO ?1")
+            .bind(target)
+            .execute(&self.pool)
+            .await
+        {
+            let _ = std::fs::remove_file(&path);
+            return Err(DbError::BackupFailed(format!("{}: {}", path.display(), e)));
+        }
 
-                // For file-based databases, we'd need the actual file path
-                // For now, we'll skip automatic file-based backup for simplicity
-                // In production, you'd want to implement proper file copying
-                eprintln!("Database backup created (timestamp: {})", backup_filename);
-            }
-            _ => {
-                eprintln!("Skipping backup - database file path not available");
-            }
+        Self::cleanup_old_backups(&dir)?;
+
+        let size = std::fs::metadata(&path).map_err(io_error)?.len();
+        Ok(Some(BackupInfo {
+            id,
+            path,
+            created_at,
+            size,
+        }))
+    }
+
+    /// Returns whether the database holds keys or an initialized vault
+    async fn has_vault_data(&self) -> Result<bool> {
+        let keys: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys")
+            .fetch_one(&self.pool)
+            .await?;
+        if keys > 0 {
+            return Ok(true);
         }
 
-        Ok(())
+        let has_config: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'vault_config')",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if !has_config {
+            return Ok(false);
+        }
+        let configured: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM vault_config)")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(configured)
     }
 
-    /// Cleans up old backup files, keeping only the 5 most recent
-    #[allow(dead_code)]
-    async fn cleanup_old_backups(&self, _database_path: &std::path::Path) -> Result<()> {
-        // This would be implemented when we have proper file path access
-        // For now, it's a placeholder
+    /// Deletes all but the [`BACKUPS_TO_KEEP`] most recent snapshots in `dir`
+    fn cleanup_old_backups(dir: &Path) -> Result<()> {
+        for old in list_backups_in(dir)?.iter().skip(BACKUPS_TO_KEEP) {
+            std::fs::remove_file(&old.path)
+                .map_err(|e| DbError::BackupFailed(format!("{}: {}", old.path.display(), e)))?;
+        }
         Ok(())
     }
 
@@ -826,13 +1299,12 @@ impl VaultDb {
             .fetch_all(&self.pool)
             .await?;
 
-        let tables_to_remove = ["api_keys_new", "api_keys_v2"];
+        let tables_to_remove = ["api_keys_new", "api_keys_v2", "api_keys_v5"];
 
         for row in rows {
             let table_name: String = row.try_get("name")?;
 
             if tables_to_remove.contains(&table_name.as_str()) {
-                eprintln!("Removing orphaned table: {}", table_name);
                 sqlx::query(&format!("DROP TABLE IF EXISTS {}", table_name))
                     .execute(&self.pool)
                     .await?;
@@ -843,10 +1315,121 @@ impl VaultDb {
     }
 
     /// Manually trigger a database backup (useful for critical operations)
-    pub async fn create_backup(&self) -> Result<()> {
+    ///
+    /// Returns `None` if nothing was written because the database is in
+    /// memory or holds neither keys nor an initialized vault.
+    pub async fn create_backup(&self) -> Result<Option<BackupInfo>> {
         self.backup().await
     }
 
+    /// Returns the path of the database file, or `None` for an in-memory
+    /// database
+    pub async fn file_path(&self) -> Result<Option<PathBuf>> {
+        let file: Option<String> =
+            sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(file
+            .filter(|f| !f.is_empty() && !f.contains(":memory:"))
+            .map(PathBuf::from))
+    }
+
+    /// Returns the snapshot directory: `backups` next to the database file,
+    /// so `~/.vult/backups` for the default vault
+    pub async fn backup_dir(&self) -> Result<Option<PathBuf>> {
+        Ok(self.file_path().await?.map(|path| {
+            path.parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join(BACKUP_DIR_NAME)
+        }))
+    }
+
+    /// Lists the snapshots in [`Self::backup_dir`], newest first
+    pub async fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        match self.backup_dir().await? {
+            Some(dir) => list_backups_in(&dir),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Reads the schema version of a snapshot without changing it
+    ///
+    /// # Errors
+    ///
+    /// - [`DbError::BackupFailed`] if the file is not a vault database
+    /// - [`DbError::IncompatibleVersion`] if it was written by a newer version
+    pub async fn backup_schema_version(path: &Path) -> Result<i64> {
+        let not_a_snapshot =
+            || DbError::BackupFailed(format!("{} is not a vault snapshot", path.display()));
+
+        let options = SqliteConnectOptions::new().filename(path).read_only(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(|_| not_a_snapshot())?;
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&pool)
+            .await
+            .map_err(|_| not_a_snapshot())?;
+        let has_keys_table = sqlx::query("SELECT 1 FROM api_keys LIMIT 1")
+            .fetch_optional(&pool)
+            .await
+            .is_ok();
+        pool.close().await;
+
+        let version = version.unwrap_or(1);
+        if !has_keys_table {
+            return Err(not_a_snapshot());
+        }
+        if version > SCHEMA_VERSION {
+            return Err(DbError::IncompatibleVersion {
+                db_version: version,
+                app_version: SCHEMA_VERSION,
+            });
+        }
+        Ok(version)
+    }
+
+    /// Replaces the database file with the snapshot at `backup`
+    ///
+    /// The snapshot's schema version is checked first, and the current
+    /// database is snapshotted before it is replaced, so a restore can be
+    /// undone by restoring that snapshot. The connection pool is closed
+    /// afterwards; open the database again to use it.
+    ///
+    /// Returns the snapshot of the replaced database, if one was made.
+    pub async fn restore_backup(&self, backup: &Path) -> Result<Option<BackupInfo>> {
+        Self::backup_schema_version(backup).await?;
+        let db_path = self.file_path().await?.ok_or_else(|| {
+            DbError::BackupFailed("an in-memory database cannot be restored".to_string())
+        })?;
+        let io_error = |path: &Path, e: std::io::Error| {
+            DbError::BackupFailed(format!("{}: {}", path.display(), e))
+        };
+
+        // Stage the copy first: rotation after the next snapshot may delete
+        // the snapshot being restored
+        let staging = PathBuf::from(format!("{}.restore", db_path.display()));
+        std::fs::copy(backup, &staging).map_err(|e| io_error(backup, e))?;
+
+        let previous = self.backup().await?;
+        self.pool.close().await;
+
+        // A leftover journal would be applied to the restored file
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let journal = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+            match std::fs::remove_file(&journal) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(io_error(&journal, e))
+                }
+                _ => {}
+            }
+        }
+        std::fs::rename(&staging, &db_path).map_err(|e| io_error(&db_path, e))?;
+
+        Ok(previous)
+    }
+
     /// Gets the current schema version of the database
     pub async fn get_schema_version(&self) -> Result<i64> {
         let version =
@@ -939,6 +1522,160 @@ impl VaultDb {
     }
 }
 
+/// Adds a `schema_version` row for `version`
+async fn record_schema_version(conn: &mut SqliteConnection, version: i64) -> Result<()> {
+    sqlx::query("INSERT INTO schema_version (version, migrated_at) VALUES (?1, ?2)")
+        .bind(version)
+        .bind(Utc::now().timestamp())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Checks whether the api_keys table has the given column
+async fn api_keys_has_column(conn: &mut SqliteConnection, column: &str) -> Result<bool> {
+    Ok(table_columns(conn, "api_keys")
+        .await?
+        .iter()
+        .any(|name| name == column))
+}
+
+/// Lists the columns of `table`; empty if there is no such table
+async fn table_columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<String>> {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?1)")
+        .bind(table)
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(columns)
+}
+
+/// Checks whether the api_keys table has a table-level UNIQUE constraint,
+/// as every schema before v5 did
+async fn api_keys_has_unique_constraint(conn: &mut SqliteConnection) -> Result<bool> {
+    let indexes = sqlx::query("PRAGMA index_list(api_keys)")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(indexes.iter().any(|row| {
+        let origin: Option<String> = row.try_get("origin").ok();
+        origin.as_deref() == Some("u")
+    }))
+}
+
+/// Copies a v1 api_keys table into the v2 layout, giving every key its own
+/// salt. Values are not re-encrypted here but on next access.
+async fn add_key_salts(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE api_keys_v2 (
+            id TEXT PRIMARY KEY,
+            app_name TEXT,
+            key_name TEXT NOT NULL,
+            api_url TEXT,
+            description TEXT,
+            encrypted_key_value BLOB NOT NULL,
+            nonce BLOB NOT NULL,
+            key_salt BLOB NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM api_keys")
+        .fetch_all(&mut *conn)
+        .await?;
+    for id in ids {
+        let key_salt = generate_salt();
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys_v2 (id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at)
+            SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, ?1, created_at, updated_at
+            FROM api_keys WHERE id = ?2
+            "#,
+        )
+        .bind(&key_salt[..])
+        .bind(&id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query("DROP TABLE api_keys")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("ALTER TABLE api_keys_v2 RENAME TO api_keys")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Copies api_keys into a table created from [`API_KEYS_V5_COLUMNS`],
+/// which has no table constraints, and swaps it in
+async fn rebuild_api_keys(conn: &mut SqliteConnection) -> Result<()> {
+    let columns = "id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, key_scheme, metadata_ciphertext, metadata_nonce, created_at, updated_at";
+
+    sqlx::query("DROP TABLE IF EXISTS api_keys_v5")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(&format!(
+        "CREATE TABLE api_keys_v5 ({})",
+        API_KEYS_V5_COLUMNS
+    ))
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(&format!(
+        "INSERT INTO api_keys_v5 ({columns}) SELECT {columns} FROM api_keys"
+    ))
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("DROP TABLE api_keys")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("ALTER TABLE api_keys_v5 RENAME TO api_keys")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Lists the snapshots in `dir`, newest first; files with other names are
+/// ignored
+fn list_backups_in(dir: &Path) -> Result<Vec<BackupInfo>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(DbError::BackupFailed(format!("{}: {}", dir.display(), e))),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(BACKUP_PREFIX))
+            .and_then(|n| n.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        let Ok(created_at) = NaiveDateTime::parse_from_str(id, BACKUP_ID_FORMAT) else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        backups.push(BackupInfo {
+            id: id.to_string(),
+            path: entry.path(),
+            created_at: created_at.and_utc(),
+            size: metadata.len(),
+        });
+    }
+
+    backups.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(backups)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -949,8 +1686,8 @@ mod tests {
 
     fn derive_test_key() -> VaultKey {
         let mut salt = [0u8; 32];
-        for i in 0..32 {
-            salt[i] = i as u8;
+        for (i, byte) in salt.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         crate::crypto::derive_key_from_pin("123456", &salt).unwrap()
     }
@@ -1459,7 +2196,149 @@ mod tests {
         let db = setup_test_db().await;
         // In-memory databases should skip backup without error
         let result = db.create_backup().await;
-        assert!(result.is_ok());
+        assert!(result.unwrap().is_none());
+        assert!(db.list_backups().await.unwrap().is_empty());
+    }
+
+    async fn setup_file_db(dir: &tempfile::TempDir) -> VaultDb {
+        let path = dir.path().join("vault.db");
+        VaultDb::new(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .unwrap()
+    }
+
+    fn test_input(key_name: &str) -> CreateApiKey {
+        CreateApiKey {
+            app_name: Some("app".to_string()),
+            key_name: key_name.to_string(),
+            api_url: None,
+            description: None,
+            key_value: "secret".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backup_snapshots_and_rotates() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_file_db(&dir).await;
+
+        // Nothing worth keeping yet
+        assert!(db.create_backup().await.unwrap().is_none());
+
+        db.create_api_key(test_input("first"), &derive_test_key())
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..BACKUPS_TO_KEEP + 2 {
+            ids.push(db.create_backup().await.unwrap().unwrap().id);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+
+        let backups = db.list_backups().await.unwrap();
+        let listed: Vec<_> = backups.iter().map(|b| b.id.clone()).collect();
+        ids.reverse();
+        ids.truncate(BACKUPS_TO_KEEP);
+        assert_eq!(listed, ids);
+        assert_eq!(
+            backups[0].path.parent(),
+            Some(dir.path().join("backups").as_path())
+        );
+        assert_eq!(
+            VaultDb::backup_schema_version(&backups[0].path)
+                .await
+                .unwrap(),
+            SCHEMA_VERSION
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&backups[0].path)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backup_of_initialized_vault_without_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_file_db(&dir).await;
+        sqlx::query("CREATE TABLE vault_config (id INTEGER PRIMARY KEY, pin_hash TEXT)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        // An empty configuration table is not a vault yet
+        assert!(db.create_backup().await.unwrap().is_none());
+
+        sqlx::query("INSERT INTO vault_config (id, pin_hash) VALUES (1, 'hash')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let snapshot = db.create_backup().await.unwrap().unwrap();
+        assert_eq!(
+            VaultDb::backup_schema_version(&snapshot.path)
+                .await
+                .unwrap(),
+            SCHEMA_VERSION
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_backup_replaces_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = derive_test_key();
+        let db = setup_file_db(&dir).await;
+        db.create_api_key(test_input("first"), &key).await.unwrap();
+        let snapshot = db.create_backup().await.unwrap().unwrap();
+        db.create_api_key(test_input("second"), &key).await.unwrap();
+
+        let previous = db.restore_backup(&snapshot.path).await.unwrap().unwrap();
+        assert!(previous.path.exists());
+
+        let db = setup_file_db(&dir).await;
+        let names: Vec<_> = db
+            .list_api_keys()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|k| k.key_name)
+            .collect();
+        assert_eq!(names, vec!["first"]);
+    }
+
+    #[tokio::test]
+    async fn test_backup_schema_version_rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_file_db(&dir).await;
+        db.create_api_key(test_input("first"), &derive_test_key())
+            .await
+            .unwrap();
+        let snapshot = db.create_backup().await.unwrap().unwrap();
+
+        let newer = VaultDb::new(&format!("sqlite://{}", snapshot.path.display()))
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO schema_version (version, migrated_at) VALUES (99, 0)")
+            .execute(&newer.pool)
+            .await
+            .unwrap();
+        newer.pool.close().await;
+        let result = VaultDb::backup_schema_version(&snapshot.path).await;
+        assert!(matches!(
+            result,
+            Err(DbError::IncompatibleVersion { db_version: 99, .. })
+        ));
+        assert!(matches!(
+            db.restore_backup(&snapshot.path).await,
+            Err(DbError::IncompatibleVersion { .. })
+        ));
+
+        let other = dir.path().join("notes.txt");
+        std::fs::write(&other, "not a database").unwrap();
+        let result = VaultDb::backup_schema_version(&other).await;
+        assert!(matches!(result, Err(DbError::BackupFailed(_))));
     }
 
     #[tokio::test]
@@ -1503,4 +2382,481 @@ mod tests {
         // For now, let's just verify the cleanup function works by checking our original db
         // We need to make cleanup_orphaned_tables public or test it through migrate
     }
+
+    #[tokio::test]
+    async fn test_migrate_v2_adds_new_columns() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", temp_dir.path().join("v2.db").display());
+
+        // Lay out a v2 database by hand
+        {
+            let pool = SqlitePool::connect(&url).await.unwrap();
+            sqlx::query("CREATE TABLE schema_version (version INTEGER NOT NULL, migrated_at INTEGER NOT NULL)")
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO schema_version (version, migrated_at) VALUES (2, 0)")
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE api_keys (id TEXT PRIMARY KEY, app_name TEXT, key_name TEXT NOT NULL, api_url TEXT, description TEXT, encrypted_key_value BLOB NOT NULL, nonce BLOB NOT NULL, key_salt BLOB NOT NULL, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL, UNIQUE(key_name))",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO api_keys VALUES ('k1', 'github', 'token', NULL, NULL, x'00', x'00', x'00', 0, 0)")
+                .execute(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+        }
+
+        let db = VaultDb::new(&url).await.unwrap();
+        assert_eq!(db.get_schema_version().await.unwrap(), SCHEMA_VERSION);
+
+        // Existing rows are marked as using the legacy scheme
+        let scheme: i64 = sqlx::query_scalar("SELECT key_scheme FROM api_keys WHERE id = 'k1'")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(scheme, KeyScheme::LegacyArgon2.as_i64());
+
+        // ...and as having plaintext metadata
+        let metadata: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT metadata_ciphertext FROM api_keys WHERE id = 'k1'")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert!(metadata.is_none());
+
+        // UNIQUE(key_name) is gone: the name is unique within its app only
+        let key = derive_test_key();
+        let input = CreateApiKey {
+            app_name: Some("gitlab".to_string()),
+            key_name: "token".to_string(),
+            api_url: None,
+            description: None,
+            key_value: "glpat-123".to_string(),
+        };
+        db.create_api_key(input.clone(), &key).await.unwrap();
+        assert!(db.create_api_key(input, &key).await.is_err());
+
+        // NULL and '' both mean no app, so they clash with each other
+        let insert = "INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, key_salt, created_at, updated_at) VALUES (?1, ?2, 'loose', x'00', x'00', x'00', 0, 0)";
+        sqlx::query(insert)
+            .bind("k2")
+            .bind(None::<String>)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        for (id, app) in [("k3", None), ("k4", Some(""))] {
+            let result = sqlx::query(insert)
+                .bind(id)
+                .bind(app)
+                .execute(&db.pool)
+                .await;
+            assert!(result.is_err(), "{:?} should clash with NULL", app);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_v1_keeps_key_names_per_app() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", temp_dir.path().join("v1.db").display());
+
+        // A v1 database: app_name required, no version table, and two keys
+        // sharing a name
+        {
+            let pool = SqlitePool::connect(&url).await.unwrap();
+            sqlx::query(
+                "CREATE TABLE api_keys (id TEXT PRIMARY KEY, app_name TEXT NOT NULL, key_name TEXT NOT NULL, api_url TEXT, description TEXT, encrypted_key_value BLOB NOT NULL, nonce BLOB NOT NULL, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL, UNIQUE(app_name, key_name))",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            for (id, app) in [("k1", "github"), ("k2", "gitlab")] {
+                sqlx::query(
+                    "INSERT INTO api_keys VALUES (?1, ?2, 'token', NULL, NULL, x'00', x'00', 0, 0)",
+                )
+                .bind(id)
+                .bind(app)
+                .execute(&pool)
+                .await
+                .unwrap();
+            }
+            pool.close().await;
+        }
+
+        let db = VaultDb::new(&url).await.unwrap();
+        assert_eq!(db.get_schema_version().await.unwrap(), SCHEMA_VERSION);
+
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT app_name, key_name FROM api_keys ORDER BY id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("github".to_string(), "token".to_string()),
+                ("gitlab".to_string(), "token".to_string())
+            ]
+        );
+
+        // The new unique index replaced the table constraint
+        let mut conn = db.pool.acquire().await.unwrap();
+        assert!(!api_keys_has_unique_constraint(&mut conn).await.unwrap());
+        let duplicate = sqlx::query("INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, key_salt, created_at, updated_at) VALUES ('k3', 'github', 'token', x'00', x'00', x'00', 0, 0)")
+            .execute(&mut *conn)
+            .await;
+        assert!(duplicate.is_err());
+    }
+
+    /// Lays out a database at the historical schema `version` by hand, the
+    /// way that version's build left it, holding github/token and
+    /// gitlab/deploy
+    async fn create_fixture(dir: &tempfile::TempDir, version: i64) -> String {
+        let path = dir.path().join(format!("v{}.db", version));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        let pool = SqlitePool::connect(&url).await.unwrap();
+
+        let insert = if version == 1 {
+            sqlx::query(
+                "CREATE TABLE api_keys (id TEXT PRIMARY KEY, app_name TEXT NOT NULL, key_name TEXT NOT NULL, api_url TEXT, description TEXT, encrypted_key_value BLOB NOT NULL, nonce BLOB NOT NULL, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL, UNIQUE(app_name, key_name))",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            "INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, created_at, updated_at) VALUES (?1, ?2, ?3, x'00', x'00', 0, 0)"
+        } else {
+            sqlx::query(SCHEMA_VERSION_TABLE)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO schema_version (version, migrated_at) VALUES (?1, 0)")
+                .bind(version)
+                .execute(&pool)
+                .await
+                .unwrap();
+            // v5 replaced the constraint with API_KEYS_NAME_INDEX
+            let unique = if version < 5 {
+                ", UNIQUE(key_name)"
+            } else {
+                ""
+            };
+            sqlx::query(&format!(
+                "CREATE TABLE api_keys (id TEXT PRIMARY KEY, app_name TEXT, key_name TEXT NOT NULL, api_url TEXT, description TEXT, encrypted_key_value BLOB NOT NULL, nonce BLOB NOT NULL, key_salt BLOB NOT NULL, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL{})",
+                unique
+            ))
+            .execute(&pool)
+            .await
+            .unwrap();
+            "INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, key_salt, created_at, updated_at) VALUES (?1, ?2, ?3, x'00', x'00', x'00', 0, 0)"
+        };
+        if version >= 3 {
+            sqlx::query("ALTER TABLE api_keys ADD COLUMN key_scheme INTEGER NOT NULL DEFAULT 1")
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        if version >= 4 {
+            for column in ["metadata_ciphertext", "metadata_nonce"] {
+                sqlx::query(&format!("ALTER TABLE api_keys ADD COLUMN {} BLOB", column))
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+        }
+        if version >= 5 {
+            sqlx::query(API_KEYS_NAME_INDEX)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        if version >= 6 {
+            for column in [
+                "version INTEGER NOT NULL DEFAULT 1",
+                "history_limit INTEGER",
+            ] {
+                sqlx::query(&format!("ALTER TABLE api_keys ADD COLUMN {}", column))
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+            sqlx::query(KEY_VERSIONS_TABLE)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        if version >= 7 {
+            for sql in [
+                AUDIT_LOG_TABLE,
+                AUDIT_LOG_NO_UPDATE,
+                AUDIT_LOG_NO_DELETE,
+                AUDIT_HEAD_TABLE,
+            ] {
+                sqlx::query(sql).execute(&pool).await.unwrap();
+            }
+        }
+        if version >= 8 {
+            for column in ["expires_at", "rotate_every", "rotated_at"] {
+                sqlx::query(&format!(
+                    "ALTER TABLE api_keys ADD COLUMN {} INTEGER",
+                    column
+                ))
+                .execute(&pool)
+                .await
+                .unwrap();
+            }
+        }
+        // vault_config as first released; v9 adds the rest
+        sqlx::query(
+            "CREATE TABLE vault_config (id INTEGER PRIMARY KEY, salt BLOB NOT NULL, pin_hash TEXT NOT NULL, created_at INTEGER NOT NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO vault_config (id, salt, pin_hash, created_at) VALUES (1, x'00', '$00:0', 0)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (id, app, key) in [("k1", "github", "token"), ("k2", "gitlab", "deploy")] {
+            sqlx::query(insert)
+                .bind(id)
+                .bind(app)
+                .bind(key)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        if version >= 8 {
+            sqlx::query(BACKFILL_ROTATED_AT)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        pool.close().await;
+        url
+    }
+
+    /// Name, type, NOT NULL flag and default of each column of `table`, sorted
+    async fn table_layout(
+        pool: &SqlitePool,
+        table: &str,
+    ) -> Vec<(String, String, bool, Option<String>)> {
+        let mut columns: Vec<(String, String, bool, Option<String>)> =
+            sqlx::query_as("SELECT name, type, \"notnull\", dflt_value FROM pragma_table_info(?1)")
+                .bind(table)
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        columns.sort();
+        columns
+    }
+
+    #[tokio::test]
+    async fn test_migrate_every_historical_schema() {
+        let fresh = setup_test_db().await;
+        let expected = table_layout(&fresh.pool, "api_keys").await;
+        let expected_slots = table_layout(&fresh.pool, "key_slots").await;
+        assert!(fresh.migrate(false).await.unwrap().migrations.is_empty());
+        sqlx::query(VAULT_CONFIG_TABLE)
+            .execute(&fresh.pool)
+            .await
+            .unwrap();
+        let expected_config = table_layout(&fresh.pool, "vault_config").await;
+
+        for version in 1..SCHEMA_VERSION {
+            let temp_dir = tempfile::TempDir::new().unwrap();
+            let url = create_fixture(&temp_dir, version).await;
+            let db = VaultDb::open(&url).await.unwrap();
+
+            // A dry run lists the pending migrations and writes nothing
+            let plan = db.migrate(true).await.unwrap();
+            let pending: Vec<i64> = plan.migrations.iter().map(|m| m.version).collect();
+            assert_eq!(
+                pending,
+                ((version + 1)..=SCHEMA_VERSION).collect::<Vec<_>>()
+            );
+            assert_eq!(plan.from_version, version);
+            assert!(plan.snapshot.is_none());
+            assert_eq!(db.stored_schema_version().await.unwrap(), Some(version));
+            assert!(db.list_backups().await.unwrap().is_empty());
+
+            let report = db.migrate(false).await.unwrap();
+            assert_eq!(report.migrations.len(), pending.len());
+            assert!(report.snapshot.is_some(), "no snapshot from v{}", version);
+            assert_eq!(db.get_schema_version().await.unwrap(), SCHEMA_VERSION);
+            assert_eq!(
+                table_layout(&db.pool, "api_keys").await,
+                expected,
+                "from v{}",
+                version
+            );
+            assert_eq!(
+                table_layout(&db.pool, "vault_config").await,
+                expected_config,
+                "from v{}",
+                version
+            );
+            assert_eq!(
+                table_layout(&db.pool, "key_slots").await,
+                expected_slots,
+                "from v{}",
+                version
+            );
+
+            let mut conn = db.pool.acquire().await.unwrap();
+            assert!(!api_keys_has_unique_constraint(&mut conn).await.unwrap());
+            let index: Option<String> = sqlx::query_scalar(
+                "SELECT name FROM sqlite_master WHERE type = 'index' AND name = 'idx_api_keys_app_key'",
+            )
+            .fetch_optional(&mut *conn)
+            .await
+            .unwrap();
+            assert!(index.is_some(), "no name index from v{}", version);
+            let history: Option<String> = sqlx::query_scalar(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'key_versions'",
+            )
+            .fetch_optional(&mut *conn)
+            .await
+            .unwrap();
+            assert!(history.is_some(), "no key_versions from v{}", version);
+            let audit: Vec<String> = sqlx::query_scalar(
+                "SELECT name FROM sqlite_master WHERE name LIKE 'audit_%' ORDER BY name",
+            )
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+            assert_eq!(
+                audit,
+                [
+                    "audit_head",
+                    "audit_log",
+                    "audit_log_no_delete",
+                    "audit_log_no_update"
+                ],
+                "from v{}",
+                version
+            );
+            drop(conn);
+
+            let rows: Vec<(String, String)> =
+                sqlx::query_as("SELECT app_name, key_name FROM api_keys ORDER BY id")
+                    .fetch_all(&db.pool)
+                    .await
+                    .unwrap();
+            assert_eq!(
+                rows,
+                vec![
+                    ("github".to_string(), "token".to_string()),
+                    ("gitlab".to_string(), "deploy".to_string())
+                ]
+            );
+            let unset: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE rotated_at IS NULL")
+                    .fetch_one(&db.pool)
+                    .await
+                    .unwrap();
+            assert_eq!(unset, 0, "rotated_at not backfilled from v{}", version);
+
+            // Every step is recorded, and a second run has nothing to do
+            let versions: Vec<i64> =
+                sqlx::query_scalar("SELECT version FROM schema_version ORDER BY version")
+                    .fetch_all(&db.pool)
+                    .await
+                    .unwrap();
+            assert_eq!(versions.last(), Some(&SCHEMA_VERSION));
+            assert!(db.migrate(false).await.unwrap().migrations.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_rolls_back() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let url = create_fixture(&temp_dir, 2).await;
+
+        // Claim v4 without the v3 column, so the v5 rebuild fails halfway
+        {
+            let pool = SqlitePool::connect(&url).await.unwrap();
+            sqlx::query("UPDATE schema_version SET version = 4")
+                .execute(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+        }
+
+        let db = VaultDb::open(&url).await.unwrap();
+        let result = db.migrate(false).await;
+        assert!(matches!(
+            result,
+            Err(DbError::MigrationFailed { version: 5, .. })
+        ));
+
+        // The half-built table is gone and the old one untouched
+        assert_eq!(db.get_schema_version().await.unwrap(), 4);
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'api_keys%'",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(tables, vec!["api_keys".to_string()]);
+        let mut conn = db.pool.acquire().await.unwrap();
+        assert!(api_keys_has_unique_constraint(&mut conn).await.unwrap());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_rename_rebinds_ciphertext() {
+        let db = setup_test_db().await;
+        let key = derive_test_key();
+
+        let created = db
+            .create_api_key(
+                CreateApiKey {
+                    app_name: Some("github".to_string()),
+                    key_name: "token".to_string(),
+                    api_url: None,
+                    description: None,
+                    key_value: "secret".to_string(),
+                },
+                &key,
+            )
+            .await
+            .unwrap();
+        let before: Vec<u8> =
+            sqlx::query_scalar("SELECT encrypted_key_value FROM api_keys WHERE id = ?1")
+                .bind(&created.api_key.id)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+
+        let update = UpdateApiKey {
+            id: created.api_key.id.clone(),
+            app_name: None,
+            key_name: Some("renamed".to_string()),
+            api_url: None,
+            description: None,
+            key_value: None,
+        };
+        db.update_api_key(update, &key).await.unwrap();
+
+        let after: Vec<u8> =
+            sqlx::query_scalar("SELECT encrypted_key_value FROM api_keys WHERE id = ?1")
+                .bind(&created.api_key.id)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert_ne!(before, after);
+        let fetched = db.get_api_key(&created.api_key.id, &key).await.unwrap();
+        assert_eq!(fetched.key_value, "secret");
+        assert_eq!(fetched.api_key.key_name, "renamed");
+    }
 }
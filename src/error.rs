@@ -13,6 +13,7 @@
 //! - **Cryptographic**: Encryption/decryption failures
 //! - **Database**: SQLite errors, schema issues
 //! - **State**: Invalid operation for current state
+//! - **Expiry**: Keys expired or due for rotation
 //!
 //! # Exit Codes
 //!
@@ -31,6 +32,8 @@
 //! | 8 | I/O error |
 //! | 9 | State error |
 //! | 10 | Clipboard error |
+//! | 11 | Keys due for expiry or rotation |
+//! | 12 | Keys expired or overdue for rotation |
 //!
 //! # Example
 //!
@@ -75,9 +78,9 @@ pub enum VaultError {
     #[error("PIN too long (maximum 64 characters allowed)")]
     PinTooLong,
 
-    /// Too many failed authentication attempts
-    #[error("Too many failed attempts. Please wait before trying again.")]
-    TooManyAttempts,
+    /// Unlocking is refused for `retry_after` more seconds after failed attempts
+    #[error("Too many failed attempts. Try again in {retry_after} seconds.")]
+    TooManyAttempts { retry_after: u64 },
 
     /// Vault is not initialized (no PIN set)
     #[error("Vault not initialized. Run 'init' first.")]
@@ -91,6 +94,14 @@ pub enum VaultError {
     #[error("Vault is locked. Unlock with your PIN first.")]
     Locked,
 
+    /// Vault requires a key file but none was given
+    #[error("This vault requires a key file")]
+    KeyFileRequired,
+
+    /// Key file could not be read
+    #[error("Key file not found or unreadable: {0}")]
+    KeyFileUnavailable(String),
+
     // =========================================================================
     // Cryptographic Errors
     // =========================================================================
@@ -166,6 +177,13 @@ pub enum VaultError {
     #[error("Clipboard error: {0}")]
     Clipboard(String),
 
+    // =========================================================================
+    // Expiry Errors
+    // =========================================================================
+    /// Keys expire or are due for rotation; `overdue` of them already are
+    #[error("{due} key(s) due for expiry or rotation, {overdue} already past due")]
+    KeysDue { due: usize, overdue: usize },
+
     // =========================================================================
     // I/O Errors
     // =========================================================================
@@ -197,6 +215,23 @@ impl From<sqlx::Error> for VaultError {
     }
 }
 
+impl From<crate::database::DbError> for VaultError {
+    fn from(err: crate::database::DbError) -> Self {
+        use crate::database::DbError;
+        match err {
+            DbError::IncompatibleVersion {
+                db_version,
+                app_version,
+            } => VaultError::IncompatibleVersion {
+                db_version,
+                app_version,
+            },
+            DbError::BackupFailed(msg) => VaultError::BackupFailed(msg),
+            other => VaultError::Database(other.to_string()),
+        }
+    }
+}
+
 impl From<std::io::Error> for VaultError {
     fn from(err: std::io::Error) -> Self {
         VaultError::Io(err.to_string())
@@ -239,10 +274,12 @@ impl VaultError {
             VaultError::InvalidPin
                 | VaultError::PinTooShort
                 | VaultError::PinTooLong
-                | VaultError::TooManyAttempts
+                | VaultError::TooManyAttempts { .. }
                 | VaultError::NotInitialized
                 | VaultError::AlreadyInitialized
                 | VaultError::Locked
+                | VaultError::KeyFileRequired
+                | VaultError::KeyFileUnavailable(_)
         )
     }
 
@@ -265,6 +302,8 @@ impl VaultError {
     /// - 8: I/O error
     /// - 9: Invalid state
     /// - 10: Clipboard error
+    /// - 11: Keys due for expiry or rotation
+    /// - 12: Keys expired or overdue for rotation
     /// - 64-78: Reserved for future use (sysexits.h compatibility)
     pub fn exit_code(&self) -> i32 {
         match self {
@@ -272,8 +311,10 @@ impl VaultError {
             VaultError::InvalidPin
             | VaultError::PinTooShort
             | VaultError::PinTooLong
-            | VaultError::TooManyAttempts
-            | VaultError::Locked => 1,
+            | VaultError::TooManyAttempts { .. }
+            | VaultError::Locked
+            | VaultError::KeyFileRequired
+            | VaultError::KeyFileUnavailable(_) => 1,
 
             // Not found
             VaultError::NotFound(_) => 2,
@@ -308,6 +349,10 @@ impl VaultError {
 
             // Clipboard errors
             VaultError::Clipboard(_) => 10,
+
+            // Expiry
+            VaultError::KeysDue { overdue: 0, .. } => 11,
+            VaultError::KeysDue { .. } => 12,
         }
     }
 
@@ -316,12 +361,21 @@ impl VaultError {
         match self {
             VaultError::InvalidPin => Some("Check your PIN and try again."),
             VaultError::PinTooShort => Some("PIN must be at least 6 characters."),
-            VaultError::TooManyAttempts => Some("Wait a moment before trying again."),
+            VaultError::TooManyAttempts { .. } => {
+                Some("Wait for it to expire, or clear it with 'vult unlock --reset-lockout'.")
+            }
             VaultError::NotInitialized => Some("Run 'vult init' to set up your vault."),
             VaultError::AlreadyInitialized => Some("Your vault is already set up."),
             VaultError::Locked => Some("Unlock your vault first with your PIN."),
+            VaultError::KeyFileRequired => Some("Pass the key file with --keyfile or VULT_KEYFILE."),
+            VaultError::KeyFileUnavailable(_) => {
+                Some("Restore the key file from your backup; the vault cannot be opened without it.")
+            }
             VaultError::NotFound(_) => Some("Check the app and key name."),
             VaultError::DuplicateKey { .. } => Some("Use 'vult update' to modify an existing key."),
+            VaultError::KeysDue { .. } => Some(
+                "Rotate them with 'vult update --value', or change --expires-at/--rotate-every.",
+            ),
             _ => None,
         }
     }
@@ -350,6 +404,7 @@ mod tests {
     fn test_is_auth_error() {
         assert!(VaultError::InvalidPin.is_auth_error());
         assert!(VaultError::Locked.is_auth_error());
+        assert!(VaultError::KeyFileRequired.is_auth_error());
         assert!(!VaultError::NotFound("test".to_string()).is_auth_error());
     }
 
@@ -358,5 +413,15 @@ mod tests {
         assert_eq!(VaultError::InvalidPin.exit_code(), 1);
         assert_eq!(VaultError::NotFound("test".to_string()).exit_code(), 2);
         assert_eq!(VaultError::NotInitialized.exit_code(), 3);
+        assert_eq!(
+            VaultError::KeyFileUnavailable("vault.key".to_string()).exit_code(),
+            1
+        );
+        assert_eq!(
+            VaultError::TooManyAttempts { retry_after: 4 }.exit_code(),
+            1
+        );
+        assert_eq!(VaultError::KeysDue { due: 2, overdue: 0 }.exit_code(), 11);
+        assert_eq!(VaultError::KeysDue { due: 2, overdue: 1 }.exit_code(), 12);
     }
 }
@@ -0,0 +1,483 @@
+//! Portable encrypted vault exports (`.vultbak`).
+//!
+//! An export holds every key with its metadata, encrypted under a
+//! passphrase chosen at export time rather than the vault key. It can be
+//! imported into any vault, on any machine, without the PIN of the vault it
+//! came from.
+//!
+//! # Format
+//!
+//! All integers are little-endian.
+//!
+//! | Offset     | Size | Field                                             |
+//! |------------|------|---------------------------------------------------|
+//! | 0          | 8    | Magic, `VULTBAK\0`                                |
+//! | 8          | 2    | Format version (currently 1)                      |
+//! | 10         | 2    | Length `L` of the KDF parameters                  |
+//! | 12         | `L`  | KDF parameters, e.g. `argon2id$m=65536,t=3,p=4`   |
+//! | 12 + `L`   | 32   | Argon2id salt                                     |
+//! | 44 + `L`   | 12   | AES-GCM nonce                                     |
+//! | 56 + `L`   | rest | AES-256-GCM ciphertext and tag of the payload     |
+//!
+//! The key is derived from the passphrase with the stored KDF parameters
+//! and salt. Everything before the nonce is passed as associated data, so
+//! a modified header fails to decrypt like a modified payload does. The
+//! payload is the JSON form of [`VaultExport`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::crypto::KdfParams;
+//! use vult::export::VaultExport;
+//! use vult::services::ImportOptions;
+//!
+//! let bytes = VaultExport::from_vault(vault.keys()).await?
+//!     .seal("correct horse battery", &KdfParams::default())?;
+//!
+//! let export = VaultExport::open(&bytes, "correct horse battery")?;
+//! other_vault.keys().import(&export.imported_keys(), ImportOptions::default()).await?;
+//! ```
+//!
+//! Keys can also be exported as a KeePass database with
+//! [`crate::kdbx::write`]; [`ExportFormat`] names the two formats.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::core::{MAX_PASSPHRASE_LENGTH, MIN_PASSPHRASE_LENGTH};
+use crate::crypto::{
+    decrypt_with_aad, derive_key_from_pin_with_params, encrypt_with_aad, generate_salt,
+    EncryptedData, KdfParams,
+};
+use crate::error::{Result, VaultError};
+use crate::services::{ApiKey, ImportedKey, KeyService};
+
+/// File extension of exports
+pub const EXPORT_FILE_EXTENSION: &str = "vultbak";
+
+/// Format version written by this build
+pub const FORMAT_VERSION: u16 = 1;
+
+/// First bytes of every export
+const MAGIC: &[u8; 8] = b"VULTBAK\0";
+
+/// Highest memory cost accepted from an export header (1 GiB)
+///
+/// Export files come from elsewhere, so their KDF costs are held to a lower
+/// bound than [`KdfParams::MAX_MEMORY_KIB`] to keep a crafted header from
+/// stalling the import.
+pub const MAX_IMPORT_MEMORY_KIB: u32 = 1024 * 1024;
+
+/// Highest iteration count accepted from an export header
+pub const MAX_IMPORT_ITERATIONS: u32 = 10;
+
+/// Salt and nonce lengths
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// A file format keys can be exported to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Encrypted `.vultbak` export, see [`VaultExport::seal`]
+    #[default]
+    Vultbak,
+    /// KeePass KDBX 4 database, see [`crate::kdbx::write`]
+    KeePass,
+}
+
+impl ExportFormat {
+    /// Every format, in the order of [`ExportFormat::NAMES`]
+    pub const ALL: [Self; 2] = [Self::Vultbak, Self::KeePass];
+
+    /// Names accepted by [`FromStr`]
+    pub const NAMES: [&'static str; 2] = ["vultbak", "keepass"];
+}
+
+impl FromStr for ExportFormat {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::NAMES
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(s))
+            .map(|i| Self::ALL[i])
+            .ok_or_else(|| {
+                VaultError::InvalidInput(format!(
+                    "unknown export format '{}' (expected {})",
+                    s,
+                    Self::NAMES.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = Self::ALL.iter().position(|format| format == self).unwrap();
+        f.write_str(Self::NAMES[i])
+    }
+}
+
+/// Contents of an export file.
+///
+/// Key values are zeroized when this is dropped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultExport {
+    /// When the export was made
+    pub exported_at: DateTime<Utc>,
+    /// Every key, with its value
+    pub keys: Vec<ApiKey>,
+}
+
+impl VaultExport {
+    /// Collects every key of an unlocked vault.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is locked
+    pub async fn from_vault(keys: &KeyService) -> Result<Self> {
+        Ok(Self {
+            exported_at: Utc::now(),
+            keys: keys.get_all().await?,
+        })
+    }
+
+    /// Returns the keys for [`KeyService::import`], keeping their
+    /// timestamps.
+    pub fn imported_keys(&self) -> Vec<ImportedKey> {
+        self.keys.iter().cloned().map(ImportedKey::from).collect()
+    }
+
+    /// Encrypts the export under `passphrase`.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::InvalidInput`] if the passphrase is not
+    ///   [`MIN_PASSPHRASE_LENGTH`] to [`MAX_PASSPHRASE_LENGTH`] characters
+    /// - [`VaultError::KeyDerivation`] if `kdf` is out of range, or above
+    ///   the limits [`Self::open`] accepts
+    pub fn seal(&self, passphrase: &str, kdf: &KdfParams) -> Result<Vec<u8>> {
+        let len = passphrase.chars().count();
+        if !(MIN_PASSPHRASE_LENGTH..=MAX_PASSPHRASE_LENGTH).contains(&len) {
+            return Err(VaultError::InvalidInput(format!(
+                "export passphrase must be {MIN_PASSPHRASE_LENGTH} to {MAX_PASSPHRASE_LENGTH} characters"
+            )));
+        }
+        check_import_kdf(kdf).map_err(VaultError::KeyDerivation)?;
+
+        let kdf_string = kdf.to_string();
+        let salt = generate_salt();
+        let mut header = Vec::with_capacity(12 + kdf_string.len() + SALT_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header.extend_from_slice(&(kdf_string.len() as u16).to_le_bytes());
+        header.extend_from_slice(kdf_string.as_bytes());
+        header.extend_from_slice(&salt);
+
+        let key = derive_key_from_pin_with_params(passphrase, &salt, kdf)
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+        let payload = Zeroizing::new(
+            serde_json::to_vec(self).map_err(|e| VaultError::Encryption(e.to_string()))?,
+        );
+        let encrypted = encrypt_with_aad(&payload, &key, &header)
+            .map_err(|e| VaultError::Encryption(e.to_string()))?;
+
+        let mut bytes = header;
+        bytes.extend_from_slice(&encrypted.nonce);
+        bytes.extend_from_slice(&encrypted.ciphertext);
+        Ok(bytes)
+    }
+
+    /// Decrypts an export file.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::InvalidInput`] if `bytes` is not an export, was
+    ///   written by a newer format version, or asks for KDF costs above
+    ///   [`MAX_IMPORT_MEMORY_KIB`] or [`MAX_IMPORT_ITERATIONS`]
+    /// - [`VaultError::Decryption`] if the passphrase is wrong or the file
+    ///   was modified
+    pub fn open(bytes: &[u8], passphrase: &str) -> Result<Self> {
+        let malformed = || VaultError::InvalidInput("not a .vultbak export file".to_string());
+
+        if bytes.len() < 12 || &bytes[..8] != MAGIC {
+            return Err(malformed());
+        }
+        let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if version > FORMAT_VERSION {
+            return Err(VaultError::InvalidInput(format!(
+                "export format version {version} is newer than this build supports ({FORMAT_VERSION}); please update vult"
+            )));
+        }
+        let kdf_len = u16::from_le_bytes([bytes[10], bytes[11]]) as usize;
+        let header_len = 12 + kdf_len + SALT_LEN;
+        if bytes.len() < header_len + NONCE_LEN {
+            return Err(malformed());
+        }
+
+        let kdf = std::str::from_utf8(&bytes[12..12 + kdf_len])
+            .map_err(|_| malformed())?
+            .parse::<KdfParams>()
+            .map_err(|e| VaultError::InvalidInput(e.to_string()))?;
+        check_import_kdf(&kdf).map_err(VaultError::InvalidInput)?;
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&bytes[12 + kdf_len..header_len]);
+
+        let key = derive_key_from_pin_with_params(passphrase, &salt, &kdf)
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+        let encrypted = EncryptedData {
+            nonce: bytes[header_len..header_len + NONCE_LEN].to_vec(),
+            ciphertext: bytes[header_len + NONCE_LEN..].to_vec(),
+        };
+        let payload = Zeroizing::new(
+            decrypt_with_aad(&encrypted, &key, &bytes[..header_len]).map_err(|_| {
+                VaultError::Decryption("wrong passphrase or damaged export file".to_string())
+            })?,
+        );
+
+        serde_json::from_slice(&payload).map_err(|e| VaultError::Decryption(e.to_string()))
+    }
+}
+
+/// Checks KDF costs against the limits for export headers.
+fn check_import_kdf(kdf: &KdfParams) -> std::result::Result<(), String> {
+    if kdf.memory_kib > MAX_IMPORT_MEMORY_KIB || kdf.iterations > MAX_IMPORT_ITERATIONS {
+        return Err(format!(
+            "export KDF costs {kdf} are above the import limit of m={MAX_IMPORT_MEMORY_KIB},t={MAX_IMPORT_ITERATIONS}"
+        ));
+    }
+    Ok(())
+}
+
+impl Drop for VaultExport {
+    fn drop(&mut self) {
+        for key in &mut self.keys {
+            key.key_value.zeroize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{ImportMode, ImportOptions, VaultManager};
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    /// Cheapest accepted costs, to keep the tests fast
+    fn test_kdf() -> KdfParams {
+        KdfParams::argon2id(KdfParams::MIN_MEMORY_KIB, 1, 1).unwrap()
+    }
+
+    async fn setup_vault(pin: &str) -> VaultManager {
+        let vault = VaultManager::new("sqlite::memory:").await.unwrap();
+        vault.auth().init_vault(pin).await.unwrap();
+        vault
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip_into_another_vault() {
+        let source = setup_vault("source-pin-123").await;
+        source
+            .keys()
+            .create(
+                Some("github"),
+                "token",
+                "ghp_123",
+                Some("https://api.github.com"),
+                Some("CI token"),
+            )
+            .await
+            .unwrap();
+        source
+            .keys()
+            .create(None, "loose", "v", None, None)
+            .await
+            .unwrap();
+
+        let bytes = VaultExport::from_vault(source.keys())
+            .await
+            .unwrap()
+            .seal(PASSPHRASE, &test_kdf())
+            .unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        assert!(!bytes.windows(7).any(|w| w == b"ghp_123"));
+
+        // The target vault has its own PIN and encrypted metadata
+        let target = setup_vault("target-pin-456").await;
+        target.keys().set_encrypted_metadata(true).await.unwrap();
+        let export = VaultExport::open(&bytes, PASSPHRASE).unwrap();
+        let summary = target
+            .keys()
+            .import(&export.imported_keys(), ImportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.imported(), 2);
+
+        let original = source.keys().get("github", "token").await.unwrap();
+        let key = target.keys().get("github", "token").await.unwrap();
+        assert_eq!(key.key_value, "ghp_123");
+        assert_eq!(key.api_url.as_deref(), Some("https://api.github.com"));
+        assert_eq!(key.description.as_deref(), Some("CI token"));
+        assert_eq!(key.created_at.timestamp(), original.created_at.timestamp());
+        assert_eq!(target.keys().get("", "loose").await.unwrap().key_value, "v");
+    }
+
+    #[tokio::test]
+    async fn test_merge_skips_and_replace_overwrites() {
+        let source = setup_vault("source-pin-123").await;
+        source
+            .keys()
+            .create(Some("github"), "token", "new", None, None)
+            .await
+            .unwrap();
+        source
+            .keys()
+            .create(Some("aws"), "key", "aws", None, None)
+            .await
+            .unwrap();
+        let export = VaultExport::from_vault(source.keys()).await.unwrap();
+
+        let target = setup_vault("target-pin-456").await;
+        target
+            .keys()
+            .create(Some("github"), "token", "old", None, None)
+            .await
+            .unwrap();
+        target
+            .keys()
+            .create(Some("local"), "only", "x", None, None)
+            .await
+            .unwrap();
+
+        let summary = target
+            .keys()
+            .import(&export.imported_keys(), ImportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.imported(), 1);
+        assert_eq!(summary.skipped, vec!["github/token"]);
+        let kept = target.keys().get("github", "token").await.unwrap();
+        assert_eq!(kept.key_value, "old");
+
+        let replace = ImportOptions {
+            mode: ImportMode::Replace,
+            ..ImportOptions::default()
+        };
+        let summary = target
+            .keys()
+            .import(&export.imported_keys(), replace)
+            .await
+            .unwrap();
+        assert_eq!((summary.replaced, summary.imported()), (3, 2));
+        let replaced = target.keys().get("github", "token").await.unwrap();
+        assert_eq!(replaced.key_value, "new");
+        assert!(!target.keys().exists("local", "only").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_failed_import_changes_nothing() {
+        let source = setup_vault("source-pin-123").await;
+        source
+            .keys()
+            .create(Some("github"), "token", "v", None, None)
+            .await
+            .unwrap();
+        let export = VaultExport::from_vault(source.keys()).await.unwrap();
+        let keys = export.imported_keys();
+        let twice: Vec<ImportedKey> = keys.iter().chain(&keys).cloned().collect();
+
+        let target = setup_vault("target-pin-456").await;
+        target
+            .keys()
+            .create(Some("local"), "only", "x", None, None)
+            .await
+            .unwrap();
+
+        let replace = ImportOptions {
+            mode: ImportMode::Replace,
+            ..ImportOptions::default()
+        };
+        let result = target.keys().import(&twice, replace).await;
+        assert!(matches!(result, Err(VaultError::DuplicateKey { .. })));
+        assert_eq!(target.keys().count().await.unwrap(), 1);
+        assert!(target.keys().exists("local", "only").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_open_rejects_wrong_passphrase_and_tampering() {
+        let vault = setup_vault("source-pin-123").await;
+        vault
+            .keys()
+            .create(None, "loose", "v", None, None)
+            .await
+            .unwrap();
+        let bytes = VaultExport::from_vault(vault.keys())
+            .await
+            .unwrap()
+            .seal(PASSPHRASE, &test_kdf())
+            .unwrap();
+
+        let result = VaultExport::open(&bytes, "wrong passphrase!");
+        assert!(matches!(result, Err(VaultError::Decryption(_))));
+
+        // Flip one bit of the salt, then of the ciphertext
+        for offset in [12 + test_kdf().to_string().len(), bytes.len() - 1] {
+            let mut tampered = bytes.clone();
+            tampered[offset] ^= 1;
+            let result = VaultExport::open(&tampered, PASSPHRASE);
+            assert!(matches!(result, Err(VaultError::Decryption(_))));
+        }
+
+        let mut newer = bytes.clone();
+        newer[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let result = VaultExport::open(&newer, PASSPHRASE);
+        assert!(matches!(result, Err(VaultError::InvalidInput(ref m)) if m.contains("newer")));
+
+        let result = VaultExport::open(b"SQLite format 3\0", PASSPHRASE);
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_kdf_costs_above_import_limit_are_refused() {
+        let export = VaultExport {
+            exported_at: Utc::now(),
+            keys: Vec::new(),
+        };
+        let costly = [
+            KdfParams::argon2id(MAX_IMPORT_MEMORY_KIB + 1, 1, 1).unwrap(),
+            KdfParams::argon2id(KdfParams::MIN_MEMORY_KIB, MAX_IMPORT_ITERATIONS + 1, 1).unwrap(),
+        ];
+
+        for kdf in costly {
+            let result = export.seal(PASSPHRASE, &kdf);
+            assert!(matches!(result, Err(VaultError::KeyDerivation(_))));
+
+            // A crafted header is refused before any key derivation
+            let kdf_string = kdf.to_string();
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+            bytes.extend_from_slice(&(kdf_string.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(kdf_string.as_bytes());
+            bytes.extend_from_slice(&[0u8; SALT_LEN + NONCE_LEN + 16]);
+            let result = VaultExport::open(&bytes, PASSPHRASE);
+            assert!(
+                matches!(result, Err(VaultError::InvalidInput(ref m)) if m.contains("import limit"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_seal_requires_long_passphrase() {
+        let export = VaultExport {
+            exported_at: Utc::now(),
+            keys: Vec::new(),
+        };
+        let result = export.seal("short", &test_kdf());
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+}
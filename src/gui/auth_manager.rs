@@ -10,8 +10,10 @@
 
 use crate::core::DEFAULT_AUTO_LOCK_DURATION;
 use crate::crypto::VaultKey;
-use crate::services::VaultManager;
+use crate::services::{create_key_file, read_key_file, LockoutReset, VaultManager};
+use crate::shamir::Share;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tauri::Emitter;
@@ -45,8 +47,14 @@ pub enum AuthError {
     #[error("Vault is locked")]
     Locked,
 
-    #[error("Too many failed attempts")]
-    TooManyAttempts,
+    #[error("Too many failed attempts, try again in {0} seconds")]
+    TooManyAttempts(u64),
+
+    #[error("This vault requires a key file")]
+    KeyFileRequired,
+
+    #[error("Key file not found or unreadable: {0}")]
+    KeyFile(String),
 }
 
 /// Result type for authentication operations
@@ -61,7 +69,11 @@ impl From<crate::error::VaultError> for AuthError {
             crate::error::VaultError::NotInitialized => AuthError::NotInitialized,
             crate::error::VaultError::AlreadyInitialized => AuthError::AlreadyInitialized,
             crate::error::VaultError::Locked => AuthError::Locked,
-            crate::error::VaultError::TooManyAttempts => AuthError::TooManyAttempts,
+            crate::error::VaultError::TooManyAttempts { retry_after } => {
+                AuthError::TooManyAttempts(retry_after)
+            }
+            crate::error::VaultError::KeyFileRequired => AuthError::KeyFileRequired,
+            crate::error::VaultError::KeyFileUnavailable(s) => AuthError::KeyFile(s),
             crate::error::VaultError::Database(s) => AuthError::Database(s),
             crate::error::VaultError::Encryption(s) => AuthError::Crypto(s),
             crate::error::VaultError::Decryption(s) => AuthError::Crypto(s),
@@ -132,22 +144,52 @@ impl AuthManager {
         self.vault.is_initialized().await.map_err(AuthError::from)
     }
 
-    /// Initializes the vault with a new PIN
-    pub async fn initialize(&self, pin: &str) -> Result<()> {
+    /// Initializes the vault with a new PIN and optional key file.
+    ///
+    /// A key file that doesn't exist yet is created with random contents.
+    pub async fn initialize(&self, pin: &str, key_file: Option<&Path>) -> Result<()> {
+        let key_file = match key_file {
+            Some(path) if path.exists() => Some(read_key_file(path)?),
+            Some(path) => Some(create_key_file(path)?),
+            None => None,
+        };
+        self.vault
+            .auth()
+            .init_vault_with_key_file(pin, key_file.as_deref())
+            .await
+            .map_err(AuthError::from)?;
+        self.update_state_unlocked().await;
+        Ok(())
+    }
+
+    /// Unlocks the vault with a PIN and, if required, a key file
+    pub async fn unlock(&self, pin: &str, key_file: Option<&Path>) -> Result<()> {
+        let key_file = key_file.map(read_key_file).transpose()?;
         self.vault
             .auth()
-            .init_vault(pin)
+            .unlock_with_key_file(pin, key_file.as_deref())
             .await
             .map_err(AuthError::from)?;
         self.update_state_unlocked().await;
         Ok(())
     }
 
-    /// Unlocks the vault with a PIN
-    pub async fn unlock(&self, pin: &str) -> Result<()> {
+    /// Clears a failed-unlock lockout with a key-file slot or recovery
+    /// shares, and unlocks the vault
+    pub async fn reset_lockout(&self, key_file: Option<&Path>, shares: &[String]) -> Result<()> {
+        let key_file = key_file.map(read_key_file).transpose()?;
+        let shares = shares
+            .iter()
+            .map(|share| share.parse::<Share>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| AuthError::from(crate::error::VaultError::InvalidInput(e.to_string())))?;
+        let credential = match key_file.as_deref() {
+            Some(contents) => LockoutReset::KeyFile(contents),
+            None => LockoutReset::Shares(&shares),
+        };
         self.vault
             .auth()
-            .unlock(pin)
+            .reset_lockout(credential)
             .await
             .map_err(AuthError::from)?;
         self.update_state_unlocked().await;
@@ -8,8 +8,10 @@ use super::auth_manager::AuthManager;
 use crate::clipboard::ClipboardManager;
 use crate::core::validate_pin;
 use crate::database::{ApiKey, ApiKeyWithSecret, CreateApiKey, UpdateApiKey};
-use crate::services::key_service::UpdateKeyRequest;
+use crate::services::key_service::{DueKey, KeyHistory, UpdateKeyRequest};
+use crate::services::{AuditEntry, AuditEvent, AuditFilter, AuditReport};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -50,30 +52,53 @@ pub struct AuthState {
 // Authentication Commands
 // =============================================================================
 
-/// Initializes the vault with a new PIN
+/// Initializes the vault with a new PIN and optional key file
 #[tauri::command]
 pub async fn init_vault(
     pin: String,
+    key_file_path: Option<String>,
     auth_manager: tauri::State<'_, Arc<AuthManager>>,
 ) -> Result<CommandResponse<()>, String> {
     // Validate PIN
     validate_pin(&pin).map_err(|e| e.to_string())?;
 
     auth_manager
-        .initialize(&pin)
+        .initialize(&pin, key_file_path.as_deref().map(Path::new))
         .await
         .map_err(|e| e.to_string())?;
 
     Ok(CommandResponse::success(()))
 }
 
-/// Unlocks the vault with a PIN
+/// Unlocks the vault with a PIN and, if required, a key file
 #[tauri::command]
 pub async fn unlock_vault(
     pin: String,
+    key_file_path: Option<String>,
     auth_manager: tauri::State<'_, Arc<AuthManager>>,
 ) -> Result<CommandResponse<()>, String> {
-    auth_manager.unlock(&pin).await.map_err(|e| e.to_string())?;
+    auth_manager
+        .unlock(&pin, key_file_path.as_deref().map(Path::new))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(CommandResponse::success(()))
+}
+
+/// Clears a failed-unlock lockout with a key file that opens a key-file
+/// slot or, without one, recovery shares, and unlocks the vault
+#[tauri::command]
+pub async fn reset_lockout(
+    key_file_path: Option<String>,
+    shares: Option<Vec<String>>,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<()>, String> {
+    auth_manager
+        .reset_lockout(
+            key_file_path.as_deref().map(Path::new),
+            &shares.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
     Ok(CommandResponse::success(()))
 }
 
@@ -289,6 +314,9 @@ pub async fn update_api_key(
         key_value: input.key_value,
         api_url: input.api_url, // Already Option<Option<String>>
         description: input.description, // Already Option<Option<String>>
+        expires_at: None,
+        rotate_every: None,
+        reason: None,
     };
 
     auth_manager
@@ -344,6 +372,175 @@ pub async fn delete_api_key(
     Ok(CommandResponse::success(()))
 }
 
+// =============================================================================
+// Version History Commands
+// =============================================================================
+
+/// Lists the previous values of an API key, without the values
+#[tauri::command]
+pub async fn get_key_history(
+    id: String,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<KeyHistory>, String> {
+    auth_manager.update_activity().await;
+
+    let history = auth_manager
+        .vault()
+        .keys()
+        .history(&id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(CommandResponse::success(history))
+}
+
+/// Gets an API key with the value it held at a given version
+#[tauri::command]
+pub async fn get_key_version(
+    id: String,
+    version: i64,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<ApiKeyWithSecret>, String> {
+    auth_manager.update_activity().await;
+
+    let key = auth_manager
+        .vault()
+        .keys()
+        .get_version(&id, version)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let result = ApiKeyWithSecret {
+        api_key: ApiKey {
+            id: key.id,
+            app_name: key.app_name,
+            key_name: key.key_name,
+            api_url: key.api_url,
+            description: key.description,
+            created_at: key.created_at,
+            updated_at: key.updated_at,
+        },
+        key_value: key.key_value,
+    };
+
+    Ok(CommandResponse::success(result))
+}
+
+/// Restores a previous value of an API key, returning the new version
+#[tauri::command]
+pub async fn rollback_api_key(
+    id: String,
+    version: i64,
+    reason: Option<String>,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<i64>, String> {
+    auth_manager.update_activity().await;
+
+    let new_version = auth_manager
+        .vault()
+        .keys()
+        .rollback(&id, version, reason.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(CommandResponse::success(new_version))
+}
+
+/// Sets how many previous values are kept for an API key (None = default)
+#[tauri::command]
+pub async fn set_key_history_limit(
+    id: String,
+    limit: Option<u32>,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<usize>, String> {
+    auth_manager.update_activity().await;
+
+    let dropped = auth_manager
+        .vault()
+        .keys()
+        .set_history_limit(&id, limit)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(CommandResponse::success(dropped))
+}
+
+/// Lists keys that expire or are due for rotation within `within_days`
+/// (default 30), including overdue ones, soonest first
+#[tauri::command]
+pub async fn list_due_keys(
+    within_days: Option<u32>,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<Vec<DueKey>>, String> {
+    auth_manager.update_activity().await;
+
+    let within = chrono::Duration::days(i64::from(within_days.unwrap_or(30)));
+    let due = auth_manager
+        .vault()
+        .keys()
+        .due(chrono::Utc::now() + within)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(CommandResponse::success(due))
+}
+
+// =============================================================================
+// Audit Log Commands
+// =============================================================================
+
+/// Lists audit log entries, oldest first, optionally filtered
+#[tauri::command]
+pub async fn get_audit_log(
+    event: Option<AuditEvent>,
+    key_id: Option<String>,
+    limit: Option<u32>,
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<Vec<AuditEntry>>, String> {
+    auth_manager.update_activity().await;
+
+    let vault = auth_manager.vault();
+    if !vault.auth().is_unlocked_async().await {
+        return Err(crate::error::VaultError::Locked.to_string());
+    }
+
+    let filter = AuditFilter {
+        event,
+        key_id,
+        limit,
+        ..AuditFilter::default()
+    };
+    let entries = vault
+        .audit()
+        .entries(&filter)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(CommandResponse::success(entries))
+}
+
+/// Checks the audit log for edited, deleted or reordered entries
+#[tauri::command]
+pub async fn verify_audit_log(
+    auth_manager: tauri::State<'_, Arc<AuthManager>>,
+) -> Result<CommandResponse<AuditReport>, String> {
+    auth_manager.update_activity().await;
+
+    let vault = auth_manager.vault();
+    let vault_key = vault
+        .auth()
+        .get_vault_key()
+        .await
+        .map_err(|e| e.to_string())?;
+    let report = vault
+        .audit()
+        .verify(&vault_key)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(CommandResponse::success(report))
+}
+
 // =============================================================================
 // Clipboard Commands
 // =============================================================================
@@ -368,6 +565,13 @@ pub async fn copy_to_clipboard(
         .copy_with_timeout(api_key.key_value.clone(), Duration::from_secs(30))
         .await;
 
+    auth_manager
+        .vault()
+        .keys()
+        .record_copy(&api_key)
+        .await
+        .map_err(|e| e.to_string())?;
+
     Ok(CommandResponse::success(api_key.key_value))
 }
 
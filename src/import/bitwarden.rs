@@ -0,0 +1,166 @@
+//! Bitwarden JSON exports.
+//!
+//! Only unencrypted exports can be read. Every item becomes an app named
+//! after it, holding its login password, username and TOTP secret plus its
+//! custom fields. A secure note becomes a single `notes` key. The first URI
+//! of a login is kept as the URL and the item notes as the description.
+
+use serde::Deserialize;
+
+use crate::error::{Result, VaultError};
+use crate::services::CreateKeyRequest;
+
+use super::request;
+
+/// Item type of secure notes
+const SECURE_NOTE: u8 = 2;
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(rename = "type")]
+    kind: u8,
+    name: String,
+    notes: Option<String>,
+    login: Option<Login>,
+    fields: Option<Vec<Field>>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    uris: Option<Vec<Uri>>,
+}
+
+#[derive(Deserialize)]
+struct Uri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    name: Option<String>,
+    value: Option<String>,
+}
+
+/// Parses the items of a Bitwarden export.
+pub(super) fn parse(text: &str) -> Result<Vec<CreateKeyRequest>> {
+    let export: Export = serde_json::from_str(text)
+        .map_err(|e| VaultError::InvalidInput(format!("not a Bitwarden JSON export: {}", e)))?;
+    if export.encrypted {
+        return Err(VaultError::InvalidInput(
+            "encrypted Bitwarden exports are not supported; export as unencrypted JSON".into(),
+        ));
+    }
+
+    let mut requests = Vec::new();
+    for item in &export.items {
+        let app = Some(item.name.as_str());
+        let notes = item.notes.as_deref();
+
+        if item.kind == SECURE_NOTE {
+            requests.extend(request(app, "notes", notes.unwrap_or(""), None, None));
+        }
+
+        let mut url = None;
+        if let Some(login) = &item.login {
+            url = login
+                .uris
+                .iter()
+                .flatten()
+                .find_map(|uri| uri.uri.as_deref());
+            for (key_name, value) in [
+                ("password", &login.password),
+                ("username", &login.username),
+                ("totp", &login.totp),
+            ] {
+                let value = value.as_deref().unwrap_or("");
+                requests.extend(request(app, key_name, value, url, notes));
+            }
+        }
+
+        for field in item.fields.iter().flatten() {
+            let (Some(name), Some(value)) = (&field.name, &field.value) else {
+                continue;
+            };
+            requests.extend(request(app, name, value, url, notes));
+        }
+    }
+
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+        "encrypted": false,
+        "folders": [],
+        "items": [
+            {
+                "id": "1", "folderId": null, "type": 1, "name": "GitHub",
+                "notes": "CI account",
+                "fields": [
+                    {"name": "api key", "value": "ghp_123", "type": 1},
+                    {"name": "linked", "value": null, "type": 3}
+                ],
+                "login": {
+                    "uris": [{"match": null, "uri": "https://github.com"}],
+                    "username": "ci-bot", "password": "hunter2", "totp": null
+                }
+            },
+            {"id": "2", "type": 2, "name": "Recovery codes", "notes": "a b c",
+             "secureNote": {"type": 0}, "fields": null},
+            {"id": "3", "type": 1, "name": "Empty",
+             "login": {"uris": null, "username": null, "password": "", "totp": null}}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_items() {
+        let requests = parse(EXPORT).unwrap();
+        let keys: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                (
+                    r.app_name.as_deref().unwrap(),
+                    r.key_name.as_str(),
+                    r.key_value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("GitHub", "password", "hunter2"),
+                ("GitHub", "username", "ci-bot"),
+                ("GitHub", "api key", "ghp_123"),
+                ("Recovery codes", "notes", "a b c"),
+            ]
+        );
+
+        assert_eq!(requests[0].api_url.as_deref(), Some("https://github.com"));
+        assert_eq!(requests[0].description.as_deref(), Some("CI account"));
+        assert_eq!(requests[3].description, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_encrypted_and_foreign_files() {
+        let result = parse(r#"{"encrypted": true, "items": []}"#);
+        assert!(
+            matches!(result, Err(VaultError::InvalidInput(ref m)) if m.contains("unencrypted"))
+        );
+
+        let result = parse(r#"{"secrets": []}"#);
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,181 @@
+//! `.env` files.
+//!
+//! Each `NAME=value` line becomes a key named `NAME` with no app. Lines may
+//! start with `export`, and `#` starts a comment on its own line or after
+//! whitespace in an unquoted value. Values can be quoted:
+//!
+//! - `"..."` may span lines and understands `\n`, `\r`, `\t`, `\"`, `\\`
+//!   and `\$` escapes
+//! - `'...'` may span lines and is taken literally
+
+use crate::error::{Result, VaultError};
+use crate::manifest::is_valid_env_name;
+use crate::services::CreateKeyRequest;
+
+use super::request;
+
+/// Parses the variables of a `.env` file.
+pub(super) fn parse(text: &str) -> Result<Vec<CreateKeyRequest>> {
+    let invalid = |line: usize, message: &str| {
+        VaultError::InvalidInput(format!("line {}: {}", line, message))
+    };
+
+    let mut requests = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let line = line.trim_start();
+        if line.trim_end().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line
+            .strip_prefix("export ")
+            .map(str::trim_start)
+            .unwrap_or(line);
+        let Some((name, rest)) = line.split_once('=') else {
+            return Err(invalid(line_number, "expected NAME=value"));
+        };
+        let name = name.trim();
+        if !is_valid_env_name(name) {
+            return Err(invalid(
+                line_number,
+                &format!("'{}' is not a valid variable name", name),
+            ));
+        }
+
+        let rest = rest.trim_start();
+        let value = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let mut raw = rest[1..].to_string();
+                let end = loop {
+                    if let Some(end) = closing_quote(&raw, quote) {
+                        break end;
+                    }
+                    let Some((_, next)) = lines.next() else {
+                        return Err(invalid(line_number, "unterminated quoted value"));
+                    };
+                    raw.push('\n');
+                    raw.push_str(next);
+                };
+                raw.truncate(end);
+                if quote == '"' {
+                    unescape(&raw)
+                } else {
+                    raw
+                }
+            }
+            _ => strip_comment(rest).trim_end().to_string(),
+        };
+
+        requests.extend(request(None, name, &value, None, None));
+    }
+
+    Ok(requests)
+}
+
+/// Returns the byte offset of the quote closing `text`, skipping escaped
+/// double quotes.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            _ if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Resolves the escapes of a double-quoted value.
+fn unescape(text: &str) -> String {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some(c @ ('"' | '\\' | '$')) => value.push(c),
+            Some(c) => {
+                value.push('\\');
+                value.push(c);
+            }
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+/// Cuts an unquoted value at a `#` that follows whitespace.
+fn strip_comment(value: &str) -> &str {
+    let mut previous = None;
+    for (i, c) in value.char_indices() {
+        if c == '#' && previous.is_some_and(char::is_whitespace) {
+            return &value[..i];
+        }
+        previous = Some(c);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str) -> Vec<(String, String)> {
+        parse(text)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.key_name, r.key_value))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_plain_and_exported_values() {
+        let text = "# comment\n\nOPENAI_API_KEY=sk-123\nexport GITHUB_TOKEN = ghp_1 # CI\nURL=http://x/#frag\nEMPTY=\n";
+        assert_eq!(
+            values(text),
+            vec![
+                ("OPENAI_API_KEY".into(), "sk-123".into()),
+                ("GITHUB_TOKEN".into(), "ghp_1".into()),
+                ("URL".into(), "http://x/#frag".into()),
+            ]
+        );
+        assert!(parse(text).unwrap().iter().all(|r| r.app_name.is_none()));
+    }
+
+    #[test]
+    fn test_parse_quoted_values() {
+        let text = "A=\"line1\\nsay \\\"hi\\\" # not a comment\"\nB='raw \\n $x'\nC=\"multi\nline\" # done\n";
+        assert_eq!(
+            values(text),
+            vec![
+                ("A".into(), "line1\nsay \"hi\" # not a comment".into()),
+                ("B".into(), "raw \\n $x".into()),
+                ("C".into(), "multi\nline".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_reports_bad_lines() {
+        for (text, line) in [
+            ("A=1\nnot a variable\n", "line 2:"),
+            ("1BAD=x", "line 1:"),
+            ("A=1\nB=\"open\nstill open", "line 2:"),
+        ] {
+            let result = parse(text);
+            assert!(
+                matches!(result, Err(VaultError::InvalidInput(ref m)) if m.starts_with(line)),
+                "{text:?} gave {result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Importers for other secret managers' export files.
+//!
+//! Each [`ImportFormat`] reads its source into [`ImportedKey`]s, which
+//! [`KeyService::import`](crate::services::KeyService::import) then writes
+//! in a single transaction.
+//!
+//! | Format | Source | App name | Key names |
+//! |---|---|---|---|
+//! | `env` | `.env` file | none | variable names |
+//! | `bitwarden` | unencrypted Bitwarden JSON export | item name | `password`, `username`, `totp`, custom fields |
+//! | `1password` | 1Password CSV export | item title | `password`, `username`, `otp` |
+//! | `pass` | `pass` password store directory | first directory | rest of the path |
+//! | `keepass` | KeePass KDBX 4 database | group path | entry titles |
+//!
+//! Item URLs become the key URL and notes the description. Entries
+//! without a value are left out, and a `/` in an app name is replaced with
+//! `-` so every key can still be referred to as `app/key`. KeePass
+//! databases are read by [`crate::kdbx`], which also keeps entry times.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::import::ImportFormat;
+//! use vult::services::ImportOptions;
+//!
+//! let keys = ImportFormat::Bitwarden.read(Path::new("bitwarden.json"), None)?;
+//! let summary = vault.keys().import(&keys, ImportOptions::default()).await?;
+//! ```
+
+mod bitwarden;
+mod env;
+mod onepassword;
+mod pass;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::{Result, VaultError};
+use crate::kdbx;
+use crate::services::{CreateKeyRequest, ImportedKey};
+
+/// A file format keys can be imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// `NAME=value` lines of a `.env` file
+    Env,
+    /// Unencrypted JSON export of Bitwarden
+    Bitwarden,
+    /// CSV export of 1Password
+    OnePassword,
+    /// Directory tree of a `pass` password store
+    Pass,
+    /// KeePass KDBX 4 database
+    KeePass,
+}
+
+impl ImportFormat {
+    /// Every format, in the order of [`ImportFormat::NAMES`]
+    pub const ALL: [Self; 5] = [
+        Self::Env,
+        Self::Bitwarden,
+        Self::OnePassword,
+        Self::Pass,
+        Self::KeePass,
+    ];
+
+    /// Names accepted by [`FromStr`]
+    pub const NAMES: [&'static str; 5] = ["env", "bitwarden", "1password", "pass", "keepass"];
+
+    /// Whether the source is encrypted with a password [`ImportFormat::read`]
+    /// needs.
+    pub fn needs_password(self) -> bool {
+        self == Self::KeePass
+    }
+
+    /// Reads every key from `path`, a file or, for [`ImportFormat::Pass`],
+    /// the store directory. `password` opens a KeePass database and is
+    /// ignored by the other formats.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Io`] if the source cannot be read
+    /// - [`VaultError::InvalidInput`] if it is not in this format, or a
+    ///   password is needed but missing
+    /// - [`VaultError::Decryption`] if the password is wrong
+    pub fn read(self, path: &Path, password: Option<&str>) -> Result<Vec<ImportedKey>> {
+        let read_error = |e: std::io::Error| VaultError::Io(format!("{}: {}", path.display(), e));
+        let result = match self {
+            Self::Pass => pass::read(path).map(from_requests),
+            Self::KeePass => {
+                let password = password.ok_or_else(|| {
+                    VaultError::InvalidInput("a KeePass database needs its password".into())
+                })?;
+                kdbx::read(&std::fs::read(path).map_err(read_error)?, password)
+            }
+            _ => {
+                let text = std::fs::read_to_string(path).map_err(read_error)?;
+                self.parse(&text).map(from_requests)
+            }
+        };
+        result.map_err(|e| match e {
+            VaultError::InvalidInput(message) => {
+                VaultError::InvalidInput(format!("{}: {}", path.display(), message))
+            }
+            other => other,
+        })
+    }
+
+    /// Parses the contents of an export file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::InvalidInput`] if `text` is not in this format,
+    /// or for [`ImportFormat::Pass`] and [`ImportFormat::KeePass`], which
+    /// are a directory and an encrypted binary file.
+    pub fn parse(self, text: &str) -> Result<Vec<CreateKeyRequest>> {
+        match self {
+            Self::Env => env::parse(text),
+            Self::Bitwarden => bitwarden::parse(text),
+            Self::OnePassword => onepassword::parse(text),
+            Self::Pass => Err(VaultError::InvalidInput(
+                "a pass store is a directory, not a file".into(),
+            )),
+            Self::KeePass => Err(VaultError::InvalidInput(
+                "a KeePass database is encrypted, not text".into(),
+            )),
+        }
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::NAMES
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(s))
+            .map(|i| Self::ALL[i])
+            .ok_or_else(|| {
+                VaultError::InvalidInput(format!(
+                    "unknown import format '{}' (expected {})",
+                    s,
+                    Self::NAMES.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = Self::ALL.iter().position(|format| format == self).unwrap();
+        f.write_str(Self::NAMES[i])
+    }
+}
+
+/// Wraps the requests of a format that keeps no times.
+fn from_requests(requests: Vec<CreateKeyRequest>) -> Vec<ImportedKey> {
+    requests.into_iter().map(ImportedKey::from).collect()
+}
+
+/// Builds the request for one imported secret, or `None` if it has no
+/// value.
+///
+/// Names, URL and description are trimmed, and empty ones dropped.
+pub(crate) fn request(
+    app_name: Option<&str>,
+    key_name: &str,
+    key_value: &str,
+    api_url: Option<&str>,
+    description: Option<&str>,
+) -> Option<CreateKeyRequest> {
+    let text = |s: Option<&str>| {
+        s.map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+    let key_name = key_name.trim();
+    if key_name.is_empty() || key_value.is_empty() {
+        return None;
+    }
+
+    Some(CreateKeyRequest {
+        app_name: text(app_name).map(|app| app.replace('/', "-")),
+        key_name: key_name.to_string(),
+        key_value: key_value.to_string(),
+        api_url: text(api_url),
+        description: text(description),
+        expires_at: None,
+        rotate_every: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_names_roundtrip() {
+        for format in ImportFormat::ALL {
+            assert_eq!(format.to_string().parse::<ImportFormat>().unwrap(), format);
+        }
+        assert_eq!(
+            "1Password".parse::<ImportFormat>().unwrap(),
+            ImportFormat::OnePassword
+        );
+        assert!(matches!(
+            "lastpass".parse::<ImportFormat>(),
+            Err(VaultError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_request_cleans_up_fields() {
+        let cleaned = request(Some(" Work/GitHub "), " token ", "v", Some(" "), Some("n")).unwrap();
+        assert_eq!(cleaned.app_name.as_deref(), Some("Work-GitHub"));
+        assert_eq!(cleaned.key_name, "token");
+        assert_eq!(cleaned.api_url, None);
+        assert_eq!(cleaned.description.as_deref(), Some("n"));
+
+        assert!(request(None, "token", "", None, None).is_none());
+    }
+
+    #[test]
+    fn test_read_prefixes_errors_with_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let result = ImportFormat::Bitwarden.read(&path, None);
+        assert!(
+            matches!(result, Err(VaultError::InvalidInput(ref m)) if m.starts_with(&path.display().to_string()))
+        );
+        let result = ImportFormat::Env.read(&dir.path().join("missing.env"), None);
+        assert!(matches!(result, Err(VaultError::Io(_))));
+        let result = ImportFormat::KeePass.read(&path, None);
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+}
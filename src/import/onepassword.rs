@@ -0,0 +1,108 @@
+//! 1Password CSV exports.
+//!
+//! Columns are found by their header, so exports of 1Password 7 and 8 both
+//! work. Every row becomes an app named after its title, holding the
+//! password, username and one-time password secret. The URL and notes
+//! columns become the URL and description.
+
+use crate::error::{Result, VaultError};
+use crate::services::CreateKeyRequest;
+
+use super::request;
+
+/// Parses the rows of a 1Password CSV export.
+pub(super) fn parse(text: &str) -> Result<Vec<CreateKeyRequest>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let invalid = |e: csv::Error| VaultError::InvalidInput(format!("invalid CSV: {}", e));
+
+    let headers = reader.headers().map_err(invalid)?.clone();
+    let column = |names: &[&str]| {
+        headers.iter().position(|header| {
+            names
+                .iter()
+                .any(|name| header.trim().eq_ignore_ascii_case(name))
+        })
+    };
+    let (Some(title), Some(password)) = (column(&["title", "name"]), column(&["password"])) else {
+        return Err(VaultError::InvalidInput(
+            "not a 1Password CSV export: expected Title and Password columns".into(),
+        ));
+    };
+    let username = column(&["username"]);
+    let otp = column(&["otpauth", "one-time password"]);
+    let url = column(&["url", "website", "urls"]);
+    let notes = column(&["notes", "notesplain"]);
+
+    let mut requests = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        let field = |column: Option<usize>| column.and_then(|i| record.get(i));
+
+        let app = field(Some(title));
+        let (url, notes) = (field(url), field(notes));
+        for (key_name, column) in [
+            ("password", Some(password)),
+            ("username", username),
+            ("otp", otp),
+        ] {
+            let value = field(column).unwrap_or("");
+            requests.extend(request(app, key_name, value, url, notes));
+        }
+    }
+
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_1password_8_export() {
+        let text = "\u{feff}Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\n\
+                    OpenAI,https://platform.openai.com,me@example.com,\"sk-1,2\",,false,false,,\"Billing\nkey\"\n\
+                    Server,,,,otpauth://totp/x?secret=ABC,false,false,,\n";
+        let requests = parse(text).unwrap();
+        let keys: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                (
+                    r.app_name.as_deref().unwrap(),
+                    r.key_name.as_str(),
+                    r.key_value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("OpenAI", "password", "sk-1,2"),
+                ("OpenAI", "username", "me@example.com"),
+                ("Server", "otp", "otpauth://totp/x?secret=ABC"),
+            ]
+        );
+        assert_eq!(
+            requests[0].api_url.as_deref(),
+            Some("https://platform.openai.com")
+        );
+        assert_eq!(requests[0].description.as_deref(), Some("Billing\nkey"));
+    }
+
+    #[test]
+    fn test_parse_1password_7_export() {
+        let text = "title,website,username,password,notes\nAWS,,,AKIA123,\n";
+        let requests = parse(text).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].app_name.as_deref(), Some("AWS"));
+        assert_eq!(requests[0].key_value, "AKIA123");
+    }
+
+    #[test]
+    fn test_parse_rejects_other_csv() {
+        let result = parse("name,value\nA,1\n");
+        assert!(matches!(result, Err(VaultError::InvalidInput(ref m)) if m.contains("Password")));
+    }
+}
@@ -0,0 +1,193 @@
+//! `pass` password stores.
+//!
+//! Every file below the store directory is an entry. `.gpg` files are
+//! decrypted with `gpg`, as `pass show` does; other files are read as they
+//! are, so a decrypted copy of a store can be imported too. Hidden files
+//! and directories such as `.git` and `.gpg-id` are ignored.
+//!
+//! The first directory of an entry's path is its app and the rest its key
+//! name: `api/github/token.gpg` becomes `api` / `github/token`, and a file
+//! at the top of the store has no app. Following the `pass` convention,
+//! the first line of an entry is the value. A later `url:` line becomes the
+//! URL, an `otpauth://` line (from `pass otp`) a separate `<key>-otp` key,
+//! and the remaining lines the description.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{Result, VaultError};
+use crate::services::CreateKeyRequest;
+
+use super::request;
+
+/// Extension of encrypted entries
+const GPG_EXTENSION: &str = "gpg";
+
+/// Reads every entry of the store at `root`.
+pub(super) fn read(root: &Path) -> Result<Vec<CreateKeyRequest>> {
+    if !root.is_dir() {
+        return Err(VaultError::InvalidInput(
+            "not a directory; pass the root of the password store".into(),
+        ));
+    }
+
+    let mut files = Vec::new();
+    collect_entries(root, &mut files)?;
+    files.sort();
+
+    let mut requests = Vec::new();
+    for path in files {
+        let text = if path.extension().is_some_and(|ext| ext == GPG_EXTENSION) {
+            decrypt(&path)?
+        } else {
+            std::fs::read_to_string(&path)
+                .map_err(|e| VaultError::Io(format!("{}: {}", path.display(), e)))?
+        };
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        requests.extend(parse_entry(relative, &text));
+    }
+
+    Ok(requests)
+}
+
+/// Adds the files below `dir` to `files`, skipping hidden ones.
+fn collect_entries(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let io_error = |e: std::io::Error| VaultError::Io(format!("{}: {}", dir.display(), e));
+
+    for entry in std::fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type().map_err(io_error)?.is_dir() {
+            collect_entries(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Decrypts one entry with `gpg`.
+fn decrypt(path: &Path) -> Result<String> {
+    let output = Command::new("gpg")
+        .args(["--quiet", "--decrypt"])
+        .arg(path)
+        .output()
+        .map_err(|e| VaultError::Io(format!("could not run gpg: {}", e)))?;
+    if !output.status.success() {
+        return Err(VaultError::Decryption(format!(
+            "gpg could not decrypt {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| VaultError::InvalidInput(format!("{} is not UTF-8 text", path.display())))
+}
+
+/// Builds the keys of the entry at `relative`, a path inside the store.
+fn parse_entry(relative: &Path, text: &str) -> Vec<CreateKeyRequest> {
+    let mut names: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if let Some(last) = names.last_mut() {
+        if let Some(stem) = last.strip_suffix(".gpg") {
+            *last = stem.to_string();
+        }
+    }
+    let (app, key_name) = match names.split_first() {
+        Some((app, rest)) if !rest.is_empty() => (Some(app.as_str()), rest.join("/")),
+        _ => (None, names.join("/")),
+    };
+
+    let mut lines = text.lines();
+    let value = lines.next().unwrap_or("");
+    let mut url = None;
+    let mut otp = None;
+    let mut notes = Vec::new();
+    for line in lines {
+        let trimmed = line.trim();
+        let is_url = trimmed
+            .get(..4)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("url:"));
+        if is_url && url.is_none() {
+            url = Some(trimmed[4..].trim());
+        } else if trimmed.starts_with("otpauth://") && otp.is_none() {
+            otp = Some(trimmed);
+        } else {
+            notes.push(line);
+        }
+    }
+    let description = notes.join("\n");
+
+    let mut requests = Vec::new();
+    requests.extend(request(app, &key_name, value, url, Some(&description)));
+    if let Some(otp) = otp {
+        let otp_name = format!("{}-otp", key_name);
+        requests.extend(request(app, &otp_name, otp, url, None));
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry_fields() {
+        let requests = parse_entry(
+            Path::new("api/github/token.gpg"),
+            "ghp_123\nURL: https://github.com\nlogin: ci-bot\notpauth://totp/gh?secret=ABC\n",
+        );
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].app_name.as_deref(), Some("api"));
+        assert_eq!(requests[0].key_name, "github/token");
+        assert_eq!(requests[0].key_value, "ghp_123");
+        assert_eq!(requests[0].api_url.as_deref(), Some("https://github.com"));
+        assert_eq!(requests[0].description.as_deref(), Some("login: ci-bot"));
+        assert_eq!(requests[1].key_name, "github/token-otp");
+        assert_eq!(requests[1].key_value, "otpauth://totp/gh?secret=ABC");
+
+        let requests = parse_entry(Path::new("gmail.com"), "hunter2");
+        assert_eq!(requests[0].app_name, None);
+        assert_eq!(requests[0].key_name, "gmail.com");
+    }
+
+    #[test]
+    fn test_read_plaintext_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("aws/prod")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gpg-id"), "me@example.com\n").unwrap();
+        std::fs::write(root.join(".git/config"), "[core]\n").unwrap();
+        std::fs::write(root.join("aws/prod/key"), "AKIA123\n").unwrap();
+        std::fs::write(root.join("openai"), "sk-123\n").unwrap();
+        std::fs::write(root.join("empty"), "").unwrap();
+
+        let requests = read(root).unwrap();
+        let keys: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                (
+                    r.app_name.as_deref(),
+                    r.key_name.as_str(),
+                    r.key_value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (Some("aws"), "prod/key", "AKIA123"),
+                (None, "openai", "sk-123")
+            ]
+        );
+
+        let result = read(&root.join("openai"));
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+}
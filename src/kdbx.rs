@@ -0,0 +1,1338 @@
+//! KeePass databases (KDBX 4).
+//!
+//! [`read`] opens a database protected by a password and returns its
+//! entries as keys; [`write`] does the reverse, so keys can move between
+//! vult and KeePass in both directions. Only KDBX 4 with an Argon2 key
+//! derivation (Argon2d or Argon2id) is supported, with an AES-256 or
+//! ChaCha20 payload. Databases using AES-KDF, Twofish or a key file have to
+//! be re-saved in KeePass first.
+//!
+//! # Mapping
+//!
+//! | KeePass | vult |
+//! |---|---|
+//! | group path below the root group | app name, e.g. `Work/GitHub` becomes `Work-GitHub` |
+//! | entry in the root group | key without an app |
+//! | `Title` | key name |
+//! | `Password` | key value |
+//! | `URL`, or a custom `url` / `api_url` field | URL |
+//! | `Notes`, or a custom `description` field | description |
+//! | `UserName` and other plain custom fields | `Name: value` lines of the description |
+//! | protected custom field, e.g. `otp` | separate `<title>-<field>` key |
+//! | creation and modification times | `created_at` and `updated_at` |
+//!
+//! Entry history and the recycle bin are left out. [`write`] puts keys
+//! without an app in the root group and every app in a group of its own.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::kdbx::{self, KdbxOptions};
+//! use vult::services::ImportOptions;
+//!
+//! let bytes = kdbx::write(&vault.keys().get_all().await?, "correct horse battery", &KdbxOptions::default())?;
+//!
+//! let keys = kdbx::read(&bytes, "correct horse battery")?;
+//! other_vault.keys().import(&keys, ImportOptions::default()).await?;
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::core::{MAX_PASSPHRASE_LENGTH, MIN_PASSPHRASE_LENGTH};
+use crate::crypto::KdfParams;
+use crate::error::{Result, VaultError};
+use crate::import::request;
+use crate::services::{ApiKey, ImportedKey};
+
+/// File extension of KeePass databases
+pub const KDBX_FILE_EXTENSION: &str = "kdbx";
+
+/// First eight bytes of every KeePass 2 database
+const SIGNATURE: [u32; 2] = [0x9AA2_D903, 0xB54B_FB67];
+
+/// Format version written by [`write`]; [`read`] accepts any 4.x
+const VERSION_MAJOR: u16 = 4;
+const VERSION_MINOR: u16 = 0;
+
+/// Outer header field IDs
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_IV: u8 = 7;
+const HEADER_KDF: u8 = 11;
+
+/// Inner header field IDs
+const INNER_END: u8 = 0;
+const INNER_STREAM_ID: u8 = 1;
+const INNER_STREAM_KEY: u8 = 2;
+
+/// Inner stream cipher protecting passwords inside the XML
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+/// Cipher and key derivation UUIDs
+const AES256_UUID: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CHACHA20_UUID: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+const ARGON2D_UUID: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const ARGON2ID_UUID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+const AES_KDF_UUID: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+
+/// VariantDictionary version and value types used by [`write`]
+const VARIANT_VERSION: u16 = 0x0100;
+const VARIANT_END: u8 = 0x00;
+const VARIANT_U32: u8 = 0x04;
+const VARIANT_U64: u8 = 0x05;
+const VARIANT_BYTES: u8 = 0x42;
+
+/// Highest Argon2 memory cost accepted from a database header (1 GiB)
+///
+/// Databases come from elsewhere, so their costs are held below
+/// [`KdfParams::MAX_MEMORY_KIB`] to keep a crafted header from stalling
+/// [`read`]; KeePassXC defaults to 64 MiB. Iterations and lanes are capped
+/// at [`KdfParams::MAX_ITERATIONS`] and [`KdfParams::MAX_PARALLELISM`].
+pub const MAX_KDBX_MEMORY_KIB: u32 = 1024 * 1024;
+
+/// Payload block size of [`write`], as KeePass uses
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Seconds from 0001-01-01, where KeePass times start, to the Unix epoch
+const KEEPASS_EPOCH_OFFSET: i64 = 62_135_596_800;
+
+/// Name of the root group written by [`write`]
+const ROOT_GROUP_NAME: &str = "vult";
+
+/// Custom fields read as the URL of an entry without one
+const URL_FIELDS: [&str; 2] = ["url", "api_url"];
+
+/// Custom field read as the description of an entry without notes
+const DESCRIPTION_FIELD: &str = "description";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Cipher of the database payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KdbxCipher {
+    /// AES-256 in CBC mode, the KeePass default
+    #[default]
+    Aes256,
+    /// ChaCha20
+    ChaCha20,
+}
+
+impl KdbxCipher {
+    fn uuid(self) -> [u8; 16] {
+        match self {
+            Self::Aes256 => AES256_UUID,
+            Self::ChaCha20 => CHACHA20_UUID,
+        }
+    }
+
+    fn from_uuid(uuid: &[u8]) -> Result<Self> {
+        if uuid == AES256_UUID {
+            Ok(Self::Aes256)
+        } else if uuid == CHACHA20_UUID {
+            Ok(Self::ChaCha20)
+        } else {
+            Err(VaultError::InvalidInput(
+                "unsupported database cipher; choose AES-256 or ChaCha20 in KeePass".to_string(),
+            ))
+        }
+    }
+
+    fn iv_len(self) -> usize {
+        match self {
+            Self::Aes256 => 16,
+            Self::ChaCha20 => 12,
+        }
+    }
+
+    fn encrypt(self, key: &[u8; 32], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        let invalid = |e: cbc::cipher::InvalidLength| VaultError::Encryption(e.to_string());
+        match self {
+            Self::Aes256 => Ok(cbc::Encryptor::<aes::Aes256>::new_from_slices(key, iv)
+                .map_err(invalid)?
+                .encrypt_padded_vec_mut::<Pkcs7>(data)),
+            Self::ChaCha20 => {
+                let mut out = data.to_vec();
+                ChaCha20::new_from_slices(key, iv)
+                    .map_err(invalid)?
+                    .apply_keystream(&mut out);
+                Ok(out)
+            }
+        }
+    }
+
+    fn decrypt(self, key: &[u8; 32], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        let damaged = || VaultError::Decryption("KeePass database payload is damaged".to_string());
+        match self {
+            Self::Aes256 => cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv)
+                .map_err(|_| damaged())?
+                .decrypt_padded_vec_mut::<Pkcs7>(data)
+                .map_err(|_| damaged()),
+            Self::ChaCha20 => {
+                let mut out = data.to_vec();
+                ChaCha20::new_from_slices(key, iv)
+                    .map_err(|_| damaged())?
+                    .apply_keystream(&mut out);
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Settings of [`write`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KdbxOptions {
+    /// Payload cipher
+    pub cipher: KdbxCipher,
+    /// Argon2id costs of the password
+    pub kdf: KdfParams,
+}
+
+/// Reads every entry of a KDBX 4 database.
+///
+/// # Errors
+///
+/// - [`VaultError::InvalidInput`] if `bytes` is not a KDBX 4 database,
+///   uses a cipher or key derivation this build does not support, or asks
+///   for Argon2 costs above [`MAX_KDBX_MEMORY_KIB`],
+///   [`KdfParams::MAX_ITERATIONS`] or [`KdfParams::MAX_PARALLELISM`]
+/// - [`VaultError::Decryption`] if the password is wrong or the database
+///   was modified
+pub fn read(bytes: &[u8], password: &str) -> Result<Vec<ImportedKey>> {
+    let mut cursor = Cursor::new(bytes);
+    if cursor.u32().ok() != Some(SIGNATURE[0]) || cursor.u32().ok() != Some(SIGNATURE[1]) {
+        return Err(VaultError::InvalidInput(
+            "not a KeePass database".to_string(),
+        ));
+    }
+    let minor = cursor.u16()?;
+    let major = cursor.u16()?;
+    if major != VERSION_MAJOR {
+        return Err(VaultError::InvalidInput(format!(
+            "KDBX {major}.{minor} databases are not supported; save it as KDBX 4 in KeePass first"
+        )));
+    }
+
+    let header = OuterHeader::parse(&mut cursor)?;
+    let header_bytes = &bytes[..cursor.pos];
+    if Sha256::digest(header_bytes).as_slice() != cursor.take(32)? {
+        return Err(VaultError::InvalidInput(
+            "KeePass database header is damaged".to_string(),
+        ));
+    }
+    let header_mac = cursor.take(32)?;
+
+    let keys = Keys::derive(password, &header.master_seed, &header.kdf)?;
+    if keys
+        .mac(u64::MAX, &[header_bytes])
+        .verify_slice(header_mac)
+        .is_err()
+    {
+        return Err(VaultError::Decryption(
+            "wrong password or damaged KeePass database".to_string(),
+        ));
+    }
+
+    let encrypted = read_blocks(&mut cursor, &keys)?;
+    let mut payload = Zeroizing::new(header.cipher.decrypt(
+        &keys.cipher,
+        &header.iv,
+        &encrypted,
+    )?);
+    if header.compressed {
+        let mut inflated = Zeroizing::new(Vec::new());
+        GzDecoder::new(payload.as_slice())
+            .read_to_end(&mut inflated)
+            .map_err(|e| VaultError::Decryption(format!("KeePass database payload: {}", e)))?;
+        payload = inflated;
+    }
+
+    let (stream_key, xml) = parse_inner_header(&payload)?;
+    let xml = std::str::from_utf8(xml)
+        .map_err(|_| VaultError::InvalidInput("KeePass database XML is not UTF-8".to_string()))?;
+    XmlParser::new(inner_stream(stream_key)?).parse(xml)
+}
+
+/// Writes `keys` to a new KDBX 4 database protected by `password`.
+///
+/// # Errors
+///
+/// - [`VaultError::InvalidInput`] if the password is not
+///   [`MIN_PASSPHRASE_LENGTH`] to [`MAX_PASSPHRASE_LENGTH`] characters
+/// - [`VaultError::KeyDerivation`] if the KDF costs are out of range
+pub fn write(keys: &[ApiKey], password: &str, options: &KdbxOptions) -> Result<Vec<u8>> {
+    encrypt_database(password, options, |stream| write_xml(keys, stream))
+}
+
+/// Builds a database around the XML returned by `xml`, which protects its
+/// values with the inner stream it is given.
+fn encrypt_database(
+    password: &str,
+    options: &KdbxOptions,
+    xml: impl FnOnce(&mut ChaCha20) -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    let len = password.chars().count();
+    if !(MIN_PASSPHRASE_LENGTH..=MAX_PASSPHRASE_LENGTH).contains(&len) {
+        return Err(VaultError::InvalidInput(format!(
+            "KeePass password must be {MIN_PASSPHRASE_LENGTH} to {MAX_PASSPHRASE_LENGTH} characters"
+        )));
+    }
+    options
+        .kdf
+        .validate()
+        .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+
+    let header = OuterHeader {
+        cipher: options.cipher,
+        compressed: true,
+        master_seed: random_bytes(32),
+        iv: random_bytes(options.cipher.iv_len()),
+        kdf: Argon2Kdf {
+            algorithm: Algorithm::Argon2id,
+            version: Version::V0x13,
+            salt: random_bytes(32),
+            memory_kib: options.kdf.memory_kib,
+            iterations: options.kdf.iterations,
+            parallelism: options.kdf.parallelism,
+        },
+    };
+    let mut bytes = header.to_bytes();
+    let keys = Keys::derive(password, &header.master_seed, &header.kdf)?;
+    let header_hash = Sha256::digest(&bytes);
+    let header_mac = keys
+        .mac(u64::MAX, &[bytes.as_slice()])
+        .finalize()
+        .into_bytes();
+    bytes.extend_from_slice(&header_hash);
+    bytes.extend_from_slice(&header_mac);
+
+    let stream_key = Zeroizing::new(random_bytes(64));
+    let mut payload = Zeroizing::new(Vec::new());
+    write_field(
+        &mut payload,
+        INNER_STREAM_ID,
+        &INNER_STREAM_CHACHA20.to_le_bytes(),
+    );
+    write_field(&mut payload, INNER_STREAM_KEY, &stream_key);
+    write_field(&mut payload, INNER_END, &[]);
+    payload.extend_from_slice(&xml(&mut inner_stream(&stream_key)?)?);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&payload)
+        .map_err(|e| VaultError::Encryption(e.to_string()))?;
+    let compressed = Zeroizing::new(
+        encoder
+            .finish()
+            .map_err(|e| VaultError::Encryption(e.to_string()))?,
+    );
+    let encrypted = options
+        .cipher
+        .encrypt(&keys.cipher, &header.iv, &compressed)?;
+
+    for (index, block) in encrypted.chunks(BLOCK_SIZE).chain([&[][..]]).enumerate() {
+        let size = (block.len() as i32).to_le_bytes();
+        let index = index as u64;
+        let mac = keys.mac(index, &[&index.to_le_bytes(), &size, block]);
+        bytes.extend_from_slice(&mac.finalize().into_bytes());
+        bytes.extend_from_slice(&size);
+        bytes.extend_from_slice(block);
+    }
+    Ok(bytes)
+}
+
+/// Fields of the unencrypted outer header.
+struct OuterHeader {
+    cipher: KdbxCipher,
+    compressed: bool,
+    master_seed: Vec<u8>,
+    iv: Vec<u8>,
+    kdf: Argon2Kdf,
+}
+
+impl OuterHeader {
+    fn parse(cursor: &mut Cursor<'_>) -> Result<Self> {
+        let (mut cipher, mut compressed, mut master_seed, mut iv, mut kdf) =
+            (None, false, None, None, None);
+        loop {
+            let id = cursor.u8()?;
+            let len = cursor.u32()? as usize;
+            let data = cursor.take(len)?;
+            match id {
+                HEADER_END => break,
+                HEADER_CIPHER => cipher = Some(KdbxCipher::from_uuid(data)?),
+                HEADER_COMPRESSION => compressed = data != [0, 0, 0, 0],
+                HEADER_MASTER_SEED if data.len() == 32 => master_seed = Some(data.to_vec()),
+                HEADER_IV => iv = Some(data.to_vec()),
+                HEADER_KDF => kdf = Some(Argon2Kdf::parse(data)?),
+                _ => {}
+            }
+        }
+
+        let cipher = cipher.ok_or_else(damaged)?;
+        let iv = iv
+            .filter(|iv| iv.len() == cipher.iv_len())
+            .ok_or_else(damaged)?;
+        Ok(Self {
+            cipher,
+            compressed,
+            master_seed: master_seed.ok_or_else(damaged)?,
+            iv,
+            kdf: kdf.ok_or_else(damaged)?,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&SIGNATURE[0].to_le_bytes());
+        bytes.extend_from_slice(&SIGNATURE[1].to_le_bytes());
+        bytes.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        bytes.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        write_field(&mut bytes, HEADER_CIPHER, &self.cipher.uuid());
+        write_field(
+            &mut bytes,
+            HEADER_COMPRESSION,
+            &u32::from(self.compressed).to_le_bytes(),
+        );
+        write_field(&mut bytes, HEADER_MASTER_SEED, &self.master_seed);
+        write_field(&mut bytes, HEADER_IV, &self.iv);
+        write_field(&mut bytes, HEADER_KDF, &self.kdf.to_bytes());
+        write_field(&mut bytes, HEADER_END, b"\r\n\r\n");
+        bytes
+    }
+}
+
+/// Argon2 parameters of the KDF header field.
+struct Argon2Kdf {
+    algorithm: Algorithm,
+    version: Version,
+    salt: Vec<u8>,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Argon2Kdf {
+    /// Reads the parameters from a KeePass `VariantDictionary`.
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+        if cursor.u16()? >> 8 != VARIANT_VERSION >> 8 {
+            return Err(damaged());
+        }
+        let mut values = HashMap::new();
+        loop {
+            let kind = cursor.u8()?;
+            if kind == VARIANT_END {
+                break;
+            }
+            let name_len = cursor.u32()? as usize;
+            let name = String::from_utf8_lossy(cursor.take(name_len)?).into_owned();
+            let value_len = cursor.u32()? as usize;
+            values.insert(name, cursor.take(value_len)?);
+        }
+        let number = |name: &str| -> Result<u64> {
+            match values.get(name) {
+                Some(value) if value.len() == 4 => {
+                    Ok(u32::from_le_bytes((*value).try_into().unwrap()).into())
+                }
+                Some(value) if value.len() == 8 => {
+                    Ok(u64::from_le_bytes((*value).try_into().unwrap()))
+                }
+                _ => Err(damaged()),
+            }
+        };
+
+        let algorithm = match values.get("$UUID").copied() {
+            Some(uuid) if uuid == ARGON2D_UUID => Algorithm::Argon2d,
+            Some(uuid) if uuid == ARGON2ID_UUID => Algorithm::Argon2id,
+            Some(uuid) if uuid == AES_KDF_UUID => return Err(VaultError::InvalidInput(
+                "the database uses AES-KDF; switch its key derivation to Argon2 in KeePass first"
+                    .to_string(),
+            )),
+            _ => {
+                return Err(VaultError::InvalidInput(
+                    "unsupported key derivation function".to_string(),
+                ))
+            }
+        };
+        let version = match number("V")? {
+            0x10 => Version::V0x10,
+            0x13 => Version::V0x13,
+            _ => return Err(damaged()),
+        };
+        let (memory_kib, iterations, parallelism) =
+            (number("M")? / 1024, number("I")?, number("P")?);
+        if memory_kib > u64::from(MAX_KDBX_MEMORY_KIB)
+            || iterations > u64::from(KdfParams::MAX_ITERATIONS)
+            || parallelism > u64::from(KdfParams::MAX_PARALLELISM)
+        {
+            return Err(VaultError::InvalidInput(format!(
+                "database KDF costs m={memory_kib},t={iterations},p={parallelism} are above the import limit of m={MAX_KDBX_MEMORY_KIB},t={},p={}; lower them in KeePass first",
+                KdfParams::MAX_ITERATIONS,
+                KdfParams::MAX_PARALLELISM
+            )));
+        }
+
+        Ok(Self {
+            algorithm,
+            version,
+            salt: values.get("S").ok_or_else(damaged)?.to_vec(),
+            memory_kib: memory_kib as u32,
+            iterations: iterations as u32,
+            parallelism: parallelism as u32,
+        })
+    }
+
+    /// Writes the parameters as a KeePass `VariantDictionary`.
+    fn to_bytes(&self) -> Vec<u8> {
+        let uuid = match self.algorithm {
+            Algorithm::Argon2d => ARGON2D_UUID,
+            _ => ARGON2ID_UUID,
+        };
+        let memory = u64::from(self.memory_kib) * 1024;
+
+        let mut bytes = VARIANT_VERSION.to_le_bytes().to_vec();
+        write_variant(&mut bytes, VARIANT_BYTES, "$UUID", &uuid);
+        write_variant(&mut bytes, VARIANT_BYTES, "S", &self.salt);
+        write_variant(
+            &mut bytes,
+            VARIANT_U32,
+            "P",
+            &self.parallelism.to_le_bytes(),
+        );
+        write_variant(&mut bytes, VARIANT_U64, "M", &memory.to_le_bytes());
+        write_variant(
+            &mut bytes,
+            VARIANT_U64,
+            "I",
+            &u64::from(self.iterations).to_le_bytes(),
+        );
+        write_variant(
+            &mut bytes,
+            VARIANT_U32,
+            "V",
+            &(self.version as u32).to_le_bytes(),
+        );
+        bytes.push(VARIANT_END);
+        bytes
+    }
+}
+
+/// Keys derived from the password and master seed.
+struct Keys {
+    /// Payload cipher key
+    cipher: Zeroizing<[u8; 32]>,
+    /// Base of the per-block HMAC keys
+    hmac: Zeroizing<[u8; 64]>,
+}
+
+impl Keys {
+    fn derive(password: &str, master_seed: &[u8], kdf: &Argon2Kdf) -> Result<Self> {
+        let composite: Zeroizing<[u8; 32]> =
+            Zeroizing::new(Sha256::digest(Sha256::digest(password.as_bytes())).into());
+
+        let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+        let mut transformed = Zeroizing::new([0u8; 32]);
+        Argon2::new(kdf.algorithm, kdf.version, params)
+            .hash_password_into(composite.as_slice(), &kdf.salt, transformed.as_mut_slice())
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+
+        Ok(Self {
+            cipher: Zeroizing::new(
+                Sha256::new()
+                    .chain_update(master_seed)
+                    .chain_update(transformed.as_slice())
+                    .finalize()
+                    .into(),
+            ),
+            hmac: Zeroizing::new(
+                Sha512::new()
+                    .chain_update(master_seed)
+                    .chain_update(transformed.as_slice())
+                    .chain_update([1u8])
+                    .finalize()
+                    .into(),
+            ),
+        })
+    }
+
+    /// HMAC of `parts` under the key of block `index`; the header uses
+    /// index `u64::MAX`.
+    fn mac(&self, index: u64, parts: &[&[u8]]) -> HmacSha256 {
+        let key: Zeroizing<[u8; 64]> = Zeroizing::new(
+            Sha512::new()
+                .chain_update(index.to_le_bytes())
+                .chain_update(self.hmac.as_slice())
+                .finalize()
+                .into(),
+        );
+        let mut mac =
+            HmacSha256::new_from_slice(key.as_slice()).expect("HMAC accepts keys of any length");
+        for part in parts {
+            mac.update(part);
+        }
+        mac
+    }
+}
+
+/// Reads the HMAC-protected blocks of the encrypted payload.
+fn read_blocks(cursor: &mut Cursor<'_>, keys: &Keys) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut index = 0u64;
+    loop {
+        let mac = cursor.take(32)?;
+        let size = cursor.take(4)?;
+        let len =
+            usize::try_from(i32::from_le_bytes(size.try_into().unwrap())).map_err(|_| damaged())?;
+        let block = cursor.take(len)?;
+        if keys
+            .mac(index, &[&index.to_le_bytes(), size, block])
+            .verify_slice(mac)
+            .is_err()
+        {
+            return Err(VaultError::Decryption(format!(
+                "KeePass database is damaged (block {} fails its integrity check)",
+                index
+            )));
+        }
+        if block.is_empty() {
+            return Ok(data);
+        }
+        data.extend_from_slice(block);
+        index += 1;
+    }
+}
+
+/// Splits the decrypted payload into the inner stream key and the XML.
+fn parse_inner_header(payload: &[u8]) -> Result<(&[u8], &[u8])> {
+    let mut cursor = Cursor::new(payload);
+    let mut stream_key = None;
+    loop {
+        let id = cursor.u8()?;
+        let len = cursor.u32()? as usize;
+        let data = cursor.take(len)?;
+        match id {
+            INNER_END => break,
+            INNER_STREAM_ID if data != INNER_STREAM_CHACHA20.to_le_bytes() => {
+                return Err(VaultError::InvalidInput(
+                    "unsupported inner stream cipher; save the database as KDBX 4 in KeePass first"
+                        .to_string(),
+                ))
+            }
+            INNER_STREAM_KEY => stream_key = Some(data),
+            _ => {}
+        }
+    }
+    Ok((stream_key.ok_or_else(damaged)?, &payload[cursor.pos..]))
+}
+
+/// ChaCha20 stream that protects passwords inside the XML.
+fn inner_stream(key: &[u8]) -> Result<ChaCha20> {
+    let hash: Zeroizing<[u8; 64]> = Zeroizing::new(Sha512::digest(key).into());
+    ChaCha20::new_from_slices(&hash[..32], &hash[32..44])
+        .map_err(|e| VaultError::Encryption(e.to_string()))
+}
+
+/// Appends a header field: ID, length and data.
+fn write_field(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Appends a `VariantDictionary` item: type, name and value.
+fn write_variant(out: &mut Vec<u8>, kind: u8, name: &str, value: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn damaged() -> VaultError {
+    VaultError::InvalidInput("KeePass database is truncated or damaged".to_string())
+}
+
+fn xml_error(e: impl std::fmt::Display) -> VaultError {
+    VaultError::InvalidInput(format!("KeePass database XML: {}", e))
+}
+
+/// KeePass times are base64 seconds since 0001-01-01; KDBX 3 used text.
+fn decode_time(text: &str) -> Option<DateTime<Utc>> {
+    match BASE64.decode(text.trim()) {
+        Ok(bytes) if bytes.len() == 8 => {
+            let seconds = i64::from_le_bytes(bytes.try_into().unwrap());
+            DateTime::from_timestamp(seconds.checked_sub(KEEPASS_EPOCH_OFFSET)?, 0)
+        }
+        _ => DateTime::parse_from_rfc3339(text.trim())
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+    }
+}
+
+fn encode_time(time: DateTime<Utc>) -> String {
+    BASE64.encode((time.timestamp() + KEEPASS_EPOCH_OFFSET).to_le_bytes())
+}
+
+/// Little-endian reader over a byte slice.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(damaged)?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// A group being read, with the name entries get their app from.
+struct Group {
+    name: String,
+    /// Whether this is, or is inside, the recycle bin
+    recycled: bool,
+}
+
+/// String field of an entry.
+struct Field {
+    name: String,
+    value: Zeroizing<String>,
+    protected: bool,
+}
+
+/// An entry being read.
+#[derive(Default)]
+struct Entry {
+    fields: Vec<Field>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl Entry {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value.as_str())
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    /// Builds the keys of this entry, following the table in the module
+    /// documentation.
+    fn into_keys(self, app: Option<&str>) -> Vec<ImportedKey> {
+        let title = self.field("Title").unwrap_or_default();
+        let mut url = self.field("URL");
+        let mut notes = self.field("Notes");
+        let mut lines = Vec::new();
+        let mut secrets = Vec::new();
+        for field in &self.fields {
+            let name = field.name.as_str();
+            let value = field.value.as_str();
+            if matches!(name, "Title" | "Password" | "URL" | "Notes") || value.trim().is_empty() {
+                continue;
+            }
+            if field.protected {
+                secrets.push((format!("{}-{}", title, name), value));
+            } else if URL_FIELDS.iter().any(|f| f.eq_ignore_ascii_case(name)) && url.is_none() {
+                url = Some(value);
+            } else if name.eq_ignore_ascii_case(DESCRIPTION_FIELD) && notes.is_none() {
+                notes = Some(value);
+            } else {
+                lines.push(format!("{}: {}", name, value.trim()));
+            }
+        }
+        let description = notes
+            .into_iter()
+            .map(str::to_string)
+            .chain(lines)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let password = self.field("Password").unwrap_or_default();
+        let mut requests = Vec::new();
+        requests.extend(request(app, title, password, url, Some(&description)));
+        for (name, value) in secrets {
+            requests.extend(request(app, &name, value, url, None));
+        }
+        requests
+            .into_iter()
+            .map(|request| ImportedKey {
+                request,
+                created_at: self.created_at,
+                updated_at: self.updated_at.or(self.created_at),
+                rotated_at: None,
+            })
+            .collect()
+    }
+}
+
+/// Walks the XML document, collecting the keys of its entries.
+///
+/// Protected values are XORed with the inner stream in document order, so
+/// every one of them is decrypted, including those of skipped entries.
+struct XmlParser {
+    stream: ChaCha20,
+    /// Names of the open elements
+    path: Vec<String>,
+    /// Text of the innermost open element
+    text: String,
+    /// Whether the innermost open element is a protected value
+    protected: bool,
+    recycle_bin: Option<String>,
+    groups: Vec<Group>,
+    entry: Option<Entry>,
+    field_name: Option<String>,
+    keys: Vec<ImportedKey>,
+}
+
+impl XmlParser {
+    fn new(stream: ChaCha20) -> Self {
+        Self {
+            stream,
+            path: Vec::new(),
+            text: String::new(),
+            protected: false,
+            recycle_bin: None,
+            groups: Vec::new(),
+            entry: None,
+            field_name: None,
+            keys: Vec::new(),
+        }
+    }
+
+    fn parse(mut self, xml: &str) -> Result<Vec<ImportedKey>> {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event().map_err(xml_error)? {
+                Event::Start(element) => self.start(&element)?,
+                Event::Empty(element) => {
+                    self.start(&element)?;
+                    self.end()?;
+                }
+                Event::End(_) => self.end()?,
+                Event::Text(text) => self
+                    .text
+                    .push_str(&text.xml10_content().map_err(xml_error)?),
+                Event::CData(text) => self
+                    .text
+                    .push_str(&text.xml10_content().map_err(xml_error)?),
+                Event::GeneralRef(reference) => {
+                    if let Some(c) = reference.resolve_char_ref().map_err(xml_error)? {
+                        self.text.push(c);
+                    } else {
+                        let name = reference.decode().map_err(xml_error)?;
+                        let value = quick_xml::escape::resolve_xml_entity(&name)
+                            .ok_or_else(|| xml_error(format!("unknown entity &{};", name)))?;
+                        self.text.push_str(value);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if self.path.is_empty() {
+            Ok(self.keys)
+        } else {
+            Err(xml_error("unexpected end of document"))
+        }
+    }
+
+    fn start(&mut self, element: &BytesStart<'_>) -> Result<()> {
+        let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+        self.protected = element
+            .try_get_attribute("Protected")
+            .map_err(xml_error)?
+            .is_some_and(|attribute| attribute.value.as_ref() == b"True");
+        self.text.clear();
+
+        let parent = self.path.last().map(String::as_str);
+        match name.as_str() {
+            "Group" => {
+                let recycled = self.groups.last().is_some_and(|group| group.recycled);
+                self.groups.push(Group {
+                    name: String::new(),
+                    recycled,
+                });
+            }
+            "Entry" if parent == Some("Group") => self.entry = Some(Entry::default()),
+            _ => {}
+        }
+        self.path.push(name);
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        let Some(name) = self.path.pop() else {
+            return Ok(());
+        };
+        let mut text = std::mem::take(&mut self.text);
+        let protected = name == "Value" && self.protected;
+        if protected {
+            let mut bytes = BASE64
+                .decode(text.trim())
+                .map_err(|_| xml_error("protected value is not base64"))?;
+            self.stream.apply_keystream(&mut bytes);
+            text = String::from_utf8(bytes).map_err(|_| {
+                VaultError::Decryption("protected value is not UTF-8 text".to_string())
+            })?;
+        }
+
+        let in_history = self.path.iter().any(|element| element == "History");
+        let parent = self.path.last().map(String::as_str);
+        let grandparent = self.path.iter().rev().nth(1).map(String::as_str);
+        match (parent, name.as_str()) {
+            (Some("Meta"), "RecycleBinUUID") => self.recycle_bin = Some(text),
+            (Some("Group"), "Name") => {
+                if let Some(group) = self.groups.last_mut() {
+                    group.name = text;
+                }
+            }
+            (Some("Group"), "UUID") if self.recycle_bin.as_deref() == Some(text.as_str()) => {
+                if let Some(group) = self.groups.last_mut() {
+                    group.recycled = true;
+                }
+            }
+            (_, "Group") => {
+                self.groups.pop();
+            }
+            (Some("Group"), "Entry") => {
+                if let Some(entry) = self.entry.take() {
+                    self.finish_entry(entry);
+                }
+            }
+            _ if in_history => {}
+            (Some("String"), "Key") => self.field_name = Some(text),
+            (Some("String"), "Value") => {
+                if let (Some(entry), Some(name)) = (self.entry.as_mut(), self.field_name.take()) {
+                    entry.fields.push(Field {
+                        name,
+                        value: Zeroizing::new(text),
+                        protected,
+                    });
+                }
+            }
+            (Some("Times"), "CreationTime") if grandparent == Some("Entry") => {
+                if let Some(entry) = self.entry.as_mut() {
+                    entry.created_at = decode_time(&text);
+                }
+            }
+            (Some("Times"), "LastModificationTime") if grandparent == Some("Entry") => {
+                if let Some(entry) = self.entry.as_mut() {
+                    entry.updated_at = decode_time(&text);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish_entry(&mut self, entry: Entry) {
+        if self.groups.last().is_some_and(|group| group.recycled) {
+            return;
+        }
+        // Entries of the root group have no app
+        let app = match self.groups.get(1..) {
+            Some(groups) if !groups.is_empty() => Some(
+                groups
+                    .iter()
+                    .map(|group| group.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            ),
+            _ => None,
+        };
+        self.keys.extend(entry.into_keys(app.as_deref()));
+    }
+}
+
+/// Writes the XML document of [`write`].
+fn write_xml(keys: &[ApiKey], stream: &mut ChaCha20) -> Result<Vec<u8>> {
+    let mut apps: BTreeMap<Option<&str>, Vec<&ApiKey>> = BTreeMap::new();
+    for key in keys {
+        let app = key.app_name.as_deref().filter(|app| !app.is_empty());
+        apps.entry(app).or_default().push(key);
+    }
+
+    let mut xml = XmlWriter {
+        writer: Writer::new(Vec::new()),
+        stream,
+    };
+    xml.event(Event::Decl(BytesDecl::new(
+        "1.0",
+        Some("utf-8"),
+        Some("yes"),
+    )))?;
+    xml.start("KeePassFile")?;
+    xml.start("Meta")?;
+    xml.leaf("Generator", ROOT_GROUP_NAME)?;
+    xml.leaf("DatabaseName", ROOT_GROUP_NAME)?;
+    xml.start("MemoryProtection")?;
+    for (field, protect) in [
+        ("ProtectTitle", false),
+        ("ProtectUserName", false),
+        ("ProtectPassword", true),
+        ("ProtectURL", false),
+        ("ProtectNotes", false),
+    ] {
+        xml.leaf(field, if protect { "True" } else { "False" })?;
+    }
+    xml.end("MemoryProtection")?;
+    xml.end("Meta")?;
+
+    xml.start("Root")?;
+    xml.start("Group")?;
+    xml.leaf("UUID", &new_uuid())?;
+    xml.leaf("Name", ROOT_GROUP_NAME)?;
+    for (app, keys) in &apps {
+        if let Some(app) = app {
+            xml.start("Group")?;
+            xml.leaf("UUID", &new_uuid())?;
+            xml.leaf("Name", app)?;
+        }
+        for key in keys {
+            xml.entry(key)?;
+        }
+        if app.is_some() {
+            xml.end("Group")?;
+        }
+    }
+    xml.end("Group")?;
+    xml.end("Root")?;
+    xml.end("KeePassFile")?;
+    Ok(xml.writer.into_inner())
+}
+
+/// Base64 form of a random UUID, as KeePass stores them.
+fn new_uuid() -> String {
+    BASE64.encode(uuid::Uuid::new_v4().as_bytes())
+}
+
+/// XML writer that protects values with the inner stream.
+struct XmlWriter<'a> {
+    writer: Writer<Vec<u8>>,
+    stream: &'a mut ChaCha20,
+}
+
+impl XmlWriter<'_> {
+    fn event(&mut self, event: Event<'_>) -> Result<()> {
+        self.writer
+            .write_event(event)
+            .map_err(|e| VaultError::Encryption(e.to_string()))
+    }
+
+    fn start(&mut self, name: &str) -> Result<()> {
+        self.event(Event::Start(BytesStart::new(name)))
+    }
+
+    fn end(&mut self, name: &str) -> Result<()> {
+        self.event(Event::End(BytesEnd::new(name)))
+    }
+
+    /// Writes an element holding only `text`.
+    fn leaf(&mut self, name: &str, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return self.event(Event::Empty(BytesStart::new(name)));
+        }
+        self.start(name)?;
+        self.event(Event::Text(BytesText::new(text)))?;
+        self.end(name)
+    }
+
+    fn string_field(&mut self, key: &str, value: &str, protect: bool) -> Result<()> {
+        self.start("String")?;
+        self.leaf("Key", key)?;
+        if protect {
+            let mut bytes = Zeroizing::new(value.as_bytes().to_vec());
+            self.stream.apply_keystream(&mut bytes);
+            let element = BytesStart::new("Value").with_attributes([("Protected", "True")]);
+            self.event(Event::Start(element))?;
+            self.event(Event::Text(BytesText::new(&BASE64.encode(&bytes))))?;
+            self.end("Value")?;
+        } else {
+            self.leaf("Value", value)?;
+        }
+        self.end("String")
+    }
+
+    fn entry(&mut self, key: &ApiKey) -> Result<()> {
+        self.start("Entry")?;
+        self.leaf("UUID", &new_uuid())?;
+        self.start("Times")?;
+        self.leaf("CreationTime", &encode_time(key.created_at))?;
+        self.leaf("LastModificationTime", &encode_time(key.updated_at))?;
+        self.leaf("LastAccessTime", &encode_time(key.updated_at))?;
+        self.leaf("Expires", "False")?;
+        self.end("Times")?;
+        self.string_field("Title", &key.key_name, false)?;
+        self.string_field("UserName", "", false)?;
+        self.string_field("Password", &key.key_value, true)?;
+        self.string_field("URL", key.api_url.as_deref().unwrap_or_default(), false)?;
+        self.string_field(
+            "Notes",
+            key.description.as_deref().unwrap_or_default(),
+            false,
+        )?;
+        self.end("Entry")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery";
+
+    /// Cheapest accepted costs, to keep the tests fast
+    fn test_options(cipher: KdbxCipher) -> KdbxOptions {
+        KdbxOptions {
+            cipher,
+            kdf: KdfParams::argon2id(KdfParams::MIN_MEMORY_KIB, 1, 1).unwrap(),
+        }
+    }
+
+    fn api_key(app: Option<&str>, name: &str, value: &str) -> ApiKey {
+        let created_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        ApiKey {
+            id: uuid::Uuid::new_v4().to_string(),
+            app_name: app.map(str::to_string),
+            key_name: name.to_string(),
+            key_value: value.to_string(),
+            api_url: Some("https://api.example.com".to_string()),
+            description: Some("first line\nsecond & <last> line".to_string()),
+            expires_at: None,
+            rotate_every: None,
+            rotate_by: None,
+            created_at,
+            updated_at: created_at + chrono::Duration::days(3),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_aes_and_chacha20() {
+        let keys = vec![
+            api_key(Some("github"), "token", "ghp_123"),
+            api_key(Some("github"), "webhook", "whsec_456"),
+            api_key(None, "loose", "v\u{e9}rt \"quoted\""),
+            api_key(Some("aws"), "secret", "wJalr/XUtnFEMI"),
+        ];
+
+        for cipher in [KdbxCipher::Aes256, KdbxCipher::ChaCha20] {
+            let bytes = write(&keys, PASSWORD, &test_options(cipher)).unwrap();
+            assert!(!bytes.windows(7).any(|w| w == b"ghp_123"));
+
+            let mut read_back = read(&bytes, PASSWORD).unwrap();
+            read_back.sort_by(|a, b| a.request.key_name.cmp(&b.request.key_name));
+            assert_eq!(read_back.len(), keys.len());
+            for imported in &read_back {
+                let original = keys
+                    .iter()
+                    .find(|k| k.key_name == imported.request.key_name)
+                    .unwrap();
+                assert_eq!(imported.request.app_name, original.app_name);
+                assert_eq!(imported.request.key_value, original.key_value);
+                assert_eq!(imported.request.api_url, original.api_url);
+                assert_eq!(imported.request.description, original.description);
+                assert_eq!(imported.created_at, Some(original.created_at));
+                assert_eq!(imported.updated_at, Some(original.updated_at));
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_rejects_wrong_password_and_tampering() {
+        let keys = vec![api_key(Some("github"), "token", "ghp_123")];
+        let mut bytes = write(&keys, PASSWORD, &test_options(KdbxCipher::ChaCha20)).unwrap();
+
+        let result = read(&bytes, "wrong horse battery");
+        assert!(matches!(result, Err(VaultError::Decryption(_))));
+
+        let last = bytes.len() - 40;
+        bytes[last] ^= 1;
+        let result = read(&bytes, PASSWORD);
+        assert!(matches!(result, Err(VaultError::Decryption(_))));
+
+        let result = read(b"not a database", PASSWORD);
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        let result = write(&keys, "short", &test_options(KdbxCipher::Aes256));
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+
+    /// Overwrites the value of KDF parameter `name` in a written database.
+    fn patch_kdf_value(bytes: &mut [u8], kind: u8, name: u8, value: &[u8]) {
+        let mut entry = vec![kind, 1, 0, 0, 0, name];
+        entry.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        let start = bytes
+            .windows(entry.len())
+            .position(|w| w == entry.as_slice())
+            .unwrap()
+            + entry.len();
+        bytes[start..start + value.len()].copy_from_slice(value);
+    }
+
+    #[test]
+    fn test_read_refuses_excessive_kdf_costs() {
+        let keys = vec![api_key(Some("github"), "token", "ghp_123")];
+        let written = write(&keys, PASSWORD, &test_options(KdbxCipher::Aes256)).unwrap();
+
+        let too_much_memory = (u64::from(MAX_KDBX_MEMORY_KIB) + 1024) * 1024;
+        let patches = [
+            (VARIANT_U64, b'M', too_much_memory.to_le_bytes().to_vec()),
+            (
+                VARIANT_U64,
+                b'I',
+                u64::from(u32::MAX).to_le_bytes().to_vec(),
+            ),
+            (VARIANT_U32, b'P', 1024u32.to_le_bytes().to_vec()),
+        ];
+        for (kind, name, value) in patches {
+            let mut bytes = written.clone();
+            patch_kdf_value(&mut bytes, kind, name, &value);
+            let result = read(&bytes, PASSWORD);
+            assert!(
+                matches!(&result, Err(VaultError::InvalidInput(msg)) if msg.contains("import limit")),
+                "{result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_maps_groups_and_custom_fields() {
+        // Written the way KeePass does, with history, a recycle bin and
+        // custom fields vult never writes itself
+        let stream_key = [7u8; 64];
+        let mut stream = inner_stream(&stream_key).unwrap();
+        let mut protect = |value: &str| {
+            let mut bytes = value.as_bytes().to_vec();
+            stream.apply_keystream(&mut bytes);
+            BASE64.encode(bytes)
+        };
+        let field = |key: &str, value: &str| {
+            format!("<String><Key>{key}</Key><Value>{value}</Value></String>")
+        };
+        let secret = |key: &str, value: &str| {
+            format!("<String><Key>{key}</Key><Value Protected=\"True\">{value}</Value></String>")
+        };
+
+        let github = [
+            field("Title", "token"),
+            secret("Password", &protect("ghp_new")),
+            field("UserName", "ci-bot"),
+            field("api_url", "https://api.github.com"),
+            field("description", "CI &amp; deploys"),
+            secret("otp", &protect("otpauth://totp/gh")),
+            format!(
+                "<History><Entry>{}</Entry></History>",
+                secret("Password", &protect("ghp_old"))
+            ),
+        ]
+        .concat();
+        let root = [field("Title", "loose"), secret("Password", &protect("v1"))].concat();
+        let deleted = [field("Title", "gone"), secret("Password", &protect("x"))].concat();
+        let xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <KeePassFile><Meta><RecycleBinUUID>cmVjeWNsZWQ=</RecycleBinUUID></Meta>\
+             <Root><Group><UUID>cm9vdA==</UUID><Name>Database</Name>\
+             <Group><UUID>d29yaw==</UUID><Name>Work</Name>\
+             <Group><UUID>Z2g=</UUID><Name>GitHub</Name><Entry>\
+             <Times><CreationTime>2023-11-14T22:13:20Z</CreationTime></Times>{github}</Entry></Group>\
+             </Group><Entry>{root}</Entry>\
+             <Group><UUID>cmVjeWNsZWQ=</UUID><Name>Recycle Bin</Name><Entry>{deleted}</Entry></Group>\
+             </Group></Root></KeePassFile>"
+        );
+
+        let keys = XmlParser::new(inner_stream(&stream_key).unwrap())
+            .parse(&xml)
+            .unwrap();
+        let summary: Vec<_> = keys
+            .iter()
+            .map(|k| {
+                (
+                    k.request.app_name.as_deref(),
+                    k.request.key_name.as_str(),
+                    k.request.key_value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("Work-GitHub"), "token", "ghp_new"),
+                (Some("Work-GitHub"), "token-otp", "otpauth://totp/gh"),
+                (None, "loose", "v1"),
+            ]
+        );
+
+        let token = &keys[0];
+        assert_eq!(
+            token.request.api_url.as_deref(),
+            Some("https://api.github.com")
+        );
+        assert_eq!(
+            token.request.description.as_deref(),
+            Some("CI & deploys\nUserName: ci-bot")
+        );
+        assert_eq!(token.created_at, DateTime::from_timestamp(1_700_000_000, 0));
+        assert_eq!(token.updated_at, token.created_at);
+    }
+}
@@ -55,7 +55,8 @@
 //! - Master key derived using Argon2id (memory-hard)
 //! - Each API key encrypted with unique derived key
 //! - Sensitive data zeroized when no longer needed
-//! - No recovery mechanism by design (lost PIN = lost data)
+//! - No recovery unless opted into: without Shamir recovery shares
+//!   ([`shamir`]), a lost PIN means lost data
 
 // =============================================================================
 // Public modules - Library API
@@ -64,6 +65,9 @@
 /// Core types, constants, and validation utilities
 pub mod core;
 
+/// Session agent that keeps unlocked vault keys between CLI commands
+pub mod agent;
+
 /// Clipboard operations with auto-clear
 pub mod clipboard;
 
@@ -80,9 +84,27 @@ pub mod database;
 /// Unified error types
 pub mod error;
 
+/// Portable encrypted exports (`.vultbak`)
+pub mod export;
+
+/// Importers for other secret managers' export files
+pub mod import;
+
+/// KeePass KDBX 4 databases
+pub mod kdbx;
+
+/// Project secret manifests (`.vult.toml`)
+pub mod manifest;
+
 /// High-level service layer
 pub mod services;
 
+/// Shamir secret sharing for recovery shares of the vault key
+pub mod shamir;
+
+/// Templates with `{{ vult://app/key }}` references
+pub mod template;
+
 // =============================================================================
 // GUI-specific modules - Only available with gui feature
 // =============================================================================
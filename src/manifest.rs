@@ -0,0 +1,350 @@
+//! Project secret manifests (`.vult.toml`).
+//!
+//! A manifest lives in a repository and declares which vault keys the
+//! project needs and the environment variable each one is exposed as. It
+//! never contains secret values, so it can be committed.
+//!
+//! # Format
+//!
+//! ```toml
+//! [secrets]
+//! OPENAI_API_KEY = "openai/key"
+//!
+//! [secrets.GITHUB_TOKEN]
+//! key = "github/token"
+//! description = "Token with repo scope"
+//! optional = true
+//! ```
+//!
+//! Each entry maps a variable name to a key reference (`app/key`, or `key`
+//! for a key without an app). Optional entries may be missing from the
+//! vault; they are reported by [`Manifest::check`] but skipped by
+//! [`Manifest::resolve`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::manifest::Manifest;
+//!
+//! let path = Manifest::find(&std::env::current_dir()?).expect("no manifest");
+//! let manifest = Manifest::load(&path)?;
+//! for status in manifest.check(vault.keys()).await? {
+//!     println!("{} -> {}: {}", status.env, status.key, status.present);
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::core::KeyRef;
+use crate::error::{Result, VaultError};
+use crate::services::KeyService;
+
+/// File name looked up by [`Manifest::find`]
+pub const MANIFEST_FILE_NAME: &str = ".vult.toml";
+
+/// One variable declared in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Environment variable name
+    pub env: String,
+    /// Key the variable is read from
+    pub key: KeyRef,
+    /// What the key is for, shown to people setting up the project
+    pub description: Option<String>,
+    /// Whether the project works without this key
+    pub optional: bool,
+}
+
+/// Whether a manifest entry is present in the vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryStatus {
+    /// Environment variable name
+    pub env: String,
+    /// Key reference, as `app/key`
+    pub key: String,
+    /// What the key is for
+    pub description: Option<String>,
+    /// Whether the project works without this key
+    pub optional: bool,
+    /// Whether the key exists in the vault
+    pub present: bool,
+}
+
+/// A parsed `.vult.toml`, with entries sorted by variable name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// Declared variables
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    #[serde(default)]
+    secrets: BTreeMap<String, RawEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEntry {
+    Key(String),
+    Table {
+        key: String,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        optional: bool,
+    },
+}
+
+impl Manifest {
+    /// Parses a manifest from TOML text.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::InvalidInput`] for malformed TOML, an invalid
+    /// variable name or an invalid key reference.
+    pub fn parse(text: &str) -> Result<Self> {
+        let raw: RawManifest = toml::from_str(text)
+            .map_err(|e| VaultError::InvalidInput(format!("Invalid manifest: {}", e.message())))?;
+
+        let mut entries = Vec::with_capacity(raw.secrets.len());
+        for (env, entry) in raw.secrets {
+            if !is_valid_env_name(&env) {
+                return Err(VaultError::InvalidInput(format!(
+                    "Invalid manifest: '{env}' is not a valid environment variable name"
+                )));
+            }
+            let (key, description, optional) = match entry {
+                RawEntry::Key(key) => (key, None, false),
+                RawEntry::Table {
+                    key,
+                    description,
+                    optional,
+                } => (key, description, optional),
+            };
+            let key = key
+                .parse::<KeyRef>()
+                .map_err(|e| VaultError::InvalidInput(format!("Invalid manifest: {e}")))?;
+            entries.push(ManifestEntry {
+                env,
+                key,
+                description,
+                optional,
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Reads and parses the manifest at `path`.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Io`] if the file cannot be read
+    /// - [`VaultError::InvalidInput`] if it is not a valid manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| VaultError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&text).map_err(|e| match e {
+            VaultError::InvalidInput(message) => {
+                VaultError::InvalidInput(format!("{}: {}", path.display(), message))
+            }
+            other => other,
+        })
+    }
+
+    /// Finds the nearest [`MANIFEST_FILE_NAME`] in `start` or one of its
+    /// parents, like git does for `.git`.
+    pub fn find(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(MANIFEST_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// Reports which entries exist in the vault, without decrypting values.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is locked
+    pub async fn check(&self, keys: &KeyService) -> Result<Vec<EntryStatus>> {
+        let mut statuses = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let present = keys
+                .exists(&entry.key.app_name, &entry.key.key_name)
+                .await?;
+            statuses.push(EntryStatus {
+                env: entry.env.clone(),
+                key: entry.key.to_string(),
+                description: entry.description.clone(),
+                optional: entry.optional,
+                present,
+            });
+        }
+        Ok(statuses)
+    }
+
+    /// Reads the value of every entry, skipping optional entries missing
+    /// from the vault.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is locked
+    /// - [`VaultError::NotFound`] naming every required key that is missing;
+    ///   no values are returned in that case
+    pub async fn resolve(&self, keys: &KeyService) -> Result<Vec<(String, Zeroizing<String>)>> {
+        let missing: Vec<String> = self
+            .check(keys)
+            .await?
+            .into_iter()
+            .filter(|status| !status.present && !status.optional)
+            .map(|status| status.key)
+            .collect();
+        if !missing.is_empty() {
+            return Err(VaultError::NotFound(missing.join(", ")));
+        }
+
+        let mut values = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            match keys.get(&entry.key.app_name, &entry.key.key_name).await {
+                Ok(key) => values.push((entry.env.clone(), Zeroizing::new(key.key_value))),
+                Err(VaultError::NotFound(_)) if entry.optional => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Returns whether `name` is a portable environment variable name: ASCII
+/// letters, digits and `_`, not starting with a digit.
+pub fn is_valid_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::VaultManager;
+
+    const MANIFEST: &str = r#"
+        [secrets]
+        OPENAI_API_KEY = "openai/key"
+        LOOSE = "loose"
+
+        [secrets.GITHUB_TOKEN]
+        key = "github/token"
+        description = "Token with repo scope"
+        optional = true
+    "#;
+
+    async fn setup_vault() -> VaultManager {
+        let vault = VaultManager::new("sqlite::memory:").await.unwrap();
+        vault.auth().init_vault("test-pin-123").await.unwrap();
+        vault
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let names: Vec<_> = manifest.entries.iter().map(|e| e.env.as_str()).collect();
+        assert_eq!(names, vec!["GITHUB_TOKEN", "LOOSE", "OPENAI_API_KEY"]);
+
+        let github = &manifest.entries[0];
+        assert_eq!(github.key.to_string(), "github/token");
+        assert_eq!(github.description.as_deref(), Some("Token with repo scope"));
+        assert!(github.optional);
+        assert!(!manifest.entries[2].optional);
+        assert_eq!(manifest.entries[1].key.app_name, "");
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_entries() {
+        for text in [
+            "[secrets]\n\"1BAD\" = \"a/b\"",
+            "[secrets]\nGOOD = \"a/\"",
+            "[secrets]\nGOOD = 3",
+            "[other]\nGOOD = \"a/b\"",
+        ] {
+            let result = Manifest::parse(text);
+            assert!(
+                matches!(result, Err(VaultError::InvalidInput(_))),
+                "accepted {text:?}"
+            );
+        }
+        assert_eq!(Manifest::parse("").unwrap(), Manifest::default());
+    }
+
+    #[test]
+    fn test_find_walks_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert!(Manifest::find(&nested).is_none_or(|p| !p.starts_with(dir.path())));
+
+        std::fs::write(dir.path().join(MANIFEST_FILE_NAME), MANIFEST).unwrap();
+        assert_eq!(
+            Manifest::find(&nested),
+            Some(dir.path().join(MANIFEST_FILE_NAME))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_missing_keys() {
+        let vault = setup_vault().await;
+        vault
+            .keys()
+            .create(Some("openai"), "key", "sk-123", None, None)
+            .await
+            .unwrap();
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+
+        let statuses = manifest.check(vault.keys()).await.unwrap();
+        let present: Vec<_> = statuses
+            .iter()
+            .map(|s| (s.env.as_str(), s.present))
+            .collect();
+        assert_eq!(
+            present,
+            vec![
+                ("GITHUB_TOKEN", false),
+                ("LOOSE", false),
+                ("OPENAI_API_KEY", true)
+            ]
+        );
+
+        // Only the required missing key is reported, and nothing is resolved
+        let result = manifest.resolve(vault.keys()).await;
+        assert!(matches!(result, Err(VaultError::NotFound(ref keys)) if keys == "loose"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_skips_missing_optional_keys() {
+        let vault = setup_vault().await;
+        vault
+            .keys()
+            .create(Some("openai"), "key", "sk-123", None, None)
+            .await
+            .unwrap();
+        vault
+            .keys()
+            .create(None, "loose", "v", None, None)
+            .await
+            .unwrap();
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+
+        let values = manifest.resolve(vault.keys()).await.unwrap();
+        let values: Vec<_> = values
+            .iter()
+            .map(|(env, value)| (env.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(values, vec![("LOOSE", "v"), ("OPENAI_API_KEY", "sk-123")]);
+    }
+}
@@ -0,0 +1,712 @@
+//! Audit service - tamper-evident record of vault access
+//!
+//! Every unlock (and failed unlock), PIN change and key read, copy, create,
+//! update or delete appends an entry to the `audit_log` table. Secret values
+//! are never written to the log; entries name the key and the OS user.
+//!
+//! # Tamper Evidence
+//!
+//! Each entry stores a SHA-256 hash over its fields and the hash of the
+//! entry before it, so editing, deleting or reordering an entry breaks
+//! every later link. Entries written while the vault is unlocked also carry
+//! an HMAC keyed from the data key, and `audit_head` holds a MAC naming the
+//! newest such entry, so entries cut off the end are detected as well.
+//! Without the data key the chain cannot be rewritten to hide a change.
+//!
+//! Failed unlocks happen without the data key, so their entries are hashed
+//! but not MACed. They are covered by the next signed entry; until then
+//! [`AuditService::verify`] reports them as unsigned.
+//!
+//! Deleting the whole log together with its head cannot be told apart from
+//! a vault that never had one.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let report = vault.audit().verify(&vault.auth().get_vault_key().await?).await?;
+//! if let Some(broken) = report.broken {
+//!     eprintln!("audit log broken at entry {}: {}", broken.seq, broken.reason);
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::crypto::VaultKey;
+use crate::database::VaultDb;
+use crate::error::{Result, VaultError};
+
+use super::CryptoService;
+
+/// Something that happened to the vault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// The vault was created
+    Init,
+    /// The vault was unlocked
+    Unlock,
+    /// A credential was rejected
+    UnlockFailed,
+    /// A failed-unlock lockout was cleared with a key-file slot or
+    /// recovery shares
+    LockoutReset,
+    /// The PIN was changed or reset from recovery shares
+    PinChange,
+    /// A key value was decrypted
+    Get,
+    /// A key value was copied to the clipboard
+    Copy,
+    /// A key was created
+    Create,
+    /// A key was changed
+    Update,
+    /// A key was deleted
+    Delete,
+    /// Keys were written by an import
+    Import,
+}
+
+impl AuditEvent {
+    /// Names accepted by [`FromStr`](std::str::FromStr)
+    pub const NAMES: [&'static str; 11] = [
+        "init",
+        "unlock",
+        "unlock_failed",
+        "lockout_reset",
+        "pin_change",
+        "get",
+        "copy",
+        "create",
+        "update",
+        "delete",
+        "import",
+    ];
+
+    /// Returns the name stored in the `event` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::Init => "init",
+            AuditEvent::Unlock => "unlock",
+            AuditEvent::UnlockFailed => "unlock_failed",
+            AuditEvent::LockoutReset => "lockout_reset",
+            AuditEvent::PinChange => "pin_change",
+            AuditEvent::Get => "get",
+            AuditEvent::Copy => "copy",
+            AuditEvent::Create => "create",
+            AuditEvent::Update => "update",
+            AuditEvent::Delete => "delete",
+            AuditEvent::Import => "import",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AuditEvent {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "init" => Ok(Self::Init),
+            "unlock" => Ok(Self::Unlock),
+            "unlock_failed" => Ok(Self::UnlockFailed),
+            "lockout_reset" => Ok(Self::LockoutReset),
+            "pin_change" => Ok(Self::PinChange),
+            "get" => Ok(Self::Get),
+            "copy" => Ok(Self::Copy),
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            "import" => Ok(Self::Import),
+            _ => Err(VaultError::InvalidInput(format!(
+                "unknown audit event '{}' (expected {})",
+                s,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// An entry to append with [`AuditService::record`].
+///
+/// Never put secret values in `detail`.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditRecord<'a> {
+    /// What happened
+    pub event: AuditEvent,
+    /// Id of the key concerned
+    pub key_id: Option<&'a str>,
+    /// Name of the key as `app/key`; leave out in encrypted-metadata mode
+    pub key_name: Option<&'a str>,
+    /// Extra, non-secret context such as a key version or slot
+    pub detail: Option<&'a str>,
+}
+
+impl AuditRecord<'_> {
+    /// An entry about the vault rather than a key.
+    pub fn new(event: AuditEvent) -> Self {
+        Self {
+            event,
+            key_id: None,
+            key_name: None,
+            detail: None,
+        }
+    }
+}
+
+/// An entry of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, from 1
+    pub seq: i64,
+    /// When it was recorded
+    pub at: DateTime<Utc>,
+    /// What happened
+    pub event: AuditEvent,
+    /// OS user that was running vult
+    pub actor: Option<String>,
+    /// Id of the key concerned
+    pub key_id: Option<String>,
+    /// Name of the key as `app/key`, unless metadata was encrypted
+    pub key_name: Option<String>,
+    /// Extra context
+    pub detail: Option<String>,
+    /// Whether the entry carries a MAC
+    pub signed: bool,
+}
+
+/// Which entries [`AuditService::entries`] returns. Unset fields match
+/// every entry.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Only this event
+    pub event: Option<AuditEvent>,
+    /// Only entries about the key with this id, or (if set) the name below
+    pub key_id: Option<String>,
+    /// Only entries about the key recorded under this `app/key` name, or
+    /// (if set) the id above
+    pub key_name: Option<String>,
+    /// Only entries at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries at or before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Only the newest entries that match, up to this many
+    pub limit: Option<u32>,
+}
+
+/// Where [`AuditService::verify`] found the log to be altered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditBreak {
+    /// The first entry that fails a check
+    pub seq: i64,
+    /// What is wrong with it
+    pub reason: String,
+}
+
+/// Outcome of [`AuditService::verify`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuditReport {
+    /// Number of entries in the log
+    pub entries: usize,
+    /// Entries after the newest signed one; they were written while locked
+    /// and are not covered by a MAC yet
+    pub unsigned: usize,
+    /// The first problem found, if any
+    pub broken: Option<AuditBreak>,
+}
+
+impl AuditReport {
+    /// Whether every check passed.
+    pub fn is_intact(&self) -> bool {
+        self.broken.is_none()
+    }
+}
+
+/// A row of `audit_log` as stored.
+#[derive(sqlx::FromRow)]
+struct StoredEntry {
+    seq: i64,
+    at: i64,
+    event: String,
+    actor: Option<String>,
+    key_id: Option<String>,
+    key_name: Option<String>,
+    detail: Option<String>,
+    prev_hash: Vec<u8>,
+    hash: Vec<u8>,
+    mac: Option<Vec<u8>>,
+}
+
+/// Appends to and checks the audit log.
+///
+/// Appends are serialized, so entries recorded concurrently by one process
+/// still form a single chain.
+pub struct AuditService {
+    db: Arc<VaultDb>,
+    crypto: Arc<CryptoService>,
+    append_lock: Mutex<()>,
+}
+
+impl AuditService {
+    /// Creates a new audit service.
+    pub fn new(db: Arc<VaultDb>, crypto: Arc<CryptoService>) -> Self {
+        Self {
+            db,
+            crypto,
+            append_lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends an entry to the log.
+    ///
+    /// With the data key the entry is MACed and becomes the new head;
+    /// without it the entry is only chained.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The entry to append
+    /// * `vault_key` - The data key, if the vault is unlocked
+    pub async fn record(
+        &self,
+        record: AuditRecord<'_>,
+        vault_key: Option<&VaultKey>,
+    ) -> Result<()> {
+        let audit_key = vault_key
+            .map(|key| self.crypto.derive_audit_key(key))
+            .transpose()?;
+
+        let _append = self.append_lock.lock().await;
+        let mut tx = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        self.append(&mut tx, record, audit_key.as_ref()).await?;
+        tx.commit()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Appends an entry as part of the caller's transaction, so it is only
+    /// kept if the change it records is committed.
+    ///
+    /// `tx` must already have written to the database: SQLite then holds
+    /// its write lock, and no other append can take the same place in the
+    /// chain.
+    pub(crate) async fn record_in(
+        &self,
+        tx: &mut sqlx::Transaction<'static, sqlx::Sqlite>,
+        record: AuditRecord<'_>,
+        vault_key: &VaultKey,
+    ) -> Result<()> {
+        let audit_key = self.crypto.derive_audit_key(vault_key)?;
+        let _append = self.append_lock.lock().await;
+        self.append(tx, record, Some(&audit_key)).await
+    }
+
+    /// Writes the entry and, if it is signed, the new head within `tx`.
+    async fn append(
+        &self,
+        tx: &mut sqlx::Transaction<'static, sqlx::Sqlite>,
+        record: AuditRecord<'_>,
+        audit_key: Option<&VaultKey>,
+    ) -> Result<()> {
+        let last: Option<(i64, Vec<u8>)> =
+            sqlx::query_as("SELECT seq, hash FROM audit_log ORDER BY seq DESC LIMIT 1")
+                .fetch_optional(&mut **tx)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+        let (seq, prev_hash) = match last {
+            Some((seq, hash)) => (seq + 1, hash),
+            None => (1, vec![0u8; 32]),
+        };
+
+        let entry = StoredEntry {
+            seq,
+            at: Utc::now().timestamp(),
+            event: record.event.as_str().to_string(),
+            actor: current_actor(),
+            key_id: record.key_id.map(str::to_string),
+            key_name: record.key_name.map(str::to_string),
+            detail: record.detail.map(str::to_string),
+            prev_hash,
+            hash: Vec::new(),
+            mac: None,
+        };
+        let hash = self.hash_entry(&entry);
+        let mac = audit_key.map(|key| self.crypto.audit_mac(key, &hash));
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (seq, at, event, actor, key_id, key_name, detail,
+                                   prev_hash, hash, mac)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "#,
+        )
+        .bind(entry.seq)
+        .bind(entry.at)
+        .bind(&entry.event)
+        .bind(&entry.actor)
+        .bind(&entry.key_id)
+        .bind(&entry.key_name)
+        .bind(&entry.detail)
+        .bind(&entry.prev_hash)
+        .bind(&hash[..])
+        .bind(mac.as_ref().map(|mac| &mac[..]))
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        if let Some(key) = audit_key {
+            let head = self.crypto.audit_head_mac(key, seq, &hash);
+            sqlx::query(
+                "INSERT INTO audit_head (id, seq, mac) VALUES (1, ?1, ?2) ON CONFLICT(id) DO UPDATE SET seq = excluded.seq, mac = excluded.mac",
+            )
+            .bind(seq)
+            .bind(&head[..])
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the entries that match `filter`, oldest first.
+    ///
+    /// With a limit, the newest matching entries are returned.
+    pub async fn entries(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let rows: Vec<StoredEntry> = sqlx::query_as(
+            r#"
+            SELECT * FROM audit_log
+            WHERE (?1 IS NULL OR event = ?1)
+              AND ((?2 IS NULL AND ?3 IS NULL) OR key_id = ?2 OR key_name = ?3)
+              AND (?4 IS NULL OR at >= ?4)
+              AND (?5 IS NULL OR at <= ?5)
+            ORDER BY seq DESC
+            LIMIT ?6
+            "#,
+        )
+        .bind(filter.event.map(|event| event.as_str()))
+        .bind(&filter.key_id)
+        .bind(&filter.key_name)
+        .bind(filter.since.map(|t| t.timestamp()))
+        .bind(filter.until.map(|t| t.timestamp()))
+        .bind(filter.limit.map_or(-1, i64::from))
+        .fetch_all(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        rows.into_iter()
+            .rev()
+            .map(|row| {
+                Ok(AuditEntry {
+                    seq: row.seq,
+                    at: DateTime::from_timestamp(row.at, 0).unwrap_or_default(),
+                    event: row.event.parse().map_err(|_| {
+                        VaultError::Database(format!(
+                            "unknown event '{}' in audit entry {}",
+                            row.event, row.seq
+                        ))
+                    })?,
+                    actor: row.actor,
+                    key_id: row.key_id,
+                    key_name: row.key_name,
+                    detail: row.detail,
+                    signed: row.mac.is_some(),
+                })
+            })
+            .collect()
+    }
+
+    /// Checks the whole log for edited, deleted or reordered entries.
+    ///
+    /// Checking stops at the first problem.
+    ///
+    /// # Arguments
+    ///
+    /// * `vault_key` - The data key the entries were MACed with
+    pub async fn verify(&self, vault_key: &VaultKey) -> Result<AuditReport> {
+        let audit_key = self.crypto.derive_audit_key(vault_key)?;
+
+        let rows: Vec<StoredEntry> = sqlx::query_as("SELECT * FROM audit_log ORDER BY seq")
+            .fetch_all(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        let head: Option<(i64, Vec<u8>)> =
+            sqlx::query_as("SELECT seq, mac FROM audit_head WHERE id = 1")
+                .fetch_optional(&self.db.pool)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let mut report = AuditReport {
+            entries: rows.len(),
+            ..AuditReport::default()
+        };
+        let broken = |seq: i64, reason: &str| {
+            Some(AuditBreak {
+                seq,
+                reason: reason.to_string(),
+            })
+        };
+
+        let mut prev_hash = vec![0u8; 32];
+        let mut last_signed: Option<&StoredEntry> = None;
+        for (expected_seq, row) in (1..).zip(&rows) {
+            report.broken = if row.seq != expected_seq {
+                broken(expected_seq, "the entry is missing")
+            } else if row.prev_hash != prev_hash {
+                broken(row.seq, "the entry does not follow the one before it")
+            } else if self.hash_entry(row)[..] != row.hash[..] {
+                broken(row.seq, "the entry was changed")
+            } else {
+                match &row.mac {
+                    Some(mac) if !self.crypto.verify_audit_mac(&audit_key, &row.hash, mac) => {
+                        broken(row.seq, "the entry's MAC does not match")
+                    }
+                    Some(_) => {
+                        last_signed = Some(row);
+                        report.unsigned = 0;
+                        None
+                    }
+                    None => {
+                        report.unsigned += 1;
+                        None
+                    }
+                }
+            };
+            if report.broken.is_some() {
+                return Ok(report);
+            }
+            prev_hash = row.hash.clone();
+        }
+
+        report.broken = match (head, last_signed) {
+            (None, None) => None,
+            (None, Some(row)) => broken(row.seq, "the log head is missing"),
+            (Some((seq, _)), _) if seq > rows.len() as i64 => {
+                broken(seq, "entries were removed from the end")
+            }
+            (Some((seq, mac)), Some(row))
+                if seq == row.seq
+                    && self
+                        .crypto
+                        .verify_audit_head_mac(&audit_key, seq, &row.hash, &mac) =>
+            {
+                None
+            }
+            (Some((seq, _)), _) => broken(seq, "the log head does not match"),
+        };
+
+        Ok(report)
+    }
+
+    /// Hashes an entry's fields, chained to its `prev_hash`.
+    fn hash_entry(&self, entry: &StoredEntry) -> [u8; 32] {
+        self.crypto.audit_entry_hash(
+            &entry.prev_hash,
+            &[
+                &entry.seq.to_be_bytes(),
+                &entry.at.to_be_bytes(),
+                entry.event.as_bytes(),
+                &optional_field(entry.actor.as_deref()),
+                &optional_field(entry.key_id.as_deref()),
+                &optional_field(entry.key_name.as_deref()),
+                &optional_field(entry.detail.as_deref()),
+            ],
+        )
+    }
+}
+
+/// Encodes an optional field so that `None` and `Some("")` hash differently.
+fn optional_field(value: Option<&str>) -> Vec<u8> {
+    match value {
+        Some(value) => [&[1u8][..], value.as_bytes()].concat(),
+        None => Vec::new(),
+    }
+}
+
+/// The OS user running this process, as reported by the environment.
+fn current_actor() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_vault_key;
+
+    async fn setup_test_service() -> AuditService {
+        let db = Arc::new(VaultDb::new("sqlite::memory:").await.unwrap());
+        AuditService::new(db, Arc::new(CryptoService::new()))
+    }
+
+    fn key_record<'a>(event: AuditEvent, id: &'a str, name: &'a str) -> AuditRecord<'a> {
+        AuditRecord {
+            key_id: Some(id),
+            key_name: Some(name),
+            ..AuditRecord::new(event)
+        }
+    }
+
+    /// Runs `sql` with the append-only triggers out of the way, as an
+    /// attacker with file access could.
+    async fn tamper(service: &AuditService, sql: &str) {
+        let pool = &service.db.pool;
+        for trigger in ["audit_log_no_update", "audit_log_no_delete"] {
+            sqlx::query(&format!("DROP TRIGGER IF EXISTS {trigger}"))
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query(sql).execute(pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_and_filter_entries() {
+        let service = setup_test_service().await;
+        let key = generate_vault_key();
+
+        service
+            .record(AuditRecord::new(AuditEvent::Unlock), Some(&key))
+            .await
+            .unwrap();
+        service
+            .record(key_record(AuditEvent::Get, "k1", "stripe/live"), Some(&key))
+            .await
+            .unwrap();
+        service
+            .record(
+                key_record(AuditEvent::Copy, "k1", "stripe/live"),
+                Some(&key),
+            )
+            .await
+            .unwrap();
+        service
+            .record(
+                key_record(AuditEvent::Get, "k2", "github/token"),
+                Some(&key),
+            )
+            .await
+            .unwrap();
+
+        let all = service.entries(&AuditFilter::default()).await.unwrap();
+        let seqs: Vec<i64> = all.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3, 4]);
+        assert!(all.iter().all(|e| e.signed));
+
+        let stripe = service
+            .entries(&AuditFilter {
+                key_name: Some("stripe/live".to_string()),
+                ..AuditFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(stripe.len(), 2);
+
+        let reads = service
+            .entries(&AuditFilter {
+                event: Some(AuditEvent::Get),
+                limit: Some(1),
+                ..AuditFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(reads.len(), 1);
+        assert_eq!(reads[0].key_id.as_deref(), Some("k2"));
+
+        let report = service.verify(&key).await.unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.entries, 4);
+        assert_eq!(report.unsigned, 0);
+
+        // The log only takes appends
+        assert!(sqlx::query("DELETE FROM audit_log WHERE seq = 2")
+            .execute(&service.db.pool)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_entries_are_covered_by_next_signed() {
+        let service = setup_test_service().await;
+        let key = generate_vault_key();
+
+        service
+            .record(AuditRecord::new(AuditEvent::Unlock), Some(&key))
+            .await
+            .unwrap();
+        service
+            .record(AuditRecord::new(AuditEvent::UnlockFailed), None)
+            .await
+            .unwrap();
+
+        let report = service.verify(&key).await.unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.unsigned, 1);
+
+        service
+            .record(AuditRecord::new(AuditEvent::Unlock), Some(&key))
+            .await
+            .unwrap();
+        let report = service.verify(&key).await.unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.unsigned, 0);
+
+        // Dropping the failed attempt now breaks the chain
+        tamper(&service, "DELETE FROM audit_log WHERE seq = 2").await;
+        let report = service.verify(&key).await.unwrap();
+        assert_eq!(report.broken.unwrap().seq, 2);
+    }
+
+    #[tokio::test]
+    async fn test_verify_detects_tampering() {
+        let key = generate_vault_key();
+        let cases = [
+            (
+                "UPDATE audit_log SET key_name = 'other/key' WHERE seq = 2",
+                2,
+            ),
+            ("DELETE FROM audit_log WHERE seq = 1", 1),
+            ("DELETE FROM audit_log WHERE seq = 3", 3),
+            ("UPDATE audit_log SET mac = NULL WHERE seq = 3", 3),
+            ("DELETE FROM audit_head", 3),
+        ];
+
+        for (sql, seq) in cases {
+            let service = setup_test_service().await;
+            for id in ["k1", "k2", "k3"] {
+                service
+                    .record(key_record(AuditEvent::Get, id, "stripe/live"), Some(&key))
+                    .await
+                    .unwrap();
+            }
+            tamper(&service, sql).await;
+
+            let report = service.verify(&key).await.unwrap();
+            assert_eq!(report.broken.map(|b| b.seq), Some(seq), "{sql}");
+        }
+
+        // A different data key cannot vouch for the log
+        let service = setup_test_service().await;
+        service
+            .record(AuditRecord::new(AuditEvent::Unlock), Some(&key))
+            .await
+            .unwrap();
+        let report = service.verify(&generate_vault_key()).await.unwrap();
+        assert!(!report.is_intact());
+    }
+}
@@ -3,6 +3,31 @@
 //! This service provides vault authentication without framework coupling.
 //! It can be used by both the GUI (via Tauri adapter) and CLI binaries.
 //!
+//! # Key Hierarchy
+//!
+//! Secrets are encrypted under a random data-encryption key (DEK). The DEK is
+//! stored in `vault_config` wrapped by the key derived from the PIN, so a PIN
+//! change only rewraps the DEK and never touches stored ciphertexts.
+//!
+//! The Argon2 costs used for the PIN are recorded in `vault_config` as well.
+//! Stronger costs can be scheduled with [`AuthService::set_kdf_target`]; they
+//! are applied by rewrapping the DEK on the next successful unlock.
+//!
+//! A vault can also require a key file. Its contents are mixed into the
+//! PIN-derived key with HKDF, so both are needed to unwrap the DEK.
+//!
+//! Besides this primary credential, up to [`MAX_KEY_SLOTS`] key slots in the
+//! `key_slots` table each hold their own copy of the DEK, wrapped by another
+//! PIN, a passphrase or a key file. Adding or revoking a slot only touches
+//! that slot's row.
+//!
+//! As an opt-in last resort, the DEK can be split into Shamir recovery
+//! shares. Enough of them rebuild the DEK and set a new PIN, see
+//! [`AuthService::recover_with_shares`].
+//!
+//! Initialization, unlocks, failed unlocks and PIN changes are recorded in
+//! the audit log, see [`AuditService`].
+//!
 //! # Example
 //!
 //! ```rust,ignore
@@ -19,24 +44,257 @@
 //! auth_service.lock().await?;
 //! ```
 
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
 
 use tokio::sync::RwLock;
 
-use crate::core::{validate_pin, MAX_PIN_LENGTH, MIN_PIN_LENGTH};
-use crate::crypto::VaultKey;
-use crate::database::VaultDb;
+use crate::core::{
+    validate_pin, LOCKOUT_DURATION, MAX_FAILED_ATTEMPTS, MAX_KEY_SLOTS, MAX_PASSPHRASE_LENGTH,
+    MAX_PIN_LENGTH, MIN_PASSPHRASE_LENGTH, MIN_PIN_LENGTH,
+};
+use crate::crypto::{EncryptedData, KdfParams, KeyContext, KeyScheme, VaultKey};
+use crate::database::{VaultDb, VAULT_CONFIG_TABLE};
+use crate::shamir::Share;
 use crate::error::{Result, VaultError};
 
-use super::CryptoService;
+use super::{AuditEvent, AuditRecord, AuditService, CryptoService};
+
+/// Prefix of a `pin_hash` that holds a full HMAC-SHA256 verifier.
+///
+/// Older vaults stored `$<salt>:<first byte of derived key>` instead; those
+/// are upgraded to this format on the next successful unlock.
+const PIN_VERIFIER_PREFIX: &str = "$hmac-sha256$";
+
+/// Parsed form of the `pin_hash` column.
+enum StoredPinHash {
+    /// Full HMAC-SHA256 verifier
+    Verifier(Vec<u8>),
+    /// Legacy format: only the first byte of the derived key
+    LegacyFirstByte(u8),
+    /// Unrecognised contents; never matches
+    Invalid,
+}
+
+impl StoredPinHash {
+    fn parse(stored: &str) -> Self {
+        if let Some(encoded) = stored.strip_prefix(PIN_VERIFIER_PREFIX) {
+            return hex::decode(encoded)
+                .map(StoredPinHash::Verifier)
+                .unwrap_or(StoredPinHash::Invalid);
+        }
+
+        stored
+            .split(':')
+            .nth(1)
+            .and_then(|s| s.parse::<u8>().ok())
+            .map(StoredPinHash::LegacyFirstByte)
+            .unwrap_or(StoredPinHash::Invalid)
+    }
+}
+
+/// Size of a key file generated by [`create_key_file`]
+pub const KEY_FILE_SIZE: usize = 64;
+
+/// Failed-unlock state persisted in `vault_config`.
+///
+/// Stored in the database so the backoff applies across processes, e.g.
+/// separate `vult` CLI invocations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockoutState {
+    /// Consecutive failed unlock attempts
+    pub failed_attempts: u32,
+    /// Unix timestamp of the most recent failure
+    pub last_failed_at: Option<i64>,
+    /// Unix timestamp before which unlocking is delayed or refused
+    pub locked_until: Option<i64>,
+}
+
+/// KDF parameters recorded for the vault.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct KdfConfig {
+    /// Parameters the current PIN key was derived with
+    pub current: KdfParams,
+    /// Parameters to upgrade to on the next successful unlock
+    pub pending: Option<KdfParams>,
+}
+
+/// Label reported for the primary credential stored in `vault_config`.
+pub const PRIMARY_SLOT_LABEL: &str = "primary";
+
+/// Kind of credential that opens a key slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlotKind {
+    /// A PIN, stretched with Argon2id
+    Pin,
+    /// A long passphrase, stretched with Argon2id
+    Passphrase,
+    /// A key file, used without a PIN
+    KeyFile,
+}
+
+impl SlotKind {
+    /// Returns the name stored in the `kind` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlotKind::Pin => "pin",
+            SlotKind::Passphrase => "passphrase",
+            SlotKind::KeyFile => "keyfile",
+        }
+    }
+}
+
+impl std::fmt::Display for SlotKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SlotKind {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pin" => Ok(SlotKind::Pin),
+            "passphrase" => Ok(SlotKind::Passphrase),
+            "keyfile" => Ok(SlotKind::KeyFile),
+            other => Err(VaultError::InvalidInput(format!("unknown slot kind: {other}"))),
+        }
+    }
+}
+
+/// A credential presented to open a key slot.
+#[derive(Clone, Copy)]
+pub enum SlotCredential<'a> {
+    /// A PIN or passphrase
+    Secret(&'a str),
+    /// Contents of a key file
+    KeyFile(&'a [u8]),
+}
+
+impl SlotCredential<'_> {
+    /// Returns whether this credential can open a slot of `kind`.
+    fn opens(&self, kind: SlotKind) -> bool {
+        matches!(
+            (self, kind),
+            (SlotCredential::Secret(_), SlotKind::Pin | SlotKind::Passphrase)
+                | (SlotCredential::KeyFile(_), SlotKind::KeyFile)
+        )
+    }
+}
+
+/// A credential that clears a lockout with [`AuthService::reset_lockout`].
+///
+/// PINs and passphrases can be guessed, which is what the lockout guards
+/// against, so only credentials that can't are accepted.
+#[derive(Clone, Copy)]
+pub enum LockoutReset<'a> {
+    /// Contents of a key file that opens a key-file slot
+    KeyFile(&'a [u8]),
+    /// Enough recovery shares of the current set
+    Shares(&'a [Share]),
+}
+
+/// Public description of a key slot; never includes key material.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct KeySlot {
+    /// Slot id; `0` is the primary credential in `vault_config`
+    pub id: i64,
+    /// User-chosen label
+    pub label: String,
+    /// Kind of credential that opens the slot
+    pub kind: SlotKind,
+    /// Whether a key file is needed in addition to the PIN (primary only)
+    pub requires_key_file: bool,
+    /// Unix timestamp the slot was added
+    pub created_at: i64,
+}
+
+/// A `key_slots` row, including the wrapped data key.
+struct SlotRecord {
+    slot: KeySlot,
+    salt: [u8; 32],
+    kdf: Option<KdfParams>,
+    verifier: String,
+    wrapped: EncryptedData,
+}
+
+impl LockoutState {
+    /// Returns `true` if the hard lockout is in effect at `now`.
+    pub fn is_locked_out(&self, now: i64) -> bool {
+        self.failed_attempts >= MAX_FAILED_ATTEMPTS && self.remaining_secs(now) > 0
+    }
+
+    /// Seconds until the current backoff or lockout expires.
+    pub fn remaining_secs(&self, now: i64) -> u64 {
+        self.locked_until
+            .map(|until| until.saturating_sub(now).max(0) as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// Encodes a verifier into the `pin_hash` column format.
+fn encode_pin_hash(verifier: &[u8; 32]) -> String {
+    format!("{PIN_VERIFIER_PREFIX}{}", hex::encode(verifier))
+}
+
+/// Reads the contents of a key file.
+///
+/// # Errors
+///
+/// Returns [`VaultError::KeyFileUnavailable`] if the file is missing,
+/// unreadable or empty.
+pub fn read_key_file(path: &Path) -> Result<Vec<u8>> {
+    match fs::read(path) {
+        Ok(contents) if !contents.is_empty() => Ok(contents),
+        _ => Err(VaultError::KeyFileUnavailable(path.display().to_string())),
+    }
+}
+
+/// Creates a new key file of [`KEY_FILE_SIZE`] random bytes.
+///
+/// The file is created with mode 0600 on Unix and is never overwritten.
+///
+/// # Returns
+///
+/// The contents written.
+///
+/// # Errors
+///
+/// Returns [`VaultError::Io`] if the file already exists or cannot be written.
+pub fn create_key_file(path: &Path) -> Result<Vec<u8>> {
+    use rand::RngCore;
+
+    let mut contents = vec![0u8; KEY_FILE_SIZE];
+    rand::thread_rng().fill_bytes(&mut contents);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .map_err(|e| VaultError::Io(format!("{}: {e}", path.display())))?;
+    file.write_all(&contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| VaultError::Io(format!("{}: {e}", path.display())))?;
+
+    Ok(contents)
+}
 
 /// Authentication service for vault PIN operations.
 ///
 /// This service handles:
 /// - Vault initialization with PIN
 /// - Unlocking/locking the vault
-/// - PIN changes with re-encryption
+/// - PIN changes (rewrapping the data key)
 /// - Session state tracking
 ///
 /// # Thread Safety
@@ -45,9 +303,11 @@ use super::CryptoService;
 pub struct AuthService {
     db: Arc<VaultDb>,
     crypto: Arc<CryptoService>,
+    /// The unwrapped data-encryption key while unlocked
     vault_key: Arc<RwLock<Option<VaultKey>>>,
     is_unlocked: Arc<RwLock<bool>>,
-    failed_attempts: Arc<RwLock<u32>>,
+    /// Audit log, shared with the key service
+    audit: Arc<AuditService>,
 }
 
 impl AuthService {
@@ -58,15 +318,27 @@ impl AuthService {
     /// * `db` - Database connection
     /// * `crypto` - Cryptographic service
     pub fn new(db: Arc<VaultDb>, crypto: Arc<CryptoService>) -> Self {
+        let audit = Arc::new(AuditService::new(Arc::clone(&db), Arc::clone(&crypto)));
         Self {
             db,
             crypto,
             vault_key: Arc::new(RwLock::new(None)),
             is_unlocked: Arc::new(RwLock::new(false)),
-            failed_attempts: Arc::new(RwLock::new(0)),
+            audit,
         }
     }
 
+    /// Returns the audit log this service records to.
+    pub fn audit_log(&self) -> &Arc<AuditService> {
+        &self.audit
+    }
+
+    /// Appends `record` to the audit log, MACed if the vault is unlocked.
+    pub async fn record_audit(&self, record: AuditRecord<'_>) -> Result<()> {
+        let vault_key = self.vault_key.read().await;
+        self.audit.record(record, vault_key.as_ref()).await
+    }
+
     /// Checks if the vault is initialized (has a PIN set).
     ///
     /// # Returns
@@ -95,7 +367,8 @@ impl AuthService {
 
     /// Initializes the vault with a new PIN.
     ///
-    /// This creates the vault configuration and derives the master key.
+    /// This creates the vault configuration, generates a random data key and
+    /// stores it wrapped by the key derived from the PIN.
     /// The vault will be automatically unlocked after initialization.
     ///
     /// # Arguments
@@ -115,6 +388,24 @@ impl AuthService {
     /// assert!(auth_service.is_unlocked());
     /// ```
     pub async fn init_vault(&self, pin: &str) -> Result<()> {
+        self.init_vault_with_key_file(pin, None).await
+    }
+
+    /// Initializes the vault with a PIN and an optional key file.
+    ///
+    /// With a key file, every later unlock needs both the PIN and the same
+    /// file contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The PIN to use (minimum 6 characters)
+    /// * `key_file` - Contents of the key file, if one is required
+    ///
+    /// # Errors
+    ///
+    /// As [`Self::init_vault`], plus [`VaultError::KeyDerivation`] if the key
+    /// file is empty.
+    pub async fn init_vault_with_key_file(&self, pin: &str, key_file: Option<&[u8]>) -> Result<()> {
         // Validate PIN using core validation
         validate_pin(pin).map_err(|e| match e {
             crate::core::PinValidationError::TooShort => VaultError::PinTooShort,
@@ -130,38 +421,37 @@ impl AuthService {
         }
 
         // Generate salt and derive key
+        let kdf = KdfParams::default();
         let salt = self.crypto.generate_salt();
-        let vault_key = self.crypto.derive_master_key(pin, &salt)?;
+        let master_key = self.derive_kek(pin, key_file, &salt, &kdf)?;
+
+        // Store a full-strength verifier so wrong PINs are always rejected
+        let pin_hash = encode_pin_hash(&self.crypto.pin_verifier(&master_key));
 
-        // Create verification hash (first byte of derived key for verification)
-        // NOTE: This is a simplified verification - see security note in unlock()
-        let salt_hex = hex::encode(salt);
-        let first_byte = vault_key.as_bytes()[0];
-        let pin_hash = format!("${}:{first_byte}", salt_hex);
+        // Secrets are encrypted under a random data key, wrapped by the PIN key
+        let vault_key = self.crypto.generate_data_key();
+        let wrapped = self.crypto.wrap_data_key(&vault_key, &master_key)?;
+        let dek_verifier = hex::encode(self.crypto.data_key_verifier(&vault_key));
 
         // Create vault config table
         let pool = &self.db.pool;
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS vault_config (
-                id INTEGER PRIMARY KEY,
-                salt BLOB NOT NULL,
-                pin_hash TEXT NOT NULL,
-                created_at INTEGER NOT NULL
-            );
-            "#,
-        )
-        .execute(pool)
-        .await
-        .map_err(|e| VaultError::Database(e.to_string()))?;
+        sqlx::query(VAULT_CONFIG_TABLE)
+            .execute(pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
 
         // Insert config
         sqlx::query(
-            "INSERT INTO vault_config (id, salt, pin_hash, created_at) VALUES (1, ?1, ?2, ?3)",
+            "INSERT INTO vault_config (id, salt, pin_hash, created_at, wrapped_dek, dek_nonce, kdf_params, keyfile_required, dek_verifier) VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
         )
         .bind(salt.as_slice())
         .bind(&pin_hash)
         .bind(chrono::Utc::now().timestamp())
+        .bind(&wrapped.ciphertext)
+        .bind(&wrapped.nonce)
+        .bind(kdf.to_string())
+        .bind(key_file.is_some())
+        .bind(&dek_verifier)
         .execute(pool)
         .await
         .map_err(|e| VaultError::Database(e.to_string()))?;
@@ -170,7 +460,7 @@ impl AuthService {
         *self.vault_key.write().await = Some(vault_key);
         *self.is_unlocked.write().await = true;
 
-        Ok(())
+        self.record_audit(AuditRecord::new(AuditEvent::Init)).await
     }
 
     /// Unlocks the vault with a PIN.
@@ -183,11 +473,28 @@ impl AuthService {
     ///
     /// - [`VaultError::NotInitialized`] if vault hasn't been initialized
     /// - [`VaultError::InvalidPin`] if PIN is incorrect
-    /// - [`VaultError::TooManyAttempts`] after multiple failed attempts
+    /// - [`VaultError::TooManyAttempts`] with the seconds left, during the
+    ///   backoff after a failure or the lockout after [`MAX_FAILED_ATTEMPTS`]
+    ///   failures; see [`Self::reset_lockout`]
     ///
     /// # Security Note
     ///
     /// Failed attempts trigger exponential backoff to mitigate brute force attacks.
+    /// The attempt counter and deadlines are stored in the database, so the
+    /// backoff holds across processes and restarts. Attempts during the
+    /// backoff are refused right away rather than delayed.
+    ///
+    /// Vaults created before full verifiers existed only stored one byte of
+    /// the derived key. For those, the PIN is additionally confirmed by
+    /// decrypting a stored key, and the verifier is upgraded in place. Such a
+    /// vault without any stored keys can't confirm the PIN and is refused.
+    ///
+    /// Vaults created before envelope encryption used the PIN-derived key
+    /// directly. On their first unlock that key becomes the data key and is
+    /// stored wrapped, so no secrets need to be re-encrypted.
+    ///
+    /// If stronger KDF parameters are pending, the data key is rewrapped
+    /// under a key derived with them before this returns.
     ///
     /// # Example
     ///
@@ -196,25 +503,58 @@ impl AuthService {
     /// // Now vault operations are available
     /// ```
     pub async fn unlock(&self, pin: &str) -> Result<()> {
-        // Check rate limiting
-        let attempts = *self.failed_attempts.read().await;
-        if attempts >= 10 {
-            return Err(VaultError::TooManyAttempts);
-        }
-        if attempts > 0 {
-            let backoff = 2_u64.pow(attempts.min(5));
-            tokio::time::sleep(Duration::from_secs(backoff)).await;
-        }
+        self.unlock_with_key_file(pin, None).await
+    }
 
+    /// Unlocks the vault with a PIN and, if the vault requires one, a key file.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The vault PIN
+    /// * `key_file` - Contents of the key file
+    ///
+    /// # Errors
+    ///
+    /// As [`Self::unlock`], plus:
+    /// - [`VaultError::KeyFileRequired`] if the vault needs a key file and
+    ///   none was given; this does not count as a failed attempt unless a
+    ///   PIN or passphrase slot was tried instead
+    /// - [`VaultError::InvalidInput`] if a key file is given for a vault
+    ///   that does not use one
+    ///
+    /// A wrong key file is reported as [`VaultError::InvalidPin`], since the
+    /// two factors cannot be told apart.
+    ///
+    /// If the primary credential does not match, the PIN (and key file) are
+    /// tried against every key slot.
+    pub async fn unlock_with_key_file(&self, pin: &str, key_file: Option<&[u8]>) -> Result<()> {
         // Get stored config
         let pool = &self.db.pool;
-        let row = sqlx::query("SELECT salt, pin_hash FROM vault_config WHERE id = 1")
-            .fetch_optional(pool)
-            .await
-            .map_err(|e| VaultError::Database(e.to_string()))?
-            .ok_or(VaultError::NotInitialized)?;
+        let row = sqlx::query(
+            "SELECT salt, pin_hash, wrapped_dek, dek_nonce, kdf_params, kdf_target, keyfile_required FROM vault_config WHERE id = 1",
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?
+        .ok_or(VaultError::NotInitialized)?;
 
         use sqlx::Row;
+        let keyfile_required: bool = row.get("keyfile_required");
+        if keyfile_required != key_file.is_some() {
+            // The primary credential can't match; only key slots can
+            let mut credentials = vec![SlotCredential::Secret(pin)];
+            credentials.extend(key_file.map(SlotCredential::KeyFile));
+            return self
+                .unlock_from_slots(&credentials, false, || {
+                    if keyfile_required {
+                        VaultError::KeyFileRequired
+                    } else {
+                        VaultError::InvalidInput("this vault does not use a key file".to_string())
+                    }
+                })
+                .await;
+        }
+
         let salt: Vec<u8> = row.get("salt");
         let mut salt_array = [0u8; 32];
         if salt.len() != 32 {
@@ -222,28 +562,276 @@ impl AuthService {
         }
         salt_array.copy_from_slice(&salt);
 
-        // Derive key from PIN
-        let vault_key = self.crypto.derive_master_key(pin, &salt_array)?;
+        // Derive key from PIN with the costs recorded for this vault
+        let kdf = Self::parse_kdf(row.get("kdf_params"))?.unwrap_or_default();
+        let kdf_target = Self::parse_kdf(row.get("kdf_target"))?;
+        self.reserve_attempt().await?;
+        let master_key = self.derive_kek(pin, key_file, &salt_array, &kdf)?;
 
-        // Verify by checking first byte (simplified verification)
-        // SECURITY NOTE: This only checks the first byte - consider improving
         let stored_hash: String = row.get("pin_hash");
-        let parts: Vec<&str> = stored_hash.split(':').collect();
-        let expected_byte = parts
-            .get(1)
-            .and_then(|s| s.parse::<u8>().ok())
-            .unwrap_or(255);
+        let verified = match StoredPinHash::parse(&stored_hash) {
+            StoredPinHash::Verifier(verifier) => self.crypto.verify_pin(&master_key, &verifier),
+            StoredPinHash::LegacyFirstByte(expected_byte) => {
+                let verified = master_key.as_bytes()[0] == expected_byte
+                    && self.confirm_legacy_key(&master_key).await?;
+                if verified {
+                    self.upgrade_pin_hash(&master_key).await?;
+                }
+                verified
+            }
+            StoredPinHash::Invalid => false,
+        };
+
+        if !verified {
+            let mut credentials = vec![SlotCredential::Secret(pin)];
+            credentials.extend(key_file.map(SlotCredential::KeyFile));
+            return self
+                .unlock_from_slots(&credentials, true, || VaultError::InvalidPin)
+                .await;
+        }
+
+        let wrapped_dek: Option<Vec<u8>> = row.get("wrapped_dek");
+        let dek_nonce: Option<Vec<u8>> = row.get("dek_nonce");
+        let vault_key = match (wrapped_dek, dek_nonce) {
+            (Some(ciphertext), Some(nonce)) => self
+                .crypto
+                .unwrap_data_key(&EncryptedData { ciphertext, nonce }, &master_key)?,
+            _ => {
+                // Pre-envelope vault: the PIN key encrypted every secret, so it
+                // becomes the data key as-is
+                self.store_wrapped_data_key(&master_key, &master_key).await?;
+                master_key
+            }
+        };
+
+        // Apply scheduled KDF costs now that the PIN is known to be correct
+        if let Some(target) = kdf_target.filter(|target| *target != kdf) {
+            let current_hash = self.current_pin_hash().await?;
+            self.rewrap_data_key(pin, key_file, &vault_key, &target, &current_hash, None)
+                .await?;
+        }
+
+        self.finish_unlock(vault_key).await
+    }
+
+    /// Unlocks the vault with a key slot credential.
+    ///
+    /// Only slots in `key_slots` are tried; the primary PIN in `vault_config`
+    /// is not. Failures count towards the lockout like a wrong PIN.
+    ///
+    /// # Arguments
+    ///
+    /// * `credential` - PIN, passphrase or key-file contents
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotInitialized`] if vault hasn't been initialized
+    /// - [`VaultError::InvalidPin`] if no slot accepts the credential
+    /// - [`VaultError::InvalidInput`] if no slot of a matching kind exists
+    /// - [`VaultError::TooManyAttempts`] during a backoff or lockout
+    pub async fn unlock_with_slot(&self, credential: SlotCredential<'_>) -> Result<()> {
+        if !self.is_initialized().await? {
+            return Err(VaultError::NotInitialized);
+        }
+
+        self.unlock_from_slots(&[credential], false, || {
+            VaultError::InvalidInput("no key slot accepts this kind of credential".to_string())
+        })
+        .await
+    }
+
+    /// Unlocks the vault with a data key held by the session agent.
+    ///
+    /// The key is checked against the vault's data-key verifier, so a key
+    /// cached for another vault at the same path is refused. No PIN is
+    /// involved, so this neither counts nor clears failed attempts.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The vault's data key
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotInitialized`] if vault hasn't been initialized
+    /// - [`VaultError::InvalidState`] if the vault has not been unlocked
+    ///   with a PIN since data-key verifiers were introduced
+    /// - [`VaultError::InvalidInput`] if the key does not belong to this vault
+    pub async fn unlock_with_data_key(&self, data_key: VaultKey) -> Result<()> {
+        if !self.is_initialized().await? {
+            return Err(VaultError::NotInitialized);
+        }
+
+        let stored: Option<String> =
+            sqlx::query_scalar("SELECT dek_verifier FROM vault_config WHERE id = 1")
+                .fetch_one(&self.db.pool)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+        let Some(stored) = stored else {
+            return Err(VaultError::InvalidState(
+                "vault has no data-key verifier yet; unlock it with the PIN once".to_string(),
+            ));
+        };
+        let verifier = hex::decode(&stored)
+            .map_err(|_| VaultError::Database("Invalid data-key verifier".to_string()))?;
+        if !self.crypto.verify_data_key(&data_key, &verifier) {
+            return Err(VaultError::InvalidInput(
+                "data key does not belong to this vault".to_string(),
+            ));
+        }
+
+        *self.vault_key.write().await = Some(data_key);
+        *self.is_unlocked.write().await = true;
+
+        self.record_audit(AuditRecord {
+            detail: Some("session agent"),
+            ..AuditRecord::new(AuditEvent::Unlock)
+        })
+        .await
+    }
+
+    /// Tries `credentials` against every key slot and unlocks on a match.
+    ///
+    /// `primary_tried` means the primary credential was already checked,
+    /// which reserved the attempt. Otherwise the attempt is reserved before
+    /// the first slot is tried. If nothing could be tried, `untried` is
+    /// returned without counting an attempt. A miss is reported as `untried`
+    /// when that is [`VaultError::KeyFileRequired`], or as
+    /// [`VaultError::InvalidPin`].
+    async fn unlock_from_slots(
+        &self,
+        credentials: &[SlotCredential<'_>],
+        primary_tried: bool,
+        untried: impl FnOnce() -> VaultError,
+    ) -> Result<()> {
+        let mut tried = usize::from(primary_tried);
+        for record in self.slot_records().await? {
+            for credential in credentials.iter().filter(|c| c.opens(record.slot.kind)) {
+                if tried == 0 {
+                    self.reserve_attempt().await?;
+                }
+                tried += 1;
+                if let Some(vault_key) = self.open_slot(&record, *credential)? {
+                    return self.finish_unlock(vault_key).await;
+                }
+            }
+        }
 
-        if vault_key.as_bytes()[0] != expected_byte {
-            *self.failed_attempts.write().await += 1;
-            return Err(VaultError::InvalidPin);
+        if tried == 0 {
+            return Err(untried());
         }
+        self.record_failed_attempt().await?;
+        match untried() {
+            VaultError::KeyFileRequired => Err(VaultError::KeyFileRequired),
+            _ => Err(VaultError::InvalidPin),
+        }
+    }
 
-        // Reset failed attempts and unlock
-        *self.failed_attempts.write().await = 0;
+    /// Stores the data key and clears the failed-attempt counter, including
+    /// the attempt reserved for this unlock.
+    ///
+    /// Vaults created before [`Self::unlock_with_data_key`] existed get their
+    /// data-key verifier here.
+    async fn finish_unlock(&self, vault_key: VaultKey) -> Result<()> {
+        self.clear_failed_attempts().await?;
+        let dek_verifier = hex::encode(self.crypto.data_key_verifier(&vault_key));
+        sqlx::query(
+            "UPDATE vault_config SET dek_verifier = ?1 WHERE id = 1 AND dek_verifier IS NULL",
+        )
+        .bind(&dek_verifier)
+        .execute(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
         *self.vault_key.write().await = Some(vault_key);
         *self.is_unlocked.write().await = true;
 
+        self.record_audit(AuditRecord::new(AuditEvent::Unlock))
+            .await
+    }
+
+    /// Confirms a key that passed the legacy first-byte check.
+    ///
+    /// One byte lets roughly 1 in 256 wrong PINs through, so the key must
+    /// also open the wrapped data key or, in vaults without one, a stored
+    /// ciphertext. A vault with neither has nothing to confirm against and
+    /// is refused: accepting it would make the wrong PIN's key the verifier
+    /// and the data key for good.
+    async fn confirm_legacy_key(&self, vault_key: &VaultKey) -> Result<bool> {
+        use sqlx::Row;
+
+        let config = sqlx::query("SELECT wrapped_dek, dek_nonce FROM vault_config WHERE id = 1")
+            .fetch_one(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        if let (Some(ciphertext), Some(nonce)) =
+            (config.get("wrapped_dek"), config.get("dek_nonce"))
+        {
+            return Ok(self
+                .crypto
+                .unwrap_data_key(&EncryptedData { ciphertext, nonce }, vault_key)
+                .is_ok());
+        }
+
+        let row = sqlx::query(
+            "SELECT id, app_name, key_name, encrypted_key_value, nonce, key_salt, key_scheme FROM api_keys LIMIT 1",
+        )
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Ok(false);
+        };
+
+        let id: String = row.get("id");
+        let app_name: Option<String> = row.get("app_name");
+        let key_name: String = row.get("key_name");
+        let key_salt: Vec<u8> = row.get("key_salt");
+        let Ok(key_salt) = <[u8; 32]>::try_from(key_salt.as_slice()) else {
+            return Ok(false);
+        };
+        let Some(scheme) = KeyScheme::from_i64(row.get("key_scheme")) else {
+            return Ok(false);
+        };
+        let encrypted = crate::crypto::EncryptedData {
+            ciphertext: row.get("encrypted_key_value"),
+            nonce: row.get("nonce"),
+        };
+        let context = KeyContext {
+            id: &id,
+            app_name: app_name.as_deref().unwrap_or(""),
+            key_name: &key_name,
+        };
+
+        Ok(self
+            .crypto
+            .decrypt_key_value(&encrypted, vault_key, &context, &key_salt, scheme)
+            .is_ok())
+    }
+
+    /// Replaces a legacy `pin_hash` with a full verifier for `vault_key`.
+    async fn upgrade_pin_hash(&self, vault_key: &VaultKey) -> Result<()> {
+        let pin_hash = encode_pin_hash(&self.crypto.pin_verifier(vault_key));
+
+        sqlx::query("UPDATE vault_config SET pin_hash = ?1 WHERE id = 1")
+            .bind(&pin_hash)
+            .execute(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Stores `data_key` in `vault_config`, wrapped by `master_key`.
+    async fn store_wrapped_data_key(&self, data_key: &VaultKey, master_key: &VaultKey) -> Result<()> {
+        let wrapped = self.crypto.wrap_data_key(data_key, master_key)?;
+
+        sqlx::query("UPDATE vault_config SET wrapped_dek = ?1, dek_nonce = ?2 WHERE id = 1")
+            .bind(&wrapped.ciphertext)
+            .bind(&wrapped.nonce)
+            .execute(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
         Ok(())
     }
 
@@ -284,6 +872,8 @@ impl AuthService {
 
     /// Gets the current vault key (for internal use by other services).
     ///
+    /// This is the unwrapped data-encryption key, not the PIN-derived key.
+    ///
     /// # Errors
     ///
     /// Returns [`VaultError::Locked`] if the vault is not unlocked.
@@ -296,9 +886,17 @@ impl AuthService {
     ///
     /// This will:
     /// 1. Verify the old PIN
-    /// 2. Derive a new master key from the new PIN
-    /// 3. Re-encrypt all existing keys with the new master key
-    /// 4. Update the stored verification hash
+    /// 2. Derive a new master key from the new PIN (applying any pending
+    ///    KDF parameters)
+    /// 3. Rewrap the data key under the new master key
+    /// 4. Update the stored salt, verification hash and KDF parameters
+    ///
+    /// Stored secrets are encrypted under the data key, so none of them are
+    /// re-encrypted. The configuration update and its `pin_change` audit
+    /// entry are written in a single transaction: if any write fails, nothing
+    /// is changed and the old PIN keeps working. The snapshot taken before the
+    /// change (see [`VaultDb::create_backup`]) is written outside that
+    /// transaction and is kept either way.
     ///
     /// # Arguments
     ///
@@ -309,7 +907,8 @@ impl AuthService {
     ///
     /// - [`VaultError::InvalidPin`] if old PIN is incorrect
     /// - [`VaultError::PinTooShort`] if new PIN is too short
-    /// - [`VaultError::Decryption`] if any keys fail to decrypt
+    /// - [`VaultError::Database`] if the update fails or the PIN was changed
+    ///   concurrently; the vault is left unchanged
     ///
     /// # Example
     ///
@@ -317,6 +916,29 @@ impl AuthService {
     /// auth_service.change_pin("old-pin", "new-secure-pin").await?;
     /// ```
     pub async fn change_pin(&self, old_pin: &str, new_pin: &str) -> Result<()> {
+        self.change_pin_with_key_file(old_pin, new_pin, None).await
+    }
+
+    /// Changes the PIN of a vault that may require a key file.
+    ///
+    /// The key file stays the same; it is needed to verify the old PIN and
+    /// is mixed into the new PIN key.
+    ///
+    /// # Arguments
+    ///
+    /// * `old_pin` - Current PIN for verification
+    /// * `new_pin` - New PIN to set
+    /// * `key_file` - Contents of the key file, if the vault requires one
+    ///
+    /// # Errors
+    ///
+    /// As [`Self::change_pin`] and [`Self::unlock_with_key_file`].
+    pub async fn change_pin_with_key_file(
+        &self,
+        old_pin: &str,
+        new_pin: &str,
+        key_file: Option<&[u8]>,
+    ) -> Result<()> {
         // Validate new PIN
         if new_pin.len() < MIN_PIN_LENGTH {
             return Err(VaultError::PinTooShort);
@@ -325,261 +947,2112 @@ impl AuthService {
             return Err(VaultError::PinTooLong);
         }
 
-        // Verify old PIN first and get the old master key
-        self.unlock(old_pin).await?;
+        // Verify old PIN first; this also loads the data key
+        self.unlock_with_key_file(old_pin, key_file).await?;
+        let vault_key = self.get_vault_key().await?;
+
+        // The snapshot is taken outside the transaction below and is kept
+        // even if the change is rolled back
+        self.db.create_backup().await?;
+
+        // Remember which verifier the old PIN matched, so a concurrent change
+        // is detected instead of overwritten
+        let old_pin_hash = self.current_pin_hash().await?;
+
+        // A PIN change re-derives anyway, so pick up any pending KDF upgrade
+        let kdf = self.kdf_config().await?;
+        let params = kdf.pending.unwrap_or(kdf.current);
+
+        self.rewrap_data_key(
+            new_pin,
+            key_file,
+            &vault_key,
+            &params,
+            &old_pin_hash,
+            Some(AuditRecord::new(AuditEvent::PinChange)),
+        )
+        .await
+    }
 
-        // Get the old master key before we change it
-        let old_vault_key = {
-            let key_guard = self.vault_key.read().await;
-            key_guard.clone().ok_or(VaultError::Locked)?
-        };
+    /// Derives the key-encryption key from the PIN and optional key file.
+    fn derive_kek(
+        &self,
+        pin: &str,
+        key_file: Option<&[u8]>,
+        salt: &[u8; 32],
+        params: &KdfParams,
+    ) -> Result<VaultKey> {
+        let pin_key = self.crypto.derive_master_key_with_params(pin, salt, params)?;
+        match key_file {
+            Some(contents) => self.crypto.combine_with_key_file(&pin_key, contents),
+            None => Ok(pin_key),
+        }
+    }
 
+    /// Rewraps the data key under a key derived from `pin` (and `key_file`)
+    /// with `params`.
+    ///
+    /// The salt, verifier, wrapped key, KDF parameters and key-file flag are
+    /// replaced in a single transaction, and only if the stored verifier still equals
+    /// `expected_pin_hash`. `audit`, if given, is appended to the audit log in
+    /// the same transaction. On error nothing is changed and the previous PIN
+    /// keeps working.
+    async fn rewrap_data_key(
+        &self,
+        pin: &str,
+        key_file: Option<&[u8]>,
+        vault_key: &VaultKey,
+        params: &KdfParams,
+        expected_pin_hash: &str,
+        audit: Option<AuditRecord<'_>>,
+    ) -> Result<()> {
         // Generate new salt and key
         let new_salt = self.crypto.generate_salt();
-        let new_vault_key = self.crypto.derive_master_key(new_pin, &new_salt)?;
+        let new_master_key = self.derive_kek(pin, key_file, &new_salt, params)?;
 
-        // Re-encrypt all existing keys with the new master key
-        let pool = &self.db.pool;
-        let rows = sqlx::query_as::<_, crate::database::EncryptedApiKeyRow>(
-            "SELECT id, app_name, key_name, api_url, description, encrypted_key_value, nonce, key_salt, created_at, updated_at FROM api_keys"
+        // Rewrap the data key under the new PIN key
+        let wrapped = self.crypto.wrap_data_key(vault_key, &new_master_key)?;
+        let new_pin_hash = encode_pin_hash(&self.crypto.pin_verifier(&new_master_key));
+
+        // Swap the credential atomically; on error the transaction is dropped
+        // and rolled back, leaving the old PIN in place
+        let mut tx = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        let result = sqlx::query(
+            "UPDATE vault_config SET salt = ?1, pin_hash = ?2, wrapped_dek = ?3, dek_nonce = ?4, kdf_params = ?5, kdf_target = NULL, keyfile_required = ?6 WHERE id = 1 AND pin_hash = ?7",
         )
-        .fetch_all(pool)
+        .bind(new_salt.as_slice())
+        .bind(&new_pin_hash)
+        .bind(&wrapped.ciphertext)
+        .bind(&wrapped.nonce)
+        .bind(params.to_string())
+        .bind(key_file.is_some())
+        .bind(expected_pin_hash)
+        .execute(&mut *tx)
         .await
         .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        // Re-encrypt each key
-        for row in rows {
-            // Derive per-key encryption key with OLD master key
-            let app_name_for_encryption = row.app_name.as_deref().unwrap_or("");
-            let key_salt: &[u8; 32] = row
-                .key_salt
-                .as_slice()
-                .try_into()
-                .map_err(|_| VaultError::InvalidInput("Invalid key salt length".to_string()))?;
-
-            let old_per_key_key = crate::crypto::derive_per_key_encryption_key(
-                &old_vault_key,
-                app_name_for_encryption,
-                &row.key_name,
-                key_salt,
-            )
-            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
-
-            // Decrypt with old key
-            let encrypted_data = crate::crypto::EncryptedData {
-                ciphertext: row.encrypted_key_value,
-                nonce: row.nonce,
-            };
-            let key_value = crate::crypto::decrypt(&encrypted_data, &old_per_key_key)
-                .map_err(|e| VaultError::Decryption(e.to_string()))?;
-
-            // Derive per-key encryption key with NEW master key (same salt)
-            let new_per_key_key = crate::crypto::derive_per_key_encryption_key(
-                &new_vault_key,
-                app_name_for_encryption,
-                &row.key_name,
-                key_salt,
-            )
-            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
-
-            // Re-encrypt with new key
-            let new_encrypted = crate::crypto::encrypt(&key_value, &new_per_key_key)
-                .map_err(|e| VaultError::Encryption(e.to_string()))?;
-
-            // Update the database
-            sqlx::query("UPDATE api_keys SET encrypted_key_value = ?1, nonce = ?2 WHERE id = ?3")
-                .bind(&new_encrypted.ciphertext)
-                .bind(&new_encrypted.nonce)
-                .bind(&row.id)
-                .execute(pool)
-                .await
-                .map_err(|e| VaultError::Database(e.to_string()))?;
+        if result.rows_affected() != 1 {
+            return Err(VaultError::Database(
+                "vault PIN was changed concurrently".to_string(),
+            ));
         }
 
-        // Update vault config with new PIN
-        let new_salt_hex = hex::encode(new_salt);
-        let new_pin_hash = format!("${new_salt_hex}:{}", new_vault_key.as_bytes()[0]);
+        if let Some(record) = audit {
+            self.audit.record_in(&mut tx, record, vault_key).await?;
+        }
 
-        sqlx::query("UPDATE vault_config SET salt = ?1, pin_hash = ?2 WHERE id = 1")
-            .bind(new_salt.as_slice())
-            .bind(&new_pin_hash)
-            .execute(pool)
+        tx.commit()
             .await
             .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        // Update in-memory key
-        *self.vault_key.write().await = Some(new_vault_key);
-
         Ok(())
     }
 
-    /// Resets failed attempt counter (for testing or admin purposes).
-    pub async fn reset_failed_attempts(&self) {
-        *self.failed_attempts.write().await = 0;
+    /// Reads the stored `pin_hash`.
+    async fn current_pin_hash(&self) -> Result<String> {
+        sqlx::query_scalar("SELECT pin_hash FROM vault_config WHERE id = 1")
+            .fetch_optional(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?
+            .ok_or(VaultError::NotInitialized)
     }
 
-    /// Gets the number of failed authentication attempts.
-    pub async fn get_failed_attempts(&self) -> u32 {
-        *self.failed_attempts.read().await
+    /// Parses an optional stored KDF parameter string.
+    fn parse_kdf(stored: Option<String>) -> Result<Option<KdfParams>> {
+        stored
+            .map(|s| {
+                s.parse::<KdfParams>()
+                    .map_err(|e| VaultError::Database(e.to_string()))
+            })
+            .transpose()
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::core::validate_pin;
 
-    // Helper to create test instances
+    /// Returns the KDF parameters recorded for the vault.
+    ///
+    /// Vaults created before parameters were recorded report the defaults
+    /// they were created with.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::NotInitialized`] if the vault has no PIN yet.
+    pub async fn kdf_config(&self) -> Result<KdfConfig> {
+        use sqlx::Row;
+
+        if !self.is_initialized().await? {
+            return Err(VaultError::NotInitialized);
+        }
+
+        let row = sqlx::query("SELECT kdf_params, kdf_target FROM vault_config WHERE id = 1")
+            .fetch_optional(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?
+            .ok_or(VaultError::NotInitialized)?;
+
+        Ok(KdfConfig {
+            current: Self::parse_kdf(row.get("kdf_params"))?.unwrap_or_default(),
+            pending: Self::parse_kdf(row.get("kdf_target"))?,
+        })
+    }
+
+    /// Schedules new KDF parameters, applied on the next successful unlock.
+    ///
+    /// The master key cannot be re-derived without the PIN, so the upgrade
+    /// happens the next time the PIN is entered (or on [`Self::change_pin`]).
+    /// Lowering the memory or time cost below the current parameters weakens
+    /// the PIN, so it is refused unless `allow_downgrade` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters to upgrade to
+    /// * `allow_downgrade` - Accept a memory or time cost below the current one
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is not unlocked
+    /// - [`VaultError::KeyDerivation`] if the parameters are out of range
+    /// - [`VaultError::InvalidInput`] if the parameters are weaker than the
+    ///   current ones and `allow_downgrade` is not set
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let params = vault.crypto().calibrate_kdf(Duration::from_secs(1), 65536, 4)?;
+    /// auth_service.set_kdf_target(params, false).await?;
+    /// ```
+    pub async fn set_kdf_target(&self, params: KdfParams, allow_downgrade: bool) -> Result<()> {
+        if !self.is_unlocked_async().await {
+            return Err(VaultError::Locked);
+        }
+        params
+            .validate()
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+
+        let current = self.kdf_config().await?.current;
+        if !allow_downgrade
+            && (params.memory_kib < current.memory_kib || params.iterations < current.iterations)
+        {
+            return Err(VaultError::InvalidInput(format!(
+                "{params} is weaker than the current {current}; the downgrade must be allowed explicitly"
+            )));
+        }
+
+        sqlx::query("UPDATE vault_config SET kdf_target = ?1 WHERE id = 1")
+            .bind(params.to_string())
+            .execute(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Returns whether the vault stores key metadata encrypted.
+    ///
+    /// See [`crate::services::KeyService::set_encrypted_metadata`]. An
+    /// uninitialized vault reports `false`.
+    pub async fn encrypted_metadata(&self) -> Result<bool> {
+        if !self.is_initialized().await? {
+            return Ok(false);
+        }
+
+        let enabled: Option<bool> =
+            sqlx::query_scalar("SELECT encrypted_metadata FROM vault_config WHERE id = 1")
+                .fetch_optional(&self.db.pool)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(enabled.unwrap_or(false))
+    }
+
+    /// Returns whether unlocking the vault requires a key file.
+    ///
+    /// An uninitialized vault reports `false`.
+    pub async fn requires_key_file(&self) -> Result<bool> {
+        if !self.is_initialized().await? {
+            return Ok(false);
+        }
+
+        let required: Option<bool> =
+            sqlx::query_scalar("SELECT keyfile_required FROM vault_config WHERE id = 1")
+                .fetch_optional(&self.db.pool)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(required.unwrap_or(false))
+    }
+
+    /// Returns the persisted failed-unlock state.
+    ///
+    /// An uninitialized vault reports no failures.
+    pub async fn lockout_state(&self) -> Result<LockoutState> {
+        use sqlx::Row;
+
+        if !self.is_initialized().await? {
+            return Ok(LockoutState::default());
+        }
+
+        let row = sqlx::query(
+            "SELECT failed_attempts, last_failed_at, locked_until FROM vault_config WHERE id = 1",
+        )
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(row
+            .map(|row| LockoutState {
+                failed_attempts: row.get::<i64, _>("failed_attempts").max(0) as u32,
+                last_failed_at: row.get("last_failed_at"),
+                locked_until: row.get("locked_until"),
+            })
+            .unwrap_or_default())
+    }
+
+    /// Counts an unlock attempt as failed before its credential is checked.
+    ///
+    /// The counter and the next allowed attempt time are updated in one
+    /// conditional statement, so of several processes racing past an
+    /// expired backoff only one gets to check a credential; the others are
+    /// refused. A successful unlock clears the counter again in
+    /// [`Self::finish_unlock`].
+    ///
+    /// The delay doubles with each failure up to 32 seconds. Reaching
+    /// [`MAX_FAILED_ATTEMPTS`] locks the vault for [`LOCKOUT_DURATION`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::TooManyAttempts`] with the seconds left while a
+    /// backoff or lockout is in effect, so callers can exit right away
+    /// instead of waiting.
+    async fn reserve_attempt(&self) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let reserved = sqlx::query(
+            r#"
+            UPDATE vault_config SET
+                failed_attempts = failed_attempts + 1,
+                last_failed_at = ?1,
+                locked_until = ?1 + CASE
+                    WHEN failed_attempts + 1 >= ?2 THEN ?3
+                    ELSE 1 << MIN(failed_attempts + 1, 5)
+                END
+            WHERE id = 1 AND (locked_until IS NULL OR locked_until <= ?1)
+            "#,
+        )
+        .bind(now)
+        .bind(MAX_FAILED_ATTEMPTS as i64)
+        .bind(LOCKOUT_DURATION.as_secs() as i64)
+        .execute(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?
+        .rows_affected();
+
+        if reserved == 0 {
+            let retry_after = self.lockout_state().await?.remaining_secs(now).max(1);
+            return Err(VaultError::TooManyAttempts { retry_after });
+        }
+        Ok(())
+    }
+
+    /// Records that the reserved attempt failed.
+    ///
+    /// The backoff is restarted from now, so a slow key derivation doesn't
+    /// use up the delay before the failure is known.
+    async fn record_failed_attempt(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE vault_config SET
+                last_failed_at = ?1,
+                locked_until = ?1 + CASE
+                    WHEN failed_attempts >= ?2 THEN ?3
+                    ELSE 1 << MIN(failed_attempts, 5)
+                END
+            WHERE id = 1
+            "#,
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(MAX_FAILED_ATTEMPTS as i64)
+        .bind(LOCKOUT_DURATION.as_secs() as i64)
+        .execute(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        self.record_audit(AuditRecord::new(AuditEvent::UnlockFailed))
+            .await
+    }
+
+    /// Clears the persisted failed-unlock state.
+    async fn clear_failed_attempts(&self) -> Result<()> {
+        sqlx::query(
+            "UPDATE vault_config SET failed_attempts = 0, last_failed_at = NULL, locked_until = NULL WHERE id = 1",
+        )
+        .execute(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Resets the failed-attempt counter and any lockout.
+    ///
+    /// This is an administrative action, so it requires an unlocked session:
+    /// a holder of the PIN can clear a lockout caused by someone else's
+    /// guesses, but the lockout cannot be lifted without authenticating.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::Locked`] if the vault is not unlocked.
+    pub async fn reset_failed_attempts(&self) -> Result<()> {
+        if !self.is_unlocked_async().await {
+            return Err(VaultError::Locked);
+        }
+        self.clear_failed_attempts().await
+    }
+
+    /// Clears a lockout and unlocks the vault with a credential that can't be
+    /// guessed.
+    ///
+    /// Unlike [`Self::reset_failed_attempts`] this works while unlocking is
+    /// refused, so the vault's owner is not locked out by someone else's
+    /// guesses. The reset is recorded in the audit log.
+    ///
+    /// # Arguments
+    ///
+    /// * `credential` - A key file that opens a key-file slot, or recovery
+    ///   shares
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotInitialized`] if vault hasn't been initialized
+    /// - [`VaultError::InvalidInput`] if no key-file slot accepts the key
+    ///   file, or the shares don't rebuild this vault's key
+    pub async fn reset_lockout(&self, credential: LockoutReset<'_>) -> Result<()> {
+        if !self.is_initialized().await? {
+            return Err(VaultError::NotInitialized);
+        }
+
+        let (data_key, detail) = match credential {
+            LockoutReset::KeyFile(contents) => {
+                let mut opened = None;
+                for record in self.slot_records().await? {
+                    if record.slot.kind == SlotKind::KeyFile {
+                        opened = self.open_slot(&record, SlotCredential::KeyFile(contents))?;
+                        if opened.is_some() {
+                            break;
+                        }
+                    }
+                }
+                let data_key = opened.ok_or_else(|| {
+                    VaultError::InvalidInput("no key-file slot accepts this key file".to_string())
+                })?;
+                (data_key, "key-file slot")
+            }
+            LockoutReset::Shares(shares) => {
+                (self.data_key_from_shares(shares).await?, "recovery shares")
+            }
+        };
+
+        let record = AuditRecord {
+            detail: Some(detail),
+            ..AuditRecord::new(AuditEvent::LockoutReset)
+        };
+        self.audit.record(record, Some(&data_key)).await?;
+
+        self.finish_unlock(data_key).await
+    }
+
+    /// Gets the number of consecutive failed authentication attempts.
+    pub async fn get_failed_attempts(&self) -> Result<u32> {
+        Ok(self.lockout_state().await?.failed_attempts)
+    }
+
+    // =========================================================================
+    // Key slots
+    // =========================================================================
+
+    /// Loads every row of `key_slots`, oldest first.
+    async fn slot_records(&self) -> Result<Vec<SlotRecord>> {
+        let rows = sqlx::query(
+            "SELECT id, label, kind, salt, kdf_params, verifier, wrapped_dek, dek_nonce, created_at FROM key_slots ORDER BY id",
+        )
+        .fetch_all(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        use sqlx::Row;
+        rows.iter()
+            .map(|row| {
+                let kind: String = row.get("kind");
+                let salt: Vec<u8> = row.get("salt");
+                let salt = <[u8; 32]>::try_from(salt.as_slice())
+                    .map_err(|_| VaultError::Database("Invalid slot salt length".to_string()))?;
+
+                Ok(SlotRecord {
+                    slot: KeySlot {
+                        id: row.get("id"),
+                        label: row.get("label"),
+                        kind: kind.parse()?,
+                        requires_key_file: false,
+                        created_at: row.get("created_at"),
+                    },
+                    salt,
+                    kdf: Self::parse_kdf(row.get("kdf_params"))?,
+                    verifier: row.get("verifier"),
+                    wrapped: EncryptedData {
+                        ciphertext: row.get("wrapped_dek"),
+                        nonce: row.get("dek_nonce"),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Derives the key-encryption key of a slot from `credential`.
+    fn derive_slot_kek(
+        &self,
+        credential: SlotCredential<'_>,
+        salt: &[u8; 32],
+        kdf: Option<&KdfParams>,
+    ) -> Result<VaultKey> {
+        match credential {
+            SlotCredential::Secret(secret) => self.crypto.derive_master_key_with_params(
+                secret,
+                salt,
+                &kdf.cloned().unwrap_or_default(),
+            ),
+            SlotCredential::KeyFile(contents) => self.crypto.derive_key_file_key(contents, salt),
+        }
+    }
+
+    /// Returns the data key if `credential` opens the slot.
+    fn open_slot(
+        &self,
+        record: &SlotRecord,
+        credential: SlotCredential<'_>,
+    ) -> Result<Option<VaultKey>> {
+        let kek = self.derive_slot_kek(credential, &record.salt, record.kdf.as_ref())?;
+        let StoredPinHash::Verifier(verifier) = StoredPinHash::parse(&record.verifier) else {
+            return Ok(None);
+        };
+        if !self.crypto.verify_pin(&kek, &verifier) {
+            return Ok(None);
+        }
+
+        self.crypto.unwrap_data_key(&record.wrapped, &kek).map(Some)
+    }
+
+    /// Adds a key slot that wraps the current data key under `credential`.
+    ///
+    /// PIN and passphrase slots use the vault's current KDF parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Unique, non-empty name for the slot
+    /// * `kind` - Kind of credential; must match `credential`
+    /// * `credential` - PIN, passphrase or key-file contents
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is not unlocked
+    /// - [`VaultError::InvalidInput`] if the label is empty, taken or
+    ///   reserved, the credential doesn't fit `kind`, or [`MAX_KEY_SLOTS`]
+    ///   slots already exist
+    /// - [`VaultError::PinTooShort`]/[`VaultError::PinTooLong`] for a bad PIN
+    pub async fn add_slot(
+        &self,
+        label: &str,
+        kind: SlotKind,
+        credential: SlotCredential<'_>,
+    ) -> Result<KeySlot> {
+        let vault_key = self.get_vault_key().await?;
+
+        let label = label.trim();
+        if label.is_empty() || label == PRIMARY_SLOT_LABEL {
+            return Err(VaultError::InvalidInput(format!(
+                "slot label must be non-empty and not '{PRIMARY_SLOT_LABEL}'"
+            )));
+        }
+        match (kind, credential) {
+            (SlotKind::Pin, SlotCredential::Secret(pin)) => {
+                validate_pin(pin).map_err(|e| match e {
+                    crate::core::PinValidationError::TooShort => VaultError::PinTooShort,
+                    crate::core::PinValidationError::TooLong => VaultError::PinTooLong,
+                    e => VaultError::InvalidInput(e.to_string()),
+                })?;
+            }
+            (SlotKind::Passphrase, SlotCredential::Secret(passphrase)) => {
+                let len = passphrase.chars().count();
+                if !(MIN_PASSPHRASE_LENGTH..=MAX_PASSPHRASE_LENGTH).contains(&len) {
+                    return Err(VaultError::InvalidInput(format!(
+                        "passphrase must be {MIN_PASSPHRASE_LENGTH} to {MAX_PASSPHRASE_LENGTH} characters"
+                    )));
+                }
+            }
+            (SlotKind::KeyFile, SlotCredential::KeyFile(_)) => {}
+            _ => {
+                return Err(VaultError::InvalidInput(format!(
+                    "credential does not match slot kind '{kind}'"
+                )))
+            }
+        }
+
+        let records = self.slot_records().await?;
+        if records.len() >= MAX_KEY_SLOTS {
+            return Err(VaultError::InvalidInput(format!(
+                "a vault can have at most {MAX_KEY_SLOTS} key slots"
+            )));
+        }
+        if records.iter().any(|r| r.slot.label == label) {
+            return Err(VaultError::InvalidInput(format!(
+                "a key slot named '{label}' already exists"
+            )));
+        }
+
+        let salt = self.crypto.generate_salt();
+        let kdf = match kind {
+            SlotKind::KeyFile => None,
+            SlotKind::Pin | SlotKind::Passphrase => Some(self.kdf_config().await?.current),
+        };
+        let kek = self.derive_slot_kek(credential, &salt, kdf.as_ref())?;
+        let wrapped = self.crypto.wrap_data_key(&vault_key, &kek)?;
+        let verifier = encode_pin_hash(&self.crypto.pin_verifier(&kek));
+        let created_at = chrono::Utc::now().timestamp();
+
+        let result = sqlx::query(
+            "INSERT INTO key_slots (label, kind, salt, kdf_params, verifier, wrapped_dek, dek_nonce, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .bind(label)
+        .bind(kind.as_str())
+        .bind(salt.as_slice())
+        .bind(kdf.map(|k| k.to_string()))
+        .bind(&verifier)
+        .bind(&wrapped.ciphertext)
+        .bind(&wrapped.nonce)
+        .bind(created_at)
+        .execute(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(KeySlot {
+            id: result.last_insert_rowid(),
+            label: label.to_string(),
+            kind,
+            requires_key_file: false,
+            created_at,
+        })
+    }
+
+    /// Lists the primary credential (slot `0`) and every key slot.
+    ///
+    /// Works while locked; only labels and kinds are returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::NotInitialized`] if the vault has no PIN yet.
+    pub async fn list_slots(&self) -> Result<Vec<KeySlot>> {
+        let row = sqlx::query("SELECT created_at, keyfile_required FROM vault_config WHERE id = 1")
+            .fetch_optional(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?
+            .ok_or(VaultError::NotInitialized)?;
+
+        use sqlx::Row;
+        let mut slots = vec![KeySlot {
+            id: 0,
+            label: PRIMARY_SLOT_LABEL.to_string(),
+            kind: SlotKind::Pin,
+            requires_key_file: row.get("keyfile_required"),
+            created_at: row.get("created_at"),
+        }];
+        slots.extend(self.slot_records().await?.into_iter().map(|r| r.slot));
+
+        Ok(slots)
+    }
+
+    /// Checks whether `credential` opens slot `id`, without unlocking.
+    ///
+    /// A credential that doesn't match counts as a failed unlock attempt,
+    /// so this cannot be used to guess faster than [`Self::unlock`].
+    ///
+    /// # Returns
+    ///
+    /// `true` if the credential opens the slot.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotFound`] if there is no slot `id`
+    /// - [`VaultError::InvalidInput`] for the primary slot or a credential
+    ///   of the wrong kind
+    /// - [`VaultError::TooManyAttempts`] during a backoff or lockout
+    pub async fn test_slot(&self, id: i64, credential: SlotCredential<'_>) -> Result<bool> {
+        if id == 0 {
+            return Err(VaultError::InvalidInput(
+                "the primary PIN is checked by unlocking".to_string(),
+            ));
+        }
+
+        let record = self
+            .slot_records()
+            .await?
+            .into_iter()
+            .find(|r| r.slot.id == id)
+            .ok_or_else(|| VaultError::NotFound(format!("key slot {id}")))?;
+        if !credential.opens(record.slot.kind) {
+            return Err(VaultError::InvalidInput(format!(
+                "slot {id} is opened by a {}",
+                record.slot.kind
+            )));
+        }
+
+        self.reserve_attempt().await?;
+        if self.open_slot(&record, credential)?.is_some() {
+            self.clear_failed_attempts().await?;
+            Ok(true)
+        } else {
+            self.record_failed_attempt().await?;
+            Ok(false)
+        }
+    }
+
+    /// Revokes key slot `id` by deleting its wrapped data key.
+    ///
+    /// Secrets are not re-encrypted, and the other credentials keep working.
+    /// Copies of the vault file taken earlier still contain the slot.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is not unlocked
+    /// - [`VaultError::InvalidInput`] for the primary slot
+    /// - [`VaultError::NotFound`] if there is no slot `id`
+    pub async fn revoke_slot(&self, id: i64) -> Result<()> {
+        if !*self.is_unlocked.read().await {
+            return Err(VaultError::Locked);
+        }
+        if id == 0 {
+            return Err(VaultError::InvalidInput(
+                "the primary PIN cannot be revoked; use change-pin".to_string(),
+            ));
+        }
+
+        let result = sqlx::query("DELETE FROM key_slots WHERE id = ?1")
+            .bind(id)
+            .execute(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(VaultError::NotFound(format!("key slot {id}")));
+        }
+
+        Ok(())
+    }
+
+    // =========================================================================
+    // Recovery shares
+    // =========================================================================
+
+    /// Splits the data key into Shamir recovery shares.
+    ///
+    /// Any `threshold` of the returned shares can set a new PIN with
+    /// [`Self::recover_with_shares`]. Creating a new set retires the previous
+    /// one, but old shares still encode the same data key, so destroy them.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Number of shares needed to recover
+    /// * `shares` - Number of shares to create
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is not unlocked
+    /// - [`VaultError::InvalidInput`] unless `2 <= threshold <= shares`
+    pub async fn create_recovery_shares(&self, threshold: u8, shares: u8) -> Result<Vec<Share>> {
+        let vault_key = self.get_vault_key().await?;
+
+        let shares = self.crypto.split_data_key(&vault_key, threshold, shares)?;
+        let set_id = hex::encode(shares[0].set_id());
+        let verifier = hex::encode(self.crypto.recovery_verifier(&vault_key));
+
+        sqlx::query("UPDATE vault_config SET recovery_set = ?1, recovery_verifier = ?2 WHERE id = 1")
+            .bind(&set_id)
+            .bind(&verifier)
+            .execute(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(shares)
+    }
+
+    /// Returns whether recovery shares were created for this vault.
+    ///
+    /// An uninitialized vault reports `false`.
+    pub async fn has_recovery_shares(&self) -> Result<bool> {
+        if !self.is_initialized().await? {
+            return Ok(false);
+        }
+
+        let set: Option<Option<String>> =
+            sqlx::query_scalar("SELECT recovery_set FROM vault_config WHERE id = 1")
+                .fetch_optional(&self.db.pool)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(set.flatten().is_some())
+    }
+
+    /// Rebuilds the data key from recovery shares and sets a new PIN.
+    ///
+    /// This works during a lockout. The vault is left unlocked and the
+    /// failed-attempt counter is cleared. With `key_file` the new PIN
+    /// requires that key file; without it, any key-file requirement is
+    /// dropped. Key slots are not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `shares` - At least the threshold number of shares of the latest set
+    /// * `new_pin` - PIN to set
+    /// * `key_file` - Contents of a key file to require from now on
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotInitialized`] if vault hasn't been initialized
+    /// - [`VaultError::PinTooShort`]/[`VaultError::PinTooLong`] for a bad PIN
+    /// - [`VaultError::InvalidInput`] if the vault has no recovery shares,
+    ///   or the shares are too few, mixed, from an older set or don't rebuild
+    ///   this vault's key
+    pub async fn recover_with_shares(
+        &self,
+        shares: &[Share],
+        new_pin: &str,
+        key_file: Option<&[u8]>,
+    ) -> Result<()> {
+        validate_pin(new_pin).map_err(|e| match e {
+            crate::core::PinValidationError::TooShort => VaultError::PinTooShort,
+            crate::core::PinValidationError::TooLong => VaultError::PinTooLong,
+            e => VaultError::InvalidInput(e.to_string()),
+        })?;
+        if !self.is_initialized().await? {
+            return Err(VaultError::NotInitialized);
+        }
+
+        let data_key = self.data_key_from_shares(shares).await?;
+        let pin_hash = self.current_pin_hash().await?;
+
+        self.db.create_backup().await?;
+
+        let kdf = self.kdf_config().await?;
+        let params = kdf.pending.unwrap_or(kdf.current);
+        let audit = AuditRecord {
+            detail: Some("recovery shares"),
+            ..AuditRecord::new(AuditEvent::PinChange)
+        };
+        self.rewrap_data_key(
+            new_pin,
+            key_file,
+            &data_key,
+            &params,
+            &pin_hash,
+            Some(audit),
+        )
+        .await?;
+
+        self.finish_unlock(data_key).await
+    }
+
+    /// Rebuilds the data key from recovery shares of the vault's current set.
+    async fn data_key_from_shares(&self, shares: &[Share]) -> Result<VaultKey> {
+        let row =
+            sqlx::query("SELECT recovery_set, recovery_verifier FROM vault_config WHERE id = 1")
+                .fetch_one(&self.db.pool)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        use sqlx::Row;
+        let recovery_set: Option<String> = row.get("recovery_set");
+        let recovery_verifier: Option<String> = row.get("recovery_verifier");
+        let (Some(recovery_set), Some(recovery_verifier)) = (recovery_set, recovery_verifier)
+        else {
+            return Err(VaultError::InvalidInput(
+                "no recovery shares were created for this vault".to_string(),
+            ));
+        };
+        if shares
+            .iter()
+            .any(|s| hex::encode(s.set_id()) != recovery_set)
+        {
+            return Err(VaultError::InvalidInput(
+                "recovery shares are not from this vault's latest set".to_string(),
+            ));
+        }
+
+        let data_key = self.crypto.combine_data_key(shares)?;
+        let verifier = hex::decode(&recovery_verifier)
+            .map_err(|_| VaultError::Database("Invalid recovery verifier".to_string()))?;
+        if !self.crypto.verify_recovery(&data_key, &verifier) {
+            return Err(VaultError::InvalidInput(
+                "recovery shares do not rebuild this vault's key".to_string(),
+            ));
+        }
+        Ok(data_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::validate_pin;
+
+    // Helper to create test instances
     async fn setup_test_service() -> AuthService {
         let db = Arc::new(VaultDb::new("sqlite::memory:").await.unwrap());
         let crypto = Arc::new(CryptoService::new());
-        AuthService::new(db, crypto)
+        AuthService::new(db, crypto)
+    }
+
+    // Ends the backoff after a failed attempt, as if its delay had passed
+    async fn expire_backoff(service: &AuthService) {
+        sqlx::query("UPDATE vault_config SET locked_until = ?1 WHERE id = 1")
+            .bind(chrono::Utc::now().timestamp() - 1)
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+    }
+
+    // Counts a failed attempt without waiting out the previous backoff
+    async fn record_failure(service: &AuthService) {
+        expire_backoff(service).await;
+        service.reserve_attempt().await.unwrap();
+    }
+
+    #[test]
+    fn test_validate_pin_too_short() {
+        let result = validate_pin("12345");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_pin_empty() {
+        let result = validate_pin("");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_pin_valid() {
+        let result = validate_pin("my-secure-pin-123!");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_validate_pin_max_length() {
+        // 64 chars of mixed content should be OK
+        let pin = "a1b2c3d4".repeat(8); // 64 chars
+        assert!(validate_pin(&pin).is_ok());
+
+        // 65+ chars should fail
+        let pin = "a1b2c3d4".repeat(9); // 72 chars
+        assert!(validate_pin(&pin).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_initial_state() {
+        let service = setup_test_service().await;
+
+        assert!(!service.is_unlocked());
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_is_initialized_empty_db() {
+        let service = setup_test_service().await;
+        let is_init = service.is_initialized().await.unwrap();
+        assert!(!is_init);
+    }
+
+    #[tokio::test]
+    async fn test_init_vault() {
+        let service = setup_test_service().await;
+
+        service.init_vault("secure123").await.unwrap();
+
+        assert!(service.is_initialized().await.unwrap());
+        assert!(service.is_unlocked());
+    }
+
+    #[tokio::test]
+    async fn test_init_vault_too_short_pin() {
+        let service = setup_test_service().await;
+
+        let result = service.init_vault("12345").await;
+
+        assert!(result.is_err());
+        assert!(!service.is_initialized().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unlock_success() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service.lock().await.unwrap();
+
+        assert!(!service.is_unlocked());
+
+        service.unlock("secure123").await.unwrap();
+
+        assert!(service.is_unlocked());
+    }
+
+    #[tokio::test]
+    async fn test_unlock_wrong_pin() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service.lock().await.unwrap();
+
+        let result = service.unlock("wrong-pin").await;
+
+        assert!(result.is_err());
+        assert!(!service.is_unlocked());
+        assert!(service.get_failed_attempts().await.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_lock_clears_key() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+
+        assert!(service.is_unlocked());
+        assert!(service.get_vault_key().await.is_ok());
+
+        service.lock().await.unwrap();
+
+        assert!(!service.is_unlocked());
+        assert!(service.get_vault_key().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_change_pin() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+
+        service
+            .change_pin("secure123", "new-pin-456")
+            .await
+            .unwrap();
+        service.lock().await.unwrap();
+
+        // Old PIN should fail
+        let result = service.unlock("secure123").await;
+        assert!(result.is_err());
+        expire_backoff(&service).await;
+
+        // New PIN should work
+        service.unlock("new-pin-456").await.unwrap();
+        assert!(service.is_unlocked());
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_tracking() {
+        let service = setup_test_service().await;
+        service.init_vault("secure-pin-123").await.unwrap();
+        service.lock().await.unwrap();
+
+        // Initially no failed attempts
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 0);
+
+        // Attempt with clearly wrong PIN
+        let result = service.unlock("completely-different-pin-xyz").await;
+        assert!(matches!(result, Err(VaultError::InvalidPin)));
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 1);
+        expire_backoff(&service).await;
+
+        // Successful unlock should always reset
+        service.unlock("secure-pin-123").await.unwrap();
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_init_stores_full_verifier() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+
+        let pin_hash: String = sqlx::query_scalar("SELECT pin_hash FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+
+        assert!(pin_hash.starts_with(PIN_VERIFIER_PREFIX));
+        assert!(matches!(
+            StoredPinHash::parse(&pin_hash),
+            StoredPinHash::Verifier(v) if v.len() == 32
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_pins_rejected_by_verifier() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service.lock().await.unwrap();
+
+        for wrong_pin in ["secure124", "Secure123", "secure1234", "123secure"] {
+            let result = service.unlock(wrong_pin).await;
+            assert!(matches!(result, Err(VaultError::InvalidPin)));
+            assert!(!service.is_unlocked());
+            service.clear_failed_attempts().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_pin_with_matching_first_byte_rejected() {
+        let service = setup_test_service().await;
+        setup_legacy_vault(&service, "secure123").await;
+
+        // Re-key the legacy layout with the cheapest accepted costs and a
+        // salt for which the search below ends on its first candidate
+        let kdf = KdfParams::argon2id(KdfParams::MIN_MEMORY_KIB, 1, 1).unwrap();
+        let salt = [101u8; 32];
+        let derive = |pin: &str| {
+            service
+                .crypto
+                .derive_master_key_with_params(pin, &salt, &kdf)
+                .unwrap()
+        };
+        let real_key = derive("secure123");
+        let first_byte = real_key.as_bytes()[0];
+        sqlx::query(
+            "UPDATE vault_config SET salt = ?1, pin_hash = ?2, kdf_params = ?3 WHERE id = 1",
+        )
+        .bind(salt.as_slice())
+        .bind(format!("${}:{first_byte}", hex::encode(salt)))
+        .bind(kdf.to_string())
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+        sqlx::query("DELETE FROM api_keys")
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        insert_raw_key(&service, &real_key, "k1", "github", "token", "ghp_legacy").await;
+
+        // A wrong PIN the first-byte check alone would have accepted
+        let wrong_pin = (0..)
+            .map(|i| format!("wrong-pin-{i}"))
+            .find(|pin| derive(pin).as_bytes()[0] == first_byte)
+            .unwrap();
+
+        let result = service.unlock(&wrong_pin).await;
+        assert!(matches!(result, Err(VaultError::InvalidPin)));
+
+        // With nothing to decrypt, the match can't be confirmed either
+        sqlx::query("DELETE FROM api_keys")
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        service.clear_failed_attempts().await.unwrap();
+        let result = service.unlock(&wrong_pin).await;
+        assert!(matches!(result, Err(VaultError::InvalidPin)));
+        assert!(!service.is_unlocked());
+
+        // Nothing derived from the wrong PIN was stored
+        let (pin_hash, wrapped): (String, Option<Vec<u8>>) =
+            sqlx::query_as("SELECT pin_hash, wrapped_dek FROM vault_config WHERE id = 1")
+                .fetch_one(&service.db.pool)
+                .await
+                .unwrap();
+        assert!(!pin_hash.starts_with(PIN_VERIFIER_PREFIX));
+        assert!(wrapped.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_pin_hash_upgraded_on_unlock() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+
+        // Rewrite the config in the legacy `$<salt>:<first byte>` format
+        let salt: Vec<u8> = sqlx::query_scalar("SELECT salt FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        let salt_array: [u8; 32] = salt.clone().try_into().unwrap();
+        let first_byte = service
+            .crypto
+            .derive_master_key("secure123", &salt_array)
+            .unwrap()
+            .as_bytes()[0];
+        let legacy_hash = format!("${}:{first_byte}", hex::encode(&salt));
+        sqlx::query("UPDATE vault_config SET pin_hash = ?1 WHERE id = 1")
+            .bind(&legacy_hash)
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        service.lock().await.unwrap();
+
+        service.unlock("secure123").await.unwrap();
+
+        let pin_hash: String = sqlx::query_scalar("SELECT pin_hash FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        assert!(pin_hash.starts_with(PIN_VERIFIER_PREFIX));
+
+        // The upgraded verifier still accepts the PIN and rejects others
+        service.lock().await.unwrap();
+        service.unlock("secure123").await.unwrap();
+        service.lock().await.unwrap();
+        assert!(service.unlock("not-the-pin").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_first_byte_match_confirmed_against_ciphertext() {
+        let service = setup_test_service().await;
+        let legacy_key = setup_legacy_vault(&service, "secure123").await;
+
+        // A key that shares the first byte passes the legacy check but not decryption
+        let mut impostor = *legacy_key.as_bytes();
+        impostor[31] ^= 0xff;
+        let impostor = crate::crypto::VaultKey::from_bytes(impostor);
+        assert!(!service.confirm_legacy_key(&impostor).await.unwrap());
+        assert!(service.confirm_legacy_key(&legacy_key).await.unwrap());
+
+        // Without a ciphertext not even the right key can be confirmed
+        sqlx::query("DELETE FROM api_keys")
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        assert!(!service.confirm_legacy_key(&legacy_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_first_byte_match_confirmed_against_wrapped_data_key() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let salt: Vec<u8> = sqlx::query_scalar("SELECT salt FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        let master_key = service
+            .crypto
+            .derive_master_key("secure123", &salt.try_into().unwrap())
+            .unwrap();
+
+        let mut impostor = *master_key.as_bytes();
+        impostor[31] ^= 0xff;
+        let impostor = crate::crypto::VaultKey::from_bytes(impostor);
+        assert!(!service.confirm_legacy_key(&impostor).await.unwrap());
+        assert!(service.confirm_legacy_key(&master_key).await.unwrap());
+    }
+
+    /// Creates a vault laid out the way releases before envelope encryption
+    /// did: no wrapped data key, legacy verifier, and one secret encrypted
+    /// directly under the PIN-derived key.
+    async fn setup_legacy_vault(service: &AuthService, pin: &str) -> VaultKey {
+        let salt = service.crypto.generate_salt();
+        let master_key = service.crypto.derive_master_key(pin, &salt).unwrap();
+
+        sqlx::query(
+            "CREATE TABLE vault_config (id INTEGER PRIMARY KEY, salt BLOB NOT NULL, pin_hash TEXT NOT NULL, created_at INTEGER NOT NULL)",
+        )
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO vault_config (id, salt, pin_hash, created_at) VALUES (1, ?1, ?2, 0)")
+            .bind(salt.as_slice())
+            .bind(format!("${}:{}", hex::encode(salt), master_key.as_bytes()[0]))
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        // Those releases predate schema v9, which adds the newer columns
+        sqlx::query("DELETE FROM schema_version WHERE version >= 9")
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        service.db.migrate(false).await.unwrap();
+
+        insert_raw_key(service, &master_key, "k1", "github", "token", "ghp_legacy").await;
+        master_key
+    }
+
+    async fn insert_raw_key(
+        service: &AuthService,
+        key: &VaultKey,
+        id: &str,
+        app_name: &str,
+        key_name: &str,
+        value: &str,
+    ) {
+        let (encrypted, key_salt) = service
+            .crypto
+            .encrypt_api_key(value, key, app_name, key_name)
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, key_salt, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, 0)",
+        )
+        .bind(id)
+        .bind(app_name)
+        .bind(key_name)
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&key_salt[..])
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+    }
+
+    async fn read_raw_key(service: &AuthService, id: &str) -> (Vec<u8>, Vec<u8>, [u8; 32]) {
+        use sqlx::Row;
+        let row = sqlx::query("SELECT encrypted_key_value, nonce, key_salt FROM api_keys WHERE id = ?1")
+            .bind(id)
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        let key_salt: Vec<u8> = row.get("key_salt");
+        (row.get("encrypted_key_value"), row.get("nonce"), key_salt.try_into().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_data_key_is_not_pin_derived() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+
+        let salt: Vec<u8> = sqlx::query_scalar("SELECT salt FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        let master_key = service
+            .crypto
+            .derive_master_key("secure123", &salt.try_into().unwrap())
+            .unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        assert_ne!(data_key.as_bytes(), master_key.as_bytes());
+
+        // The same data key comes back after a lock/unlock cycle
+        service.lock().await.unwrap();
+        service.unlock("secure123").await.unwrap();
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_change_pin_leaves_ciphertexts_untouched() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        insert_raw_key(&service, &data_key, "k1", "github", "token", "ghp_secret").await;
+        let before = read_raw_key(&service, "k1").await;
+
+        service.change_pin("secure123", "newpin456").await.unwrap();
+
+        assert_eq!(read_raw_key(&service, "k1").await, before);
+        service.lock().await.unwrap();
+        assert!(service.unlock("secure123").await.is_err());
+        service.clear_failed_attempts().await.unwrap();
+        service.unlock("newpin456").await.unwrap();
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_vault_migrates_to_wrapped_data_key() {
+        let service = setup_test_service().await;
+        let legacy_key = setup_legacy_vault(&service, "secure123").await;
+        let before = read_raw_key(&service, "k1").await;
+
+        service.unlock("secure123").await.unwrap();
+
+        // The old master key is kept as the data key, now stored wrapped
+        let data_key = service.get_vault_key().await.unwrap();
+        assert_eq!(data_key.as_bytes(), legacy_key.as_bytes());
+        let wrapped: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT wrapped_dek FROM vault_config WHERE id = 1")
+                .fetch_one(&service.db.pool)
+                .await
+                .unwrap();
+        assert!(wrapped.is_some());
+
+        // Changing the PIN afterwards keeps existing secrets readable as-is
+        service.change_pin("secure123", "newpin456").await.unwrap();
+        let (ciphertext, nonce, key_salt) = read_raw_key(&service, "k1").await;
+        assert_eq!((ciphertext.clone(), nonce.clone(), key_salt), before);
+        let value = service
+            .crypto
+            .decrypt_api_key(
+                &EncryptedData { ciphertext, nonce },
+                &service.get_vault_key().await.unwrap(),
+                "github",
+                "token",
+                &key_salt,
+            )
+            .unwrap();
+        assert_eq!(value, "ghp_legacy");
+    }
+
+    #[tokio::test]
+    async fn test_change_pin_rolls_back_on_write_failure() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        insert_raw_key(&service, &data_key, "k1", "github", "token", "ghp_secret").await;
+        let old_pin_hash = service.current_pin_hash().await.unwrap();
+
+        // Fail the audit entry, which is written after the config row
+        sqlx::query(
+            "CREATE TRIGGER fail_pin_change BEFORE INSERT ON audit_log WHEN NEW.event = 'pin_change' BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+        )
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+
+        let result = service.change_pin("secure123", "newpin456").await;
+        assert!(matches!(result, Err(VaultError::Database(msg)) if msg.contains("injected failure")));
+
+        sqlx::query("DROP TRIGGER fail_pin_change")
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+
+        // The config row written before the failure was rolled back too
+        assert_eq!(service.current_pin_hash().await.unwrap(), old_pin_hash);
+
+        // The old PIN still unlocks the same data key; the new one never took effect
+        service.lock().await.unwrap();
+        assert!(service.unlock("newpin456").await.is_err());
+        service.clear_failed_attempts().await.unwrap();
+        service.unlock("secure123").await.unwrap();
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_persist_across_instances() {
+        let db = Arc::new(VaultDb::new("sqlite::memory:").await.unwrap());
+        let crypto = Arc::new(CryptoService::new());
+        let first = AuthService::new(db.clone(), crypto.clone());
+        first.init_vault("secure123").await.unwrap();
+        first.lock().await.unwrap();
+
+        assert!(first.unlock("wrong-pin-1").await.is_err());
+
+        // A fresh service (e.g. the next CLI invocation) sees the failure
+        let second = AuthService::new(db, crypto);
+        let state = second.lockout_state().await.unwrap();
+        assert_eq!(state.failed_attempts, 1);
+        assert!(state.last_failed_at.is_some());
+        assert_eq!(
+            state.locked_until.unwrap() - state.last_failed_at.unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_unlocks_get_one_attempt_per_backoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("vault.db").display()
+        );
+        let crypto = Arc::new(CryptoService::new());
+        let owner = AuthService::new(Arc::new(VaultDb::new(&url).await.unwrap()), crypto.clone());
+        owner.init_vault("secure123").await.unwrap();
+        owner.lock().await.unwrap();
+
+        // One service per connection pool, like separate `vult` processes
+        let mut guessers = Vec::new();
+        for _ in 0..8 {
+            let db = Arc::new(VaultDb::new(&url).await.unwrap());
+            guessers.push(AuthService::new(db, crypto.clone()));
+        }
+        let tasks: Vec<_> = guessers
+            .into_iter()
+            .enumerate()
+            .map(|(i, service)| {
+                tokio::spawn(async move { service.unlock(&format!("wrong-pin-{i}")).await })
+            })
+            .collect();
+
+        let mut checked = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Err(VaultError::InvalidPin) => checked += 1,
+                Err(VaultError::TooManyAttempts { .. }) => {}
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+        assert!(checked <= 1, "{checked} guesses got past the backoff");
+        assert_eq!(owner.get_failed_attempts().await.unwrap(), checked);
+    }
+
+    #[tokio::test]
+    async fn test_lockout_after_max_attempts() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service.lock().await.unwrap();
+
+        // Record failures directly to avoid waiting out each backoff
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            record_failure(&service).await;
+        }
+        let state = service.lockout_state().await.unwrap();
+        assert_eq!(state.failed_attempts, MAX_FAILED_ATTEMPTS);
+        assert!(state.is_locked_out(chrono::Utc::now().timestamp()));
+
+        // Even the correct PIN is refused while locked out
+        let result = service.unlock("secure123").await;
+        assert!(matches!(result, Err(VaultError::TooManyAttempts { .. })));
+        assert!(!service.is_unlocked());
+
+        // Once the lockout has expired the correct PIN works and clears it
+        expire_backoff(&service).await;
+        service.unlock("secure123").await.unwrap();
+        assert_eq!(service.lockout_state().await.unwrap(), LockoutState::default());
     }
 
-    #[test]
-    fn test_validate_pin_too_short() {
-        let result = validate_pin("12345");
-        assert!(result.is_err());
+    #[tokio::test]
+    async fn test_backoff_doubles_and_caps() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+
+        let mut delays = Vec::new();
+        for _ in 0..7 {
+            record_failure(&service).await;
+            let state = service.lockout_state().await.unwrap();
+            delays.push(state.locked_until.unwrap() - state.last_failed_at.unwrap());
+        }
+
+        assert_eq!(delays, vec![2, 4, 8, 16, 32, 32, 32]);
     }
 
-    #[test]
-    fn test_validate_pin_empty() {
-        let result = validate_pin("");
+    #[tokio::test]
+    async fn test_reset_failed_attempts_requires_unlock() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            record_failure(&service).await;
+        }
+
+        // Admin reset from an authenticated session
+        service.reset_failed_attempts().await.unwrap();
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 0);
+
+        // Not allowed once locked
+        record_failure(&service).await;
+        service.lock().await.unwrap();
+        let result = service.reset_failed_attempts().await;
+        assert!(matches!(result, Err(VaultError::Locked)));
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_refuses_without_waiting() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service.lock().await.unwrap();
+
+        assert!(matches!(
+            service.unlock("wrong-pin").await,
+            Err(VaultError::InvalidPin)
+        ));
+
+        // Even the correct PIN is refused until the backoff is over
+        let result = service.unlock("secure123").await;
+        assert!(
+            matches!(result, Err(VaultError::TooManyAttempts { retry_after }) if (1..=2).contains(&retry_after))
+        );
+        assert!(!service.is_unlocked());
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reset_lockout_with_key_file_slot_or_shares() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        let key_file: &[u8] = b"key file contents";
+        service
+            .add_slot("usb", SlotKind::KeyFile, SlotCredential::KeyFile(key_file))
+            .await
+            .unwrap();
+        let shares = service.create_recovery_shares(2, 3).await.unwrap();
+
+        for credential in [
+            LockoutReset::KeyFile(key_file),
+            LockoutReset::Shares(&shares[1..]),
+        ] {
+            service.lock().await.unwrap();
+            for _ in 0..MAX_FAILED_ATTEMPTS {
+                record_failure(&service).await;
+            }
+            assert!(matches!(
+                service
+                    .unlock_with_slot(SlotCredential::KeyFile(key_file))
+                    .await,
+                Err(VaultError::TooManyAttempts { .. })
+            ));
+
+            service.reset_lockout(credential).await.unwrap();
+            assert!(service.is_unlocked());
+            assert_eq!(
+                service.get_vault_key().await.unwrap().as_bytes(),
+                data_key.as_bytes()
+            );
+            assert_eq!(
+                service.lockout_state().await.unwrap(),
+                LockoutState::default()
+            );
+        }
+
+        // Wrong credentials leave the lockout in place
+        service.lock().await.unwrap();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            record_failure(&service).await;
+        }
+        let result = service
+            .reset_lockout(LockoutReset::KeyFile(b"another file"))
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        let result = service
+            .reset_lockout(LockoutReset::Shares(&shares[..1]))
+            .await;
         assert!(result.is_err());
+        assert!(!service.is_unlocked());
+        assert_eq!(
+            service.get_failed_attempts().await.unwrap(),
+            MAX_FAILED_ATTEMPTS
+        );
+
+        let filter = crate::services::AuditFilter {
+            event: Some(AuditEvent::LockoutReset),
+            ..Default::default()
+        };
+        let entries = service.audit_log().entries(&filter).await.unwrap();
+        let details: Vec<_> = entries.iter().map(|e| e.detail.as_deref()).collect();
+        assert_eq!(details, [Some("key-file slot"), Some("recovery shares")]);
     }
 
-    #[test]
-    fn test_validate_pin_valid() {
-        let result = validate_pin("my-secure-pin-123!");
-        assert!(result.is_ok());
+    fn light_kdf(iterations: u32) -> KdfParams {
+        KdfParams::argon2id(KdfParams::MIN_MEMORY_KIB, iterations, 1).unwrap()
     }
 
-    #[test]
-    fn test_validate_pin_max_length() {
-        // 64 chars of mixed content should be OK
-        let pin = "a1b2c3d4".repeat(8); // 64 chars
-        assert!(validate_pin(&pin).is_ok());
+    #[tokio::test]
+    async fn test_init_records_default_kdf() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
 
-        // 65+ chars should fail
-        let pin = "a1b2c3d4".repeat(9); // 72 chars
-        assert!(validate_pin(&pin).is_err());
+        let config = service.kdf_config().await.unwrap();
+        assert_eq!(config.current, KdfParams::default());
+        assert_eq!(config.pending, None);
     }
 
     #[tokio::test]
-    async fn test_service_initial_state() {
+    async fn test_kdf_upgraded_after_unlock() {
         let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        let old_salt: Vec<u8> = sqlx::query_scalar("SELECT salt FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
 
-        assert!(!service.is_unlocked());
-        assert_eq!(service.get_failed_attempts().await, 0);
+        service.set_kdf_target(light_kdf(2), true).await.unwrap();
+        assert_eq!(service.kdf_config().await.unwrap().pending, Some(light_kdf(2)));
+
+        service.lock().await.unwrap();
+        service.unlock("secure123").await.unwrap();
+
+        // The PIN key was re-derived with the new costs and a fresh salt
+        let config = service.kdf_config().await.unwrap();
+        assert_eq!(config.current, light_kdf(2));
+        assert_eq!(config.pending, None);
+        let new_salt: Vec<u8> = sqlx::query_scalar("SELECT salt FROM vault_config WHERE id = 1")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        assert_ne!(new_salt, old_salt);
+
+        // Same data key, same PIN, wrong PINs still rejected
+        service.lock().await.unwrap();
+        service.unlock("secure123").await.unwrap();
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
+        service.lock().await.unwrap();
+        assert!(matches!(service.unlock("wrong-pin").await, Err(VaultError::InvalidPin)));
     }
 
     #[tokio::test]
-    async fn test_is_initialized_empty_db() {
+    async fn test_change_pin_applies_pending_kdf() {
         let service = setup_test_service().await;
-        let is_init = service.is_initialized().await.unwrap();
-        assert!(!is_init);
+        service.init_vault("secure123").await.unwrap();
+        service.set_kdf_target(light_kdf(1), true).await.unwrap();
+
+        service.change_pin("secure123", "newpin456").await.unwrap();
+
+        let config = service.kdf_config().await.unwrap();
+        assert_eq!(config.current, light_kdf(1));
+        assert_eq!(config.pending, None);
+        service.lock().await.unwrap();
+        service.unlock("newpin456").await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_init_vault() {
+    async fn test_set_kdf_target_requires_unlock() {
         let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service.lock().await.unwrap();
 
+        let result = service.set_kdf_target(light_kdf(1), true).await;
+        assert!(matches!(result, Err(VaultError::Locked)));
+    }
+
+    #[tokio::test]
+    async fn test_set_kdf_target_refuses_downgrade() {
+        let service = setup_test_service().await;
         service.init_vault("secure123").await.unwrap();
 
-        assert!(service.is_initialized().await.unwrap());
-        assert!(service.is_unlocked());
+        // Lower memory cost than the default
+        let result = service.set_kdf_target(light_kdf(3), false).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        // Lower time cost with the same memory
+        let fewer_passes = KdfParams::argon2id(65536, 1, 4).unwrap();
+        let result = service.set_kdf_target(fewer_passes, false).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        assert_eq!(service.kdf_config().await.unwrap().pending, None);
+
+        // Stronger costs need no confirmation
+        let stronger = KdfParams::argon2id(65536, 4, 4).unwrap();
+        service.set_kdf_target(stronger, false).await.unwrap();
+        assert_eq!(service.kdf_config().await.unwrap().pending, Some(stronger));
+
+        // Forcing schedules the weaker costs
+        service.set_kdf_target(light_kdf(1), true).await.unwrap();
+        assert_eq!(
+            service.kdf_config().await.unwrap().pending,
+            Some(light_kdf(1))
+        );
     }
 
     #[tokio::test]
-    async fn test_init_vault_too_short_pin() {
+    async fn test_legacy_vault_reports_default_kdf() {
         let service = setup_test_service().await;
+        setup_legacy_vault(&service, "secure123").await;
 
-        let result = service.init_vault("12345").await;
+        assert_eq!(service.kdf_config().await.unwrap().current, KdfParams::default());
+        service.unlock("secure123").await.unwrap();
+    }
 
-        assert!(result.is_err());
-        assert!(!service.is_initialized().await.unwrap());
+    #[tokio::test]
+    async fn test_key_file_required_for_unlock() {
+        let service = setup_test_service().await;
+        service
+            .init_vault_with_key_file("secure123", Some(b"key file contents"))
+            .await
+            .unwrap();
+        assert!(service.requires_key_file().await.unwrap());
+        service.lock().await.unwrap();
+
+        // Missing key file is a distinct error and not a failed attempt
+        let result = service.unlock("secure123").await;
+        assert!(matches!(result, Err(VaultError::KeyFileRequired)));
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 0);
+
+        // Wrong key file looks like a wrong PIN
+        let result = service
+            .unlock_with_key_file("secure123", Some(b"other contents"))
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidPin)));
+        service.clear_failed_attempts().await.unwrap();
+
+        service
+            .unlock_with_key_file("secure123", Some(b"key file contents"))
+            .await
+            .unwrap();
+        assert!(service.is_unlocked());
     }
 
     #[tokio::test]
-    async fn test_unlock_success() {
+    async fn test_key_file_rejected_for_plain_vault() {
         let service = setup_test_service().await;
         service.init_vault("secure123").await.unwrap();
         service.lock().await.unwrap();
 
-        assert!(!service.is_unlocked());
+        assert!(!service.requires_key_file().await.unwrap());
+        let result = service
+            .unlock_with_key_file("secure123", Some(b"key file contents"))
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_change_pin_keeps_key_file() {
+        let service = setup_test_service().await;
+        let key_file: &[u8] = b"key file contents";
+        service
+            .init_vault_with_key_file("secure123", Some(key_file))
+            .await
+            .unwrap();
+
+        service
+            .change_pin_with_key_file("secure123", "newpin456", Some(key_file))
+            .await
+            .unwrap();
+        service.lock().await.unwrap();
+
+        assert!(matches!(
+            service.unlock("newpin456").await,
+            Err(VaultError::KeyFileRequired)
+        ));
+        service
+            .unlock_with_key_file("newpin456", Some(key_file))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_key_slots_unlock_and_revoke() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        let key_file: &[u8] = b"key file contents";
+
+        let passphrase = service
+            .add_slot(
+                "offline",
+                SlotKind::Passphrase,
+                SlotCredential::Secret("correct horse battery staple"),
+            )
+            .await
+            .unwrap();
+        let file_slot = service
+            .add_slot("usb", SlotKind::KeyFile, SlotCredential::KeyFile(key_file))
+            .await
+            .unwrap();
+
+        let slots = service.list_slots().await.unwrap();
+        let labels: Vec<&str> = slots.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, [PRIMARY_SLOT_LABEL, "offline", "usb"]);
+
+        // Every credential opens the same data key
+        service.lock().await.unwrap();
+        service.unlock("correct horse battery staple").await.unwrap();
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
+        service.lock().await.unwrap();
+        service
+            .unlock_with_slot(SlotCredential::KeyFile(key_file))
+            .await
+            .unwrap();
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
 
+        // Revoking one slot leaves the others working
+        service.revoke_slot(passphrase.id).await.unwrap();
+        service.lock().await.unwrap();
+        assert!(matches!(
+            service.unlock("correct horse battery staple").await,
+            Err(VaultError::InvalidPin)
+        ));
+        service.clear_failed_attempts().await.unwrap();
+        service
+            .unlock_with_slot(SlotCredential::KeyFile(key_file))
+            .await
+            .unwrap();
+        service.lock().await.unwrap();
         service.unlock("secure123").await.unwrap();
 
-        assert!(service.is_unlocked());
+        assert!(matches!(
+            service.revoke_slot(passphrase.id).await,
+            Err(VaultError::NotFound(_))
+        ));
+        assert!(matches!(service.revoke_slot(0).await, Err(VaultError::InvalidInput(_))));
+        assert_eq!(service.list_slots().await.unwrap().len(), 2);
+        assert_eq!(service.list_slots().await.unwrap()[1].id, file_slot.id);
     }
 
     #[tokio::test]
-    async fn test_unlock_wrong_pin() {
+    async fn test_revoke_slot_leaves_ciphertexts_untouched() {
         let service = setup_test_service().await;
         service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        insert_raw_key(&service, &data_key, "k1", "app", "token", "secret-value").await;
+        let before = read_raw_key(&service, "k1").await;
+
+        let slot = service
+            .add_slot("usb", SlotKind::KeyFile, SlotCredential::KeyFile(b"key file"))
+            .await
+            .unwrap();
+        service.revoke_slot(slot.id).await.unwrap();
+
+        assert_eq!(read_raw_key(&service, "k1").await, before);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_pin_with_slots_counts_as_failure() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        service
+            .add_slot("usb", SlotKind::KeyFile, SlotCredential::KeyFile(b"key file"))
+            .await
+            .unwrap();
         service.lock().await.unwrap();
 
-        let result = service.unlock("wrong-pin").await;
+        // No PIN slot exists, but the primary PIN was still tried
+        assert!(matches!(service.unlock("wrong-pin").await, Err(VaultError::InvalidPin)));
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 1);
+        expire_backoff(&service).await;
+        assert!(matches!(
+            service
+                .unlock_with_slot(SlotCredential::KeyFile(b"other file"))
+                .await,
+            Err(VaultError::InvalidPin)
+        ));
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 2);
+        expire_backoff(&service).await;
+
+        // No slot of this kind: nothing was tried
+        assert!(matches!(
+            service.unlock_with_slot(SlotCredential::Secret("secure123")).await,
+            Err(VaultError::InvalidInput(_))
+        ));
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 2);
+    }
 
-        assert!(result.is_err());
+    #[tokio::test]
+    async fn test_add_slot_validation() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let key_file = SlotCredential::KeyFile(b"key file");
+
+        let result = service
+            .add_slot(PRIMARY_SLOT_LABEL, SlotKind::KeyFile, key_file)
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        let result = service
+            .add_slot("short", SlotKind::Passphrase, SlotCredential::Secret("too short"))
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        let result = service.add_slot("pin", SlotKind::Pin, key_file).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+
+        service.add_slot("usb", SlotKind::KeyFile, key_file).await.unwrap();
+        let result = service.add_slot("usb", SlotKind::KeyFile, key_file).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+
+        service.lock().await.unwrap();
+        let result = service.add_slot("usb2", SlotKind::KeyFile, key_file).await;
+        assert!(matches!(result, Err(VaultError::Locked)));
+    }
+
+    #[tokio::test]
+    async fn test_test_slot_does_not_unlock() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let slot = service
+            .add_slot("usb", SlotKind::KeyFile, SlotCredential::KeyFile(b"key file"))
+            .await
+            .unwrap();
+        service.lock().await.unwrap();
+
+        assert!(service
+            .test_slot(slot.id, SlotCredential::KeyFile(b"key file"))
+            .await
+            .unwrap());
         assert!(!service.is_unlocked());
-        assert!(service.get_failed_attempts().await > 0);
+        assert!(!service
+            .test_slot(slot.id, SlotCredential::KeyFile(b"other file"))
+            .await
+            .unwrap());
+        assert_eq!(service.get_failed_attempts().await.unwrap(), 1);
+        assert!(matches!(
+            service.test_slot(slot.id, SlotCredential::Secret("secure123")).await,
+            Err(VaultError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            service.test_slot(99, SlotCredential::KeyFile(b"key file")).await,
+            Err(VaultError::NotFound(_))
+        ));
     }
 
     #[tokio::test]
-    async fn test_lock_clears_key() {
+    async fn test_recover_with_shares_sets_new_pin() {
         let service = setup_test_service().await;
         service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        assert!(!service.has_recovery_shares().await.unwrap());
 
+        let shares = service.create_recovery_shares(3, 5).await.unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(service.has_recovery_shares().await.unwrap());
+
+        // Recovery works even while locked out
+        service.lock().await.unwrap();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            record_failure(&service).await;
+        }
+        let subset = [shares[4].clone(), shares[0].clone(), shares[2].clone()];
+        service
+            .recover_with_shares(&subset, "newpin456", None)
+            .await
+            .unwrap();
         assert!(service.is_unlocked());
-        assert!(service.get_vault_key().await.is_ok());
+        assert_eq!(service.get_vault_key().await.unwrap().as_bytes(), data_key.as_bytes());
+        assert_eq!(service.lockout_state().await.unwrap(), LockoutState::default());
+
+        service.lock().await.unwrap();
+        assert!(matches!(service.unlock("secure123").await, Err(VaultError::InvalidPin)));
+        service.clear_failed_attempts().await.unwrap();
+        service.unlock("newpin456").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recover_rejects_bad_shares() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
 
+        // No shares created yet
+        let other = setup_test_service().await;
+        other.init_vault("secure123").await.unwrap();
+        let foreign = other.create_recovery_shares(2, 3).await.unwrap();
+        let result = service.recover_with_shares(&foreign, "newpin456", None).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+
+        let old = service.create_recovery_shares(3, 5).await.unwrap();
+        let shares = service.create_recovery_shares(3, 5).await.unwrap();
         service.lock().await.unwrap();
 
+        // Below the threshold
+        let result = service
+            .recover_with_shares(&shares[..2], "newpin456", None)
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+
+        // Another vault's shares, or a retired set of this vault
+        let result = service.recover_with_shares(&foreign, "newpin456", None).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        let result = service.recover_with_shares(&old[..3], "newpin456", None).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+
+        // A mistyped share fails its checksum
+        let text = shares[0].to_text();
+        let (header, body) = text.split_once('\n').unwrap();
+        let first = if body.starts_with('0') { '1' } else { '0' };
+        let typo = format!("{header}\n{first}{}", &body[1..]);
+        assert!(typo.parse::<Share>().is_err());
+
         assert!(!service.is_unlocked());
-        assert!(service.get_vault_key().await.is_err());
+        service.unlock("secure123").await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_change_pin() {
+    async fn test_recover_replaces_key_file_requirement() {
         let service = setup_test_service().await;
-        service.init_vault("secure123").await.unwrap();
+        service
+            .init_vault_with_key_file("secure123", Some(b"lost key file"))
+            .await
+            .unwrap();
+        let shares = service.create_recovery_shares(2, 2).await.unwrap();
+        service.lock().await.unwrap();
 
         service
-            .change_pin("secure123", "new-pin-456")
+            .recover_with_shares(&shares, "newpin456", None)
             .await
             .unwrap();
+        assert!(!service.requires_key_file().await.unwrap());
         service.lock().await.unwrap();
+        service.unlock("newpin456").await.unwrap();
+    }
 
-        // Old PIN should fail
-        let result = service.unlock("secure123").await;
-        assert!(result.is_err());
+    #[tokio::test]
+    async fn test_unlock_with_data_key() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        service.lock().await.unwrap();
 
-        // New PIN should work
-        service.unlock("new-pin-456").await.unwrap();
+        let result = service
+            .unlock_with_data_key(VaultKey::from_bytes([7u8; 32]))
+            .await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
+        assert!(!service.is_unlocked());
+
+        service
+            .unlock_with_data_key(data_key.clone())
+            .await
+            .unwrap();
         assert!(service.is_unlocked());
+        assert_eq!(
+            service.get_vault_key().await.unwrap().as_bytes(),
+            data_key.as_bytes()
+        );
     }
 
     #[tokio::test]
-    async fn test_failed_attempts_tracking() {
+    async fn test_auth_events_are_audited() {
         let service = setup_test_service().await;
-        service.init_vault("secure-pin-123").await.unwrap();
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
         service.lock().await.unwrap();
 
-        // Initially no failed attempts
-        assert_eq!(service.get_failed_attempts().await, 0);
+        assert!(service.unlock("wrong-pin").await.is_err());
+        service.unlock_with_data_key(data_key.clone()).await.unwrap();
+        expire_backoff(&service).await;
+        service.change_pin("secure123", "secure456").await.unwrap();
 
-        // Attempt with clearly wrong PIN
-        let result = service.unlock("completely-different-pin-xyz").await;
+        let entries = service
+            .audit_log()
+            .entries(&crate::services::AuditFilter::default())
+            .await
+            .unwrap();
+        let events: Vec<(AuditEvent, bool)> = entries.iter().map(|e| (e.event, e.signed)).collect();
+        assert_eq!(
+            events,
+            vec![
+                (AuditEvent::Init, true),
+                (AuditEvent::UnlockFailed, false),
+                (AuditEvent::Unlock, true),
+                (AuditEvent::Unlock, true),
+                (AuditEvent::PinChange, true),
+            ]
+        );
+        assert_eq!(entries[2].detail.as_deref(), Some("session agent"));
+
+        // The failed attempt is covered by the entries signed after it
+        let report = service.audit_log().verify(&data_key).await.unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.unsigned, 0);
+    }
+
+    #[tokio::test]
+    async fn test_data_key_verifier_added_on_pin_unlock() {
+        let service = setup_test_service().await;
+        service.init_vault("secure123").await.unwrap();
+        let data_key = service.get_vault_key().await.unwrap();
+        sqlx::query("UPDATE vault_config SET dek_verifier = NULL WHERE id = 1")
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        service.lock().await.unwrap();
 
-        // If the PIN was actually wrong (result is Err), check counter incremented
-        // Note: The current implementation uses simplified verification
-        if result.is_err() {
-            assert!(service.get_failed_attempts().await >= 1);
+        let result = service.unlock_with_data_key(data_key.clone()).await;
+        assert!(matches!(result, Err(VaultError::InvalidState(_))));
+
+        service.unlock("secure123").await.unwrap();
+        service.lock().await.unwrap();
+        service.unlock_with_data_key(data_key).await.unwrap();
+    }
+
+    #[test]
+    fn test_key_file_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.key");
+
+        let contents = create_key_file(&path).unwrap();
+        assert_eq!(contents.len(), KEY_FILE_SIZE);
+        assert_eq!(read_key_file(&path).unwrap(), contents);
+
+        // Never overwrites an existing key file
+        assert!(matches!(create_key_file(&path), Err(VaultError::Io(_))));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
         }
 
-        // Successful unlock should always reset
-        service.unlock("secure-pin-123").await.unwrap();
-        assert_eq!(service.get_failed_attempts().await, 0);
+        let missing = dir.path().join("missing.key");
+        assert!(matches!(
+            read_key_file(&missing),
+            Err(VaultError::KeyFileUnavailable(_))
+        ));
     }
 }
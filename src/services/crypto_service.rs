@@ -2,11 +2,21 @@
 //!
 //! This service wraps the crypto module to provide a clean service interface.
 
+use std::time::Duration;
+
 use crate::crypto::{
-    decrypt, derive_key_from_pin, derive_per_key_encryption_key, encrypt, generate_salt,
-    EncryptedData, VaultKey,
+    audit_entry_hash, blind_index, calibrate_kdf, combine_with_key_file, compute_audit_head_mac,
+    compute_audit_mac, compute_data_key_verifier, compute_pin_verifier, compute_recovery_verifier,
+    decrypt, decrypt_api_key_value, decrypt_with_aad, derive_audit_key, derive_blind_index_key,
+    derive_key_from_key_file, derive_key_from_pin, derive_key_from_pin_with_params,
+    derive_metadata_key, derive_per_key_encryption_key, encrypt, encrypt_api_key_value,
+    encrypt_with_aad, generate_salt, generate_vault_key, unwrap_key, verify_audit_head_mac,
+    verify_audit_mac, verify_data_key_verifier, verify_pin_verifier, verify_recovery_verifier,
+    wrap_key, EncryptedData, IndexedField, KdfParams, KeyContext, KeyScheme, VaultKey,
 };
 use crate::error::{Result, VaultError};
+use crate::shamir::{self, Share};
+use zeroize::Zeroize;
 
 /// Cryptographic operations service.
 ///
@@ -39,6 +49,192 @@ impl CryptoService {
         derive_key_from_pin(pin, salt).map_err(|e| VaultError::KeyDerivation(e.to_string()))
     }
 
+    /// Derives a master key from a PIN using the vault's stored KDF parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The user's PIN (minimum 6 characters)
+    /// * `salt` - The salt for key derivation
+    /// * `params` - Algorithm and costs recorded for the vault
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::KeyDerivation`] if the parameters are out of range
+    /// or derivation fails.
+    pub fn derive_master_key_with_params(
+        &self,
+        pin: &str,
+        salt: &[u8; 32],
+        params: &KdfParams,
+    ) -> Result<VaultKey> {
+        derive_key_from_pin_with_params(pin, salt, params)
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Measures this machine and picks KDF costs that take about `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Desired unlock time
+    /// * `memory_kib` - Memory cost in KiB
+    /// * `parallelism` - Degree of parallelism
+    pub fn calibrate_kdf(
+        &self,
+        target: Duration,
+        memory_kib: u32,
+        parallelism: u32,
+    ) -> Result<KdfParams> {
+        calibrate_kdf(target, memory_kib, parallelism)
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Mixes key-file contents into a PIN-derived key.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin_key` - The key derived from the PIN
+    /// * `key_file` - Raw contents of the key file
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::KeyDerivation`] if the key file is empty.
+    pub fn combine_with_key_file(&self, pin_key: &VaultKey, key_file: &[u8]) -> Result<VaultKey> {
+        combine_with_key_file(pin_key, key_file)
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Derives a key-encryption key from a key file alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_file` - Raw contents of the key file
+    /// * `salt` - Per-slot salt
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::KeyDerivation`] if the key file is empty.
+    pub fn derive_key_file_key(&self, key_file: &[u8], salt: &[u8; 32]) -> Result<VaultKey> {
+        derive_key_from_key_file(key_file, salt)
+            .map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Computes the verifier stored alongside the salt for PIN checks.
+    ///
+    /// # Arguments
+    ///
+    /// * `master_key` - The key derived from the PIN
+    pub fn pin_verifier(&self, master_key: &VaultKey) -> [u8; 32] {
+        compute_pin_verifier(master_key)
+    }
+
+    /// Checks a stored verifier against a derived key in constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `master_key` - The key derived from the candidate PIN
+    /// * `verifier` - The stored verifier bytes
+    pub fn verify_pin(&self, master_key: &VaultKey, verifier: &[u8]) -> bool {
+        verify_pin_verifier(master_key, verifier)
+    }
+
+    /// Computes the verifier that recovery shares are checked against.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The vault's data key
+    pub fn recovery_verifier(&self, data_key: &VaultKey) -> [u8; 32] {
+        compute_recovery_verifier(data_key)
+    }
+
+    /// Checks a data key rebuilt from recovery shares in constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The rebuilt data key
+    /// * `verifier` - The stored verifier bytes
+    pub fn verify_recovery(&self, data_key: &VaultKey, verifier: &[u8]) -> bool {
+        verify_recovery_verifier(data_key, verifier)
+    }
+
+    /// Computes the verifier that data keys from the session agent are
+    /// checked against.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The vault's data key
+    pub fn data_key_verifier(&self, data_key: &VaultKey) -> [u8; 32] {
+        compute_data_key_verifier(data_key)
+    }
+
+    /// Checks a data key handed over by the session agent in constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The candidate data key
+    /// * `verifier` - The stored verifier bytes
+    pub fn verify_data_key(&self, data_key: &VaultKey, verifier: &[u8]) -> bool {
+        verify_data_key_verifier(data_key, verifier)
+    }
+
+    /// Splits the data key into Shamir shares.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The vault's data key
+    /// * `threshold` - Number of shares needed to rebuild it
+    /// * `shares` - Number of shares to create
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::InvalidInput`] unless `2 <= threshold <= shares`.
+    pub fn split_data_key(&self, data_key: &VaultKey, threshold: u8, shares: u8) -> Result<Vec<Share>> {
+        shamir::split(data_key.as_bytes(), threshold, shares)
+            .map_err(|e| VaultError::InvalidInput(e.to_string()))
+    }
+
+    /// Rebuilds the data key from Shamir shares.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::InvalidInput`] if the shares are too few, mixed
+    /// or duplicated.
+    pub fn combine_data_key(&self, shares: &[Share]) -> Result<VaultKey> {
+        let mut secret =
+            shamir::combine(shares).map_err(|e| VaultError::InvalidInput(e.to_string()))?;
+        let data_key = VaultKey::from_bytes(secret);
+        secret.zeroize();
+        Ok(data_key)
+    }
+
+    /// Generates a random data-encryption key for a new vault.
+    pub fn generate_data_key(&self) -> VaultKey {
+        generate_vault_key()
+    }
+
+    /// Wraps the vault's data key under a PIN-derived key.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_key` - The data-encryption key to protect
+    /// * `master_key` - The key derived from the PIN
+    pub fn wrap_data_key(&self, data_key: &VaultKey, master_key: &VaultKey) -> Result<EncryptedData> {
+        wrap_key(data_key, master_key).map_err(|e| VaultError::Encryption(e.to_string()))
+    }
+
+    /// Unwraps the vault's data key with a PIN-derived key.
+    ///
+    /// # Arguments
+    ///
+    /// * `wrapped` - The stored wrapped key
+    /// * `master_key` - The key derived from the PIN
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::Decryption`] if the key is wrong or the data is corrupted.
+    pub fn unwrap_data_key(&self, wrapped: &EncryptedData, master_key: &VaultKey) -> Result<VaultKey> {
+        unwrap_key(wrapped, master_key).map_err(|e| VaultError::Decryption(e.to_string()))
+    }
+
     /// Derives a unique encryption key for a specific API key.
     ///
     /// Each API key gets its own encryption key derived from:
@@ -63,6 +259,80 @@ impl CryptoService {
             .map_err(|e| VaultError::KeyDerivation(e.to_string()))
     }
 
+    /// Derives the subkey that encrypts key metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `master_key` - The vault's data-encryption key
+    pub fn derive_metadata_key(&self, master_key: &VaultKey) -> Result<VaultKey> {
+        derive_metadata_key(master_key).map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Derives the subkey used for blind indexes.
+    ///
+    /// # Arguments
+    ///
+    /// * `master_key` - The vault's data-encryption key
+    pub fn derive_blind_index_key(&self, master_key: &VaultKey) -> Result<VaultKey> {
+        derive_blind_index_key(master_key).map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Computes the blind index of `value` in `field` (hex HMAC-SHA256).
+    ///
+    /// # Arguments
+    ///
+    /// * `index_key` - Key from [`Self::derive_blind_index_key`]
+    /// * `field` - Column the index is stored in
+    /// * `value` - Value to index
+    pub fn blind_index(&self, index_key: &VaultKey, field: IndexedField, value: &str) -> String {
+        blind_index(index_key, field, value)
+    }
+
+    /// Derives the subkey that MACs audit log entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `master_key` - The vault's data-encryption key
+    pub fn derive_audit_key(&self, master_key: &VaultKey) -> Result<VaultKey> {
+        derive_audit_key(master_key).map_err(|e| VaultError::KeyDerivation(e.to_string()))
+    }
+
+    /// Hashes an audit entry's fields, chained to the previous entry's hash.
+    pub fn audit_entry_hash(&self, prev_hash: &[u8], fields: &[&[u8]]) -> [u8; 32] {
+        audit_entry_hash(prev_hash, fields)
+    }
+
+    /// Computes the MAC of an audit entry hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit_key` - Key from [`Self::derive_audit_key`]
+    /// * `entry_hash` - Hash from [`Self::audit_entry_hash`]
+    pub fn audit_mac(&self, audit_key: &VaultKey, entry_hash: &[u8]) -> [u8; 32] {
+        compute_audit_mac(audit_key, entry_hash)
+    }
+
+    /// Checks an audit entry MAC in constant time.
+    pub fn verify_audit_mac(&self, audit_key: &VaultKey, entry_hash: &[u8], mac: &[u8]) -> bool {
+        verify_audit_mac(audit_key, entry_hash, mac)
+    }
+
+    /// Computes the MAC naming entry `seq` as the newest signed audit entry.
+    pub fn audit_head_mac(&self, audit_key: &VaultKey, seq: i64, entry_hash: &[u8]) -> [u8; 32] {
+        compute_audit_head_mac(audit_key, seq, entry_hash)
+    }
+
+    /// Checks an audit head MAC in constant time.
+    pub fn verify_audit_head_mac(
+        &self,
+        audit_key: &VaultKey,
+        seq: i64,
+        entry_hash: &[u8],
+        mac: &[u8],
+    ) -> bool {
+        verify_audit_head_mac(audit_key, seq, entry_hash, mac)
+    }
+
     /// Encrypts data using AES-256-GCM.
     ///
     /// # Arguments
@@ -91,6 +361,38 @@ impl CryptoService {
         decrypt(encrypted, key).map_err(|e| VaultError::Decryption(e.to_string()))
     }
 
+    /// Encrypts data using AES-256-GCM with associated data.
+    ///
+    /// # Arguments
+    ///
+    /// * `plaintext` - Data to encrypt
+    /// * `key` - Encryption key
+    /// * `aad` - Associated data to authenticate alongside the ciphertext
+    pub fn encrypt_with_aad(
+        &self,
+        plaintext: &[u8],
+        key: &VaultKey,
+        aad: &[u8],
+    ) -> Result<EncryptedData> {
+        encrypt_with_aad(plaintext, key, aad).map_err(|e| VaultError::Encryption(e.to_string()))
+    }
+
+    /// Decrypts data using AES-256-GCM with associated data.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted` - Encrypted data with nonce
+    /// * `key` - Decryption key (must match encryption key)
+    /// * `aad` - Associated data given at encryption time
+    pub fn decrypt_with_aad(
+        &self,
+        encrypted: &EncryptedData,
+        key: &VaultKey,
+        aad: &[u8],
+    ) -> Result<Vec<u8>> {
+        decrypt_with_aad(encrypted, key, aad).map_err(|e| VaultError::Decryption(e.to_string()))
+    }
+
     /// Encrypts an API key value with per-key encryption.
     ///
     /// This is a convenience method that:
@@ -128,6 +430,61 @@ impl CryptoService {
         String::from_utf8(plaintext)
             .map_err(|_| VaultError::Decryption("Invalid UTF-8 in decrypted data".to_string()))
     }
+
+    /// Encrypts a stored key value under [`KeyScheme::CURRENT`].
+    ///
+    /// The row identity in `context` is bound as associated data, so the
+    /// value must be re-encrypted whenever the row is renamed.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The secret value
+    /// * `master_key` - The vault's data-encryption key
+    /// * `context` - The row the value belongs to
+    ///
+    /// # Returns
+    ///
+    /// Tuple of (encrypted_data, per_key_salt)
+    pub fn encrypt_key_value(
+        &self,
+        value: &str,
+        master_key: &VaultKey,
+        context: &KeyContext<'_>,
+    ) -> Result<(EncryptedData, [u8; 32])> {
+        let salt = self.generate_salt();
+        let encrypted = encrypt_api_key_value(
+            value.as_bytes(),
+            master_key,
+            context,
+            &salt,
+            KeyScheme::CURRENT,
+        )
+        .map_err(|e| VaultError::Encryption(e.to_string()))?;
+        Ok((encrypted, salt))
+    }
+
+    /// Decrypts a stored key value written under `scheme`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted` - The stored ciphertext and nonce
+    /// * `master_key` - The vault's data-encryption key
+    /// * `context` - The row the value belongs to
+    /// * `salt` - Per-key salt
+    /// * `scheme` - Scheme recorded with the row
+    pub fn decrypt_key_value(
+        &self,
+        encrypted: &EncryptedData,
+        master_key: &VaultKey,
+        context: &KeyContext<'_>,
+        salt: &[u8; 32],
+        scheme: KeyScheme,
+    ) -> Result<String> {
+        let plaintext = decrypt_api_key_value(encrypted, master_key, context, salt, scheme)
+            .map_err(|e| VaultError::Decryption(e.to_string()))?;
+        String::from_utf8(plaintext)
+            .map_err(|_| VaultError::Decryption("Invalid UTF-8 in decrypted data".to_string()))
+    }
 }
 
 impl Default for CryptoService {
@@ -153,6 +510,34 @@ mod tests {
         assert_eq!(plaintext.as_slice(), decrypted.as_slice());
     }
 
+    #[test]
+    fn test_pin_verifier() {
+        let service = CryptoService::new();
+        let salt = service.generate_salt();
+        let key = service.derive_master_key("test-pin-123", &salt).unwrap();
+        let other = service.derive_master_key("test-pin-456", &salt).unwrap();
+
+        let verifier = service.pin_verifier(&key);
+
+        assert!(service.verify_pin(&key, &verifier));
+        assert!(!service.verify_pin(&other, &verifier));
+    }
+
+    #[test]
+    fn test_wrap_data_key() {
+        let service = CryptoService::new();
+        let salt = service.generate_salt();
+        let master_key = service.derive_master_key("test-pin-123", &salt).unwrap();
+        let data_key = service.generate_data_key();
+
+        let wrapped = service.wrap_data_key(&data_key, &master_key).unwrap();
+        let unwrapped = service.unwrap_data_key(&wrapped, &master_key).unwrap();
+        assert_eq!(unwrapped.as_bytes(), data_key.as_bytes());
+
+        let other = service.derive_master_key("test-pin-456", &salt).unwrap();
+        assert!(service.unwrap_data_key(&wrapped, &other).is_err());
+    }
+
     #[test]
     fn test_per_key_encryption() {
         let service = CryptoService::new();
@@ -169,4 +554,52 @@ mod tests {
 
         assert_eq!("my-secret-key", decrypted);
     }
+
+    #[test]
+    fn test_aad_roundtrip() {
+        let service = CryptoService::new();
+        let key = service.generate_data_key();
+
+        let encrypted = service.encrypt_with_aad(b"secret", &key, b"context").unwrap();
+        assert_eq!(service.decrypt_with_aad(&encrypted, &key, b"context").unwrap(), b"secret");
+        assert!(matches!(
+            service.decrypt_with_aad(&encrypted, &key, b"other"),
+            Err(VaultError::Decryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_value_bound_to_row() {
+        let service = CryptoService::new();
+        let master_key = service.generate_data_key();
+        let context = KeyContext {
+            id: "row-1",
+            app_name: "github",
+            key_name: "token",
+        };
+
+        let (encrypted, key_salt) = service
+            .encrypt_key_value("my-secret-key", &master_key, &context)
+            .unwrap();
+
+        let renamed = KeyContext {
+            key_name: "renamed",
+            ..context
+        };
+        let decrypted = service
+            .decrypt_key_value(&encrypted, &master_key, &context, &key_salt, KeyScheme::CURRENT)
+            .unwrap();
+        assert_eq!("my-secret-key", decrypted);
+        assert!(service
+            .decrypt_key_value(&encrypted, &master_key, &renamed, &key_salt, KeyScheme::CURRENT)
+            .is_err());
+
+        let other_row = KeyContext {
+            id: "row-2",
+            ..context
+        };
+        assert!(service
+            .decrypt_key_value(&encrypted, &master_key, &other_row, &key_salt, KeyScheme::CURRENT)
+            .is_err());
+    }
 }
@@ -3,6 +3,15 @@
 //! This service provides high-level operations for managing API keys,
 //! with automatic encryption and decryption.
 //!
+//! Each value is bound to its row (id, app name, key name and scheme) as
+//! AES-GCM associated data, so ciphertexts cannot be swapped between rows.
+//! Each row records the [`KeyScheme`] its value was encrypted under. Rows
+//! written by an older scheme are re-encrypted under the current one the
+//! first time they are read.
+//!
+//! Reads, copies, creates, updates and deletes are recorded in the audit
+//! log with the key's id and, unless metadata is encrypted, its name.
+//!
 //! # Example
 //!
 //! ```rust,ignore
@@ -19,16 +28,17 @@
 
 use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnection, SqliteRow};
 use sqlx::Row;
 use uuid::Uuid;
 
-use crate::crypto::EncryptedData;
+use crate::crypto::{metadata_aad, EncryptedData, IndexedField, KeyContext, KeyScheme, VaultKey};
 use crate::database::VaultDb;
 use crate::error::{Result, VaultError};
 
-use super::{AuthService, CryptoService};
+use super::{AuditEvent, AuditRecord, AuthService, CryptoService};
 
 /// Complete API key with decrypted value.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +55,16 @@ pub struct ApiKey {
     pub api_url: Option<String>,
     /// Optional description
     pub description: Option<String>,
+    /// When the key expires, if it does
+    // Defaults keep export files from before expiry readable
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Seconds between value changes, if a rotation reminder is set
+    #[serde(default)]
+    pub rotate_every: Option<u64>,
+    /// When the value is next due for rotation
+    #[serde(default)]
+    pub rotate_by: Option<DateTime<Utc>>,
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
     /// Last update timestamp
@@ -66,6 +86,12 @@ pub struct ApiKeyMetadata {
     pub api_url: Option<String>,
     /// Optional description
     pub description: Option<String>,
+    /// When the key expires, if it does
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Seconds between value changes, if a rotation reminder is set
+    pub rotate_every: Option<u64>,
+    /// When the value is next due for rotation
+    pub rotate_by: Option<DateTime<Utc>>,
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
     /// Last update timestamp
@@ -85,6 +111,12 @@ pub struct CreateKeyRequest {
     pub api_url: Option<String>,
     /// Optional description
     pub description: Option<String>,
+    /// Optional expiry
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Optional rotation reminder, in seconds between value changes
+    #[serde(default)]
+    pub rotate_every: Option<u64>,
 }
 
 /// Request to update an existing API key.
@@ -100,6 +132,251 @@ pub struct UpdateKeyRequest {
     pub api_url: Option<Option<String>>,
     /// New description (None = keep existing)
     pub description: Option<Option<String>>,
+    /// New expiry (None = keep existing, Some(None) = never expires)
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    /// New rotation reminder in seconds (None = keep existing,
+    /// Some(None) = no reminder)
+    pub rotate_every: Option<Option<u64>>,
+    /// Why the value is changing, kept with the value it replaces
+    pub reason: Option<String>,
+}
+
+/// Number of previous values kept for a key without its own limit.
+pub const DEFAULT_HISTORY_LIMIT: u32 = 10;
+
+/// A previous value of a key, without the value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyVersion {
+    /// Version number; a key's first value is version 1
+    pub version: i64,
+    /// When a newer value replaced it
+    pub replaced_at: DateTime<Utc>,
+    /// Reason given for the change that replaced it
+    pub reason: Option<String>,
+}
+
+/// Version history of a key, from [`KeyService::history`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyHistory {
+    /// Version of the current value
+    pub current_version: i64,
+    /// Number of previous values kept
+    pub history_limit: u32,
+    /// Previous values, newest first
+    pub versions: Vec<KeyVersion>,
+}
+
+/// What a [`DueKey`] is due for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DueReason {
+    /// The key expires
+    Expiry,
+    /// The key's value should be changed
+    Rotation,
+}
+
+/// A key that expires or is due for rotation, from [`KeyService::due`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DueKey {
+    /// The key, without its value
+    pub key: ApiKeyMetadata,
+    /// What is due
+    pub reason: DueReason,
+    /// When it is due
+    pub due_at: DateTime<Utc>,
+    /// Whether `due_at` has already passed
+    pub overdue: bool,
+}
+
+/// How [`KeyService::import`] treats the keys already in the vault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportMode {
+    /// Keep existing keys; name clashes are settled by the [`ConflictStrategy`]
+    #[default]
+    Merge,
+    /// Delete every existing key before importing
+    Replace,
+}
+
+/// What [`KeyService::import`] does with a key whose name is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictStrategy {
+    /// Keep the existing key and leave the imported one out
+    #[default]
+    Skip,
+    /// Replace the existing key with the imported one
+    Overwrite,
+    /// Import the key as `name-2`, `name-3`, ... (the first free name)
+    Rename,
+}
+
+impl ConflictStrategy {
+    /// Names accepted by [`FromStr`](std::str::FromStr)
+    pub const NAMES: [&'static str; 3] = ["skip", "overwrite", "rename"];
+}
+
+impl std::str::FromStr for ConflictStrategy {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "rename" => Ok(Self::Rename),
+            _ => Err(VaultError::InvalidInput(format!(
+                "unknown conflict strategy '{}' (expected {})",
+                s,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Options for [`KeyService::import`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportOptions {
+    /// Whether existing keys are kept
+    pub mode: ImportMode,
+    /// What to do with keys whose names are taken
+    pub on_conflict: ConflictStrategy,
+    /// Work out the outcome without changing the vault
+    pub dry_run: bool,
+}
+
+/// A key to write with [`KeyService::import`].
+#[derive(Debug, Clone)]
+pub struct ImportedKey {
+    /// Names, value, URL and description
+    pub request: CreateKeyRequest,
+    /// Original creation time; the import time if `None`
+    pub created_at: Option<DateTime<Utc>>,
+    /// Original update time; the import time if `None`
+    pub updated_at: Option<DateTime<Utc>>,
+    /// When the value was last changed; the update time if `None`
+    pub rotated_at: Option<DateTime<Utc>>,
+}
+
+impl From<CreateKeyRequest> for ImportedKey {
+    fn from(request: CreateKeyRequest) -> Self {
+        Self {
+            request,
+            created_at: None,
+            updated_at: None,
+            rotated_at: None,
+        }
+    }
+}
+
+impl From<ApiKey> for ImportedKey {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            request: CreateKeyRequest {
+                app_name: key.app_name.clone(),
+                key_name: key.key_name.clone(),
+                key_value: key.key_value.clone(),
+                api_url: key.api_url.clone(),
+                description: key.description.clone(),
+                expires_at: key.expires_at,
+                rotate_every: key.rotate_every,
+            },
+            created_at: Some(key.created_at),
+            updated_at: Some(key.updated_at),
+            // Keeps the next rotation on the same date in the new vault
+            rotated_at: key
+                .rotate_by
+                .zip(key.rotate_every.and_then(rotation_interval))
+                .map(|(by, every)| by - every),
+        }
+    }
+}
+
+/// A key imported under a new name by [`ConflictStrategy::Rename`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenamedKey {
+    /// Name in the import, as `app/key`
+    pub from: String,
+    /// Name it was stored under
+    pub to: String,
+}
+
+/// Outcome of [`KeyService::import`]. Keys are named `app/key`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportSummary {
+    /// Keys written under their own names
+    pub added: Vec<String>,
+    /// Existing keys replaced by [`ConflictStrategy::Overwrite`]
+    pub overwritten: Vec<String>,
+    /// Keys written under a new name by [`ConflictStrategy::Rename`]
+    pub renamed: Vec<RenamedKey>,
+    /// Keys left out by [`ConflictStrategy::Skip`]
+    pub skipped: Vec<String>,
+    /// Number of keys deleted first in [`ImportMode::Replace`]
+    pub replaced: usize,
+    /// Whether the vault was left unchanged
+    pub dry_run: bool,
+}
+
+impl ImportSummary {
+    /// Number of keys written (or that would be, in a dry run).
+    pub fn imported(&self) -> usize {
+        self.added.len() + self.overwritten.len() + self.renamed.len()
+    }
+}
+
+/// Formats a key name as `app/key`, or `key` without an app.
+fn display_name(app_name: Option<&str>, key_name: &str) -> String {
+    match app_name {
+        Some(app) if !app.is_empty() => format!("{}/{}", app, key_name),
+        _ => key_name.to_string(),
+    }
+}
+
+/// Maps an UPDATE error, reporting a rename onto a taken name as a duplicate.
+fn rename_error(err: sqlx::Error, metadata: &KeyMetadata) -> VaultError {
+    let err_str = err.to_string();
+    if err_str.contains("UNIQUE constraint") {
+        VaultError::duplicate_key(
+            metadata.app_name.as_deref().unwrap_or(""),
+            &metadata.key_name,
+        )
+    } else {
+        VaultError::Database(err_str)
+    }
+}
+
+/// Plaintext key metadata.
+///
+/// Stored in the `api_keys` columns by default, or as JSON in
+/// `metadata_ciphertext` when the vault uses encrypted metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyMetadata {
+    app_name: Option<String>,
+    key_name: String,
+    api_url: Option<String>,
+    description: Option<String>,
+}
+
+/// Subkeys used in encrypted-metadata mode.
+struct MetadataKeys {
+    encryption: VaultKey,
+    index: VaultKey,
+}
+
+/// Keys an import encrypts under.
+struct ImportKeys {
+    master: VaultKey,
+    metadata: Option<MetadataKeys>,
+}
+
+/// Values written to the `api_keys` metadata columns.
+struct MetadataColumns {
+    app_name: Option<String>,
+    key_name: String,
+    api_url: Option<String>,
+    description: Option<String>,
+    ciphertext: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
 }
 
 /// Key management service.
@@ -152,37 +429,84 @@ impl KeyService {
         api_url: Option<&str>,
         description: Option<&str>,
     ) -> Result<String> {
+        self.create_from_request(&CreateKeyRequest {
+            app_name: app_name.map(str::to_string),
+            key_name: key_name.to_string(),
+            key_value: key_value.to_string(),
+            api_url: api_url.map(str::to_string),
+            description: description.map(str::to_string),
+            expires_at: None,
+            rotate_every: None,
+        })
+        .await
+    }
+
+    /// Creates a new API key, with an optional expiry and rotation
+    /// reminder.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the created key.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::DuplicateKey`] if key already exists
+    /// - [`VaultError::InvalidInput`] if `rotate_every` is zero
+    pub async fn create_from_request(&self, request: &CreateKeyRequest) -> Result<String> {
         self.require_unlocked().await?;
 
+        let app_name = request.app_name.as_deref();
+        let key_name = request.key_name.as_str();
+        let rotate_every = rotate_every_column(request.rotate_every)?;
         let master_key = self.auth.get_vault_key().await?;
-        let app_name_str = app_name.unwrap_or("");
+        let metadata_keys = self.metadata_keys().await?;
+
+        // Generate ID (the per-key key is bound to it)
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().timestamp();
 
         // Encrypt with per-key encryption
+        let context = KeyContext {
+            id: &id,
+            app_name: app_name.unwrap_or(""),
+            key_name,
+        };
         let (encrypted, salt) =
             self.crypto
-                .encrypt_api_key(key_value, &master_key, app_name_str, key_name)?;
+                .encrypt_key_value(&request.key_value, &master_key, &context)?;
 
-        // Generate ID
-        let id = Uuid::new_v4().to_string();
-        let now = Utc::now().timestamp();
+        let metadata = KeyMetadata {
+            app_name: request.app_name.clone(),
+            key_name: request.key_name.clone(),
+            api_url: request.api_url.clone(),
+            description: request.description.clone(),
+        };
+        let columns = self.encode_metadata(&id, &metadata, metadata_keys.as_ref())?;
 
         // Insert into database
         sqlx::query(
             r#"
-            INSERT INTO api_keys (id, app_name, key_name, api_url, description, 
-                                  encrypted_key_value, nonce, key_salt, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            INSERT INTO api_keys (id, app_name, key_name, api_url, description,
+                                  encrypted_key_value, nonce, key_salt, key_scheme,
+                                  metadata_ciphertext, metadata_nonce, expires_at, rotate_every,
+                                  rotated_at, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?14, ?14)
             "#,
         )
         .bind(&id)
-        .bind(app_name)
-        .bind(key_name)
-        .bind(api_url)
-        .bind(description)
+        .bind(&columns.app_name)
+        .bind(&columns.key_name)
+        .bind(&columns.api_url)
+        .bind(&columns.description)
         .bind(&encrypted.ciphertext)
         .bind(&encrypted.nonce)
         .bind(&salt[..])
-        .bind(now)
+        .bind(KeyScheme::CURRENT.as_i64())
+        .bind(&columns.ciphertext)
+        .bind(&columns.nonce)
+        .bind(request.expires_at.map(|at| at.timestamp()))
+        .bind(rotate_every)
         .bind(now)
         .execute(&self.db.pool)
         .await
@@ -195,6 +519,9 @@ impl KeyService {
             }
         })?;
 
+        self.audit(AuditEvent::Create, &id, app_name, key_name, None)
+            .await?;
+
         Ok(id)
     }
 
@@ -212,77 +539,90 @@ impl KeyService {
     pub async fn get(&self, app_name: &str, key_name: &str) -> Result<ApiKey> {
         self.require_unlocked().await?;
 
-        let master_key = self.auth.get_vault_key().await?;
+        let metadata_keys = self.metadata_keys().await?;
+        let (app_param, key_param) =
+            self.lookup_params(app_name, key_name, metadata_keys.as_ref());
 
         // Query the key
         let row = sqlx::query(
             r#"
             SELECT id, app_name, key_name, api_url, description,
-                   encrypted_key_value, nonce, key_salt, created_at, updated_at
+                   encrypted_key_value, nonce, key_salt, key_scheme,
+                   metadata_ciphertext, metadata_nonce, expires_at, rotate_every, rotated_at,
+                   created_at, updated_at
             FROM api_keys
-            WHERE (app_name = ?1 OR (app_name IS NULL AND ?1 = '')) AND key_name = ?2
+            WHERE COALESCE(app_name, '') = ?1 AND key_name = ?2
             "#,
         )
-        .bind(app_name)
-        .bind(key_name)
+        .bind(&app_param)
+        .bind(&key_param)
         .fetch_optional(&self.db.pool)
         .await
         .map_err(|e| VaultError::Database(e.to_string()))?
         .ok_or_else(|| VaultError::key_not_found(app_name, key_name))?;
 
-        // Extract fields
-        let id: String = row.get("id");
-        let db_app_name: Option<String> = row.get("app_name");
-        let db_key_name: String = row.get("key_name");
-        let api_url: Option<String> = row.get("api_url");
-        let description: Option<String> = row.get("description");
-        let encrypted_value: Vec<u8> = row.get("encrypted_key_value");
-        let nonce: Vec<u8> = row.get("nonce");
-        let key_salt: Vec<u8> = row.get("key_salt");
-        let created_at: i64 = row.get("created_at");
-        let updated_at: i64 = row.get("updated_at");
+        let key = self.decrypt_row(&row, metadata_keys.as_ref()).await?;
+        self.audit_read(AuditEvent::Get, &key, None).await?;
+        Ok(key)
+    }
 
-        // Decrypt the value
-        let mut salt_array = [0u8; 32];
-        if key_salt.len() == 32 {
-            salt_array.copy_from_slice(&key_salt);
-        }
+    /// Checks whether an API key exists, without decrypting its value.
+    ///
+    /// Uses the same lookup as [`Self::get`].
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    pub async fn exists(&self, app_name: &str, key_name: &str) -> Result<bool> {
+        self.require_unlocked().await?;
 
-        let encrypted = EncryptedData {
-            ciphertext: encrypted_value,
-            nonce,
-        };
+        let metadata_keys = self.metadata_keys().await?;
+        let (app_param, key_param) =
+            self.lookup_params(app_name, key_name, metadata_keys.as_ref());
 
-        let key_value = self.crypto.decrypt_api_key(
-            &encrypted,
-            &master_key,
-            db_app_name.as_deref().unwrap_or(""),
-            &db_key_name,
-            &salt_array,
-        )?;
+        let found: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT 1 FROM api_keys
+            WHERE COALESCE(app_name, '') = ?1 AND key_name = ?2
+            "#,
+        )
+        .bind(&app_param)
+        .bind(&key_param)
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        Ok(ApiKey {
-            id,
-            app_name: db_app_name,
-            key_name: db_key_name,
-            key_value,
-            api_url,
-            description,
-            created_at: DateTime::from_timestamp(created_at, 0).unwrap_or_default(),
-            updated_at: DateTime::from_timestamp(updated_at, 0).unwrap_or_default(),
-        })
+        Ok(found.is_some())
     }
 
     /// Gets an API key by ID with its decrypted value.
     pub async fn get_by_id(&self, id: &str) -> Result<ApiKey> {
+        let key = self.fetch_by_id(id).await?;
+        self.audit_read(AuditEvent::Get, &key, None).await?;
+        Ok(key)
+    }
+
+    /// Records that `key`'s value was copied to the clipboard.
+    ///
+    /// Call this after copying a key fetched with [`Self::get`] or
+    /// [`Self::get_by_id`].
+    pub async fn record_copy(&self, key: &ApiKey) -> Result<()> {
         self.require_unlocked().await?;
+        self.audit_read(AuditEvent::Copy, key, None).await
+    }
 
-        let master_key = self.auth.get_vault_key().await?;
+    /// As [`Self::get_by_id`], without recording a read in the audit log.
+    async fn fetch_by_id(&self, id: &str) -> Result<ApiKey> {
+        self.require_unlocked().await?;
+
+        let metadata_keys = self.metadata_keys().await?;
 
         let row = sqlx::query(
             r#"
             SELECT id, app_name, key_name, api_url, description,
-                   encrypted_key_value, nonce, key_salt, created_at, updated_at
+                   encrypted_key_value, nonce, key_salt, key_scheme,
+                   metadata_ciphertext, metadata_nonce, expires_at, rotate_every, rotated_at,
+                   created_at, updated_at
             FROM api_keys
             WHERE id = ?1
             "#,
@@ -293,17 +633,33 @@ impl KeyService {
         .map_err(|e| VaultError::Database(e.to_string()))?
         .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
 
-        // Extract and decrypt (same as get())
-        let db_app_name: Option<String> = row.get("app_name");
-        let db_key_name: String = row.get("key_name");
-        let api_url: Option<String> = row.get("api_url");
-        let description: Option<String> = row.get("description");
+        self.decrypt_row(&row, metadata_keys.as_ref()).await
+    }
+
+    /// Decrypts an `api_keys` row, upgrading it to the current scheme.
+    async fn decrypt_row(
+        &self,
+        row: &SqliteRow,
+        metadata_keys: Option<&MetadataKeys>,
+    ) -> Result<ApiKey> {
+        let master_key = self.auth.get_vault_key().await?;
+
+        // Extract fields
+        let id: String = row.get("id");
+        let metadata = self.decode_metadata(row, metadata_keys)?;
         let encrypted_value: Vec<u8> = row.get("encrypted_key_value");
         let nonce: Vec<u8> = row.get("nonce");
         let key_salt: Vec<u8> = row.get("key_salt");
+        let stored_scheme: i64 = row.get("key_scheme");
+        let schedule = Schedule::from_row(row);
         let created_at: i64 = row.get("created_at");
         let updated_at: i64 = row.get("updated_at");
 
+        let scheme = KeyScheme::from_i64(stored_scheme).ok_or_else(|| {
+            VaultError::Decryption(format!("Unknown key scheme {}", stored_scheme))
+        })?;
+
+        // Decrypt the value
         let mut salt_array = [0u8; 32];
         if key_salt.len() == 32 {
             salt_array.copy_from_slice(&key_salt);
@@ -314,37 +670,88 @@ impl KeyService {
             nonce,
         };
 
-        let key_value = self.crypto.decrypt_api_key(
-            &encrypted,
-            &master_key,
-            db_app_name.as_deref().unwrap_or(""),
-            &db_key_name,
-            &salt_array,
-        )?;
+        let context = KeyContext {
+            id: &id,
+            app_name: metadata.app_name.as_deref().unwrap_or(""),
+            key_name: &metadata.key_name,
+        };
+        let key_value =
+            self.crypto
+                .decrypt_key_value(&encrypted, &master_key, &context, &salt_array, scheme)?;
+
+        if scheme != KeyScheme::CURRENT {
+            self.upgrade_scheme(&context, &key_value, scheme).await?;
+        }
 
         Ok(ApiKey {
-            id: id.to_string(),
-            app_name: db_app_name,
-            key_name: db_key_name,
+            id,
+            app_name: metadata.app_name,
+            key_name: metadata.key_name,
             key_value,
-            api_url,
-            description,
+            api_url: metadata.api_url,
+            description: metadata.description,
+            expires_at: schedule.expires_at,
+            rotate_every: schedule.rotate_every,
+            rotate_by: schedule.rotate_by,
             created_at: DateTime::from_timestamp(created_at, 0).unwrap_or_default(),
             updated_at: DateTime::from_timestamp(updated_at, 0).unwrap_or_default(),
         })
     }
 
+    /// Re-encrypts a row written under an older scheme.
+    ///
+    /// Only the ciphertext columns change; `updated_at` is left alone since
+    /// the key itself did not. The `key_scheme` guard makes a concurrent
+    /// upgrade of the same row a no-op.
+    async fn upgrade_scheme(
+        &self,
+        context: &KeyContext<'_>,
+        key_value: &str,
+        from: KeyScheme,
+    ) -> Result<()> {
+        let master_key = self.auth.get_vault_key().await?;
+        let (encrypted, salt) = self
+            .crypto
+            .encrypt_key_value(key_value, &master_key, context)?;
+
+        sqlx::query(
+            r#"
+            UPDATE api_keys
+            SET encrypted_key_value = ?1, nonce = ?2, key_salt = ?3, key_scheme = ?4
+            WHERE id = ?5 AND key_scheme = ?6
+            "#,
+        )
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&salt[..])
+        .bind(KeyScheme::CURRENT.as_i64())
+        .bind(context.id)
+        .bind(from.as_i64())
+        .execute(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
     /// Lists all API keys (metadata only, no decryption).
     ///
+    /// In encrypted-metadata mode the metadata is decrypted, but key values
+    /// are not.
+    ///
     /// # Returns
     ///
     /// Vector of key metadata without decrypted values.
     pub async fn list(&self) -> Result<Vec<ApiKeyMetadata>> {
         self.require_unlocked().await?;
 
+        let metadata_keys = self.metadata_keys().await?;
+
         let rows = sqlx::query(
             r#"
-            SELECT id, app_name, key_name, api_url, description, created_at, updated_at
+            SELECT id, app_name, key_name, api_url, description,
+                   metadata_ciphertext, metadata_nonce, expires_at, rotate_every, rotated_at,
+                   created_at, updated_at
             FROM api_keys
             ORDER BY app_name, key_name
             "#,
@@ -353,18 +760,15 @@ impl KeyService {
         .await
         .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        let keys = rows
-            .into_iter()
-            .map(|row| ApiKeyMetadata {
-                id: row.get("id"),
-                app_name: row.get("app_name"),
-                key_name: row.get("key_name"),
-                api_url: row.get("api_url"),
-                description: row.get("description"),
-                created_at: DateTime::from_timestamp(row.get("created_at"), 0).unwrap_or_default(),
-                updated_at: DateTime::from_timestamp(row.get("updated_at"), 0).unwrap_or_default(),
-            })
-            .collect();
+        let mut keys = rows
+            .iter()
+            .map(|row| self.row_to_metadata(row, metadata_keys.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Blind indexes do not sort like the names they stand for
+        if metadata_keys.is_some() {
+            sort_metadata(&mut keys);
+        }
 
         Ok(keys)
     }
@@ -372,15 +776,33 @@ impl KeyService {
     /// Searches for API keys matching a query.
     ///
     /// Searches in app_name, key_name, and description fields.
-    /// Case-insensitive partial matching.
+    /// Case-insensitive partial matching. In encrypted-metadata mode the
+    /// metadata is decrypted and matched in memory.
     pub async fn search(&self, query: &str) -> Result<Vec<ApiKeyMetadata>> {
         self.require_unlocked().await?;
 
+        if self.auth.encrypted_metadata().await? {
+            let needle = query.to_lowercase();
+            let matches = |value: Option<&str>| {
+                value.is_some_and(|v| v.to_lowercase().contains(&needle))
+            };
+
+            let mut keys = self.list().await?;
+            keys.retain(|key| {
+                matches(key.app_name.as_deref())
+                    || matches(Some(&key.key_name))
+                    || matches(key.description.as_deref())
+            });
+            return Ok(keys);
+        }
+
         let pattern = format!("%{}%", query);
 
         let rows = sqlx::query(
             r#"
-            SELECT id, app_name, key_name, api_url, description, created_at, updated_at
+            SELECT id, app_name, key_name, api_url, description,
+                   metadata_ciphertext, metadata_nonce, expires_at, rotate_every, rotated_at,
+                   created_at, updated_at
             FROM api_keys
             WHERE app_name LIKE ?1 OR key_name LIKE ?1 OR description LIKE ?1
             ORDER BY app_name, key_name
@@ -391,31 +813,28 @@ impl KeyService {
         .await
         .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        let keys = rows
-            .into_iter()
-            .map(|row| ApiKeyMetadata {
-                id: row.get("id"),
-                app_name: row.get("app_name"),
-                key_name: row.get("key_name"),
-                api_url: row.get("api_url"),
-                description: row.get("description"),
-                created_at: DateTime::from_timestamp(row.get("created_at"), 0).unwrap_or_default(),
-                updated_at: DateTime::from_timestamp(row.get("updated_at"), 0).unwrap_or_default(),
-            })
-            .collect();
-
-        Ok(keys)
+        rows.iter()
+            .map(|row| self.row_to_metadata(row, None))
+            .collect()
     }
 
     /// Updates an existing API key.
     ///
     /// Only provided fields are updated. If `key_value` is provided,
-    /// or if `app_name`/`key_name` changes, the key will be re-encrypted.
+    /// or if `app_name`/`key_name` changes, the key will be re-encrypted,
+    /// since the names are bound into the ciphertext's associated data.
+    /// The per-key key depends only on the row id, so this is cheap.
+    ///
+    /// A new value gets the next version number. The value it replaces is
+    /// kept in the key's history with `reason`, and the oldest entries past
+    /// the key's history limit are dropped, all in one transaction.
     pub async fn update(&self, id: &str, request: UpdateKeyRequest) -> Result<()> {
         self.require_unlocked().await?;
 
         // First, get the existing key
-        let existing = self.get_by_id(id).await?;
+        let existing = self.fetch_by_id(id).await?;
+        let metadata_keys = self.metadata_keys().await?;
+        let master_key = self.auth.get_vault_key().await?;
 
         let now = Utc::now().timestamp();
 
@@ -433,6 +852,9 @@ impl KeyService {
             None => existing.description.clone(),
             Some(val) => val,
         };
+        let new_expires_at = request.expires_at.unwrap_or(existing.expires_at);
+        let new_rotate_every =
+            rotate_every_column(request.rotate_every.unwrap_or(existing.rotate_every))?;
 
         // Check if app_name or key_name changed (requires re-encryption)
         let app_changed = new_app_name != existing.app_name;
@@ -440,115 +862,525 @@ impl KeyService {
 
         // Re-encrypt if key_value changed OR if app_name/key_name changed
         let needs_reencrypt = request.key_value.is_some() || app_changed || key_changed;
+        // Setting the same value again does not make a new version
+        let value_changed = request
+            .key_value
+            .as_ref()
+            .is_some_and(|value| *value != existing.key_value);
+
+        let metadata = KeyMetadata {
+            app_name: new_app_name,
+            key_name: new_key_name,
+            api_url: new_api_url,
+            description: new_description,
+        };
+        let columns = self.encode_metadata(id, &metadata, metadata_keys.as_ref())?;
+
+        let mut tx = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        if value_changed {
+            self.archive_value(
+                &mut tx,
+                id,
+                &existing.key_value,
+                request.reason.as_deref(),
+                &master_key,
+            )
+            .await?;
+        }
 
         if needs_reencrypt {
             // Use the new value if provided, otherwise use existing decrypted value
-            let value_to_encrypt = if let Some(new_value) = request.key_value {
-                new_value
-            } else {
-                existing.key_value
-            };
+            let value_to_encrypt = request.key_value.unwrap_or(existing.key_value);
 
-            let master_key = self.auth.get_vault_key().await?;
-            let (encrypted, salt) = self.crypto.encrypt_api_key(
-                &value_to_encrypt,
-                &master_key,
-                new_app_name.as_deref().unwrap_or(""),
-                &new_key_name,
-            )?;
+            let context = KeyContext {
+                id,
+                app_name: metadata.app_name.as_deref().unwrap_or(""),
+                key_name: &metadata.key_name,
+            };
+            let (encrypted, salt) =
+                self.crypto
+                    .encrypt_key_value(&value_to_encrypt, &master_key, &context)?;
 
             sqlx::query(
                 r#"
                 UPDATE api_keys
                 SET app_name = ?1, key_name = ?2, api_url = ?3, description = ?4,
-                    encrypted_key_value = ?5, nonce = ?6, key_salt = ?7, updated_at = ?8
-                WHERE id = ?9
+                    encrypted_key_value = ?5, nonce = ?6, key_salt = ?7, key_scheme = ?8,
+                    metadata_ciphertext = ?9, metadata_nonce = ?10, updated_at = ?11,
+                    version = version + ?12,
+                    rotated_at = CASE WHEN ?12 THEN ?11 ELSE rotated_at END,
+                    expires_at = ?13, rotate_every = ?14
+                WHERE id = ?15
                 "#,
             )
-            .bind(&new_app_name)
-            .bind(&new_key_name)
-            .bind(&new_api_url)
-            .bind(&new_description)
+            .bind(&columns.app_name)
+            .bind(&columns.key_name)
+            .bind(&columns.api_url)
+            .bind(&columns.description)
             .bind(&encrypted.ciphertext)
             .bind(&encrypted.nonce)
             .bind(&salt[..])
+            .bind(KeyScheme::CURRENT.as_i64())
+            .bind(&columns.ciphertext)
+            .bind(&columns.nonce)
             .bind(now)
+            .bind(i64::from(value_changed))
+            .bind(new_expires_at.map(|at| at.timestamp()))
+            .bind(new_rotate_every)
             .bind(id)
-            .execute(&self.db.pool)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| VaultError::Database(e.to_string()))?;
+            .map_err(|e| rename_error(e, &metadata))?;
         } else {
             // Update metadata only (no encryption context changes)
             sqlx::query(
                 r#"
                 UPDATE api_keys
-                SET app_name = ?1, key_name = ?2, api_url = ?3, description = ?4, updated_at = ?5
-                WHERE id = ?6
+                SET app_name = ?1, key_name = ?2, api_url = ?3, description = ?4,
+                    metadata_ciphertext = ?5, metadata_nonce = ?6, updated_at = ?7,
+                    expires_at = ?8, rotate_every = ?9
+                WHERE id = ?10
                 "#,
             )
-            .bind(&new_app_name)
-            .bind(&new_key_name)
-            .bind(&new_api_url)
-            .bind(&new_description)
+            .bind(&columns.app_name)
+            .bind(&columns.key_name)
+            .bind(&columns.api_url)
+            .bind(&columns.description)
+            .bind(&columns.ciphertext)
+            .bind(&columns.nonce)
             .bind(now)
+            .bind(new_expires_at.map(|at| at.timestamp()))
+            .bind(new_rotate_every)
             .bind(id)
-            .execute(&self.db.pool)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| VaultError::Database(e.to_string()))?;
+            .map_err(|e| rename_error(e, &metadata))?;
         }
 
-        Ok(())
+        if value_changed {
+            prune_history(&mut tx, id).await?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let detail = value_changed.then(|| match &request.reason {
+            Some(reason) => format!("new value: {}", reason),
+            None => "new value".to_string(),
+        });
+        self.audit(
+            AuditEvent::Update,
+            id,
+            metadata.app_name.as_deref(),
+            &metadata.key_name,
+            detail.as_deref(),
+        )
+        .await
     }
 
-    /// Deletes an API key.
-    ///
-    /// # Returns
+    /// Copies a key's current value into its history, re-encrypted for the
+    /// version it held.
     ///
-    /// The metadata of the deleted key for confirmation.
-    pub async fn delete(&self, id: &str) -> Result<ApiKeyMetadata> {
-        self.require_unlocked().await?;
+    /// Archived values are bound to the row id and version only, so they
+    /// stay readable after the key is renamed.
+    async fn archive_value(
+        &self,
+        conn: &mut SqliteConnection,
+        id: &str,
+        key_value: &str,
+        reason: Option<&str>,
+        master_key: &VaultKey,
+    ) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("SELECT version FROM api_keys WHERE id = ?1")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        // First get the key metadata
-        let row = sqlx::query(
+        let version_id = version_context_id(id, version);
+        let (encrypted, salt) =
+            self.crypto
+                .encrypt_key_value(key_value, master_key, &version_context(&version_id))?;
+
+        sqlx::query(
             r#"
-            SELECT id, app_name, key_name, api_url, description, created_at, updated_at
-            FROM api_keys
-            WHERE id = ?1
+            INSERT INTO key_versions (key_id, version, encrypted_key_value, nonce, key_salt,
+                                      key_scheme, reason, replaced_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.db.pool)
+        .bind(version)
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&salt[..])
+        .bind(KeyScheme::CURRENT.as_i64())
+        .bind(reason)
+        .bind(Utc::now().timestamp())
+        .execute(&mut *conn)
         .await
-        .map_err(|e| VaultError::Database(e.to_string()))?
-        .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
+        .map_err(|e| VaultError::Database(e.to_string()))?;
 
-        let metadata = ApiKeyMetadata {
-            id: row.get("id"),
-            app_name: row.get("app_name"),
-            key_name: row.get("key_name"),
-            api_url: row.get("api_url"),
-            description: row.get("description"),
-            created_at: DateTime::from_timestamp(row.get("created_at"), 0).unwrap_or_default(),
-            updated_at: DateTime::from_timestamp(row.get("updated_at"), 0).unwrap_or_default(),
-        };
+        Ok(())
+    }
 
-        // Delete the key
-        sqlx::query("DELETE FROM api_keys WHERE id = ?1")
-            .bind(id)
+    /// Lists the previous values of a key, without decrypting them.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::NotFound`] if the key doesn't exist
+    pub async fn history(&self, id: &str) -> Result<KeyHistory> {
+        self.require_unlocked().await?;
+
+        let (current_version, history_limit) = self.version_state(id).await?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT version, reason, replaced_at
+            FROM key_versions
+            WHERE key_id = ?1
+            ORDER BY version DESC
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let versions = rows
+            .iter()
+            .map(|row| KeyVersion {
+                version: row.get("version"),
+                replaced_at: DateTime::from_timestamp(row.get("replaced_at"), 0)
+                    .unwrap_or_default(),
+                reason: row.get("reason"),
+            })
+            .collect();
+
+        Ok(KeyHistory {
+            current_version,
+            history_limit,
+            versions,
+        })
+    }
+
+    /// Gets an API key with the value it held at `version`.
+    ///
+    /// The other fields are the key's current ones.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::NotFound`] if the key doesn't exist, or the version
+    ///   never did or has been dropped from the history
+    pub async fn get_version(&self, id: &str, version: i64) -> Result<ApiKey> {
+        let key = self.fetch_version(id, version).await?;
+        let detail = format!("version {}", version);
+        self.audit_read(AuditEvent::Get, &key, Some(&detail))
+            .await?;
+        Ok(key)
+    }
+
+    /// As [`Self::get_version`], without recording a read in the audit log.
+    async fn fetch_version(&self, id: &str, version: i64) -> Result<ApiKey> {
+        let mut key = self.fetch_by_id(id).await?;
+        let (current_version, _) = self.version_state(id).await?;
+        if version == current_version {
+            return Ok(key);
+        }
+
+        let row = sqlx::query(
+            r#"
+            SELECT encrypted_key_value, nonce, key_salt, key_scheme
+            FROM key_versions
+            WHERE key_id = ?1 AND version = ?2
+            "#,
+        )
+        .bind(id)
+        .bind(version)
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?
+        .ok_or_else(|| VaultError::NotFound(format!("{} version {}", id, version)))?;
+
+        let stored_scheme: i64 = row.get("key_scheme");
+        let scheme = KeyScheme::from_i64(stored_scheme).ok_or_else(|| {
+            VaultError::Decryption(format!("Unknown key scheme {}", stored_scheme))
+        })?;
+        let key_salt: Vec<u8> = row.get("key_salt");
+        let salt: [u8; 32] = key_salt
+            .try_into()
+            .map_err(|_| VaultError::Decryption("Invalid key salt".to_string()))?;
+        let encrypted = EncryptedData {
+            ciphertext: row.get("encrypted_key_value"),
+            nonce: row.get("nonce"),
+        };
+
+        let master_key = self.auth.get_vault_key().await?;
+        let version_id = version_context_id(id, version);
+        key.key_value = self.crypto.decrypt_key_value(
+            &encrypted,
+            &master_key,
+            &version_context(&version_id),
+            &salt,
+            scheme,
+        )?;
+
+        Ok(key)
+    }
+
+    /// Makes the value a key held at `version` current again.
+    ///
+    /// This is an ordinary [`Self::update`]: the restored value gets a new
+    /// version number and the value it replaces goes into the history.
+    /// Without a `reason`, the history records "rollback to version N".
+    ///
+    /// # Returns
+    ///
+    /// The key's version after the rollback.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::NotFound`] if the key or version doesn't exist
+    /// - [`VaultError::InvalidInput`] if `version` is the current one
+    pub async fn rollback(&self, id: &str, version: i64, reason: Option<&str>) -> Result<i64> {
+        let (current_version, _) = self.version_state(id).await?;
+        if version == current_version {
+            return Err(VaultError::InvalidInput(format!(
+                "version {} is already the current value",
+                version
+            )));
+        }
+
+        let target = self.fetch_version(id, version).await?;
+        let reason = reason
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("rollback to version {}", version));
+        self.update(
+            id,
+            UpdateKeyRequest {
+                key_value: Some(target.key_value),
+                reason: Some(reason),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        Ok(self.version_state(id).await?.0)
+    }
+
+    /// Sets how many previous values are kept for a key, dropping the
+    /// oldest ones past the new limit. `None` restores
+    /// [`DEFAULT_HISTORY_LIMIT`].
+    ///
+    /// # Returns
+    ///
+    /// The number of previous values dropped.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::NotFound`] if the key doesn't exist
+    pub async fn set_history_limit(&self, id: &str, limit: Option<u32>) -> Result<usize> {
+        self.require_unlocked().await?;
+
+        let mut tx = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let result = sqlx::query("UPDATE api_keys SET history_limit = ?1 WHERE id = ?2")
+            .bind(limit.map(i64::from))
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        let dropped = prune_history(&mut tx, id).await?;
+
+        tx.commit()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(dropped)
+    }
+
+    /// Lists keys that expire or are due for rotation by `before`,
+    /// including those already past their date, soonest first.
+    ///
+    /// A key due for both appears once for each. Values are not decrypted.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    pub async fn due(&self, before: DateTime<Utc>) -> Result<Vec<DueKey>> {
+        let now = Utc::now();
+        let mut due = Vec::new();
+        for key in self.list().await? {
+            for (reason, at) in [
+                (DueReason::Expiry, key.expires_at),
+                (DueReason::Rotation, key.rotate_by),
+            ] {
+                if let Some(due_at) = at.filter(|at| *at <= before) {
+                    due.push(DueKey {
+                        key: key.clone(),
+                        reason,
+                        due_at,
+                        overdue: due_at <= now,
+                    });
+                }
+            }
+        }
+        due.sort_by_key(|entry| entry.due_at);
+        Ok(due)
+    }
+
+    /// Records `event` for a key in the audit log. In encrypted-metadata
+    /// mode the name is left out, so the log does not reveal it.
+    async fn audit(
+        &self,
+        event: AuditEvent,
+        id: &str,
+        app_name: Option<&str>,
+        key_name: &str,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        let name = if self.auth.encrypted_metadata().await? {
+            None
+        } else {
+            Some(display_name(app_name, key_name))
+        };
+        self.auth
+            .record_audit(AuditRecord {
+                event,
+                key_id: Some(id),
+                key_name: name.as_deref(),
+                detail,
+            })
+            .await
+    }
+
+    /// Records `event` for a key that was read, see [`Self::audit`].
+    async fn audit_read(
+        &self,
+        event: AuditEvent,
+        key: &ApiKey,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        self.audit(
+            event,
+            &key.id,
+            key.app_name.as_deref(),
+            &key.key_name,
+            detail,
+        )
+        .await
+    }
+
+    /// Reads a key's current version and history limit.
+    async fn version_state(&self, id: &str) -> Result<(i64, u32)> {
+        let row = sqlx::query("SELECT version, history_limit FROM api_keys WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.db.pool)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?
+            .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
+
+        let history_limit: Option<i64> = row.get("history_limit");
+        Ok((row.get("version"), history_limit_or_default(history_limit)))
+    }
+
+    /// Deletes an API key.
+    ///
+    /// # Returns
+    ///
+    /// The metadata of the deleted key for confirmation.
+    pub async fn delete(&self, id: &str) -> Result<ApiKeyMetadata> {
+        self.require_unlocked().await?;
+
+        let metadata_keys = self.metadata_keys().await?;
+
+        // First get the key metadata
+        let row = sqlx::query(
+            r#"
+            SELECT id, app_name, key_name, api_url, description,
+                   metadata_ciphertext, metadata_nonce, expires_at, rotate_every, rotated_at,
+                   created_at, updated_at
+            FROM api_keys
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?
+        .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
+
+        let metadata = self.row_to_metadata(&row, metadata_keys.as_ref())?;
+
+        // Delete the key
+        sqlx::query("DELETE FROM api_keys WHERE id = ?1")
+            .bind(id)
             .execute(&self.db.pool)
             .await
             .map_err(|e| VaultError::Database(e.to_string()))?;
 
+        self.audit(
+            AuditEvent::Delete,
+            id,
+            metadata.app_name.as_deref(),
+            &metadata.key_name,
+            None,
+        )
+        .await?;
+
         Ok(metadata)
     }
 
     /// Deletes an API key by app_name and key_name.
     pub async fn delete_by_name(&self, app_name: &str, key_name: &str) -> Result<ApiKeyMetadata> {
+        let id = self.find_id(app_name, key_name).await?;
+        self.delete(&id).await
+    }
+
+    /// Looks up the ID of an API key by app_name and key_name, without
+    /// decrypting its value.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::NotFound`] if key doesn't exist
+    pub async fn find_id(&self, app_name: &str, key_name: &str) -> Result<String> {
         self.require_unlocked().await?;
 
-        // First get the key
-        let key = self.get(app_name, key_name).await?;
-        self.delete(&key.id).await
+        let metadata_keys = self.metadata_keys().await?;
+        let (app_param, key_param) =
+            self.lookup_params(app_name, key_name, metadata_keys.as_ref());
+
+        sqlx::query_scalar(
+            r#"
+            SELECT id FROM api_keys
+            WHERE COALESCE(app_name, '') = ?1 AND key_name = ?2
+            "#,
+        )
+        .bind(&app_param)
+        .bind(&key_param)
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?
+        .ok_or_else(|| VaultError::key_not_found(app_name, key_name))
     }
 
     /// Counts total number of API keys.
@@ -562,11 +1394,683 @@ impl KeyService {
 
         Ok(row.get("count"))
     }
+
+    /// Decrypts every key, for exports.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    pub async fn get_all(&self) -> Result<Vec<ApiKey>> {
+        let mut keys = Vec::new();
+        for metadata in self.list().await? {
+            keys.push(self.get_by_id(&metadata.id).await?);
+        }
+        Ok(keys)
+    }
+
+    /// Writes `keys` into the vault in a single transaction.
+    ///
+    /// Each key is encrypted under this vault's key with a new ID; names,
+    /// URLs, descriptions and timestamps are kept. Keys whose names are
+    /// taken are handled by `options.on_conflict`; an overwritten key keeps
+    /// its ID and history, and its previous value is added to that history.
+    /// If any key fails to import, nothing is changed. The vault is
+    /// snapshotted first.
+    ///
+    /// A dry run does the same work, reports the same summary and then
+    /// rolls the transaction back.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::DuplicateKey`] if `keys` names the same key twice in
+    ///   [`ImportMode::Replace`]
+    pub async fn import(
+        &self,
+        keys: &[ImportedKey],
+        options: ImportOptions,
+    ) -> Result<ImportSummary> {
+        self.require_unlocked().await?;
+
+        // Read before the transaction takes the connection
+        let vault_keys = ImportKeys {
+            master: self.auth.get_vault_key().await?,
+            metadata: self.metadata_keys().await?,
+        };
+        let mut summary = ImportSummary {
+            dry_run: options.dry_run,
+            ..ImportSummary::default()
+        };
+
+        if !options.dry_run {
+            self.db.create_backup().await?;
+        }
+
+        let mut tx = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        if options.mode == ImportMode::Replace {
+            let result = sqlx::query("DELETE FROM api_keys")
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+            summary.replaced = result.rows_affected() as usize;
+        }
+
+        for key in keys {
+            let app_name = key.request.app_name.as_deref();
+            let key_name = &key.request.key_name;
+            let name = display_name(app_name, key_name);
+
+            if self
+                .insert_imported(&mut tx, &vault_keys, key, key_name)
+                .await?
+            {
+                summary.added.push(name);
+                continue;
+            }
+
+            // The vault was emptied, so the import itself names the key twice
+            if options.mode == ImportMode::Replace {
+                return Err(VaultError::duplicate_key(app_name.unwrap_or(""), key_name));
+            }
+
+            match options.on_conflict {
+                ConflictStrategy::Skip => summary.skipped.push(name),
+                ConflictStrategy::Overwrite => {
+                    self.overwrite_imported(&mut tx, &vault_keys, key).await?;
+                    summary.overwritten.push(name);
+                }
+                ConflictStrategy::Rename => {
+                    // Every failed attempt hits an existing row, so this ends
+                    for n in 2.. {
+                        let candidate = format!("{}-{}", key_name, n);
+                        if self
+                            .insert_imported(&mut tx, &vault_keys, key, &candidate)
+                            .await?
+                        {
+                            summary.renamed.push(RenamedKey {
+                                from: name,
+                                to: display_name(app_name, &candidate),
+                            });
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        if options.dry_run {
+            tx.rollback()
+                .await
+                .map_err(|e| VaultError::Database(e.to_string()))?;
+            return Ok(summary);
+        }
+        tx.commit()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let detail = format!(
+            "{} keys written, {} deleted first",
+            summary.imported(),
+            summary.replaced
+        );
+        self.auth
+            .record_audit(AuditRecord {
+                detail: Some(&detail),
+                ..AuditRecord::new(AuditEvent::Import)
+            })
+            .await?;
+
+        Ok(summary)
+    }
+
+    /// Inserts one imported key under `key_name`.
+    ///
+    /// Nothing is written if the name is taken.
+    ///
+    /// # Returns
+    ///
+    /// Whether the key was written.
+    async fn insert_imported(
+        &self,
+        conn: &mut SqliteConnection,
+        vault_keys: &ImportKeys,
+        key: &ImportedKey,
+        key_name: &str,
+    ) -> Result<bool> {
+        let request = &key.request;
+        let id = Uuid::new_v4().to_string();
+        let context = KeyContext {
+            id: &id,
+            app_name: request.app_name.as_deref().unwrap_or(""),
+            key_name,
+        };
+        let (encrypted, salt) =
+            self.crypto
+                .encrypt_key_value(&request.key_value, &vault_keys.master, &context)?;
+
+        let metadata = KeyMetadata {
+            app_name: request.app_name.clone(),
+            key_name: key_name.to_string(),
+            api_url: request.api_url.clone(),
+            description: request.description.clone(),
+        };
+        let columns = self.encode_metadata(&id, &metadata, vault_keys.metadata.as_ref())?;
+
+        let now = Utc::now();
+        let created_at = key.created_at.unwrap_or(now);
+        let updated_at = key.updated_at.unwrap_or(created_at);
+        let rotated_at = key.rotated_at.unwrap_or(updated_at);
+        let rotate_every = rotate_every_column(request.rotate_every)?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO api_keys (id, app_name, key_name, api_url, description,
+                                  encrypted_key_value, nonce, key_salt, key_scheme,
+                                  metadata_ciphertext, metadata_nonce, expires_at, rotate_every,
+                                  rotated_at, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(&id)
+        .bind(&columns.app_name)
+        .bind(&columns.key_name)
+        .bind(&columns.api_url)
+        .bind(&columns.description)
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&salt[..])
+        .bind(KeyScheme::CURRENT.as_i64())
+        .bind(&columns.ciphertext)
+        .bind(&columns.nonce)
+        .bind(request.expires_at.map(|at| at.timestamp()))
+        .bind(rotate_every)
+        .bind(rotated_at.timestamp())
+        .bind(created_at.timestamp())
+        .bind(updated_at.timestamp())
+        .execute(conn)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Writes an imported key over the existing key of the same name.
+    ///
+    /// The row is updated in place like [`Self::update`], so the key keeps
+    /// its ID, creation time and history. A different value is archived
+    /// first and bumps the version.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotFound`] if no key has that name
+    async fn overwrite_imported(
+        &self,
+        conn: &mut SqliteConnection,
+        vault_keys: &ImportKeys,
+        key: &ImportedKey,
+    ) -> Result<()> {
+        let request = &key.request;
+        let app_name = request.app_name.as_deref().unwrap_or("");
+        let (app_param, key_param) =
+            self.lookup_params(app_name, &request.key_name, vault_keys.metadata.as_ref());
+
+        // Read through the transaction; decrypt_row would write through the pool
+        let row = sqlx::query(
+            r#"
+            SELECT id, app_name, key_name, api_url, description,
+                   encrypted_key_value, nonce, key_salt, key_scheme,
+                   metadata_ciphertext, metadata_nonce
+            FROM api_keys
+            WHERE COALESCE(app_name, '') = ?1 AND key_name = ?2
+            "#,
+        )
+        .bind(&app_param)
+        .bind(&key_param)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?
+        .ok_or_else(|| VaultError::key_not_found(app_name, &request.key_name))?;
+
+        let id: String = row.get("id");
+        let existing = self.decode_metadata(&row, vault_keys.metadata.as_ref())?;
+        let stored_scheme: i64 = row.get("key_scheme");
+        let scheme = KeyScheme::from_i64(stored_scheme).ok_or_else(|| {
+            VaultError::Decryption(format!("Unknown key scheme {}", stored_scheme))
+        })?;
+        let key_salt: Vec<u8> = row.get("key_salt");
+        let mut salt_array = [0u8; 32];
+        if key_salt.len() == 32 {
+            salt_array.copy_from_slice(&key_salt);
+        }
+        let existing_value = self.crypto.decrypt_key_value(
+            &EncryptedData {
+                ciphertext: row.get("encrypted_key_value"),
+                nonce: row.get("nonce"),
+            },
+            &vault_keys.master,
+            &KeyContext {
+                id: &id,
+                app_name: existing.app_name.as_deref().unwrap_or(""),
+                key_name: &existing.key_name,
+            },
+            &salt_array,
+            scheme,
+        )?;
+
+        let value_changed = *existing_value != *request.key_value;
+        if value_changed {
+            self.archive_value(
+                conn,
+                &id,
+                &existing_value,
+                Some("import"),
+                &vault_keys.master,
+            )
+            .await?;
+        }
+
+        // Keep the stored spelling of the name, which may differ for no app
+        let metadata = KeyMetadata {
+            app_name: existing.app_name,
+            key_name: existing.key_name,
+            api_url: request.api_url.clone(),
+            description: request.description.clone(),
+        };
+        let columns = self.encode_metadata(&id, &metadata, vault_keys.metadata.as_ref())?;
+        let context = KeyContext {
+            id: &id,
+            app_name: metadata.app_name.as_deref().unwrap_or(""),
+            key_name: &metadata.key_name,
+        };
+        let (encrypted, salt) =
+            self.crypto
+                .encrypt_key_value(&request.key_value, &vault_keys.master, &context)?;
+
+        let now = Utc::now();
+        let rotated_at = key.rotated_at.or(key.updated_at).unwrap_or(now);
+        sqlx::query(
+            r#"
+            UPDATE api_keys
+            SET api_url = ?1, description = ?2,
+                encrypted_key_value = ?3, nonce = ?4, key_salt = ?5, key_scheme = ?6,
+                metadata_ciphertext = ?7, metadata_nonce = ?8, updated_at = ?9,
+                version = version + ?10,
+                rotated_at = CASE WHEN ?10 THEN ?11 ELSE rotated_at END,
+                expires_at = ?12, rotate_every = ?13
+            WHERE id = ?14
+            "#,
+        )
+        .bind(&columns.api_url)
+        .bind(&columns.description)
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&salt[..])
+        .bind(KeyScheme::CURRENT.as_i64())
+        .bind(&columns.ciphertext)
+        .bind(&columns.nonce)
+        .bind(now.timestamp())
+        .bind(i64::from(value_changed))
+        .bind(rotated_at.timestamp())
+        .bind(request.expires_at.map(|at| at.timestamp()))
+        .bind(rotate_every_column(request.rotate_every)?)
+        .bind(&id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        if value_changed {
+            prune_history(conn, &id).await?;
+        }
+        Ok(())
+    }
+
+    /// Turns encrypted-metadata mode on or off.
+    ///
+    /// When enabled, `app_name`, `key_name`, `api_url` and `description`
+    /// are stored encrypted. The name columns hold keyed-HMAC blind indexes
+    /// instead, so exact lookups and the uniqueness constraint keep working
+    /// without revealing the names. Every existing row is converted in a
+    /// single transaction, after snapshotting the vault.
+    ///
+    /// # Returns
+    ///
+    /// The number of rows converted (0 if the mode was already set).
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if vault is locked
+    /// - [`VaultError::NotInitialized`] if the vault has no PIN yet
+    pub async fn set_encrypted_metadata(&self, enabled: bool) -> Result<usize> {
+        self.require_unlocked().await?;
+
+        if self.auth.encrypted_metadata().await? == enabled {
+            return Ok(0);
+        }
+
+        let master_key = self.auth.get_vault_key().await?;
+        let metadata_keys = self.derive_metadata_keys(&master_key)?;
+        let target_keys = enabled.then_some(&metadata_keys);
+
+        self.db.create_backup().await?;
+
+        let mut tx = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT id, app_name, key_name, api_url, description,
+                   metadata_ciphertext, metadata_nonce
+            FROM api_keys
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        for row in &rows {
+            let id: String = row.get("id");
+            let metadata = self.decode_metadata(row, Some(&metadata_keys))?;
+            let columns = self.encode_metadata(&id, &metadata, target_keys)?;
+
+            sqlx::query(
+                r#"
+                UPDATE api_keys
+                SET app_name = ?1, key_name = ?2, api_url = ?3, description = ?4,
+                    metadata_ciphertext = ?5, metadata_nonce = ?6
+                WHERE id = ?7
+                "#,
+            )
+            .bind(&columns.app_name)
+            .bind(&columns.key_name)
+            .bind(&columns.api_url)
+            .bind(&columns.description)
+            .bind(&columns.ciphertext)
+            .bind(&columns.nonce)
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        }
+
+        let result = sqlx::query("UPDATE vault_config SET encrypted_metadata = ?1 WHERE id = 1")
+            .bind(enabled)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(VaultError::NotInitialized);
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| VaultError::Database(e.to_string()))?;
+
+        Ok(rows.len())
+    }
+
+    /// Returns the metadata subkeys if the vault uses encrypted metadata.
+    async fn metadata_keys(&self) -> Result<Option<MetadataKeys>> {
+        if !self.auth.encrypted_metadata().await? {
+            return Ok(None);
+        }
+
+        let master_key = self.auth.get_vault_key().await?;
+        self.derive_metadata_keys(&master_key).map(Some)
+    }
+
+    /// Derives the metadata encryption and blind index subkeys.
+    fn derive_metadata_keys(&self, master_key: &VaultKey) -> Result<MetadataKeys> {
+        Ok(MetadataKeys {
+            encryption: self.crypto.derive_metadata_key(master_key)?,
+            index: self.crypto.derive_blind_index_key(master_key)?,
+        })
+    }
+
+    /// Returns the values to match the name columns against.
+    fn lookup_params(
+        &self,
+        app_name: &str,
+        key_name: &str,
+        metadata_keys: Option<&MetadataKeys>,
+    ) -> (String, String) {
+        match metadata_keys {
+            // An empty app name stays empty so it still matches NULL
+            Some(keys) if app_name.is_empty() => (
+                String::new(),
+                self.crypto
+                    .blind_index(&keys.index, IndexedField::KeyName, key_name),
+            ),
+            Some(keys) => (
+                self.crypto
+                    .blind_index(&keys.index, IndexedField::AppName, app_name),
+                self.crypto
+                    .blind_index(&keys.index, IndexedField::KeyName, key_name),
+            ),
+            None => (app_name.to_string(), key_name.to_string()),
+        }
+    }
+
+    /// Builds the column values for `metadata`.
+    ///
+    /// Without `metadata_keys` the metadata is stored as-is.
+    fn encode_metadata(
+        &self,
+        id: &str,
+        metadata: &KeyMetadata,
+        metadata_keys: Option<&MetadataKeys>,
+    ) -> Result<MetadataColumns> {
+        let Some(keys) = metadata_keys else {
+            return Ok(MetadataColumns {
+                app_name: metadata.app_name.clone(),
+                key_name: metadata.key_name.clone(),
+                api_url: metadata.api_url.clone(),
+                description: metadata.description.clone(),
+                ciphertext: None,
+                nonce: None,
+            });
+        };
+
+        let plaintext = serde_json::to_vec(metadata)
+            .map_err(|e| VaultError::Encryption(e.to_string()))?;
+        let encrypted =
+            self.crypto
+                .encrypt_with_aad(&plaintext, &keys.encryption, &metadata_aad(id))?;
+
+        Ok(MetadataColumns {
+            // An empty app is stored as NULL, which lookups treat as no app
+            app_name: metadata
+                .app_name
+                .as_deref()
+                .filter(|app| !app.is_empty())
+                .map(|app| {
+                    self.crypto
+                        .blind_index(&keys.index, IndexedField::AppName, app)
+                }),
+            key_name: self.crypto.blind_index(
+                &keys.index,
+                IndexedField::KeyName,
+                &metadata.key_name,
+            ),
+            api_url: None,
+            description: None,
+            ciphertext: Some(encrypted.ciphertext),
+            nonce: Some(encrypted.nonce),
+        })
+    }
+
+    /// Reads a row's metadata, decrypting it if it is stored encrypted.
+    fn decode_metadata(
+        &self,
+        row: &SqliteRow,
+        metadata_keys: Option<&MetadataKeys>,
+    ) -> Result<KeyMetadata> {
+        let ciphertext: Option<Vec<u8>> = row.get("metadata_ciphertext");
+        let Some(ciphertext) = ciphertext else {
+            return Ok(KeyMetadata {
+                app_name: row.get("app_name"),
+                key_name: row.get("key_name"),
+                api_url: row.get("api_url"),
+                description: row.get("description"),
+            });
+        };
+
+        let keys = metadata_keys.ok_or_else(|| {
+            VaultError::Decryption("Key metadata is encrypted but the vault mode is not".into())
+        })?;
+        let id: String = row.get("id");
+        let encrypted = EncryptedData {
+            ciphertext,
+            nonce: row.get::<Option<Vec<u8>>, _>("metadata_nonce").unwrap_or_default(),
+        };
+        let plaintext =
+            self.crypto
+                .decrypt_with_aad(&encrypted, &keys.encryption, &metadata_aad(&id))?;
+
+        serde_json::from_slice(&plaintext).map_err(|e| VaultError::Decryption(e.to_string()))
+    }
+
+    /// Builds [`ApiKeyMetadata`] from a row.
+    fn row_to_metadata(
+        &self,
+        row: &SqliteRow,
+        metadata_keys: Option<&MetadataKeys>,
+    ) -> Result<ApiKeyMetadata> {
+        let metadata = self.decode_metadata(row, metadata_keys)?;
+        let schedule = Schedule::from_row(row);
+        Ok(ApiKeyMetadata {
+            id: row.get("id"),
+            app_name: metadata.app_name,
+            key_name: metadata.key_name,
+            api_url: metadata.api_url,
+            description: metadata.description,
+            expires_at: schedule.expires_at,
+            rotate_every: schedule.rotate_every,
+            rotate_by: schedule.rotate_by,
+            created_at: DateTime::from_timestamp(row.get("created_at"), 0).unwrap_or_default(),
+            updated_at: DateTime::from_timestamp(row.get("updated_at"), 0).unwrap_or_default(),
+        })
+    }
+}
+
+/// Row id an archived value is bound to. Key ids are UUIDs, so this never
+/// names a live row.
+fn version_context_id(id: &str, version: i64) -> String {
+    format!("{}/v{}", id, version)
+}
+
+/// Encryption context of an archived value. The names are left out so
+/// that renaming a key keeps its history readable.
+fn version_context(version_id: &str) -> KeyContext<'_> {
+    KeyContext {
+        id: version_id,
+        app_name: "",
+        key_name: "",
+    }
+}
+
+/// Applies [`DEFAULT_HISTORY_LIMIT`] to a stored `history_limit`.
+fn history_limit_or_default(limit: Option<i64>) -> u32 {
+    limit
+        .and_then(|limit| u32::try_from(limit).ok())
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+}
+
+/// Expiry and rotation columns of an `api_keys` row.
+struct Schedule {
+    expires_at: Option<DateTime<Utc>>,
+    rotate_every: Option<u64>,
+    rotate_by: Option<DateTime<Utc>>,
+}
+
+impl Schedule {
+    fn from_row(row: &SqliteRow) -> Self {
+        let expires_at: Option<i64> = row.get("expires_at");
+        let rotate_every: Option<i64> = row.get("rotate_every");
+        let rotate_every = rotate_every.and_then(|every| u64::try_from(every).ok());
+        // Set for every row since v8; older rows were backfilled
+        let rotated_at: Option<i64> = row.get("rotated_at");
+        let rotated_at = rotated_at.unwrap_or_else(|| row.get("created_at"));
+
+        Self {
+            expires_at: expires_at.and_then(|at| DateTime::from_timestamp(at, 0)),
+            rotate_every,
+            rotate_by: rotate_every
+                .and_then(rotation_interval)
+                .zip(DateTime::from_timestamp(rotated_at, 0))
+                .and_then(|(every, at)| at.checked_add_signed(every)),
+        }
+    }
+}
+
+/// Converts a rotation interval in seconds, `None` if out of range.
+fn rotation_interval(seconds: u64) -> Option<Duration> {
+    i64::try_from(seconds).ok().and_then(Duration::try_seconds)
+}
+
+/// Checks a rotation interval and converts it for the `rotate_every` column.
+fn rotate_every_column(seconds: Option<u64>) -> Result<Option<i64>> {
+    match seconds {
+        None => Ok(None),
+        Some(0) => Err(VaultError::InvalidInput(
+            "rotation interval must be positive".to_string(),
+        )),
+        Some(seconds) => rotation_interval(seconds)
+            .map(|_| Some(seconds as i64))
+            .ok_or_else(|| VaultError::InvalidInput("rotation interval is too long".to_string())),
+    }
+}
+
+/// Drops a key's oldest previous values past its history limit.
+///
+/// # Returns
+///
+/// The number of values dropped.
+async fn prune_history(conn: &mut SqliteConnection, id: &str) -> Result<usize> {
+    let limit: Option<i64> = sqlx::query_scalar("SELECT history_limit FROM api_keys WHERE id = ?1")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| VaultError::Database(e.to_string()))?;
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM key_versions
+        WHERE key_id = ?1 AND version NOT IN (
+            SELECT version FROM key_versions
+            WHERE key_id = ?1
+            ORDER BY version DESC
+            LIMIT ?2
+        )
+        "#,
+    )
+    .bind(id)
+    .bind(i64::from(history_limit_or_default(limit)))
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| VaultError::Database(e.to_string()))?;
+
+    Ok(result.rows_affected() as usize)
+}
+
+/// Sorts by app name then key name, keys without an app first (as SQLite
+/// orders NULLs).
+fn sort_metadata(keys: &mut [ApiKeyMetadata]) {
+    keys.sort_by(|a, b| (&a.app_name, &a.key_name).cmp(&(&b.app_name, &b.key_name)));
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::AuditFilter;
 
     // Helper to create fully configured test services
     async fn setup_test_services() -> (KeyService, Arc<AuthService>) {
@@ -706,46 +2210,313 @@ mod tests {
             .await
             .unwrap();
 
-        let results = service.search("github").await.unwrap();
-        assert_eq!(results.len(), 2);
+        let results = service.search("github").await.unwrap();
+        assert_eq!(results.len(), 2);
+
+        let results = service.search("personal").await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        let results = service.search("token").await.unwrap();
+        assert_eq!(results.len(), 2); // matches description "token"
+    }
+
+    #[tokio::test]
+    async fn test_exists() {
+        let (service, _auth) = setup_test_services().await;
+        service
+            .create(Some("github"), "token", "ghp", None, None)
+            .await
+            .unwrap();
+        service.create(None, "loose", "v", None, None).await.unwrap();
+
+        assert!(service.exists("github", "token").await.unwrap());
+        assert!(service.exists("", "loose").await.unwrap());
+        assert!(!service.exists("gitlab", "token").await.unwrap());
+        assert!(!service.exists("", "token").await.unwrap());
+
+        service.set_encrypted_metadata(true).await.unwrap();
+        assert!(service.exists("github", "token").await.unwrap());
+        assert!(!service.exists("gitlab", "token").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_delete_key() {
+        let (service, _auth) = setup_test_services().await;
+
+        let id = service
+            .create(Some("github"), "token", "secret", None, None)
+            .await
+            .unwrap();
+
+        assert!(service.get_by_id(&id).await.is_ok());
+
+        service.delete(&id).await.unwrap();
+
+        let result = service.get_by_id(&id).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_by_name() {
+        let (service, _auth) = setup_test_services().await;
+
+        service
+            .create(Some("github"), "token", "secret", None, None)
+            .await
+            .unwrap();
+
+        service.delete_by_name("github", "token").await.unwrap();
+
+        let result = service.get("github", "token").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_same_key_name_in_different_apps() {
+        let (service, _auth) = setup_test_services().await;
+
+        service
+            .create(Some("github"), "token", "ghp_123", None, None)
+            .await
+            .unwrap();
+        let gitlab_id = service
+            .create(Some("gitlab"), "token", "glpat-123", None, None)
+            .await
+            .unwrap();
+        service
+            .create(None, "token", "loose", None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            service.get("github", "token").await.unwrap().key_value,
+            "ghp_123"
+        );
+        assert_eq!(
+            service.get("gitlab", "token").await.unwrap().key_value,
+            "glpat-123"
+        );
+        assert_eq!(service.get("", "token").await.unwrap().key_value, "loose");
+
+        // An empty app name is the same as none
+        let dup = service.create(Some(""), "token", "x", None, None).await;
+        assert!(matches!(dup, Err(VaultError::DuplicateKey { .. })));
+
+        // Renaming onto a taken name is a duplicate too
+        let update = UpdateKeyRequest {
+            app_name: Some(Some("github".to_string())),
+            ..Default::default()
+        };
+        let result = service.update(&gitlab_id, update).await;
+        assert!(matches!(result, Err(VaultError::DuplicateKey { .. })));
+
+        let deleted = service.delete_by_name("gitlab", "token").await.unwrap();
+        assert_eq!(deleted.id, gitlab_id);
+        assert!(service.exists("github", "token").await.unwrap());
+        assert!(service.exists("", "token").await.unwrap());
+    }
+
+    /// Sets a key's value through [`KeyService::update`].
+    async fn set_value(service: &KeyService, id: &str, value: &str, reason: Option<&str>) {
+        let update = UpdateKeyRequest {
+            key_value: Some(value.to_string()),
+            reason: reason.map(str::to_string),
+            ..Default::default()
+        };
+        service.update(id, update).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_keeps_previous_values() {
+        let (service, _auth) = setup_test_services().await;
+
+        let id = service
+            .create(Some("github"), "token", "one", None, None)
+            .await
+            .unwrap();
+        set_value(&service, &id, "two", Some("rotated")).await;
+
+        // Neither metadata changes nor the same value make a version
+        let update = UpdateKeyRequest {
+            description: Some(Some("CI token".to_string())),
+            ..Default::default()
+        };
+        service.update(&id, update).await.unwrap();
+        set_value(&service, &id, "two", None).await;
+        set_value(&service, &id, "three", None).await;
+
+        let history = service.history(&id).await.unwrap();
+        assert_eq!(history.current_version, 3);
+        assert_eq!(history.history_limit, DEFAULT_HISTORY_LIMIT);
+        let versions: Vec<(i64, Option<&str>)> = history
+            .versions
+            .iter()
+            .map(|v| (v.version, v.reason.as_deref()))
+            .collect();
+        assert_eq!(versions, vec![(2, None), (1, Some("rotated"))]);
+
+        // Archived values survive a rename
+        let update = UpdateKeyRequest {
+            app_name: Some(Some("gh".to_string())),
+            ..Default::default()
+        };
+        service.update(&id, update).await.unwrap();
 
-        let results = service.search("personal").await.unwrap();
-        assert_eq!(results.len(), 1);
+        for (version, value) in [(1, "one"), (2, "two"), (3, "three")] {
+            let key = service.get_version(&id, version).await.unwrap();
+            assert_eq!(key.key_value, value);
+            assert_eq!(key.app_name.as_deref(), Some("gh"));
+        }
+        assert!(matches!(
+            service.get_version(&id, 4).await,
+            Err(VaultError::NotFound(_))
+        ));
+    }
 
-        let results = service.search("token").await.unwrap();
-        assert_eq!(results.len(), 2); // matches description "token"
+    #[tokio::test]
+    async fn test_rollback_restores_previous_value() {
+        let (service, _auth) = setup_test_services().await;
+
+        let id = service
+            .create(Some("stripe"), "secret", "sk_good", None, None)
+            .await
+            .unwrap();
+        set_value(&service, &id, "sk_bad", Some("bad rotation")).await;
+
+        let version = service.rollback(&id, 1, None).await.unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(service.get_by_id(&id).await.unwrap().key_value, "sk_good");
+
+        // The bad value is kept, so the rollback can be undone
+        let history = service.history(&id).await.unwrap();
+        assert_eq!(history.versions[0].version, 2);
+        assert_eq!(
+            history.versions[0].reason.as_deref(),
+            Some("rollback to version 1")
+        );
+        assert_eq!(
+            service.get_version(&id, 2).await.unwrap().key_value,
+            "sk_bad"
+        );
+
+        let result = service.rollback(&id, 3, None).await;
+        assert!(matches!(result, Err(VaultError::InvalidInput(_))));
     }
 
     #[tokio::test]
-    async fn test_delete_key() {
+    async fn test_history_limit_and_delete() {
         let (service, _auth) = setup_test_services().await;
 
         let id = service
-            .create(Some("github"), "token", "secret", None, None)
+            .create(Some("aws"), "secret", "v1", None, None)
             .await
             .unwrap();
+        for n in 2..=13 {
+            set_value(&service, &id, &format!("v{}", n), None).await;
+        }
 
-        assert!(service.get_by_id(&id).await.is_ok());
+        // The default limit keeps the ten newest previous values
+        let history = service.history(&id).await.unwrap();
+        assert_eq!(history.current_version, 13);
+        assert_eq!(history.versions.len(), DEFAULT_HISTORY_LIMIT as usize);
+        assert_eq!(history.versions.last().unwrap().version, 3);
 
-        service.delete(&id).await.unwrap();
+        assert_eq!(service.set_history_limit(&id, Some(2)).await.unwrap(), 8);
+        set_value(&service, &id, "v14", None).await;
+        let history = service.history(&id).await.unwrap();
+        assert_eq!(history.history_limit, 2);
+        let versions: Vec<i64> = history.versions.iter().map(|v| v.version).collect();
+        assert_eq!(versions, vec![13, 12]);
 
-        let result = service.get_by_id(&id).await;
-        assert!(result.is_err());
+        // Deleting the key deletes its history
+        service.delete(&id).await.unwrap();
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM key_versions")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        assert_eq!(left, 0);
+        assert!(matches!(
+            service.set_history_limit(&id, None).await,
+            Err(VaultError::NotFound(_))
+        ));
     }
 
     #[tokio::test]
-    async fn test_delete_by_name() {
-        let (service, _auth) = setup_test_services().await;
+    async fn test_key_access_is_audited() {
+        let (service, auth) = setup_test_services().await;
 
-        service
-            .create(Some("github"), "token", "secret", None, None)
+        let id = service
+            .create(Some("stripe"), "live", "sk_live_one", None, None)
             .await
             .unwrap();
+        let key = service.get("stripe", "live").await.unwrap();
+        service.record_copy(&key).await.unwrap();
+        set_value(&service, &id, "sk_live_two", Some("rotated")).await;
+        service.get_version(&id, 1).await.unwrap();
+        service.delete(&id).await.unwrap();
 
-        service.delete_by_name("github", "token").await.unwrap();
+        let entries = auth
+            .audit_log()
+            .entries(&AuditFilter::default())
+            .await
+            .unwrap();
+        let events: Vec<(AuditEvent, Option<&str>)> = entries
+            .iter()
+            .map(|e| (e.event, e.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (AuditEvent::Init, None),
+                (AuditEvent::Create, None),
+                (AuditEvent::Get, None),
+                (AuditEvent::Copy, None),
+                (AuditEvent::Update, Some("new value: rotated")),
+                (AuditEvent::Get, Some("version 1")),
+                (AuditEvent::Delete, None),
+            ]
+        );
+        for entry in &entries[1..] {
+            assert_eq!(entry.key_id.as_deref(), Some(id.as_str()));
+            assert_eq!(entry.key_name.as_deref(), Some("stripe/live"));
+        }
 
-        let result = service.get("github", "token").await;
-        assert!(result.is_err());
+        // Secret values never reach the log
+        let rows: Vec<(Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT key_name, detail FROM audit_log")
+                .fetch_all(&service.db.pool)
+                .await
+                .unwrap();
+        assert!(rows
+            .iter()
+            .flat_map(|(name, detail)| [name, detail])
+            .flatten()
+            .all(|text| !text.contains("sk_live")));
+
+        // Names are left out once metadata is encrypted
+        service.set_encrypted_metadata(true).await.unwrap();
+        service
+            .create(Some("stripe"), "test", "sk_test", None, None)
+            .await
+            .unwrap();
+        let last = auth
+            .audit_log()
+            .entries(&AuditFilter {
+                limit: Some(1),
+                ..AuditFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(last[0].event, AuditEvent::Create);
+        assert!(last[0].key_name.is_none());
+
+        let vault_key = auth.get_vault_key().await.unwrap();
+        assert!(auth
+            .audit_log()
+            .verify(&vault_key)
+            .await
+            .unwrap()
+            .is_intact());
     }
 
     #[tokio::test]
@@ -1066,4 +2837,509 @@ mod tests {
         assert_eq!(updated_key.key_value, "secret123");
         assert_eq!(updated_key.description, Some("New description".to_string()));
     }
+
+    async fn read_ciphertext(service: &KeyService, id: &str) -> (Vec<u8>, i64) {
+        let row = sqlx::query("SELECT encrypted_key_value, key_scheme FROM api_keys WHERE id = ?1")
+            .bind(id)
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        (row.get("encrypted_key_value"), row.get("key_scheme"))
+    }
+
+    #[tokio::test]
+    async fn test_rename_rebinds_ciphertext() {
+        let (service, _auth) = setup_test_services().await;
+
+        let id = service
+            .create(Some("github"), "token", "secret123", None, None)
+            .await
+            .unwrap();
+        let before = read_ciphertext(&service, &id).await;
+        assert_eq!(before.1, KeyScheme::CURRENT.as_i64());
+
+        let update = UpdateKeyRequest {
+            app_name: Some(Some("gitlab".to_string())),
+            key_name: Some("renamed".to_string()),
+            ..Default::default()
+        };
+        service.update(&id, update).await.unwrap();
+
+        assert_ne!(read_ciphertext(&service, &id).await, before);
+        assert_eq!(service.get("gitlab", "renamed").await.unwrap().key_value, "secret123");
+    }
+
+    #[tokio::test]
+    async fn test_swapped_ciphertext_rejected() {
+        let (service, _auth) = setup_test_services().await;
+
+        let id_a = service
+            .create(Some("github"), "token", "secret-a", None, None)
+            .await
+            .unwrap();
+        let id_b = service
+            .create(Some("aws"), "token2", "secret-b", None, None)
+            .await
+            .unwrap();
+
+        // Copy row A's ciphertext columns over row B
+        sqlx::query(
+            r#"
+            UPDATE api_keys
+            SET (encrypted_key_value, nonce, key_salt) =
+                (SELECT encrypted_key_value, nonce, key_salt FROM api_keys WHERE id = ?1)
+            WHERE id = ?2
+            "#,
+        )
+        .bind(&id_a)
+        .bind(&id_b)
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+
+        let result = service.get_by_id(&id_b).await;
+        assert!(matches!(result, Err(VaultError::Decryption(_))));
+        assert_eq!(service.get_by_id(&id_a).await.unwrap().key_value, "secret-a");
+    }
+
+    #[tokio::test]
+    async fn test_v2_row_upgraded_to_aad() {
+        let (service, auth) = setup_test_services().await;
+        let master_key = auth.get_vault_key().await.unwrap();
+
+        // Write a row under the scheme without associated data
+        let salt = service.crypto.generate_salt();
+        let context = KeyContext {
+            id: "k2",
+            app_name: "github",
+            key_name: "token",
+        };
+        let encrypted = crate::crypto::encrypt_api_key_value(
+            b"ghp_v2",
+            &master_key,
+            &context,
+            &salt,
+            KeyScheme::HkdfV2,
+        )
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at) VALUES ('k2', 'github', 'token', ?1, ?2, ?3, 2, 0, 0)",
+        )
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&salt[..])
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+
+        assert_eq!(service.get_by_id("k2").await.unwrap().key_value, "ghp_v2");
+        let (_, scheme) = read_ciphertext(&service, "k2").await;
+        assert_eq!(scheme, KeyScheme::HkdfAad.as_i64());
+        assert_eq!(
+            service.get("github", "token").await.unwrap().key_value,
+            "ghp_v2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_row_upgraded_on_read() {
+        let (service, auth) = setup_test_services().await;
+        let master_key = auth.get_vault_key().await.unwrap();
+
+        // Write a row the way releases before key schemes did
+        let (encrypted, key_salt) = service
+            .crypto
+            .encrypt_api_key("ghp_legacy", &master_key, "github", "token")
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO api_keys (id, app_name, key_name, encrypted_key_value, nonce, key_salt, key_scheme, created_at, updated_at) VALUES ('k1', 'github', 'token', ?1, ?2, ?3, 1, 100, 100)",
+        )
+        .bind(&encrypted.ciphertext)
+        .bind(&encrypted.nonce)
+        .bind(&key_salt[..])
+        .execute(&service.db.pool)
+        .await
+        .unwrap();
+
+        let key = service.get("github", "token").await.unwrap();
+        assert_eq!(key.key_value, "ghp_legacy");
+
+        // The row now uses the current scheme, without touching updated_at
+        let (ciphertext, scheme) = read_ciphertext(&service, "k1").await;
+        assert_eq!(scheme, KeyScheme::CURRENT.as_i64());
+        assert_ne!(ciphertext, encrypted.ciphertext);
+        let key = service.get_by_id("k1").await.unwrap();
+        assert_eq!(key.key_value, "ghp_legacy");
+        assert_eq!(key.updated_at.timestamp(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_scheme_rejected() {
+        let (service, _auth) = setup_test_services().await;
+
+        let id = service
+            .create(Some("github"), "token", "secret123", None, None)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE api_keys SET key_scheme = 99 WHERE id = ?1")
+            .bind(&id)
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+
+        let result = service.get_by_id(&id).await;
+        assert!(matches!(result, Err(VaultError::Decryption(_))));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_metadata_roundtrip() {
+        let (service, _auth) = setup_test_services().await;
+        assert_eq!(service.set_encrypted_metadata(true).await.unwrap(), 0);
+
+        let id = service
+            .create(
+                Some("github"),
+                "token",
+                "ghp_secret",
+                Some("https://api.github.com"),
+                Some("CI token"),
+            )
+            .await
+            .unwrap();
+        service.create(None, "loose", "v", None, None).await.unwrap();
+
+        // Nothing readable is left in the metadata columns
+        let row = sqlx::query("SELECT app_name, key_name, api_url, description FROM api_keys WHERE id = ?1")
+            .bind(&id)
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        let app_column: Option<String> = row.get("app_name");
+        let key_column: String = row.get("key_name");
+        assert_ne!(app_column.as_deref(), Some("github"));
+        assert_ne!(key_column, "token");
+        assert!(row.get::<Option<String>, _>("api_url").is_none());
+        assert!(row.get::<Option<String>, _>("description").is_none());
+
+        // Exact lookups go through the blind index
+        let key = service.get("github", "token").await.unwrap();
+        assert_eq!(key.key_value, "ghp_secret");
+        assert_eq!(key.api_url.as_deref(), Some("https://api.github.com"));
+        assert_eq!(key.description.as_deref(), Some("CI token"));
+        assert_eq!(service.get("", "loose").await.unwrap().key_value, "v");
+
+        // Listing and search decrypt the metadata
+        let names: Vec<_> = service
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|k| k.key_name)
+            .collect();
+        assert_eq!(names, vec!["loose", "token"]);
+        let found = service.search("CI TOK").await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].app_name.as_deref(), Some("github"));
+
+        // Uniqueness still holds
+        let dup = service.create(Some("github"), "token", "x", None, None).await;
+        assert!(matches!(dup, Err(VaultError::DuplicateKey { .. })));
+
+        // Renames update the index
+        let update = UpdateKeyRequest {
+            key_name: Some("renamed".to_string()),
+            ..Default::default()
+        };
+        service.update(&id, update).await.unwrap();
+        assert!(service.get("github", "token").await.is_err());
+        let deleted = service.delete_by_name("github", "renamed").await.unwrap();
+        assert_eq!(deleted.description.as_deref(), Some("CI token"));
+    }
+
+    #[tokio::test]
+    async fn test_blind_index_hides_app_and_key_name_equality() {
+        let (service, _auth) = setup_test_services().await;
+        service.set_encrypted_metadata(true).await.unwrap();
+        let id = service
+            .create(Some("github"), "github", "secret", None, None)
+            .await
+            .unwrap();
+
+        let (app_column, key_column): (Option<String>, String) =
+            sqlx::query_as("SELECT app_name, key_name FROM api_keys WHERE id = ?1")
+                .bind(&id)
+                .fetch_one(&service.db.pool)
+                .await
+                .unwrap();
+        assert_ne!(app_column.as_deref(), Some(key_column.as_str()));
+        assert_eq!(service.get("github", "github").await.unwrap().key_value, "secret");
+    }
+
+    #[tokio::test]
+    async fn test_toggle_encrypted_metadata_converts_rows() {
+        let (service, auth) = setup_test_services().await;
+
+        service
+            .create(Some("aws"), "access", "AKIA", None, Some("prod"))
+            .await
+            .unwrap();
+
+        assert_eq!(service.set_encrypted_metadata(true).await.unwrap(), 1);
+        assert!(auth.encrypted_metadata().await.unwrap());
+        let key_column: String = sqlx::query_scalar("SELECT key_name FROM api_keys")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        assert_ne!(key_column, "access");
+        assert_eq!(service.get("aws", "access").await.unwrap().key_value, "AKIA");
+
+        assert_eq!(service.set_encrypted_metadata(false).await.unwrap(), 1);
+        assert!(!auth.encrypted_metadata().await.unwrap());
+        let key_column: String = sqlx::query_scalar("SELECT key_name FROM api_keys")
+            .fetch_one(&service.db.pool)
+            .await
+            .unwrap();
+        assert_eq!(key_column, "access");
+        let key = service.get("aws", "access").await.unwrap();
+        assert_eq!(key.description.as_deref(), Some("prod"));
+    }
+
+    fn import_request(app_name: &str, key_name: &str, key_value: &str) -> ImportedKey {
+        ImportedKey::from(CreateKeyRequest {
+            app_name: Some(app_name.to_string()),
+            key_name: key_name.to_string(),
+            key_value: key_value.to_string(),
+            api_url: None,
+            description: None,
+            expires_at: None,
+            rotate_every: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_import_conflict_strategies() {
+        let (service, _auth) = setup_test_services().await;
+        service
+            .create(Some("github"), "token", "old", None, None)
+            .await
+            .unwrap();
+        let keys = vec![
+            import_request("github", "token", "new"),
+            import_request("aws", "key", "AKIA"),
+        ];
+
+        let options = |on_conflict| ImportOptions {
+            on_conflict,
+            ..ImportOptions::default()
+        };
+
+        let summary = service
+            .import(&keys, options(ConflictStrategy::Skip))
+            .await
+            .unwrap();
+        assert_eq!(summary.added, vec!["aws/key"]);
+        assert_eq!(summary.skipped, vec!["github/token"]);
+        assert_eq!(
+            service.get("github", "token").await.unwrap().key_value,
+            "old"
+        );
+
+        let summary = service
+            .import(&keys[..1], options(ConflictStrategy::Rename))
+            .await
+            .unwrap();
+        assert_eq!(
+            summary.renamed,
+            vec![RenamedKey {
+                from: "github/token".into(),
+                to: "github/token-2".into()
+            }]
+        );
+        let summary = service
+            .import(&keys[..1], options(ConflictStrategy::Rename))
+            .await
+            .unwrap();
+        assert_eq!(summary.renamed[0].to, "github/token-3");
+        assert_eq!(
+            service.get("github", "token-3").await.unwrap().key_value,
+            "new"
+        );
+
+        let summary = service
+            .import(&keys[..1], options(ConflictStrategy::Overwrite))
+            .await
+            .unwrap();
+        assert_eq!(summary.overwritten, vec!["github/token"]);
+        assert_eq!(
+            service.get("github", "token").await.unwrap().key_value,
+            "new"
+        );
+        assert_eq!(service.count().await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_import_overwrite_keeps_id_and_history() {
+        let (service, _auth) = setup_test_services().await;
+        let id = service
+            .create(Some("github"), "token", "one", None, None)
+            .await
+            .unwrap();
+        set_value(&service, &id, "two", Some("rotated")).await;
+        let created_at = service.get_by_id(&id).await.unwrap().created_at;
+
+        let options = ImportOptions {
+            on_conflict: ConflictStrategy::Overwrite,
+            ..ImportOptions::default()
+        };
+        let summary = service
+            .import(&[import_request("github", "token", "three")], options)
+            .await
+            .unwrap();
+        assert_eq!(summary.overwritten, vec!["github/token"]);
+
+        assert_eq!(service.find_id("github", "token").await.unwrap(), id);
+        let key = service.get_by_id(&id).await.unwrap();
+        assert_eq!(key.key_value, "three");
+        assert_eq!(key.created_at, created_at);
+
+        let history = service.history(&id).await.unwrap();
+        assert_eq!(history.current_version, 3);
+        let versions: Vec<(i64, Option<&str>)> = history
+            .versions
+            .iter()
+            .map(|v| (v.version, v.reason.as_deref()))
+            .collect();
+        assert_eq!(versions, vec![(2, Some("import")), (1, Some("rotated"))]);
+        for (version, value) in [(1, "one"), (2, "two")] {
+            assert_eq!(
+                service.get_version(&id, version).await.unwrap().key_value,
+                value
+            );
+        }
+
+        // Importing the same value again makes no new version
+        service
+            .import(&[import_request("github", "token", "three")], options)
+            .await
+            .unwrap();
+        assert_eq!(service.history(&id).await.unwrap().current_version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_import_dry_run_changes_nothing() {
+        let (service, _auth) = setup_test_services().await;
+        service
+            .create(Some("github"), "token", "old", None, None)
+            .await
+            .unwrap();
+        let keys = vec![
+            import_request("github", "token", "new"),
+            import_request("aws", "key", "AKIA"),
+        ];
+
+        let summary = service
+            .import(
+                &keys,
+                ImportOptions {
+                    on_conflict: ConflictStrategy::Overwrite,
+                    dry_run: true,
+                    ..ImportOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(summary.dry_run);
+        assert_eq!(summary.added, vec!["aws/key"]);
+        assert_eq!(summary.overwritten, vec!["github/token"]);
+
+        assert_eq!(service.count().await.unwrap(), 1);
+        assert_eq!(
+            service.get("github", "token").await.unwrap().key_value,
+            "old"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expiry_and_rotation_due() {
+        let (service, _auth) = setup_test_services().await;
+        let now = Utc::now();
+        let expired = service
+            .create_from_request(&CreateKeyRequest {
+                app_name: Some("github".to_string()),
+                key_name: "token".to_string(),
+                key_value: "secret".to_string(),
+                api_url: None,
+                description: None,
+                expires_at: Some(now - Duration::days(1)),
+                rotate_every: None,
+            })
+            .await
+            .unwrap();
+        let rotated = service
+            .create_from_request(&CreateKeyRequest {
+                app_name: Some("aws".to_string()),
+                key_name: "key".to_string(),
+                key_value: "AKIA".to_string(),
+                api_url: None,
+                description: None,
+                expires_at: None,
+                rotate_every: Some(30 * 86400),
+            })
+            .await
+            .unwrap();
+
+        let key = service.get_by_id(&rotated).await.unwrap();
+        assert!(key.expires_at.is_none());
+        let rotate_by = key.rotate_by.unwrap();
+        assert_eq!(rotate_by - key.created_at, Duration::days(30));
+
+        let due = service.due(now + Duration::days(7)).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].key.id, expired);
+        assert_eq!(due[0].reason, DueReason::Expiry);
+        assert!(due[0].overdue);
+
+        let due = service.due(now + Duration::days(31)).await.unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[1].key.id, rotated);
+        assert_eq!(due[1].reason, DueReason::Rotation);
+        assert!(!due[1].overdue);
+
+        // Backdate the last value change; only a new value resets it.
+        sqlx::query("UPDATE api_keys SET rotated_at = rotated_at - 40 * 86400 WHERE id = ?1")
+            .bind(&rotated)
+            .execute(&service.db.pool)
+            .await
+            .unwrap();
+        let update = UpdateKeyRequest {
+            description: Some(Some("prod".to_string())),
+            ..Default::default()
+        };
+        service.update(&rotated, update).await.unwrap();
+        let due = service.due(now).await.unwrap();
+        assert_eq!(due.len(), 2);
+        assert!(due.iter().all(|entry| entry.overdue));
+
+        let update = UpdateKeyRequest {
+            key_value: Some("AKIB".to_string()),
+            ..Default::default()
+        };
+        service.update(&rotated, update).await.unwrap();
+        let key = service.get_by_id(&rotated).await.unwrap();
+        assert!(key.rotate_by.unwrap() > now + Duration::days(29));
+
+        let update = UpdateKeyRequest {
+            expires_at: Some(None),
+            ..Default::default()
+        };
+        service.update(&expired, update).await.unwrap();
+        assert!(service.due(now).await.unwrap().is_empty());
+
+        let update = UpdateKeyRequest {
+            rotate_every: Some(Some(0)),
+            ..Default::default()
+        };
+        let err = service.update(&rotated, update).await.unwrap_err();
+        assert!(matches!(err, VaultError::InvalidInput(_)));
+    }
 }
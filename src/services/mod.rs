@@ -37,16 +37,27 @@
 //! let keys = vault.keys().list().await?;
 //! ```
 
+mod audit_service;
 mod auth_service;
 mod crypto_service;
 pub mod key_service;
 mod vault_manager;
 
 // Re-export main types
-pub use auth_service::AuthService;
+pub use audit_service::{
+    AuditBreak, AuditEntry, AuditEvent, AuditFilter, AuditRecord, AuditReport, AuditService,
+};
+pub use auth_service::{
+    create_key_file, read_key_file, AuthService, KdfConfig, KeySlot, LockoutReset, LockoutState,
+    SlotCredential, SlotKind, KEY_FILE_SIZE, PRIMARY_SLOT_LABEL,
+};
 pub use crypto_service::CryptoService;
 pub use key_service::KeyService;
 pub use vault_manager::VaultManager;
 
 // Re-export data types used in the API
-pub use key_service::{ApiKey, ApiKeyMetadata, CreateKeyRequest, UpdateKeyRequest};
+pub use key_service::{
+    ApiKey, ApiKeyMetadata, ConflictStrategy, CreateKeyRequest, DueKey, DueReason, ImportMode,
+    ImportOptions, ImportSummary, ImportedKey, KeyHistory, KeyVersion, RenamedKey,
+    UpdateKeyRequest, DEFAULT_HISTORY_LIMIT,
+};
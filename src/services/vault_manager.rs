@@ -27,12 +27,13 @@
 //! }
 //! ```
 
+use std::path::Path;
 use std::sync::Arc;
 
-use crate::database::VaultDb;
+use crate::database::{BackupInfo, VaultDb};
 use crate::error::{Result, VaultError};
 
-use super::{AuthService, CryptoService, KeyService};
+use super::{AuditService, AuthService, CryptoService, KeyService};
 
 /// High-level vault manager that orchestrates all vault operations.
 ///
@@ -59,6 +60,9 @@ use super::{AuthService, CryptoService, KeyService};
 /// });
 /// ```
 pub struct VaultManager {
+    /// Database shared by the services
+    db: Arc<VaultDb>,
+
     /// Authentication service
     auth_service: Arc<AuthService>,
 
@@ -117,6 +121,7 @@ impl VaultManager {
         ));
 
         Ok(Self {
+            db,
             auth_service,
             key_service,
             crypto_service,
@@ -168,6 +173,24 @@ impl VaultManager {
         &self.key_service
     }
 
+    /// Returns a reference to the audit log.
+    ///
+    /// Unlocks, key reads and other access are recorded by the other
+    /// services; use this to list or verify the entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let vault = VaultManager::new("sqlite://vault.db").await?;
+    /// vault.auth().unlock("my-pin").await?;
+    ///
+    /// let report = vault.audit().verify(&vault.auth().get_vault_key().await?).await?;
+    /// assert!(report.is_intact());
+    /// ```
+    pub fn audit(&self) -> &AuditService {
+        self.auth_service.audit_log()
+    }
+
     /// Returns a reference to the cryptographic service.
     ///
     /// This is primarily for advanced usage. Most operations
@@ -196,6 +219,112 @@ impl VaultManager {
     pub async fn is_initialized(&self) -> Result<bool> {
         self.auth_service.is_initialized().await
     }
+
+    /// Snapshots the vault into its backup directory.
+    ///
+    /// Snapshots are also taken automatically before migrations, PIN
+    /// changes and bulk operations. Returns `None` for an in-memory vault or
+    /// an uninitialized one without keys.
+    pub async fn create_backup(&self) -> Result<Option<BackupInfo>> {
+        Ok(self.db.create_backup().await?)
+    }
+
+    /// Lists the vault's snapshots, newest first.
+    pub async fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        Ok(self.db.list_backups().await?)
+    }
+
+    /// Finds the snapshot whose id starts with `id`.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::NotFound`] if no snapshot matches
+    /// - [`VaultError::InvalidInput`] if more than one does
+    pub async fn find_backup(&self, id: &str) -> Result<BackupInfo> {
+        let mut matches: Vec<BackupInfo> = self
+            .list_backups()
+            .await?
+            .into_iter()
+            .filter(|backup| backup.id.starts_with(id))
+            .collect();
+
+        match matches.len() {
+            0 => Err(VaultError::NotFound(format!("snapshot {id}"))),
+            1 => Ok(matches.remove(0)),
+            n => Err(VaultError::InvalidInput(format!(
+                "{n} snapshots start with '{id}'; give more of the timestamp"
+            ))),
+        }
+    }
+
+    /// Checks that a snapshot unlocks with `pin` and that every key in it
+    /// decrypts.
+    ///
+    /// The check runs on a temporary copy next to the snapshot, so the
+    /// snapshot itself is never migrated or otherwise changed.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys in the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::IncompatibleVersion`] if the snapshot is newer than
+    ///   this build
+    /// - [`VaultError::BackupFailed`] if it is not a vault, or does not
+    ///   unlock with `pin`
+    pub async fn verify_backup(backup: &Path, pin: &str, key_file: Option<&[u8]>) -> Result<usize> {
+        VaultDb::backup_schema_version(backup).await?;
+
+        let dir = backup
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(format!(".verify-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).map_err(|e| VaultError::BackupFailed(e.to_string()))?;
+        let result = Self::verify_copy(backup, &dir, pin, key_file).await;
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+
+    async fn verify_copy(
+        backup: &Path,
+        dir: &Path,
+        pin: &str,
+        key_file: Option<&[u8]>,
+    ) -> Result<usize> {
+        let copy = dir.join("vault.db");
+        std::fs::copy(backup, &copy).map_err(|e| VaultError::BackupFailed(e.to_string()))?;
+
+        let url = format!(
+            "sqlite://{}?mode=rw",
+            copy.to_str().unwrap_or(".").replace('\\', "/")
+        );
+        let vault = Self::new(&url).await?;
+        let result = match vault.auth().unlock_with_key_file(pin, key_file).await {
+            Ok(()) => vault.keys().get_all().await.map(|keys| keys.len()),
+            Err(VaultError::InvalidPin) => Err(VaultError::BackupFailed(
+                "the snapshot does not unlock with this PIN".to_string(),
+            )),
+            Err(e) => Err(e),
+        };
+        vault.db.pool.close().await;
+        result
+    }
+
+    /// Replaces the vault with a snapshot.
+    ///
+    /// The snapshot should be checked with [`Self::verify_backup`] first.
+    /// The current vault is snapshotted before it is replaced, so the
+    /// restore can be undone. The manager is consumed because its
+    /// connection is closed; open the vault again to use it.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot of the replaced vault, if one was made.
+    pub async fn restore_backup(self, backup: &BackupInfo) -> Result<Option<BackupInfo>> {
+        self.auth_service.lock().await?;
+        Ok(self.db.restore_backup(&backup.path).await?)
+    }
 }
 
 #[cfg(test)]
@@ -318,9 +447,27 @@ mod tests {
         assert!(result.is_err());
     }
 
-    // Note: PIN change with keys requires re-encryption which is not yet implemented
-    // in AuthService.change_pin(). Keys would need to be decrypted with old key
-    // and re-encrypted with new key. This is tracked as a future enhancement.
+    #[tokio::test]
+    async fn test_pin_change_keeps_keys_readable() {
+        let vault = VaultManager::new("sqlite::memory:").await.unwrap();
+        vault.auth().init_vault("old-pin-123").await.unwrap();
+        vault
+            .keys()
+            .create(Some("github"), "token", "ghp_secret", None, None)
+            .await
+            .unwrap();
+
+        vault
+            .auth()
+            .change_pin("old-pin-123", "new-pin-456")
+            .await
+            .unwrap();
+
+        vault.auth().lock().await.unwrap();
+        vault.auth().unlock("new-pin-456").await.unwrap();
+        let key = vault.keys().get("github", "token").await.unwrap();
+        assert_eq!(key.key_value, "ghp_secret");
+    }
 
     #[tokio::test]
     async fn test_crypto_service_generates_salt() {
@@ -332,4 +479,45 @@ mod tests {
         assert_ne!(salt1, salt2);
         assert_eq!(salt1.len(), 32);
     }
+
+    #[tokio::test]
+    async fn test_pin_change_snapshot_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("vault.db").display()
+        );
+        let vault = VaultManager::new(&url).await.unwrap();
+        vault.auth().init_vault("old-pin-123").await.unwrap();
+        vault
+            .keys()
+            .create(Some("github"), "token", "ghp_secret", None, None)
+            .await
+            .unwrap();
+
+        vault
+            .auth()
+            .change_pin("old-pin-123", "new-pin-456")
+            .await
+            .unwrap();
+        let backups = vault.list_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        let snapshot = vault.find_backup(&backups[0].id[..8]).await.unwrap();
+
+        // The snapshot still has the PIN it was taken with
+        let result = VaultManager::verify_backup(&snapshot.path, "new-pin-456", None).await;
+        assert!(matches!(result, Err(VaultError::BackupFailed(_))));
+        let keys = VaultManager::verify_backup(&snapshot.path, "old-pin-123", None)
+            .await
+            .unwrap();
+        assert_eq!(keys, 1);
+
+        // Verification leaves nothing behind
+        let entries = std::fs::read_dir(snapshot.path.parent().unwrap()).unwrap();
+        assert_eq!(entries.count(), 1);
+        assert!(matches!(
+            vault.find_backup("1999").await,
+            Err(VaultError::NotFound(_))
+        ));
+    }
 }
@@ -0,0 +1,487 @@
+//! Shamir secret sharing for vault key recovery.
+//!
+//! The 32-byte vault data key is split byte-wise over GF(2^8) into `n`
+//! shares, any `threshold` of which rebuild it. Fewer shares reveal nothing
+//! about the key.
+//!
+//! Each share is printed as a text block of Crockford base32 groups that
+//! ends in a 4-byte SHA-256 checksum, so typos are caught before the shares
+//! are combined:
+//!
+//! ```text
+//! VULT-RECOVERY-SHARE 2 (threshold 3)
+//! 04Z8K-1QX0A-...
+//! ```
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::shamir::{combine, split, Share};
+//!
+//! let shares = split(&secret, 3, 5)?;
+//! let text = shares[0].to_text();
+//! let parsed: Share = text.parse()?;
+//! let secret = combine(&[parsed, shares[2].clone(), shares[4].clone()])?;
+//! ```
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Length of the shared secret in bytes
+pub const SECRET_LEN: usize = 32;
+
+/// Format version written into every share
+const SHARE_VERSION: u8 = 1;
+
+/// First line of a share's text block
+const SHARE_HEADER: &str = "VULT-RECOVERY-SHARE";
+
+/// Crockford base32 alphabet (no I, L, O, U)
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Characters per group and groups per line in the text block
+const GROUP_LEN: usize = 5;
+const GROUPS_PER_LINE: usize = 5;
+
+/// Length of the checksum appended to each encoded share
+const CHECKSUM_LEN: usize = 4;
+
+/// Encoded share: version, set id, threshold, index, value, checksum
+const ENCODED_LEN: usize = 1 + 4 + 1 + 1 + SECRET_LEN + CHECKSUM_LEN;
+
+/// Errors from splitting, parsing or combining shares
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ShamirError {
+    #[error("Invalid share parameters: {0}")]
+    InvalidParameters(String),
+
+    #[error("Malformed recovery share: {0}")]
+    Malformed(String),
+
+    #[error("Recovery share checksum mismatch; check it for typos")]
+    Checksum,
+
+    #[error("Need {needed} recovery shares, got {got}")]
+    NotEnoughShares { needed: u8, got: usize },
+
+    #[error("Recovery shares come from different sets")]
+    MixedSets,
+
+    #[error("Recovery share {0} was given more than once")]
+    DuplicateShare(u8),
+}
+
+/// Result type for secret sharing operations
+pub type Result<T> = std::result::Result<T, ShamirError>;
+
+/// One share of a split secret.
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct Share {
+    set_id: [u8; 4],
+    threshold: u8,
+    index: u8,
+    value: [u8; SECRET_LEN],
+}
+
+impl Share {
+    /// Random id shared by every share from one [`split`] call.
+    pub fn set_id(&self) -> [u8; 4] {
+        self.set_id
+    }
+
+    /// Number of shares needed to rebuild the secret.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Position of this share, starting at 1.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Encodes the share as a human-typeable, checksummed text block.
+    pub fn to_text(&self) -> String {
+        let mut bytes = Vec::with_capacity(ENCODED_LEN);
+        bytes.push(SHARE_VERSION);
+        bytes.extend_from_slice(&self.set_id);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.value);
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+
+        let encoded = base32_encode(&bytes);
+        bytes.zeroize();
+
+        let groups: Vec<&str> = encoded
+            .as_bytes()
+            .chunks(GROUP_LEN)
+            .map(|g| std::str::from_utf8(g).expect("base32 output is ASCII"))
+            .collect();
+        let lines: Vec<String> = groups
+            .chunks(GROUPS_PER_LINE)
+            .map(|line| line.join("-"))
+            .collect();
+
+        format!(
+            "{SHARE_HEADER} {} (threshold {})\n{}",
+            self.index,
+            self.threshold,
+            lines.join("\n")
+        )
+    }
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share")
+            .field("set_id", &hex::encode(self.set_id))
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for Share {
+    type Err = ShamirError;
+
+    /// Parses a text block from [`Share::to_text`].
+    ///
+    /// The header line is optional. Case, whitespace and dashes are ignored,
+    /// and `O`, `I` and `L` are read as the digits they resemble.
+    fn from_str(s: &str) -> Result<Self> {
+        let body: String = s
+            .lines()
+            .filter(|line| !line.trim().to_ascii_uppercase().starts_with(SHARE_HEADER))
+            .flat_map(|line| line.chars())
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect();
+
+        let mut bytes = base32_decode(&body)?;
+        if bytes.len() != ENCODED_LEN {
+            bytes.zeroize();
+            return Err(ShamirError::Malformed(format!(
+                "expected {ENCODED_LEN} bytes, got {}",
+                bytes.len()
+            )));
+        }
+
+        let (payload, stored_checksum) = bytes.split_at(ENCODED_LEN - CHECKSUM_LEN);
+        let valid = checksum(payload) == stored_checksum;
+        let share = Share {
+            set_id: payload[1..5].try_into().expect("slice has 4 bytes"),
+            threshold: payload[5],
+            index: payload[6],
+            value: payload[7..].try_into().expect("slice has 32 bytes"),
+        };
+        let version = payload[0];
+        bytes.zeroize();
+
+        if !valid {
+            return Err(ShamirError::Checksum);
+        }
+        if version != SHARE_VERSION {
+            return Err(ShamirError::Malformed(format!(
+                "unsupported share version {version}"
+            )));
+        }
+        if share.index == 0 || share.threshold < 2 {
+            return Err(ShamirError::Malformed(
+                "invalid share index or threshold".to_string(),
+            ));
+        }
+
+        Ok(share)
+    }
+}
+
+/// Splits `secret` into `shares` shares, any `threshold` of which rebuild it.
+///
+/// # Errors
+/// Returns [`ShamirError::InvalidParameters`] unless
+/// `2 <= threshold <= shares`.
+pub fn split(secret: &[u8; SECRET_LEN], threshold: u8, shares: u8) -> Result<Vec<Share>> {
+    if threshold < 2 {
+        return Err(ShamirError::InvalidParameters(
+            "threshold must be at least 2".to_string(),
+        ));
+    }
+    if shares < threshold {
+        return Err(ShamirError::InvalidParameters(format!(
+            "cannot create {shares} shares with a threshold of {threshold}"
+        )));
+    }
+
+    let mut rng = rand::rngs::OsRng;
+    let mut set_id = [0u8; 4];
+    rng.fill_bytes(&mut set_id);
+
+    // One random polynomial of degree threshold - 1 per secret byte
+    let mut coefficients = vec![[0u8; SECRET_LEN]; threshold as usize];
+    coefficients[0] = *secret;
+    for row in coefficients.iter_mut().skip(1) {
+        rng.fill_bytes(row);
+    }
+
+    let result = (1..=shares)
+        .map(|x| {
+            let mut value = [0u8; SECRET_LEN];
+            for (byte, y) in value.iter_mut().enumerate() {
+                // Horner's rule, highest coefficient first
+                *y = coefficients
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, row| gf_mul(acc, x) ^ row[byte]);
+            }
+            Share {
+                set_id,
+                threshold,
+                index: x,
+                value,
+            }
+        })
+        .collect();
+
+    coefficients.zeroize();
+    Ok(result)
+}
+
+/// Rebuilds the secret from at least `threshold` shares of one set.
+///
+/// Shares beyond the threshold are ignored.
+///
+/// # Errors
+/// - [`ShamirError::NotEnoughShares`] below the threshold
+/// - [`ShamirError::MixedSets`] if the shares come from different splits
+/// - [`ShamirError::DuplicateShare`] if a share is given twice
+pub fn combine(shares: &[Share]) -> Result<[u8; SECRET_LEN]> {
+    let first = shares.first().ok_or(ShamirError::NotEnoughShares {
+        needed: 2,
+        got: 0,
+    })?;
+    if shares
+        .iter()
+        .any(|s| s.set_id != first.set_id || s.threshold != first.threshold)
+    {
+        return Err(ShamirError::MixedSets);
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(ShamirError::DuplicateShare(share.index));
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(ShamirError::NotEnoughShares {
+            needed: first.threshold,
+            got: shares.len(),
+        });
+    }
+
+    let used = &shares[..first.threshold as usize];
+    let mut secret = [0u8; SECRET_LEN];
+    for share in used {
+        // Lagrange basis polynomial for this share, evaluated at x = 0
+        let basis = used
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |acc, other| {
+                gf_mul(acc, gf_div(other.index, other.index ^ share.index))
+            });
+        for (s, y) in secret.iter_mut().zip(share.value.iter()) {
+            *s ^= gf_mul(*y, basis);
+        }
+    }
+
+    Ok(secret)
+}
+
+/// Multiplies in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1, without branches.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// Divides in GF(2^8); `b` must be non-zero.
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b
+    let mut inverse = 1u8;
+    let mut power = b;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            inverse = gf_mul(inverse, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    gf_mul(a, inverse)
+}
+
+/// First [`CHECKSUM_LEN`] bytes of SHA-256 over `bytes`.
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha256::digest(bytes);
+    digest[..CHECKSUM_LEN]
+        .try_into()
+        .expect("digest is longer than the checksum")
+}
+
+/// Encodes bytes as unpadded Crockford base32.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 8 / 5 + 1);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Decodes unpadded Crockford base32, accepting common typos.
+fn base32_decode(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for c in text.chars() {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        let value = ALPHABET
+            .iter()
+            .position(|&a| a as char == c)
+            .ok_or_else(|| ShamirError::Malformed(format!("invalid character '{c}'")))?;
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret() -> [u8; SECRET_LEN] {
+        let mut secret = [0u8; SECRET_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        secret
+    }
+
+    #[test]
+    fn test_gf_arithmetic() {
+        // Known AES field values
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x53, 0xca), 0x01);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(gf_div(1, a), a), 1);
+        }
+    }
+
+    #[test]
+    fn test_any_threshold_subset_rebuilds_secret() {
+        let secret = secret();
+        let shares = split(&secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                    assert_eq!(combine(&subset).unwrap(), secret);
+                }
+            }
+        }
+        assert_eq!(combine(&shares).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_below_threshold_fails() {
+        let shares = split(&secret(), 3, 5).unwrap();
+
+        assert_eq!(
+            combine(&shares[..2]),
+            Err(ShamirError::NotEnoughShares { needed: 3, got: 2 })
+        );
+        assert!(combine(&[]).is_err());
+    }
+
+    #[test]
+    fn test_mixed_and_duplicate_shares_rejected() {
+        let secret = secret();
+        let first = split(&secret, 2, 3).unwrap();
+        let second = split(&secret, 2, 3).unwrap();
+
+        assert_eq!(
+            combine(&[first[0].clone(), second[1].clone()]),
+            Err(ShamirError::MixedSets)
+        );
+        assert_eq!(
+            combine(&[first[0].clone(), first[0].clone()]),
+            Err(ShamirError::DuplicateShare(1))
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(split(&secret(), 1, 3).is_err());
+        assert!(split(&secret(), 4, 3).is_err());
+        assert!(split(&secret(), 255, 255).is_ok());
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let shares = split(&secret(), 2, 3).unwrap();
+        let text = shares[1].to_text();
+
+        assert!(text.starts_with("VULT-RECOVERY-SHARE 2 (threshold 2)\n"));
+        assert_eq!(text.parse::<Share>().unwrap(), shares[1]);
+
+        // Header is optional; case, spacing and look-alike letters are forgiven
+        let body = text.lines().skip(1).collect::<Vec<_>>().join(" ");
+        let sloppy = body.to_lowercase().replace('0', "o").replace('1', "l");
+        assert_eq!(sloppy.parse::<Share>().unwrap(), shares[1]);
+    }
+
+    #[test]
+    fn test_corrupted_share_detected() {
+        let shares = split(&secret(), 2, 3).unwrap();
+        let text = shares[0].to_text();
+
+        // Change one character of the body
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        let original = lines[1].remove(0);
+        lines[1].insert(0, if original == 'A' { 'B' } else { 'A' });
+        assert_eq!(lines.join("\n").parse::<Share>(), Err(ShamirError::Checksum));
+
+        // Dropped characters and foreign symbols
+        let truncated = &text[..text.len() - 3];
+        assert!(matches!(
+            truncated.parse::<Share>(),
+            Err(ShamirError::Malformed(_))
+        ));
+        let invalid = text.replacen('-', "-U", 1);
+        assert!(matches!(invalid.parse::<Share>(), Err(ShamirError::Malformed(_))));
+    }
+}
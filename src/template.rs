@@ -0,0 +1,217 @@
+//! Templates with `{{ vult://app/key }}` references.
+//!
+//! Config files often need a few secrets mixed into otherwise static text.
+//! A template marks each one with a placeholder:
+//!
+//! ```text
+//! database:
+//!   password: {{ vult://postgres/password }}
+//! github_token: "{{vult://github/token}}"
+//! ```
+//!
+//! Whitespace inside the braces is optional. Placeholders that don't start
+//! with `vult://`, such as Jinja or Helm expressions, are left untouched.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use vult::template::Template;
+//!
+//! let template = Template::parse(&text)?;
+//! let rendered = template.render(vault.keys()).await?;
+//! ```
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use zeroize::Zeroizing;
+
+use crate::core::KeyRef;
+use crate::error::{Result, VaultError};
+use crate::services::KeyService;
+
+/// Scheme that marks a placeholder as a vault reference
+const SCHEME: &str = "vult://";
+
+/// A `{{ vult://... }}` placeholder and the text it replaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placeholder {
+    range: Range<usize>,
+    key: KeyRef,
+}
+
+/// A parsed template.
+#[derive(Debug, Clone)]
+pub struct Template<'a> {
+    text: &'a str,
+    placeholders: Vec<Placeholder>,
+}
+
+impl<'a> Template<'a> {
+    /// Finds every vault reference in `text`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VaultError::InvalidInput`] with the line number if a
+    /// `vult://` placeholder has no key name.
+    pub fn parse(text: &'a str) -> Result<Self> {
+        let mut placeholders = Vec::new();
+        let mut pos = 0;
+
+        while let Some(offset) = text[pos..].find("{{") {
+            let start = pos + offset;
+            let inner_start = start + 2;
+            let Some(len) = text[inner_start..].find("}}") else {
+                break;
+            };
+            let inner_end = inner_start + len;
+
+            let Some(reference) = text[inner_start..inner_end].trim().strip_prefix(SCHEME) else {
+                pos = inner_start;
+                continue;
+            };
+            let key = reference.parse::<KeyRef>().map_err(|e| {
+                let line = text[..start].matches('\n').count() + 1;
+                VaultError::InvalidInput(format!("line {line}: {e}"))
+            })?;
+            placeholders.push(Placeholder {
+                range: start..inner_end + 2,
+                key,
+            });
+            pos = inner_end + 2;
+        }
+
+        Ok(Self { text, placeholders })
+    }
+
+    /// Returns the distinct keys referenced, in order of first use.
+    pub fn references(&self) -> Vec<&KeyRef> {
+        let mut keys: Vec<&KeyRef> = Vec::new();
+        for placeholder in &self.placeholders {
+            if !keys.contains(&&placeholder.key) {
+                keys.push(&placeholder.key);
+            }
+        }
+        keys
+    }
+
+    /// Replaces every reference with the value of its key.
+    ///
+    /// All keys are checked before any value is read, so a template with an
+    /// unknown reference produces no output at all.
+    ///
+    /// # Errors
+    ///
+    /// - [`VaultError::Locked`] if the vault is locked
+    /// - [`VaultError::NotFound`] naming every reference that doesn't exist
+    pub async fn render(&self, keys: &KeyService) -> Result<Zeroizing<String>> {
+        let references = self.references();
+
+        let mut missing = Vec::new();
+        for key in &references {
+            if !keys.exists(&key.app_name, &key.key_name).await? {
+                missing.push(key.to_string());
+            }
+        }
+        if !missing.is_empty() {
+            return Err(VaultError::NotFound(missing.join(", ")));
+        }
+
+        let mut values: HashMap<&KeyRef, Zeroizing<String>> = HashMap::new();
+        for key in references {
+            let api_key = keys.get(&key.app_name, &key.key_name).await?;
+            values.insert(key, Zeroizing::new(api_key.key_value));
+        }
+
+        // Reserve the final size up front so the buffer is never reallocated
+        // and no stray copies of the values are left behind
+        let size = self.text.len()
+            + self
+                .placeholders
+                .iter()
+                .map(|p| values[&p.key].len())
+                .sum::<usize>();
+        let mut rendered = Zeroizing::new(String::with_capacity(size));
+        let mut last = 0;
+        for placeholder in &self.placeholders {
+            rendered.push_str(&self.text[last..placeholder.range.start]);
+            rendered.push_str(&values[&placeholder.key]);
+            last = placeholder.range.end;
+        }
+        rendered.push_str(&self.text[last..]);
+
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::VaultManager;
+
+    async fn setup_vault() -> VaultManager {
+        let vault = VaultManager::new("sqlite::memory:").await.unwrap();
+        vault.auth().init_vault("test-pin-123").await.unwrap();
+        vault
+            .keys()
+            .create(Some("github"), "token", "ghp_123", None, None)
+            .await
+            .unwrap();
+        vault
+            .keys()
+            .create(None, "loose", "v", None, None)
+            .await
+            .unwrap();
+        vault
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let template = Template::parse(
+            "a: {{ vult://github/token }}\nb: {{vult://loose}}\nc: {{ vult://github/token }}",
+        )
+        .unwrap();
+        let references: Vec<String> = template
+            .references()
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        assert_eq!(references, vec!["github/token", "loose"]);
+        assert_eq!(template.placeholders.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_ignores_other_placeholders() {
+        let template = Template::parse("{{ .Values.name }} {{ vult }} {{ unclosed").unwrap();
+        assert!(template.references().is_empty());
+    }
+
+    #[test]
+    fn test_parse_reports_line_of_bad_reference() {
+        let result = Template::parse("ok\n\nkey: {{ vult://github/ }}");
+        assert!(matches!(result, Err(VaultError::InvalidInput(ref m)) if m.starts_with("line 3:")));
+    }
+
+    #[tokio::test]
+    async fn test_render() {
+        let vault = setup_vault().await;
+        let template =
+            Template::parse("token={{ vult://github/token }}\nloose={{vult://loose}}\n{{ x }}")
+                .unwrap();
+
+        let rendered = template.render(vault.keys()).await.unwrap();
+        assert_eq!(rendered.as_str(), "token=ghp_123\nloose=v\n{{ x }}");
+    }
+
+    #[tokio::test]
+    async fn test_render_reports_every_missing_key() {
+        let vault = setup_vault().await;
+        let template = Template::parse(
+            "{{ vult://github/token }} {{ vult://aws/key }} {{ vult://gitlab/token }}",
+        )
+        .unwrap();
+
+        let result = template.render(vault.keys()).await;
+        assert!(matches!(result, Err(VaultError::NotFound(ref m)) if m == "aws/key, gitlab/token"));
+    }
+}
@@ -25,7 +25,7 @@ async fn test_auto_lock_integration_flow() {
     auth.start_activity_counter();
 
     // Initialize and unlock
-    auth.initialize("integrationTest123").await.unwrap();
+    auth.initialize("integrationTest123", None).await.unwrap();
     assert!(
         auth.is_unlocked().await,
         "Vault should be unlocked after initialization"
@@ -51,7 +51,7 @@ async fn test_activity_prevents_auto_lock_integration() {
     let auth = Arc::new(AuthManager::new(vault, Some(Duration::from_secs(3))));
 
     auth.start_activity_counter();
-    auth.initialize("activityPreventTest").await.unwrap();
+    auth.initialize("activityPreventTest", None).await.unwrap();
 
     // Simulate user activity every 2 seconds for 8 seconds
     for _ in 0..4 {
@@ -77,7 +77,7 @@ async fn test_lock_stops_counter_integration() {
     let auth = Arc::new(AuthManager::new(vault, Some(Duration::from_secs(5))));
 
     auth.start_activity_counter();
-    auth.initialize("lockStopsCounter").await.unwrap();
+    auth.initialize("lockStopsCounter", None).await.unwrap();
 
     // Let counter increment
     tokio::time::sleep(Duration::from_secs(2)).await;
@@ -113,7 +113,7 @@ async fn test_unlock_restarts_counter_integration() {
     let auth = Arc::new(AuthManager::new(vault, Some(Duration::from_secs(5))));
 
     auth.start_activity_counter();
-    auth.initialize("unlockRestartsCounter").await.unwrap();
+    auth.initialize("unlockRestartsCounter", None).await.unwrap();
 
     // Let counter increment a bit
     tokio::time::sleep(Duration::from_secs(2)).await;
@@ -129,7 +129,7 @@ async fn test_unlock_restarts_counter_integration() {
     );
 
     // Unlock the vault
-    auth.unlock("unlockRestartsCounter").await.unwrap();
+    auth.unlock("unlockRestartsCounter", None).await.unwrap();
 
     // Counter should restart from 0 after unlock
     // Wait a bit for the counter to increment
@@ -151,7 +151,7 @@ async fn test_long_running_counter() {
     let auth = Arc::new(AuthManager::new(vault, Some(Duration::from_secs(60))));
 
     auth.start_activity_counter();
-    auth.initialize("longRunningCounter").await.unwrap();
+    auth.initialize("longRunningCounter", None).await.unwrap();
 
     // Wait for counter to increment several times
     tokio::time::sleep(Duration::from_secs(5)).await;
@@ -8,11 +8,13 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 use tempfile::TempDir;
 
-/// Get a command configured for the test binary with a temp database
+/// Get a command configured for the test binary with a temp database and
+/// session agent socket
 fn vult_cmd(temp_dir: &TempDir) -> Command {
     let db_path = temp_dir.path().join("test-vault.db");
     let mut cmd = cargo_bin_cmd!("vult");
     cmd.env("VULT_DB_PATH", db_path);
+    cmd.env("VULT_AGENT_SOCKET", temp_dir.path().join("agent.sock"));
     cmd
 }
 
@@ -25,6 +27,11 @@ fn init_vault(temp_dir: &TempDir, pin: &str) {
         .success();
 }
 
+/// Wait out the backoff that follows a single failed unlock
+fn wait_for_backoff() {
+    std::thread::sleep(std::time::Duration::from_secs(2));
+}
+
 #[test]
 fn test_cli_version() {
     cargo_bin_cmd!("vult")
@@ -273,6 +280,237 @@ fn test_update_key_value() {
         .stdout(predicate::str::contains("new-value"));
 }
 
+#[test]
+fn test_history_get_version_and_rollback() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "stripe", "secret", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("sk_good")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args([
+            "update", "-a", "stripe", "secret", "--value", "sk_bad", "--reason", "rotation",
+        ])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+
+    // History shows the replaced version and why, never the values
+    vult_cmd(&temp_dir)
+        .args(["history", "stripe/secret"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("version 2"))
+        .stdout(predicate::str::contains("rotation"))
+        .stdout(predicate::str::contains("sk_").not());
+
+    vult_cmd(&temp_dir)
+        .args(["get", "secret", "-a", "stripe", "--version", "1"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout("sk_good\n");
+    vult_cmd(&temp_dir)
+        .args(["get", "secret", "-a", "stripe", "--version", "7"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(2);
+
+    vult_cmd(&temp_dir)
+        .args(["rollback", "stripe/secret", "1"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("as version 3"));
+    vult_cmd(&temp_dir)
+        .args(["get", "secret", "-a", "stripe"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout("sk_good\n");
+
+    // --keep 1 drops all but the newest previous value
+    let output = vult_cmd(&temp_dir)
+        .args(["history", "stripe/secret", "--keep", "1", "--json"])
+        .env("VULT_PIN", "123456")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let history: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(history["current_version"], 3);
+    assert_eq!(history["history_limit"], 1);
+    assert_eq!(history["versions"][0]["version"], 2);
+    assert_eq!(history["versions"][0]["reason"], "rollback to version 1");
+    assert_eq!(history["versions"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_audit_show_and_verify() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "stripe", "live", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("sk_live_secret")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args(["get", "live", "-a", "stripe"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .arg("list")
+        .env("VULT_PIN", "wrong-pin")
+        .assert()
+        .failure();
+    wait_for_backoff();
+
+    let output = vult_cmd(&temp_dir)
+        .args(["audit", "show", "--key", "stripe/live", "--json"])
+        .env("VULT_PIN", "123456")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("sk_live"));
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let events: Vec<&str> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(events, ["create", "get"]);
+
+    vult_cmd(&temp_dir)
+        .args(["audit", "show", "--event", "unlock_failed"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("unlock_failed"));
+    vult_cmd(&temp_dir)
+        .args(["audit", "verify"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Audit log intact"));
+
+    // Rewriting who read the key is detected
+    let url = format!(
+        "sqlite://{}",
+        temp_dir.path().join("test-vault.db").display()
+    );
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let db = vult::database::VaultDb::open(&url).await.unwrap();
+        for sql in [
+            "DROP TRIGGER audit_log_no_update",
+            "UPDATE audit_log SET actor = 'someone-else' WHERE event = 'get'",
+        ] {
+            sqlx::query(sql).execute(db.pool()).await.unwrap();
+        }
+        db.pool().close().await;
+    });
+    vult_cmd(&temp_dir)
+        .args(["audit", "verify"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(9)
+        .stderr(predicate::str::contains("audit log was altered"));
+}
+
+#[test]
+fn test_expiring_exit_codes_and_get_warning() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["expiring"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No keys expire"));
+
+    // Due soon but nothing past due: exit 11
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "aws", "key", "--stdin", "--rotate-every", "1d"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("AKIA")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args(["expiring", "--within", "12h"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args(["expiring", "--within", "2d"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(11)
+        .stdout(predicate::str::contains("rotation due"));
+
+    // Expired: exit 12, and get warns without changing its output
+    vult_cmd(&temp_dir)
+        .args([
+            "add",
+            "-a",
+            "github",
+            "token",
+            "--stdin",
+            "--expires-at",
+            "2020-01-01",
+        ])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_old")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout("ghp_old\n")
+        .stderr(predicate::str::contains("expired"));
+
+    let output = vult_cmd(&temp_dir)
+        .args(["expiring", "--within", "2d", "--json"])
+        .env("VULT_PIN", "123456")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(12));
+    let due: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(due[0]["key"]["app_name"], "github");
+    assert_eq!(due[0]["reason"], "expiry");
+    assert_eq!(due[0]["overdue"], true);
+    assert_eq!(due[1]["reason"], "rotation");
+    assert_eq!(due[1]["overdue"], false);
+
+    vult_cmd(&temp_dir)
+        .args(["update", "-a", "github", "token", "--no-expiry"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args(["update", "-a", "aws", "key", "--no-rotation"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+    vult_cmd(&temp_dir)
+        .args(["expiring", "--within", "2d"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+}
+
 #[test]
 fn test_delete_key() {
     let temp_dir = TempDir::new().expect("Failed to create temp dir");
@@ -391,6 +629,7 @@ fn test_change_pin_success() {
         .assert()
         .failure()
         .stderr(predicate::str::contains("Invalid PIN"));
+    wait_for_backoff();
 
     // Verify new PIN works and can access the key
     vult_cmd(&temp_dir)
@@ -430,3 +669,1024 @@ fn test_change_pin_short_new_pin() {
         .failure()
         .stderr(predicate::str::contains("at least 6 characters"));
 }
+
+#[test]
+fn test_kdf_show_defaults() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["kdf", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("argon2id$m=65536,t=3,p=4"));
+}
+
+#[test]
+fn test_kdf_calibrate_applies_on_next_unlock() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    let calibrate = [
+        "kdf",
+        "calibrate",
+        "--target-ms",
+        "50",
+        "--memory-mib",
+        "19",
+        "--parallelism",
+        "1",
+    ];
+
+    // 19 MiB is below the default 64 MiB, so it needs confirmation
+    vult_cmd(&temp_dir)
+        .args(calibrate)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--allow-downgrade"));
+    vult_cmd(&temp_dir)
+        .args(["kdf", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pending: -"));
+
+    vult_cmd(&temp_dir)
+        .args(calibrate)
+        .arg("--allow-downgrade")
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("argon2id$m=19456"));
+
+    vult_cmd(&temp_dir)
+        .args(["kdf", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("applied on next unlock"));
+
+    // Any unlock performs the upgrade
+    vult_cmd(&temp_dir)
+        .arg("list")
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["kdf", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Current: argon2id$m=19456"))
+        .stdout(predicate::str::contains("Pending: -"));
+
+    // The PIN still works with the new parameters
+    vult_cmd(&temp_dir)
+        .arg("list")
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_encrypted_metadata_hides_names_on_disk() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+
+    vult_cmd(&temp_dir)
+        .args(["init", "--encrypt-metadata"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["add", "deploy-token", "-a", "acme-payments", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("secret-value")
+        .assert()
+        .success();
+
+    // Lookups, listing and search still work once unlocked
+    vult_cmd(&temp_dir)
+        .args(["get", "deploy-token", "-a", "acme-payments"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("secret-value"));
+    vult_cmd(&temp_dir)
+        .args(["search", "PAYMENTS"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploy-token"));
+
+    let db = std::fs::read(temp_dir.path().join("test-vault.db")).unwrap();
+    let contains = |needle: &[u8]| db.windows(needle.len()).any(|w| w == needle);
+    assert!(!contains(b"acme-payments"));
+    assert!(!contains(b"deploy-token"));
+}
+
+#[test]
+fn test_metadata_encrypt_existing_vault() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "token", "-a", "github", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["metadata", "encrypt"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Encrypted metadata for 1 key(s)"));
+
+    vult_cmd(&temp_dir)
+        .arg("status")
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Metadata: encrypted"));
+
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+}
+
+#[test]
+fn test_init_with_keyfile_requires_it_to_unlock() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let key_file = temp_dir.path().join("vault.key");
+
+    vult_cmd(&temp_dir)
+        .args(["init", "--keyfile"])
+        .arg(&key_file)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created key file"));
+    assert!(key_file.exists());
+
+    vult_cmd(&temp_dir)
+        .args(["add", "token", "-a", "github", "--stdin", "--keyfile"])
+        .arg(&key_file)
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    // Without the key file the PIN alone is not enough
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("requires a key file"));
+
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "123456")
+        .env("VULT_KEYFILE", &key_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+}
+
+#[test]
+fn test_missing_keyfile_reports_path() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let key_file = temp_dir.path().join("vault.key");
+
+    vult_cmd(&temp_dir)
+        .args(["init", "--keyfile"])
+        .arg(&key_file)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+    std::fs::remove_file(&key_file).unwrap();
+
+    vult_cmd(&temp_dir)
+        .args(["list", "--keyfile"])
+        .arg(&key_file)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Key file not found or unreadable"));
+}
+
+#[test]
+fn test_slot_add_unlock_and_revoke() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let key_file = temp_dir.path().join("backup.key");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "token", "-a", "github", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["slot", "add", "offline", "--kind", "passphrase"])
+        .env("VULT_PIN", "123456")
+        .env("VULT_SLOT_SECRET", "correct horse battery staple")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added passphrase slot 'offline'"));
+
+    vult_cmd(&temp_dir)
+        .args(["slot", "add", "usb", "--kind", "keyfile", "--file"])
+        .arg(&key_file)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created key file"));
+
+    vult_cmd(&temp_dir)
+        .args(["slot", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("primary"))
+        .stdout(predicate::str::contains("offline"))
+        .stdout(predicate::str::contains("usb"));
+
+    // The passphrase works at the PIN prompt, the key file without a PIN
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "correct horse battery staple")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github", "--keyfile"])
+        .arg(&key_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+
+    vult_cmd(&temp_dir)
+        .args(["slot", "revoke", "1", "--force"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Revoked slot 'offline'"));
+
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "correct horse battery staple")
+        .assert()
+        .failure()
+        .code(1);
+
+    // The failure starts a backoff; commands are refused instead of waiting
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github", "--keyfile"])
+        .arg(&key_file)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Try again in"));
+
+    // The key-file slot can clear it
+    vult_cmd(&temp_dir)
+        .args(["unlock", "--reset-lockout", "--keyfile"])
+        .arg(&key_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Lockout cleared"));
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github", "--keyfile"])
+        .arg(&key_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+}
+
+/// Runs `vult recovery create` and returns the printed share blocks.
+fn create_recovery_shares(temp_dir: &TempDir, shares: u8, threshold: u8) -> Vec<String> {
+    let output = vult_cmd(temp_dir)
+        .args(["recovery", "create", "--shares"])
+        .arg(shares.to_string())
+        .arg("--threshold")
+        .arg(threshold.to_string())
+        .env("VULT_PIN", "123456")
+        .output()
+        .expect("Failed to run recovery create");
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .split("\n\n")
+        .map(|block| block.trim().to_string())
+        .filter(|block| block.starts_with("VULT-RECOVERY-SHARE"))
+        .collect()
+}
+
+#[test]
+fn test_recovery_restore_sets_new_pin() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "token", "-a", "github", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    let shares = create_recovery_shares(&temp_dir, 5, 3);
+    assert_eq!(shares.len(), 5);
+
+    // Two shares are not enough
+    vult_cmd(&temp_dir)
+        .args(["recovery", "restore"])
+        .env("VULT_NEW_PIN", "654321")
+        .write_stdin(format!("{}\n\n{}\n", shares[0], shares[3]))
+        .assert()
+        .failure()
+        .code(5)
+        .stderr(predicate::str::contains("Need 3 recovery shares, got 2"));
+
+    vult_cmd(&temp_dir)
+        .args(["recovery", "restore"])
+        .env("VULT_NEW_PIN", "654321")
+        .write_stdin(format!("{}\n\n{}\n\n{}\n", shares[1], shares[3], shares[4]))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Vault recovered"));
+
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "654321")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+}
+
+#[test]
+fn test_recovery_restore_rejects_corrupted_share() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    let shares = create_recovery_shares(&temp_dir, 3, 2);
+
+    // A single typo in the body of the first share
+    let corrupted = shares[0].replacen("\n0", "\n1", 1);
+    assert_ne!(corrupted, shares[0]);
+
+    vult_cmd(&temp_dir)
+        .args(["recovery", "restore"])
+        .env("VULT_NEW_PIN", "654321")
+        .write_stdin(format!("{}\n\n{}\n", corrupted, shares[1]))
+        .assert()
+        .failure()
+        .code(5)
+        .stderr(predicate::str::contains("checksum mismatch"));
+
+    vult_cmd(&temp_dir)
+        .args(["list"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+}
+
+/// Stops the session agent when a test ends, even if it fails.
+#[cfg(unix)]
+struct AgentGuard<'a>(&'a TempDir);
+
+#[cfg(unix)]
+impl Drop for AgentGuard<'_> {
+    fn drop(&mut self) {
+        let _ = vult_cmd(self.0).args(["agent", "stop"]).ok();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_agent_skips_pin_until_locked() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["agent", "start"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Session agent started"));
+    let _guard = AgentGuard(&temp_dir);
+
+    // The first command unlocks with the PIN and hands the key to the agent
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["get", "-a", "github", "token"])
+        .env_remove("VULT_PIN")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+
+    vult_cmd(&temp_dir)
+        .args(["agent", "status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("test-vault.db"));
+
+    vult_cmd(&temp_dir)
+        .arg("lock")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("dropped its keys"));
+
+    vult_cmd(&temp_dir)
+        .args(["get", "-a", "github", "token"])
+        .env_remove("VULT_PIN")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure();
+
+    vult_cmd(&temp_dir)
+        .args(["agent", "stop"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Session agent stopped"));
+}
+
+#[test]
+fn test_shell_runs_key_commands_with_one_unlock() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .arg("shell")
+        .env("VULT_PIN", "123456")
+        .write_stdin(
+            "list\n\
+             update -a github token -v \"ghp new\"\n\
+             get -a github token\n\
+             init\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("github"))
+        .stdout(predicate::str::contains("ghp new"))
+        .stdout(predicate::str::contains("Vault is locked."))
+        .stderr(predicate::str::contains("not available in the shell"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_injects_keys_and_passes_exit_code() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["run", "--env", "GH_TOKEN=github/token", "--"])
+        .args(["sh", "-c", "echo \"token=$GH_TOKEN\"; exit 3"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .code(3)
+        .stdout(predicate::str::contains("token=ghp_value"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_fails_before_starting_on_missing_key() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    vult_cmd(&temp_dir)
+        .args(["run", "-e", "MISSING=github/token", "--", "echo", "started"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(2)
+        .stdout(predicate::str::contains("started").not());
+
+    vult_cmd(&temp_dir)
+        .args(["run", "-e", "1BAD=github/token", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid environment variable name"));
+}
+
+#[test]
+fn test_check_and_env_use_manifest() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    std::fs::write(
+        temp_dir.path().join(".vult.toml"),
+        "[secrets]\nGH_TOKEN = \"github/token\"\nOPENAI_API_KEY = \"openai/key\"\n",
+    )
+    .unwrap();
+    let project = temp_dir.path().join("src");
+    std::fs::create_dir(&project).unwrap();
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("it's secret")
+        .assert()
+        .success();
+
+    // The manifest is found from a subdirectory, and values never show up
+    vult_cmd(&temp_dir)
+        .arg("check")
+        .current_dir(&project)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(2)
+        .stdout(predicate::str::contains("1 of 2 keys present"))
+        .stdout(predicate::str::contains("secret").not())
+        .stderr(predicate::str::contains("openai/key"));
+
+    vult_cmd(&temp_dir)
+        .args(["env", "--manifest"])
+        .current_dir(&project)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty());
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "openai", "key", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("sk-123")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .arg("check")
+        .current_dir(&project)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 of 2 keys present"));
+
+    vult_cmd(&temp_dir)
+        .args(["env", "--manifest"])
+        .current_dir(&project)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("export GH_TOKEN='it'\\''s secret'"))
+        .stdout(predicate::str::contains("export OPENAI_API_KEY='sk-123'"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_with_manifest() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    let manifest = temp_dir.path().join("project.toml");
+    std::fs::write(&manifest, "[secrets]\nGH_TOKEN = \"github/token\"\n").unwrap();
+
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .arg("run")
+        .arg("--manifest")
+        .arg(&manifest)
+        .args(["--", "sh", "-c", "echo \"token=$GH_TOKEN\""])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("token=ghp_value"));
+}
+
+#[test]
+fn test_inject_renders_template_to_private_file() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    let template = temp_dir.path().join("config.tpl");
+    let output = temp_dir.path().join("config.yaml");
+    std::fs::write(&template, "token: {{ vult://github/token }}\nname: {{ .Name }}\n").unwrap();
+
+    vult_cmd(&temp_dir)
+        .arg("inject")
+        .arg("-i")
+        .arg(&template)
+        .arg("-o")
+        .arg(&output)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success();
+
+    assert_eq!(
+        std::fs::read_to_string(&output).unwrap(),
+        "token: ghp_value\nname: {{ .Name }}\n"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Rendering into a pipe
+    vult_cmd(&temp_dir)
+        .arg("inject")
+        .env("VULT_PIN", "123456")
+        .write_stdin("export GH={{vult://github/token}}")
+        .assert()
+        .success()
+        .stdout("export GH=ghp_value");
+}
+
+#[test]
+fn test_inject_unknown_reference_writes_nothing() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+
+    let template = temp_dir.path().join("config.tpl");
+    let output = temp_dir.path().join("config.yaml");
+    std::fs::write(&template, "token: {{ vult://github/token }}\n").unwrap();
+
+    vult_cmd(&temp_dir)
+        .arg("inject")
+        .arg("-i")
+        .arg(&template)
+        .arg("-o")
+        .arg(&output)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("github/token"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_export_import_into_vault_with_another_pin() {
+    let source = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&source, "123456");
+    vult_cmd(&source)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    let file = source.path().join("vault.vultbak");
+    vult_cmd(&source)
+        .arg("export")
+        .arg(&file)
+        .env("VULT_PIN", "123456")
+        .env("VULT_EXPORT_PASSPHRASE", "correct horse battery")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exported 1 key(s)"));
+
+    let target = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&target, "654321");
+    vult_cmd(&target)
+        .arg("import")
+        .arg(&file)
+        .env("VULT_PIN", "654321")
+        .env("VULT_EXPORT_PASSPHRASE", "correct horse battery")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 1 key(s)"));
+
+    vult_cmd(&target)
+        .args(["get", "-a", "github", "token"])
+        .env("VULT_PIN", "654321")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+
+    // Importing again keeps the existing key
+    vult_cmd(&target)
+        .arg("import")
+        .arg(&file)
+        .env("VULT_PIN", "654321")
+        .env("VULT_EXPORT_PASSPHRASE", "correct horse battery")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("github/token (already exists)"));
+}
+
+#[test]
+fn test_import_wrong_passphrase_fails() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    let file = temp_dir.path().join("vault.vultbak");
+    vult_cmd(&temp_dir)
+        .arg("export")
+        .arg(&file)
+        .env("VULT_PIN", "123456")
+        .env("VULT_EXPORT_PASSPHRASE", "correct horse battery")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["import", "--replace", "--force"])
+        .arg(&file)
+        .env("VULT_PIN", "123456")
+        .env("VULT_EXPORT_PASSPHRASE", "wrong horse battery")
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("wrong passphrase"));
+}
+
+#[test]
+fn test_import_env_file_with_conflict_strategies() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "proj", "OPENAI_API_KEY", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("sk-old")
+        .assert()
+        .success();
+
+    let file = temp_dir.path().join(".env");
+    std::fs::write(
+        &file,
+        "# keys\nOPENAI_API_KEY=sk-new\nexport GITHUB_TOKEN=\"ghp_1\"\n",
+    )
+    .unwrap();
+    let import = |extra: &[&str]| {
+        let mut cmd = vult_cmd(&temp_dir);
+        cmd.args(["import", "--from", "env", "--app", "proj"])
+            .args(extra)
+            .arg(&file)
+            .env("VULT_PIN", "123456");
+        cmd
+    };
+    let get = |name: &str| {
+        vult_cmd(&temp_dir)
+            .args(["get", "-a", "proj", name])
+            .env("VULT_PIN", "123456")
+            .assert()
+    };
+
+    // A dry run reports the outcome and writes nothing
+    import(&["--on-conflict", "overwrite", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added proj/GITHUB_TOKEN"))
+        .stdout(predicate::str::contains("Overwrote proj/OPENAI_API_KEY"))
+        .stdout(predicate::str::contains("2 key(s) would be imported"));
+    get("GITHUB_TOKEN").code(2);
+
+    import(&["--on-conflict", "rename"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Renamed proj/OPENAI_API_KEY to proj/OPENAI_API_KEY-2",
+        ))
+        .stdout(predicate::str::contains("Imported 2 key(s)"));
+    get("OPENAI_API_KEY")
+        .success()
+        .stdout(predicate::str::contains("sk-old"));
+    get("OPENAI_API_KEY-2")
+        .success()
+        .stdout(predicate::str::contains("sk-new"));
+    get("GITHUB_TOKEN")
+        .success()
+        .stdout(predicate::str::contains("ghp_1"));
+}
+
+#[test]
+fn test_import_invalid_file_changes_nothing() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    let file = temp_dir.path().join("bitwarden.json");
+    std::fs::write(&file, r#"{"encrypted": true, "items": []}"#).unwrap();
+
+    vult_cmd(&temp_dir)
+        .args(["import", "--from", "bitwarden"])
+        .arg(&file)
+        .env("VULT_PIN", "123456")
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("unencrypted JSON"));
+
+    vult_cmd(&temp_dir)
+        .args(["import", "--from", "lastpass"])
+        .arg(&file)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unknown import format"));
+}
+
+#[test]
+fn test_keepass_export_and_import() {
+    let source = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&source, "123456");
+    vult_cmd(&source)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .args(["--url", "https://api.github.com"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    let file = source.path().join("team.kdbx");
+    vult_cmd(&source)
+        .args(["export", "--to", "keepass"])
+        .arg(&file)
+        .env("VULT_PIN", "123456")
+        .env("VULT_EXPORT_PASSPHRASE", "correct horse battery")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exported 1 key(s)"));
+
+    let target = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&target, "654321");
+    vult_cmd(&target)
+        .args(["import", "--from", "keepass"])
+        .arg(&file)
+        .env("VULT_PIN", "654321")
+        .env("VULT_EXPORT_PASSPHRASE", "wrong horse battery")
+        .assert()
+        .code(6);
+
+    vult_cmd(&target)
+        .args(["import", "--from", "keepass"])
+        .arg(&file)
+        .env("VULT_PIN", "654321")
+        .env("VULT_EXPORT_PASSPHRASE", "correct horse battery")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 1 key(s)"));
+
+    vult_cmd(&target)
+        .args(["get", "-a", "github", "token"])
+        .env("VULT_PIN", "654321")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+}
+
+#[test]
+fn test_backup_restore_returns_to_snapshot() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    let add_key = |name: &str| {
+        vult_cmd(&temp_dir)
+            .args(["add", name, "--stdin"])
+            .env("VULT_PIN", "123456")
+            .write_stdin("value")
+            .assert()
+            .success();
+    };
+
+    add_key("first");
+    vult_cmd(&temp_dir)
+        .args(["backup", "create"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Snapshot"));
+    add_key("second");
+
+    let output = vult_cmd(&temp_dir)
+        .args(["backup", "list", "--json"])
+        .output()
+        .unwrap();
+    let backups: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let id = backups[0]["id"].as_str().unwrap().to_string();
+
+    // A snapshot must open with the current PIN
+    vult_cmd(&temp_dir)
+        .args(["backup", "restore", &id, "--force"])
+        .env("VULT_PIN", "654321")
+        .assert()
+        .code(1);
+    wait_for_backoff();
+
+    vult_cmd(&temp_dir)
+        .args(["backup", "restore", &id, "--force"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Restored snapshot"))
+        .stdout(predicate::str::contains("The replaced vault was saved"));
+
+    vult_cmd(&temp_dir)
+        .arg("list")
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("first"))
+        .stdout(predicate::str::contains("second").not());
+
+    vult_cmd(&temp_dir)
+        .args(["backup", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&id));
+}
+
+#[test]
+fn test_db_migrate_dry_run_then_apply() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    init_vault(&temp_dir, "123456");
+    vult_cmd(&temp_dir)
+        .args(["add", "-a", "github", "token", "--stdin"])
+        .env("VULT_PIN", "123456")
+        .write_stdin("ghp_value")
+        .assert()
+        .success();
+
+    vult_cmd(&temp_dir)
+        .args(["db", "migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Schema is up to date"));
+
+    // Roll the vault back to schema v4: the v5 index is not there yet
+    let url = format!(
+        "sqlite://{}",
+        temp_dir.path().join("test-vault.db").display()
+    );
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let db = vult::database::VaultDb::open(&url).await.unwrap();
+        for sql in [
+            "DROP INDEX idx_api_keys_app_key",
+            "UPDATE schema_version SET version = 4",
+        ] {
+            sqlx::query(sql).execute(db.pool()).await.unwrap();
+        }
+        db.pool().close().await;
+    });
+
+    vult_cmd(&temp_dir)
+        .args(["db", "migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5  Key names unique per app"))
+        .stdout(predicate::str::contains("6  Version history"))
+        .stdout(predicate::str::contains("7  Tamper-evident audit log"))
+        .stdout(predicate::str::contains(
+            "8  Expiry dates and rotation reminders",
+        ))
+        .stdout(predicate::str::contains("9  Vault settings"))
+        .stdout(predicate::str::contains(
+            "would migrate from version 4 to 9",
+        ));
+
+    vult_cmd(&temp_dir)
+        .args(["db", "migrate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Snapshot"))
+        .stdout(predicate::str::contains(
+            "Migrated schema from version 4 to 9",
+        ));
+
+    let output = vult_cmd(&temp_dir)
+        .args(["db", "migrate", "--dry-run", "--json"])
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["from_version"], 9);
+    assert_eq!(report["migrations"].as_array().unwrap().len(), 0);
+
+    vult_cmd(&temp_dir)
+        .args(["get", "token", "-a", "github"])
+        .env("VULT_PIN", "123456")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ghp_value"));
+}
@@ -74,6 +74,17 @@ describe('Tauri Service Layer', () => {
       expect(mockInvoke).toHaveBeenCalledWith('unlock_vault', { pin: 'test1234' });
     });
 
+    it('should pass the key file path when given', async () => {
+      mockInvoke.mockResolvedValue(undefined);
+
+      await unlockVault({ pin: 'test1234', keyFilePath: '/home/user/vault.key' });
+
+      expect(mockInvoke).toHaveBeenCalledWith('unlock_vault', {
+        pin: 'test1234',
+        keyFilePath: '/home/user/vault.key',
+      });
+    });
+
     it('should throw error on invalid PIN', async () => {
       mockInvoke.mockRejectedValue(new Error('Invalid PIN'));
 
@@ -31,7 +31,10 @@ import type {
  */
 export async function initVault(args: InitVaultArgs): Promise<void> {
   try {
-    await invoke('init_vault', { pin: args.pin });
+    await invoke('init_vault', {
+      pin: args.pin,
+      ...(args.keyFilePath ? { keyFilePath: args.keyFilePath } : {}),
+    });
   } catch (error) {
     throw new Error(`Failed to initialize vault: ${error}`);
   }
@@ -51,7 +54,10 @@ export async function initVault(args: InitVaultArgs): Promise<void> {
  */
 export async function unlockVault(args: UnlockVaultArgs): Promise<void> {
   try {
-    await invoke('unlock_vault', { pin: args.pin });
+    await invoke('unlock_vault', {
+      pin: args.pin,
+      ...(args.keyFilePath ? { keyFilePath: args.keyFilePath } : {}),
+    });
   } catch (error) {
     throw new Error(`Failed to unlock vault: ${error}`);
   }
@@ -75,6 +75,8 @@ export interface SessionState {
 export interface InitVaultArgs {
   /** The PIN to set for vault access (min 6 characters) */
   pin: string;
+  /** Optional key file to require on unlock; created if it doesn't exist */
+  keyFilePath?: string;
 }
 
 /**
@@ -84,6 +86,8 @@ export interface InitVaultArgs {
 export interface UnlockVaultArgs {
   /** The PIN to unlock the vault */
   pin: string;
+  /** Key file, for vaults initialized with one */
+  keyFilePath?: string;
 }
 
 /**